@@ -180,8 +180,8 @@ impl CryptoConstants {
 
         // 生成置换表（S-box）
         let mut obfuscate_table = [0u8; 256];
-        for i in 0..256 {
-            obfuscate_table[i] = i as u8;
+        for (i, slot) in obfuscate_table.iter_mut().enumerate() {
+            *slot = i as u8;
         }
 
         // Fisher-Yates 洗牌算法
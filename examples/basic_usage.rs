@@ -93,7 +93,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             // 解析hex字符串为bytes
             let hex_str = &args[2];
 
-            if hex_str.len() % 2 != 0 {
+            if !hex_str.len().is_multiple_of(2) {
                 eprintln!("错误: hex字符串长度必须是偶数");
                 return Ok(());
             }
@@ -179,6 +179,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("  - 元数据section: .key_meta");
         }
 
+        "bench" => {
+            // 测量读写耗时（使用临时密钥，结束后恢复原密钥）
+            let iters = if args.len() >= 3 {
+                args[2].parse().unwrap_or(50)
+            } else {
+                50
+            };
+
+            let mut store = KeyStore::new()?;
+            let data_len = store.capacity() / 2;
+            let result = store.bench(data_len, iters)?;
+            println!("读写 {} 字节，各 {} 次:", data_len, iters);
+            println!(
+                "  读取: 平均 {:?}, 中位数 {:?}",
+                result.read_mean, result.read_median
+            );
+            println!(
+                "  写入: 平均 {:?}, 中位数 {:?}",
+                result.write_mean, result.write_median
+            );
+        }
+
         "capacity" => {
             // 显示容量信息
             let store = KeyStore::new()?;
@@ -217,6 +239,7 @@ fn print_usage(program: &str) {
     );
     println!("  {} info                    - 显示密钥存储信息", program);
     println!("  {} capacity                - 显示总容量", program);
+    println!("  {} bench [次数]            - 测量读写耗时", program);
     println!();
     println!("示例:");
     println!("  {} init", program);
@@ -0,0 +1,53 @@
+//! 供安全审查使用的密钥流采样
+
+use crate::crypto::{xor_cipher, zeroize};
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::sections::SectionMap;
+
+impl KeyStore {
+    /// 导出指定分片加密时使用的密钥流的前 `len` 字节（需要 `audit` feature）
+    ///
+    /// 密钥流由派生sections（默认 .text 段）的哈希和该分片的nonce派生，与存储的密钥
+    /// 无关，可以交给审查人员做随机性检验。分片在异或前还会经过一层混淆，这里只返回
+    /// 异或使用的密钥流本身。分片密钥为32字节并循环使用，因此样本以32字节为周期重复
+    ///
+    /// 注意：密钥流与二进制中的密文异或即可去掉异或这一层，只应在审查环境中启用此feature
+    ///
+    /// # 参数
+    ///
+    /// * `shard_index` - 分片在当前布局中的序号（`0..分片数量`）
+    /// * `len` - 样本长度（字节）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥流样本；序号超出分片数量时返回 `Error::Config`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let sample = store.derive_keystream_sample(0, 1024)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn derive_keystream_sample(&self, shard_index: usize, len: usize) -> Result<Vec<u8>> {
+        let metadata = self.metadata();
+        if shard_index >= metadata.num_shards {
+            return Err(Error::Config(format!(
+                "分片序号 {} 超出分片数量 {}",
+                shard_index, metadata.num_shards
+            )));
+        }
+
+        let binary_data = self.read_binary()?;
+        let sections = SectionMap::parse(&binary_data)?;
+        let mut base_key = Self::derive_base_key(&sections, metadata)?;
+        let mut shard_key = Self::derive_shard_key(&base_key, metadata, shard_index);
+        let sample = xor_cipher(&vec![0u8; len], &shard_key);
+        zeroize(&mut base_key);
+        zeroize(&mut shard_key);
+
+        Ok(sample)
+    }
+}
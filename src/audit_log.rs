@@ -0,0 +1,32 @@
+//! 密钥变更的审计事件
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// 触发审计事件的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOperation {
+    /// 写入新密钥（[`KeyStore::update_bytes`](crate::KeyStore::update_bytes) 及基于它的所有写入方法）
+    Update,
+    /// 清除密钥和元数据（[`KeyStore::reset`](crate::KeyStore::reset)）
+    Reset,
+    /// 用随机数据销毁分片（[`KeyStore::shred`](crate::KeyStore::shred)）
+    Shred,
+}
+
+/// 一次成功的密钥变更，传给 [`KeyStore::set_audit_hook`](crate::KeyStore::set_audit_hook) 设置的回调
+///
+/// 只包含密钥指纹（参见 [`KeyStore::stored_key_fingerprint`](crate::KeyStore::stored_key_fingerprint)），不包含密钥明文
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// 执行的操作
+    pub operation: AuditOperation,
+    /// 操作完成的时间
+    pub timestamp: SystemTime,
+    /// 操作前存储的密钥指纹，未写入过密钥或无法读取时为None
+    pub old_fingerprint: Option<String>,
+    /// 操作后存储的密钥指纹，操作后不再有可读的密钥时为None
+    pub new_fingerprint: Option<String>,
+    /// 被修改的二进制文件路径
+    pub path: PathBuf,
+}
@@ -0,0 +1,127 @@
+//! 密钥存储的读写性能测量
+
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use std::time::{Duration, Instant};
+
+/// [`KeyStore::bench`] 的测量结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    /// 写入耗时的平均值
+    pub write_mean: Duration,
+    /// 写入耗时的中位数
+    pub write_median: Duration,
+    /// 读取耗时的平均值
+    pub read_mean: Duration,
+    /// 读取耗时的中位数
+    pub read_median: Duration,
+}
+
+impl KeyStore {
+    /// 测量当前二进制上密钥读写的耗时
+    ///
+    /// 使用随机生成的临时密钥反复写入和读取，结束后把二进制、旁路文件和内存中的配置
+    /// 逐字节恢复为测量前的状态：不会改变存储的值、历史版本和nonce，尚未写入过密钥的
+    /// 二进制也保持未写入。测量期间暂停审计回调。可用于比较不同分片配置下的性能。
+    ///
+    /// # 参数
+    ///
+    /// * `data_len` - 临时密钥的长度（字节），不能超过容量
+    /// * `iters` - 读、写各自的迭代次数，必须大于0
+    ///
+    /// # 返回
+    ///
+    /// 成功返回读写耗时的平均值和中位数，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// let result = store.bench(4096, 20)?;
+    /// println!("写入中位数: {:?}", result.write_median);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn bench(&mut self, data_len: usize, iters: usize) -> Result<BenchResult> {
+        if iters == 0 {
+            return Err(Error::Config("迭代次数必须大于0".to_string()));
+        }
+
+        if data_len > self.capacity() {
+            return Err(Error::Config(format!(
+                "测试数据长度({})超出总容量({})",
+                data_len,
+                self.capacity()
+            )));
+        }
+
+        let state = self.capture_raw_state()?;
+        let hook = self.replace_audit_hook(None);
+        let measured = self.measure(data_len, iters);
+        self.replace_audit_hook(hook);
+
+        // 无论测量是否成功，都恢复原来的存储内容
+        self.restore_raw_state(&state)?;
+
+        measured
+    }
+
+    /// 执行实际的读写测量
+    fn measure(&mut self, data_len: usize, iters: usize) -> Result<BenchResult> {
+        let data = Self::generate_random_bytes(data_len);
+
+        let mut writes = Vec::with_capacity(iters);
+        for _ in 0..iters {
+            let start = Instant::now();
+            self.update_bytes(&data)?;
+            writes.push(start.elapsed());
+        }
+
+        let mut reads = Vec::with_capacity(iters);
+        for _ in 0..iters {
+            let start = Instant::now();
+            self.read_bytes()?;
+            reads.push(start.elapsed());
+        }
+
+        Ok(BenchResult {
+            write_mean: mean(&writes),
+            write_median: median(&mut writes),
+            read_mean: mean(&reads),
+            read_median: median(&mut reads),
+        })
+    }
+}
+
+/// 计算平均耗时
+fn mean(samples: &[Duration]) -> Duration {
+    samples.iter().sum::<Duration>() / samples.len() as u32
+}
+
+/// 计算耗时中位数
+fn median(samples: &mut [Duration]) -> Duration {
+    samples.sort();
+    let mid = samples.len() / 2;
+    if samples.len().is_multiple_of(2) {
+        (samples[mid - 1] + samples[mid]) / 2
+    } else {
+        samples[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_and_median() {
+        let mut samples: Vec<Duration> = [5, 1, 3, 7]
+            .iter()
+            .map(|&ms| Duration::from_millis(ms))
+            .collect();
+
+        assert_eq!(mean(&samples), Duration::from_millis(4));
+        assert_eq!(median(&mut samples), Duration::from_millis(4));
+        assert_eq!(median(&mut samples[..3]), Duration::from_millis(3));
+    }
+}
@@ -0,0 +1,130 @@
+//! 与二进制绑定的独立加密数据块
+//!
+//! 使用与分片相同的混淆和 .text 段派生密钥加密任意数据，生成的密文自包含（带随机nonce
+//! 和校验标签），可以存放在任意位置，但只有同一个二进制才能解密。
+
+use crate::crypto::{
+    constant_time_eq, decrypt_shard, derive_key_from_section, encrypt_shard, parse_binary,
+};
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use sha2::{Digest, Sha256};
+
+/// 数据块的魔数
+const MAGIC: &[u8; 4] = b"SCKB";
+
+/// 随机nonce的长度
+const NONCE_SIZE: usize = 16;
+
+/// 校验标签的长度
+const TAG_SIZE: usize = 16;
+
+/// 数据块头部大小：魔数 + nonce + 校验标签
+const HEADER_SIZE: usize = MAGIC.len() + NONCE_SIZE + TAG_SIZE;
+
+/// 用于派生加密密钥的代码段
+const DERIVE_SECTION: &str = ".text";
+
+impl KeyStore {
+    /// 使用当前二进制加密一段任意数据
+    ///
+    /// 加密方式与分片相同（.text 段派生密钥 + 编译时生成的混淆常量），每次加密使用新的
+    /// 随机nonce。密文可以保存在配置文件等任意位置，解密时必须使用同一个二进制
+    ///
+    /// # 参数
+    ///
+    /// * `data` - 要加密的数据
+    ///
+    /// # 返回
+    ///
+    /// 成功返回自包含的密文，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let blob = store.encrypt_blob(b"database-password")?;
+    /// std::fs::write("config.bin", &blob)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn encrypt_blob(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let binary_data = self.read_binary()?;
+        let nonce: [u8; NONCE_SIZE] = rand::random();
+        let (blob_key, seed) = blob_key(&binary_data, &nonce)?;
+
+        let encrypted = encrypt_shard(data, &blob_key, seed);
+
+        let mut blob = Vec::with_capacity(HEADER_SIZE + encrypted.len());
+        blob.extend_from_slice(MAGIC);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&tag(&blob_key, &encrypted));
+        blob.extend(encrypted);
+        Ok(blob)
+    }
+
+    /// 使用当前二进制解密 [`encrypt_blob`](Self::encrypt_blob) 生成的密文
+    ///
+    /// # 参数
+    ///
+    /// * `data` - 密文
+    ///
+    /// # 返回
+    ///
+    /// 成功返回原始数据。密文格式无效时返回 `Error::Parse`，
+    /// 由其他二进制加密或被篡改时返回 `Error::Crypto`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let password = store.decrypt_blob(&std::fs::read("config.bin")?)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decrypt_blob(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < HEADER_SIZE || &data[..MAGIC.len()] != MAGIC {
+            return Err(Error::Parse("无效的加密数据块".to_string()));
+        }
+
+        let nonce = &data[MAGIC.len()..MAGIC.len() + NONCE_SIZE];
+        let expected_tag = &data[MAGIC.len() + NONCE_SIZE..HEADER_SIZE];
+        let encrypted = &data[HEADER_SIZE..];
+
+        let binary_data = self.read_binary()?;
+        let (blob_key, seed) = blob_key(&binary_data, nonce)?;
+
+        if !constant_time_eq(&tag(&blob_key, encrypted), expected_tag) {
+            return Err(Error::Crypto(
+                "数据块校验失败：不是由当前二进制加密或已被篡改".to_string(),
+            ));
+        }
+
+        Ok(decrypt_shard(encrypted, &blob_key, seed))
+    }
+}
+
+/// 由 .text 段派生密钥和nonce计算数据块密钥及混淆种子
+fn blob_key(binary_data: &[u8], nonce: &[u8]) -> Result<([u8; 32], u8)> {
+    let derive_key = derive_key_from_section(&parse_binary(binary_data)?, DERIVE_SECTION, 32)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&derive_key);
+    hasher.update(nonce);
+    let blob_key: [u8; 32] = hasher.finalize().into();
+
+    Ok((blob_key, nonce[0]))
+}
+
+/// 计算密文的校验标签
+fn tag(blob_key: &[u8], encrypted: &[u8]) -> [u8; TAG_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"tag");
+    hasher.update(blob_key);
+    hasher.update(encrypted);
+    let digest = hasher.finalize();
+
+    let mut tag = [0u8; TAG_SIZE];
+    tag.copy_from_slice(&digest[..TAG_SIZE]);
+    tag
+}
@@ -0,0 +1,144 @@
+//! 按文件变化失效的读取缓存
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// 重新读取并解密密钥的函数
+type Loader<'a> = Box<dyn Fn() -> Result<Vec<u8>> + 'a>;
+
+/// 判断二进制文件是否变化的标识
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    /// (设备号, inode)，rename替换后随之改变
+    identity: Option<(u64, u64)>,
+    /// 修改时间
+    modified: SystemTime,
+    /// 文件长度
+    len: u64,
+}
+
+impl FileStamp {
+    /// 查询文件当前的标识
+    fn of(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path).map_err(Error::io_at(path))?;
+        Ok(Self {
+            identity: KeyStore::metadata_identity(&metadata),
+            modified: metadata.modified().map_err(Error::io_at(path))?,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// [`KeyStore::cached_reader`] 创建的读取缓存
+///
+/// 缓存最近一次解密出的密钥和当时二进制文件的设备号、inode、修改时间和长度。每次
+/// [`read_bytes`](Self::read_bytes) 只查询一次文件元数据，这些都未变时直接返回缓存，
+/// 任一变化后才重新读取和解密。原子写入通过rename替换文件，即使两次写入落在文件系统
+/// 修改时间的精度之内，inode也会不同。适合频繁读取、很少更新密钥的服务。
+///
+/// 缓存失效或实例被丢弃时清零旧的密钥。文件元数据只反映写入文件的变化：
+/// 延迟写入模式下尚未 `flush` 的内容、旁路文件的变化都不会使缓存失效
+pub struct CachedReader<'a> {
+    path: PathBuf,
+    load: Loader<'a>,
+    /// 缓存时的文件标识和解密出的密钥
+    cache: Option<(FileStamp, Vec<u8>)>,
+}
+
+impl fmt::Debug for CachedReader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedReader")
+            .field("path", &self.path)
+            .field("cached", &self.cache.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for CachedReader<'_> {
+    fn drop(&mut self) {
+        self.invalidate();
+    }
+}
+
+impl<'a> CachedReader<'a> {
+    fn new(store: &KeyStore, load: Loader<'a>) -> Self {
+        Self {
+            path: store.exe_path().to_path_buf(),
+            load,
+            cache: None,
+        }
+    }
+
+    /// 读取密钥，二进制文件未变化时返回缓存
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes，失败返回Error（失败时不更新缓存）
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let stamp = FileStamp::of(&self.path)?;
+        if let Some((cached_at, key)) = &self.cache {
+            if *cached_at == stamp {
+                return Ok(key.clone());
+            }
+        }
+
+        self.invalidate();
+        let key = (self.load)()?;
+        self.cache = Some((stamp, key.clone()));
+        Ok(key)
+    }
+
+    /// 读取密钥（字符串版本）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥字符串，密钥不是有效的UTF-8时返回Error
+    pub fn read(&mut self) -> Result<String> {
+        KeyStore::bytes_to_string(self.read_bytes()?)
+    }
+
+    /// 丢弃并清零缓存的密钥，下次读取时重新解密
+    pub fn invalidate(&mut self) {
+        if let Some((_, mut key)) = self.cache.take() {
+            zeroize(&mut key);
+        }
+    }
+}
+
+impl KeyStore {
+    /// 创建按二进制文件变化失效的读取缓存
+    ///
+    /// 缓存借用当前实例，只在二进制文件被修改或替换时重新调用
+    /// [`read_bytes`](Self::read_bytes)，参见 [`CachedReader`]
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let mut reader = store.cached_reader();
+    /// let first = reader.read_bytes()?;
+    /// // 二进制未被修改，不会再次解密
+    /// assert_eq!(reader.read_bytes()?, first);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn cached_reader(&self) -> CachedReader<'_> {
+        CachedReader::new(self, Box::new(move || self.read_bytes()))
+    }
+
+    /// 使用指定的读取函数创建读取缓存（测试用）
+    ///
+    /// 缓存失效判断与 [`cached_reader`](Self::cached_reader) 相同，只是重新读取时调用 `load`
+    #[cfg(feature = "test-utils")]
+    pub fn cached_reader_with<'a, F>(&self, load: F) -> CachedReader<'a>
+    where
+        F: Fn() -> Result<Vec<u8>> + 'a,
+    {
+        CachedReader::new(self, Box::new(load))
+    }
+}
@@ -0,0 +1,94 @@
+//! 比较两个二进制的密钥存储
+
+use crate::crypto::{constant_time_eq, derive_key_from_section, parse_binary, zeroize};
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+use crate::readonly::ReadOnlyKeyStore;
+use std::fs;
+use std::path::Path;
+
+/// [`binaries_match`] 的比较结果
+///
+/// 只记录各项是否一致，不包含密钥内容或指纹，可以直接打印到CI日志中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchReport {
+    /// 两个二进制的 .text 段哈希是否相同
+    pub text_match: bool,
+    /// 存储的密钥是否相同（恒定时间比较）
+    pub key_match: bool,
+    /// 分片布局（分片名称、大小、逻辑顺序和排列方式）是否相同
+    pub layout_match: bool,
+}
+
+impl MatchReport {
+    /// 各项是否全部一致
+    pub fn is_match(&self) -> bool {
+        self.text_match && self.key_match && self.layout_match
+    }
+}
+
+/// 比较两个二进制是否以相同的方式写入了相同的密钥
+///
+/// 用于在CI中确认批量配置的二进制一致：分别比较 .text 段哈希、存储的密钥和分片布局。
+/// 以只读方式打开两个二进制，解密出的密钥在比较后清零，结果中不包含任何密钥内容
+///
+/// # 参数
+///
+/// * `a` - 第一个二进制文件路径
+/// * `b` - 第二个二进制文件路径
+///
+/// # 返回
+///
+/// 成功返回比较结果；任一二进制无法解析或读取密钥失败时返回Error
+///
+/// # 示例
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// let report = self_crypto_key::binaries_match(Path::new("dist/a"), Path::new("dist/b"))?;
+/// if !report.is_match() {
+///     eprintln!("二进制不一致: {:?}", report);
+/// }
+/// # Ok::<(), self_crypto_key::Error>(())
+/// ```
+pub fn binaries_match(a: &Path, b: &Path) -> Result<MatchReport> {
+    let data_a = fs::read(a).map_err(Error::io_at(a))?;
+    let data_b = fs::read(b).map_err(Error::io_at(b))?;
+
+    let text_match = text_hash(&data_a)? == text_hash(&data_b)?;
+
+    let layout_a = KeyStore::read_metadata(&data_a, KeyStore::METADATA_SECTION).ok();
+    let layout_b = KeyStore::read_metadata(&data_b, KeyStore::METADATA_SECTION).ok();
+    let layout_match = match (&layout_a, &layout_b) {
+        (Some(a), Some(b)) => same_layout(a, b),
+        (None, None) => true,
+        _ => false,
+    };
+
+    let mut key_a = ReadOnlyKeyStore::open(a.to_path_buf())?.read_bytes()?;
+    let mut key_b = ReadOnlyKeyStore::open(b.to_path_buf())?.read_bytes()?;
+    let key_match = constant_time_eq(&key_a, &key_b);
+    zeroize(&mut key_a);
+    zeroize(&mut key_b);
+
+    Ok(MatchReport {
+        text_match,
+        key_match,
+        layout_match,
+    })
+}
+
+/// 计算二进制 .text 段的哈希
+fn text_hash(binary_data: &[u8]) -> Result<Vec<u8>> {
+    derive_key_from_section(&parse_binary(binary_data)?, KeyStore::DERIVE_SECTION, 32)
+}
+
+/// 两份元数据的分片布局是否相同
+fn same_layout(a: &KeyMetadata, b: &KeyMetadata) -> bool {
+    a.shard_names == b.shard_names
+        && a.shard_sizes == b.shard_sizes
+        && a.logical_index == b.logical_index
+        && a.layout == b.layout
+}
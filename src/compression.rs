@@ -0,0 +1,180 @@
+//! 密钥数据的压缩和解压（`compression` feature）
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+/// DEFLATE的回溯窗口大小，字典中只有最后这么多字节能被引用
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// 使用DEFLATE压缩数据
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    encoder
+        .finish()
+        .map_err(|e| Error::Crypto(format!("压缩失败: {}", e)))
+}
+
+/// 解压DEFLATE数据
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    DeflateDecoder::new(data)
+        .read_to_end(&mut decoded)
+        .map_err(|e| Error::Parse(format!("解压失败: {}", e)))?;
+    Ok(decoded)
+}
+
+/// 字典的标识：SHA-256的前8个字节（十六进制），记录在元数据中用于确认读写使用同一个字典
+pub fn dictionary_id(dictionary: &[u8]) -> String {
+    Sha256::digest(window(dictionary))[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 字典中能被引用的部分
+fn window(dictionary: &[u8]) -> &[u8] {
+    &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..]
+}
+
+/// 使用预置字典压缩数据
+///
+/// 先压缩字典并同步刷新到字节边界，再继续压缩数据，只返回数据部分的输出。
+/// 数据中与字典相同的片段会被编码为对字典的回溯引用
+pub fn compress_with_dictionary(data: &[u8], dictionary: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(window(dictionary))?;
+    encoder.flush()?;
+    let prefix_len = encoder.get_ref().len();
+    encoder.write_all(data)?;
+    let mut output = encoder
+        .finish()
+        .map_err(|e| Error::Crypto(format!("压缩失败: {}", e)))?;
+    let compressed = output[prefix_len..].to_vec();
+    zeroize(&mut output);
+    Ok(compressed)
+}
+
+/// 解压 [`compress_with_dictionary`] 压缩的数据
+///
+/// 字典以不压缩的存储块接在数据前面，解压后去掉字典部分。
+/// 存储块与压缩时的字典输出解压结果相同，因此不依赖压缩库的具体实现
+pub fn decompress_with_dictionary(data: &[u8], dictionary: &[u8]) -> Result<Vec<u8>> {
+    let dictionary = window(dictionary);
+    let len = dictionary.len() as u16;
+    // 非最后一个的存储块：块头、长度及其反码，随后是原始字节
+    let mut stream = vec![0u8];
+    stream.extend_from_slice(&len.to_le_bytes());
+    stream.extend_from_slice(&(!len).to_le_bytes());
+    stream.extend_from_slice(dictionary);
+    stream.extend_from_slice(data);
+
+    let decoded = decompress(&stream);
+    zeroize(&mut stream);
+    let mut decoded = decoded?;
+    if !decoded.starts_with(dictionary) {
+        zeroize(&mut decoded);
+        return Err(Error::Parse("解压失败: 字典不匹配".to_string()));
+    }
+    let data = decoded[dictionary.len()..].to_vec();
+    zeroize(&mut decoded);
+    Ok(data)
+}
+
+/// Zstandard字典的标识：`zstd:` 加上整个字典SHA-256的前8个字节（十六进制）
+#[cfg(feature = "zstd")]
+pub fn zstd_dictionary_id(dictionary: &[u8]) -> String {
+    let digest: String = Sha256::digest(dictionary)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!(
+        "{}{}",
+        crate::metadata::CompressionCodec::ZSTD_PREFIX,
+        digest
+    )
+}
+
+/// 使用Zstandard和预置字典压缩数据
+///
+/// 压缩结果的帧头中记录原始长度，解压时据此分配输出缓冲区
+#[cfg(feature = "zstd")]
+pub fn zstd_compress(data: &[u8], dictionary: &[u8]) -> Result<Vec<u8>> {
+    zstd::bulk::Compressor::with_dictionary(zstd::DEFAULT_COMPRESSION_LEVEL, dictionary)
+        .and_then(|mut compressor| compressor.compress(data))
+        .map_err(|e| Error::Crypto(format!("压缩失败: {}", e)))
+}
+
+/// 解压 [`zstd_compress`] 压缩的数据
+#[cfg(feature = "zstd")]
+pub fn zstd_decompress(data: &[u8], dictionary: &[u8]) -> Result<Vec<u8>> {
+    let capacity = match zstd::zstd_safe::get_frame_content_size(data) {
+        Ok(Some(size)) => usize::try_from(size)
+            .map_err(|_| Error::Parse("解压失败: 原始长度超出范围".to_string()))?,
+        _ => return Err(Error::Parse("解压失败: 帧头中没有原始长度".to_string())),
+    };
+    zstd::bulk::Decompressor::with_dictionary(dictionary)
+        .and_then(|mut decompressor| decompressor.decompress(data, capacity))
+        .map_err(|e| Error::Parse(format!("解压失败: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data = b"abcabcabcabcabcabcabcabcabcabcabcabc".repeat(20);
+        let compressed = compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_dictionary_round_trip() {
+        let dictionary = br#"{"user":"svc-account","scope":["read","write","admin"]}"#.repeat(4);
+        let data = br#"{"user":"svc-account","scope":["read","write"]}"#;
+        let compressed = compress_with_dictionary(data, &dictionary).unwrap();
+        assert!(compressed.len() < compress(data).unwrap().len());
+        assert_eq!(
+            decompress_with_dictionary(&compressed, &dictionary).unwrap(),
+            data
+        );
+
+        // 字典超过窗口大小时只使用最后一段
+        let large: Vec<u8> = (0..WINDOW_SIZE * 2).map(|i| (i % 251) as u8).collect();
+        let compressed = compress_with_dictionary(data, &large).unwrap();
+        assert_eq!(
+            decompress_with_dictionary(&compressed, &large).unwrap(),
+            data
+        );
+        assert_eq!(dictionary_id(&large), dictionary_id(&large[WINDOW_SIZE..]));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_dictionary_round_trip() {
+        let dictionary = br#"{"user":"svc-account","scope":["read","write","admin"]}"#.repeat(4);
+        let data = br#"{"user":"svc-account","scope":["read","write"]}"#;
+        let compressed = zstd_compress(data, &dictionary).unwrap();
+        assert_eq!(zstd_decompress(&compressed, &dictionary).unwrap(), data);
+
+        // 字典不同时不能还原出原始数据
+        let other = br#"{"host":"db.internal","port":5432}"#.repeat(4);
+        assert_ne!(
+            zstd_decompress(&compressed, &other).ok().as_deref(),
+            Some(&data[..])
+        );
+        assert!(zstd_decompress(&[0xff; 16], &dictionary).is_err());
+    }
+
+    #[test]
+    fn test_decompress_garbage() {
+        assert!(decompress(&[0xff; 16]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 //! 加密和混淆相关函数
 
 use crate::error::{Error, Result};
+use hmac::{Hmac, Mac};
 use object::{Object, ObjectSection};
 use sha2::{Digest, Sha256};
 
@@ -32,7 +33,8 @@ pub fn xor_cipher(data: &[u8], key: &[u8]) -> Vec<u8> {
 /// 1. 位旋转
 /// 2. S-box 置换
 /// 3. 编译时随机化的算术运算
-/// 4. 多轮迭代
+/// 4. 与位置相关的异或掩码
+/// 5. 多轮迭代
 ///
 /// # 参数
 ///
@@ -43,42 +45,40 @@ pub fn xor_cipher(data: &[u8], key: &[u8]) -> Vec<u8> {
 ///
 /// 混淆后的数据
 pub fn obfuscate(data: &[u8], seed: u8) -> Vec<u8> {
-    let mut result: Vec<u8> = data
-        .iter()
-        .enumerate()
-        .map(|(i, &b)| {
-            let mut byte = b;
+    let extra = extra_rounds_offset(seed);
+    let mut result = data.to_vec();
+
+    // 按256字节分块处理：块内位置的低8位就是块内下标，高位在块内不变，
+    // 内层循环没有跨字节依赖，也不需要每字节做取模和移位
+    for (block, chunk) in result.chunks_mut(POSITION_BLOCK).enumerate() {
+        let high = block as u8;
+        for (j, byte) in chunk.iter_mut().enumerate() {
+            let j = j as u8;
+            let mut b = *byte;
 
             // 第1层：位旋转（使用编译时常量）
-            byte = byte.rotate_left(ROTATION_BITS);
+            b = b.rotate_left(ROTATION_BITS);
 
             // 第2层：S-box 置换（使用编译时生成的置换表）
-            byte = OBFUSCATE_TABLE[byte as usize];
+            b = OBFUSCATE_TABLE[b as usize];
 
             // 第3层：编译时随机化的算术混淆
-            byte = byte
+            b = b
                 .wrapping_mul(OBFUSCATE_MULTIPLIER)
                 .wrapping_add(OBFUSCATE_BASE)
                 .wrapping_add(seed)
-                .wrapping_add(i as u8);
+                .wrapping_add(j);
 
-            // 第4层：异或掩码
-            byte ^= XOR_MASK;
+            // 第4层：与位置相关的异或掩码
+            b ^= POSITION_MASKS[(j & 7) as usize] ^ high;
 
-            byte
-        })
-        .collect();
+            // 第5层：额外混淆轮次（编译时随机确定），各轮的加法合并为一次
+            b = b
+                .wrapping_add(extra)
+                .wrapping_add((EXTRA_ROUNDS as u8).wrapping_mul(j));
 
-    // 额外混淆轮次（编译时随机确定）
-    for round in 0..EXTRA_ROUNDS {
-        result = result
-            .iter()
-            .enumerate()
-            .map(|(i, &b)| {
-                b.wrapping_add((round as u8).wrapping_mul(seed))
-                    .wrapping_add(i as u8)
-            })
-            .collect();
+            *byte = b;
+        }
     }
 
     result
@@ -98,49 +98,74 @@ pub fn obfuscate(data: &[u8], seed: u8) -> Vec<u8> {
 ///
 /// 恢复后的原始数据
 pub fn deobfuscate(data: &[u8], seed: u8) -> Vec<u8> {
+    let extra = extra_rounds_offset(seed);
+    let inv_multiplier = mod_inverse(OBFUSCATE_MULTIPLIER);
     let mut result = data.to_vec();
 
-    // 撤销额外混淆轮次（逆序）
-    for round in (0..EXTRA_ROUNDS).rev() {
-        result = result
-            .iter()
-            .enumerate()
-            .map(|(i, &b)| {
-                b.wrapping_sub(i as u8)
-                    .wrapping_sub((round as u8).wrapping_mul(seed))
-            })
-            .collect();
-    }
+    for (block, chunk) in result.chunks_mut(POSITION_BLOCK).enumerate() {
+        let high = block as u8;
+        for (j, byte) in chunk.iter_mut().enumerate() {
+            let j = j as u8;
+            let mut b = *byte;
 
-    // 撤销主混淆层（逆序）
-    result
-        .iter()
-        .enumerate()
-        .map(|(i, &b)| {
-            let mut byte = b;
+            // 撤销第5层：额外混淆轮次
+            b = b
+                .wrapping_sub((EXTRA_ROUNDS as u8).wrapping_mul(j))
+                .wrapping_sub(extra);
 
-            // 撤销第4层：异或掩码
-            byte ^= XOR_MASK;
+            // 撤销第4层：与位置相关的异或掩码
+            b ^= POSITION_MASKS[(j & 7) as usize] ^ high;
 
-            // 撤销第3层：算术混淆
-            byte = byte
-                .wrapping_sub(i as u8)
+            // 撤销第3层：算术混淆（乘以模256的乘法逆元）
+            b = b
+                .wrapping_sub(j)
                 .wrapping_sub(seed)
-                .wrapping_sub(OBFUSCATE_BASE);
-
-            // 计算乘法逆元（对于模256）
-            let inv_multiplier = mod_inverse(OBFUSCATE_MULTIPLIER);
-            byte = byte.wrapping_mul(inv_multiplier);
+                .wrapping_sub(OBFUSCATE_BASE)
+                .wrapping_mul(inv_multiplier);
 
             // 撤销第2层：S-box 置换
-            byte = DEOBFUSCATE_TABLE[byte as usize];
+            b = DEOBFUSCATE_TABLE[b as usize];
 
             // 撤销第1层：位旋转
-            byte = byte.rotate_right(ROTATION_BITS);
+            *byte = b.rotate_right(ROTATION_BITS);
+        }
+    }
 
-            byte
-        })
-        .collect()
+    result
+}
+
+/// 位置相关参数的分块大小：块内位置的低8位等于块内下标
+const POSITION_BLOCK: usize = 256;
+
+/// 按位置低3位预先计算的异或掩码轮转值
+const POSITION_MASKS: [u8; 8] = {
+    let mut masks = [0u8; 8];
+    let mut i = 0;
+    while i < 8 {
+        masks[i] = XOR_MASK.rotate_left(i as u32);
+        i += 1;
+    }
+    masks
+};
+
+/// 所有额外混淆轮次中与位置无关部分的累加值
+///
+/// 第 `round` 轮给每个字节加上 `round * seed + i`，各轮相加后等价于一次加上
+/// `sum(round * seed) + EXTRA_ROUNDS * i`
+fn extra_rounds_offset(seed: u8) -> u8 {
+    (0..EXTRA_ROUNDS).fold(0u8, |acc, round| {
+        acc.wrapping_add((round as u8).wrapping_mul(seed))
+    })
+}
+
+/// 计算指定位置的异或掩码
+///
+/// 掩码随字节位置轮转，并混入位置的高位，避免所有字节共用同一个
+/// `XOR_MASK`（否则一组明文/密文即可直接解出掩码）。
+/// `obfuscate` 中按块使用预先计算的 `POSITION_MASKS`，结果与此函数相同
+#[cfg(test)]
+fn position_mask(i: usize) -> u8 {
+    XOR_MASK.rotate_left((i % 8) as u32) ^ ((i >> 8) as u8)
 }
 
 /// 计算模256的乘法逆元
@@ -170,11 +195,19 @@ fn mod_inverse(a: u8) -> u8 {
     t as u8
 }
 
+/// 解析二进制数据，供派生密钥等需要section内容的操作使用
+pub(crate) fn parse_binary(binary_data: &[u8]) -> Result<object::File<'_>> {
+    object::File::parse(binary_data).map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))
+}
+
 /// 从指定section计算SHA256哈希，用于派生加密密钥
 ///
+/// 直接对已解析二进制中借用的section数据计算哈希，不复制section内容；二进制数据
+/// 来自 `fs::read` 读入的 `Vec` 还是内存映射都没有区别
+///
 /// # 参数
 ///
-/// * `binary_data` - 完整的二进制文件数据
+/// * `obj_file` - 已解析的二进制
 /// * `section_name` - 要计算哈希的section名称
 /// * `key_len` - 需要的密钥长度
 ///
@@ -182,30 +215,159 @@ fn mod_inverse(a: u8) -> u8 {
 ///
 /// 派生的密钥（取哈希值的前key_len字节）
 pub fn derive_key_from_section(
-    binary_data: &[u8],
+    obj_file: &object::File,
     section_name: &str,
     key_len: usize,
 ) -> Result<Vec<u8>> {
-    let obj_file = object::File::parse(binary_data)
-        .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
-
-    for section in obj_file.sections() {
-        if let Ok(name) = section.name() {
-            if name == section_name {
-                if let Ok(data) = section.data() {
-                    // 计算section数据的SHA256哈希
-                    let mut hasher = Sha256::new();
-                    hasher.update(data);
-                    let hash = hasher.finalize();
-
-                    // 返回前key_len字节（最多32字节）
-                    return Ok(hash[..key_len.min(32)].to_vec());
-                }
-            }
-        }
+    let section = obj_file
+        .section_by_name(section_name)
+        .ok_or_else(|| Error::SectionNotFound(section_name.to_string()))?;
+    let data = section
+        .data()
+        .map_err(|e| Error::Parse(format!("无法读取section {}: {}", section_name, e)))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let hash: [u8; 32] = hasher.finalize().into();
+    Ok(hash[..key_len.min(32)].to_vec())
+}
+
+/// 按顺序对多个section计算SHA256哈希，用于派生加密密钥
+///
+/// 只有一个section时结果与 [`derive_key_from_section`] 相同
+///
+/// # 参数
+///
+/// * `binary_data` - 完整的二进制文件数据
+/// * `section_names` - 要计算哈希的section名称，按顺序拼接
+/// * `key_len` - 需要的密钥长度
+///
+/// # 返回
+///
+/// 派生的密钥（取哈希值的前key_len字节）
+#[cfg(test)]
+pub fn derive_key_from_sections(
+    binary_data: &[u8],
+    section_names: &[&str],
+    key_len: usize,
+) -> Result<Vec<u8>> {
+    crate::sections::SectionMap::parse(binary_data)?.derive_key(section_names, key_len)
+}
+
+/// 计算HMAC-SHA256（RFC 2104）
+///
+/// # 参数
+///
+/// * `key` - HMAC密钥
+/// * `data` - 要认证的数据
+///
+/// # 返回
+///
+/// 32字节的认证码
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = new_hmac(key);
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// 恒定时间校验HMAC-SHA256认证码
+///
+/// `expected` 可以是完整的32字节认证码，也可以是截取的前若干字节（例如元数据中的16字节校验值）
+///
+/// # 参数
+///
+/// * `key` - HMAC密钥
+/// * `data` - 被认证的数据
+/// * `expected` - 期望的认证码
+///
+/// # 返回
+///
+/// 认证码一致返回true；`expected` 为空或超过32字节时返回false
+pub fn hmac_sha256_verify(key: &[u8], data: &[u8], expected: &[u8]) -> bool {
+    let mut mac = new_hmac(key);
+    mac.update(data);
+    if expected.len() == 32 {
+        mac.verify_slice(expected).is_ok()
+    } else {
+        mac.verify_truncated_left(expected).is_ok()
+    }
+}
+
+fn new_hmac(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC接受任意长度的密钥")
+}
+
+/// 使用PBKDF2-HMAC-SHA256（RFC 8018）拉伸密钥，输出32字节
+///
+/// # 参数
+///
+/// * `password` - 要拉伸的密钥
+/// * `salt` - 盐
+/// * `iterations` - 迭代次数，至少为1
+///
+/// # 返回
+///
+/// 32字节的派生密钥（PBKDF2输出的第一个块）
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut output = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut output);
+    output
+}
+
+/// 恒定时间比较两段数据是否相等
+///
+/// 比较耗时只与数据长度有关，与第一个不同字节的位置无关。
+/// 长度不同时直接返回false（长度本身不视为秘密）
+#[cfg(not(feature = "secrecy"))]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a.iter().zip(b).fold(0u8, |acc, (&x, &y)| acc | (x ^ y));
+    std::hint::black_box(diff) == 0
+}
+
+/// 恒定时间比较两段数据是否相等（`secrecy` feature下使用 `subtle` 实现）
+///
+/// 长度不同时直接返回false（长度本身不视为秘密）
+#[cfg(feature = "secrecy")]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    use subtle::ConstantTimeEq;
+
+    a.len() == b.len() && bool::from(a.ct_eq(b))
+}
+
+/// 将缓冲区清零
+///
+/// 使用volatile写入，避免编译器因为数据随后不再使用而优化掉清零操作
+pub fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        // SAFETY: byte 是来自可变切片的有效、对齐的引用
+        unsafe { std::ptr::write_volatile(byte, 0) };
     }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
 
-    Err(Error::SectionNotFound(section_name.to_string()))
+/// 编译时生成的加密常量的指纹
+///
+/// 对全部常量计算SHA256，返回前8字节的十六进制。常量在每次编译时随机生成，
+/// 指纹可以区分两个二进制是否使用同一组常量，而不暴露常量本身
+pub(crate) fn constants_fingerprint() -> String {
+    let mut hasher = Sha256::new();
+    hasher.update([
+        OBFUSCATE_BASE,
+        OBFUSCATE_MULTIPLIER,
+        XOR_MASK,
+        EXTRA_ROUNDS as u8,
+    ]);
+    hasher.update(ROTATION_BITS.to_le_bytes());
+    hasher.update(OBFUSCATE_TABLE);
+    hasher.update(DEOBFUSCATE_TABLE);
+    hasher.update(SHARD_SEED_OFFSETS);
+
+    let hash: [u8; 32] = hasher.finalize().into();
+    hash[..8].iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 /// 加密数据片段
@@ -278,6 +440,90 @@ mod tests {
         assert_eq!(data, deobfuscated.as_slice());
     }
 
+    #[test]
+    fn test_obfuscate_4kb_round_trip() {
+        let data: Vec<u8> = (0..4096).map(|i| (i * 7 + 3) as u8).collect();
+        let seed = 77;
+
+        let obfuscated = obfuscate(&data, seed);
+        let deobfuscated = deobfuscate(&obfuscated, seed);
+        assert_eq!(data, deobfuscated);
+    }
+
+    /// 逐字节、逐轮计算的参考实现，用于验证分块实现的结果完全一致
+    fn reference_obfuscate(data: &[u8], seed: u8) -> Vec<u8> {
+        let mut result: Vec<u8> = data
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                let byte = OBFUSCATE_TABLE[b.rotate_left(ROTATION_BITS) as usize]
+                    .wrapping_mul(OBFUSCATE_MULTIPLIER)
+                    .wrapping_add(OBFUSCATE_BASE)
+                    .wrapping_add(seed)
+                    .wrapping_add(i as u8);
+                byte ^ position_mask(i)
+            })
+            .collect();
+
+        for round in 0..EXTRA_ROUNDS {
+            result = result
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| {
+                    b.wrapping_add((round as u8).wrapping_mul(seed))
+                        .wrapping_add(i as u8)
+                })
+                .collect();
+        }
+
+        result
+    }
+
+    #[test]
+    fn test_obfuscate_matches_reference() {
+        let data: Vec<u8> = (0..64 * 1024).map(|i| (i * 31 + 7) as u8).collect();
+        for seed in [0, 1, 77, 255] {
+            let obfuscated = obfuscate(&data, seed);
+            assert_eq!(obfuscated, reference_obfuscate(&data, seed));
+            assert_eq!(deobfuscate(&obfuscated, seed), data);
+        }
+    }
+
+    #[test]
+    #[ignore = "性能测量，使用 cargo test --release -- --ignored 运行"]
+    fn bench_obfuscate_64kb() {
+        let data = vec![0x5au8; 64 * 1024];
+        let iters = 200;
+
+        let start = std::time::Instant::now();
+        for seed in 0..iters {
+            std::hint::black_box(obfuscate(std::hint::black_box(&data), seed as u8));
+        }
+        let elapsed = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for seed in 0..iters {
+            std::hint::black_box(reference_obfuscate(std::hint::black_box(&data), seed as u8));
+        }
+        let reference = start.elapsed();
+
+        println!(
+            "obfuscate 64KB: {:?}/次, 参考实现: {:?}/次",
+            elapsed / iters,
+            reference / iters
+        );
+    }
+
+    #[test]
+    fn test_xor_mask_is_position_dependent() {
+        // 相同的输入字节位于不同位置时，混淆结果应不同
+        let data = vec![0x41u8; 4096];
+        let obfuscated = obfuscate(&data, 5);
+
+        assert_ne!(obfuscated[0], obfuscated[256]);
+        assert_ne!(position_mask(0), position_mask(256));
+    }
+
     #[test]
     fn test_encrypt_decrypt_shard() {
         let original = b"my secret key";
@@ -315,6 +561,141 @@ mod tests {
         assert_eq!(data, decrypted.as_slice());
     }
 
+    #[test]
+    fn test_derive_key_matches_one_shot_hash() {
+        // 使用测试二进制自身的.text段比较派生结果与直接计算的哈希
+        let binary_data = std::fs::read("/proc/self/exe").unwrap();
+        let obj_file = object::File::parse(binary_data.as_slice()).unwrap();
+        let text = obj_file.section_by_name(".text").unwrap();
+        let one_shot = Sha256::digest(text.data().unwrap());
+
+        let derived = derive_key_from_section(&obj_file, ".text", 32).unwrap();
+        assert_eq!(derived, one_shot.as_slice());
+    }
+
+    #[test]
+    fn test_derive_key_same_for_owned_and_mapped_binary() {
+        use std::os::unix::io::AsRawFd;
+
+        let owned = std::fs::read("/proc/self/exe").unwrap();
+        let from_owned =
+            derive_key_from_section(&parse_binary(&owned).unwrap(), ".text", 32).unwrap();
+
+        let file = std::fs::File::open("/proc/self/exe").unwrap();
+        let len = file.metadata().unwrap().len() as usize;
+        // SAFETY: 以只读、私有方式映射整个文件，映射在下面解除之前一直有效
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        assert_ne!(ptr, libc::MAP_FAILED);
+        // SAFETY: ptr指向长度为len的有效只读映射
+        let mapped = unsafe { std::slice::from_raw_parts(ptr as *const u8, len) };
+        let from_mapped =
+            derive_key_from_section(&parse_binary(mapped).unwrap(), ".text", 32).unwrap();
+        // SAFETY: 解除上面创建的映射，之后不再使用mapped
+        unsafe { libc::munmap(ptr, len) };
+
+        assert_eq!(from_owned, from_mapped);
+    }
+
+    #[test]
+    fn test_derive_from_multiple_sections() {
+        let binary_data = std::fs::read("/proc/self/exe").unwrap();
+        let text_only = derive_key_from_sections(&binary_data, &[".text"], 32).unwrap();
+        assert_eq!(
+            text_only,
+            derive_key_from_section(&parse_binary(&binary_data).unwrap(), ".text", 32).unwrap()
+        );
+
+        let combined = derive_key_from_sections(&binary_data, &[".text", ".rodata"], 32).unwrap();
+        assert_ne!(combined, text_only);
+
+        assert!(matches!(
+            derive_key_from_sections(&binary_data, &[".text", ".missing"], 32),
+            Err(Error::SectionNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_hmac_sha256_rfc4231() {
+        // RFC 4231 测试用例 1 和 2
+        let mac = hmac_sha256(&[0x0b; 20], b"Hi There");
+        assert_eq!(mac[..8], [0xb0, 0x34, 0x4c, 0x61, 0xd8, 0xdb, 0x38, 0x53]);
+
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(mac[..8], [0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e]);
+
+        // 测试用例 6：长度超过块大小的密钥
+        let mac = hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        assert_eq!(mac[..8], [0x60, 0xe4, 0x31, 0x59, 0x1e, 0xe0, 0xb6, 0x7f]);
+    }
+
+    #[test]
+    fn test_hmac_sha256_verify() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert!(hmac_sha256_verify(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &mac
+        ));
+        assert!(hmac_sha256_verify(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &mac[..16]
+        ));
+        assert!(!hmac_sha256_verify(
+            b"Jefe",
+            b"what do ya want for nothing!",
+            &mac[..16]
+        ));
+        assert!(!hmac_sha256_verify(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_pbkdf2_hmac_sha256() {
+        // RFC 7914 第11节中的PBKDF2-HMAC-SHA256测试向量
+        let derived = pbkdf2_hmac_sha256(b"passwd", b"salt", 1);
+        assert_eq!(
+            derived[..8],
+            [0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f]
+        );
+
+        // 与逐轮计算HMAC的结果一致
+        let u1 = hmac_sha256(b"passwd", b"salt\0\0\0\x01");
+        let u2 = hmac_sha256(b"passwd", &u1);
+        let expected: Vec<u8> = u1.iter().zip(&u2).map(|(a, b)| a ^ b).collect();
+        assert_eq!(pbkdf2_hmac_sha256(b"passwd", b"salt", 2).to_vec(), expected);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut buf = b"sensitive".to_vec();
+        zeroize(&mut buf);
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+
     #[test]
     fn test_large_data() {
         let data = vec![42u8; 10000];
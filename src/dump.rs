@@ -0,0 +1,161 @@
+//! 密钥相关section的十六进制转储和诊断报告
+
+use crate::crypto::constants_fingerprint;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::note::StorageFormat;
+use std::fmt::Write;
+
+/// 每行转储的字节数
+const BYTES_PER_LINE: usize = 16;
+
+impl KeyStore {
+    /// 生成 `.key_meta` 和各活动分片section的十六进制转储（诊断用）
+    ///
+    /// 输出格式与 `xxd` 类似：每个section以 `== 名称 (N bytes) ==` 开头，随后每行为
+    /// section内偏移、16个字节的十六进制和对应的可打印ASCII字符。只转储原始字节，
+    /// 不做任何解密，可以安全地附在问题报告中（分片内容仍是密文）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回转储文本，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// println!("{}", store.hex_dump()?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn hex_dump(&self) -> Result<String> {
+        let binary_data = self.read_binary()?;
+        let metadata = self.metadata();
+
+        let mut sections = vec![(
+            self.meta_section().to_string(),
+            Self::find_section(&binary_data, self.meta_section())?,
+        )];
+        for (name, &size) in metadata.shard_names.iter().zip(&metadata.shard_sizes) {
+            sections.push((
+                name.clone(),
+                Self::find_shard_section(&binary_data, name, size)?,
+            ));
+        }
+
+        let mut out = String::new();
+        for (name, (offset, size)) in sections {
+            dump_section(&mut out, &name, &binary_data[offset..offset + size]);
+        }
+        Ok(out)
+    }
+
+    /// 生成不含任何密钥内容的诊断报告，供问题报告使用
+    ///
+    /// 报告包括二进制路径、存储格式、各密钥相关section的文件偏移和大小、活动分片数量、
+    /// 元数据版本、编译时加密常量的指纹，以及分片是否位于可写段中。不读取分片内容，
+    /// 也不做任何解密
+    ///
+    /// # 返回
+    ///
+    /// 成功返回多行文本报告，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// println!("{}", store.diagnostic_report()?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn diagnostic_report(&self) -> Result<String> {
+        let binary_data = self.read_binary()?;
+        let metadata = self.metadata();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "binary: {}", self.exe_path().display());
+        let _ = match self.storage_format() {
+            StorageFormat::NamedSections => writeln!(out, "format: named sections"),
+            StorageFormat::ElfNote { vendor } => writeln!(out, "format: ELF note ({})", vendor),
+        };
+        let _ = writeln!(out, "metadata version: {}", metadata.version);
+        let _ = writeln!(out, "active shards: {}", metadata.num_shards);
+        let _ = writeln!(out, "constants fingerprint: {}", constants_fingerprint());
+        let _ = match self.shards_are_writable_segment() {
+            Ok(writable) => writeln!(out, "shards in writable segment: {}", writable),
+            Err(e) => writeln!(out, "shards in writable segment: unknown ({})", e),
+        };
+
+        let _ = writeln!(out, "sections:");
+        for name in self.storage_sections() {
+            let (offset, size) = match Self::find_section(&binary_data, &name) {
+                Ok(range) => range,
+                Err(Error::SectionNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let active = name == self.meta_section() || metadata.shard_names.contains(&name);
+            let _ = writeln!(
+                out,
+                "  {:<14} offset=0x{:08x} size={:<6} {}",
+                name,
+                offset,
+                size,
+                if active { "active" } else { "unused" }
+            );
+        }
+
+        Ok(out)
+    }
+}
+
+/// 将一个section的内容以xxd格式追加到输出
+fn dump_section(out: &mut String, name: &str, data: &[u8]) {
+    let _ = writeln!(out, "== {} ({} bytes) ==", name, data.len());
+
+    for (line, chunk) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(out, "{:08x}:", line * BYTES_PER_LINE);
+        for i in 0..BYTES_PER_LINE {
+            if i % 2 == 0 {
+                out.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(out, "{:02x}", byte);
+                }
+                None => out.push_str("  "),
+            }
+        }
+
+        out.push_str("  ");
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_section_format() {
+        let mut out = String::new();
+        dump_section(&mut out, ".key_test", b"0123456789abcdefXY\x00");
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "== .key_test (19 bytes) ==");
+        assert_eq!(
+            lines[1],
+            "00000000: 3031 3233 3435 3637 3839 6162 6364 6566  0123456789abcdef"
+        );
+        assert_eq!(
+            lines[2],
+            "00000010: 5859 00                                  XY."
+        );
+    }
+}
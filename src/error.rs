@@ -1,6 +1,7 @@
 //! 错误类型定义
 
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 /// 库的错误类型
 #[derive(Debug)]
@@ -8,6 +9,12 @@ pub enum Error {
     /// IO错误
     Io(std::io::Error),
 
+    /// 访问指定文件时发生的IO错误
+    IoAt {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     /// 二进制解析错误
     Parse(String),
 
@@ -17,6 +24,9 @@ pub enum Error {
     /// 配置错误
     Config(String),
 
+    /// 完整性校验失败：存储的数据或二进制已被篡改
+    IntegrityCheckFailed(String),
+
     /// Section未找到
     SectionNotFound(String),
 
@@ -28,9 +38,11 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Io(e) => write!(f, "IO错误: {}", e),
+            Error::IoAt { path, source } => write!(f, "IO错误 ({}): {}", path.display(), source),
             Error::Parse(e) => write!(f, "解析错误: {}", e),
             Error::Crypto(e) => write!(f, "加密错误: {}", e),
             Error::Config(e) => write!(f, "配置错误: {}", e),
+            Error::IntegrityCheckFailed(e) => write!(f, "完整性校验失败: {}", e),
             Error::SectionNotFound(name) => write!(f, "未找到section: {}", name),
             Error::SizeMismatch { expected, actual } => {
                 write!(f, "大小不匹配: 期望 {}, 实际 {}", expected, actual)
@@ -39,7 +51,24 @@ impl fmt::Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::IoAt { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// 生成将IO错误附加上文件路径的转换函数，用于 `map_err`
+    pub(crate) fn io_at(path: &Path) -> impl FnOnce(std::io::Error) -> Error + '_ {
+        move |source| Error::IoAt {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
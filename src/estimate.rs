@@ -0,0 +1,90 @@
+//! 破解难度的粗略估计
+
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+
+/// [`KeyStore::crack_difficulty`] 的估计结果
+///
+/// 各项均以比特（以2为底的对数）表示，只是帮助理解运行时随机选择带来的搜索空间，
+/// **不是密码学意义上的安全保证**。分片布局和nonce以明文记录在元数据中，拿到完整
+/// 二进制的攻击者可以直接读取；这些数值衡量的是攻击者无法获得元数据时需要猜测的空间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrackEstimate {
+    /// 分片布局的搜索空间：从8个section中选出分片并排序，即 8!/(8-n)!
+    pub layout_bits: f64,
+    /// 每次写入的随机nonce贡献的搜索空间（每个分片32位，未记录nonce时为0）
+    pub nonce_bits: f64,
+    /// KDF拉伸使每次尝试增加的工作量（迭代次数的对数，未拉伸时为0）
+    pub kdf_bits: f64,
+}
+
+impl CrackEstimate {
+    /// 根据元数据计算估计值
+    pub(crate) fn from_metadata(metadata: &KeyMetadata) -> Self {
+        let total = KeyMetadata::SHARD_NAMES.len();
+        let chosen = metadata.num_shards.min(total);
+        let layout_bits = (total - chosen + 1..=total)
+            .map(|n| (n as f64).log2())
+            .sum();
+
+        Self {
+            layout_bits,
+            nonce_bits: 32.0 * metadata.shard_nonces.len() as f64,
+            kdf_bits: (metadata.kdf_iterations.max(1) as f64).log2(),
+        }
+    }
+
+    /// 各项之和，即估计的总工作量（比特）
+    pub fn total_bits(&self) -> f64 {
+        self.layout_bits + self.nonce_bits + self.kdf_bits
+    }
+}
+
+impl KeyStore {
+    /// 粗略估计运行时随机选择带来的破解难度
+    ///
+    /// 只读取当前的元数据，不访问二进制文件。结果仅供参考，详见 [`CrackEstimate`]
+    ///
+    /// # 返回
+    ///
+    /// 各项搜索空间的估计值（比特）
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?.with_kdf_iterations(100_000);
+    /// let estimate = store.crack_difficulty();
+    /// println!("约 {:.0} 比特", estimate.total_bits());
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn crack_difficulty(&self) -> CrackEstimate {
+        CrackEstimate::from_metadata(self.metadata())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_grows_with_shards_and_iterations() {
+        let mut metadata = KeyMetadata::generate();
+        metadata.num_shards = 4;
+        let four = CrackEstimate::from_metadata(&metadata);
+        // 8*7*6*5 = 1680
+        assert!((four.layout_bits - 1680f64.log2()).abs() < 1e-9);
+        assert_eq!(four.kdf_bits, 0.0);
+
+        metadata.num_shards = 8;
+        let eight = CrackEstimate::from_metadata(&metadata);
+        assert!(eight.layout_bits > four.layout_bits);
+
+        metadata.kdf_iterations = 100_000;
+        let stretched = CrackEstimate::from_metadata(&metadata);
+        assert!(stretched.total_bits() > eight.total_bits());
+
+        metadata.shard_nonces = vec![0; 8];
+        assert_eq!(CrackEstimate::from_metadata(&metadata).nonce_bits, 256.0);
+    }
+}
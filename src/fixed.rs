@@ -0,0 +1,152 @@
+//! 固定布局的密钥存储
+
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 固定布局的密钥存储
+///
+/// 由 [`KeyStore::new_fixed`] 创建，对应 `init_key_storage!(fixed)` 生成的二进制。
+/// 布局在编译时确定：8个分片按序号全部使用，没有 `.key_meta` 和JSON元数据，
+/// 只在8字节的 `.key_len` section中记录密钥长度。
+///
+/// 由于不存储元数据，固定布局不支持压缩、自定义派生sections、KDF拉伸和每次写入的
+/// 随机nonce，相同的密钥总是得到相同的密文
+pub struct FixedKeyStore {
+    /// 二进制文件的路径
+    exe_path: PathBuf,
+    /// 固定的分片布局
+    metadata: KeyMetadata,
+    /// 写入时是否fsync临时文件和所在目录
+    durable: bool,
+}
+
+impl FixedKeyStore {
+    /// 记录密钥长度的section名称
+    const LENGTH_SECTION: &'static str = ".key_len";
+
+    /// 打开指定路径的固定布局二进制
+    pub(crate) fn open(exe_path: PathBuf) -> Result<Self> {
+        let binary_data = fs::read(&exe_path).map_err(Error::io_at(&exe_path))?;
+        Self::length_range(&binary_data)?;
+
+        Ok(Self {
+            exe_path,
+            metadata: KeyMetadata::fixed(),
+            durable: true,
+        })
+    }
+
+    /// 设置写入时是否保证持久化（默认开启），参见 [`KeyStore::set_durable`]
+    pub fn set_durable(&mut self, durable: bool) {
+        self.durable = durable;
+    }
+
+    /// 更新密钥（bytes版本）
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 新的密钥数据，不能超过 [`capacity`](Self::capacity)
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    pub fn update_bytes(&mut self, new_key: &[u8]) -> Result<()> {
+        let mut binary_data = fs::read(&self.exe_path).map_err(Error::io_at(&self.exe_path))?;
+
+        KeyStore::encode_shards(&mut binary_data, &self.metadata, new_key)?;
+
+        let (offset, _) = Self::length_range(&binary_data)?;
+        binary_data[offset..offset + 8].copy_from_slice(&(new_key.len() as u64).to_le_bytes());
+
+        KeyStore::atomic_write(&self.exe_path, &binary_data, self.durable)
+    }
+
+    /// 更新密钥（字符串版本）
+    pub fn update(&mut self, new_key: &str) -> Result<()> {
+        self.update_bytes(new_key.as_bytes())
+    }
+
+    /// 读取当前密钥（bytes版本）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes，未写入过密钥时为空
+    pub fn read_bytes(&self) -> Result<Vec<u8>> {
+        let binary_data = fs::read(&self.exe_path).map_err(Error::io_at(&self.exe_path))?;
+        let len = Self::stored_len_in(&binary_data)?;
+        KeyStore::decode_shards(&binary_data, &self.metadata, len, false)
+    }
+
+    /// 读取当前密钥（字符串版本）
+    pub fn read(&self) -> Result<String> {
+        KeyStore::bytes_to_string(self.read_bytes()?)
+    }
+
+    /// 获取密钥存储的总容量（字节）
+    pub fn capacity(&self) -> usize {
+        self.metadata.total_capacity()
+    }
+
+    /// 获取当前存储的密钥长度（字节）
+    pub fn stored_len(&self) -> Result<usize> {
+        Self::stored_len_in(&fs::read(&self.exe_path).map_err(Error::io_at(&self.exe_path))?)
+    }
+
+    /// 获取所管理的二进制文件路径
+    pub fn exe_path(&self) -> &Path {
+        &self.exe_path
+    }
+
+    /// 读取 `.key_len` 中记录的密钥长度
+    fn stored_len_in(binary_data: &[u8]) -> Result<usize> {
+        let (offset, _) = Self::length_range(binary_data)?;
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(&binary_data[offset..offset + 8]);
+        Ok(u64::from_le_bytes(len_bytes) as usize)
+    }
+
+    /// 查找 `.key_len` section，并确认其足以容纳8字节长度
+    fn length_range(binary_data: &[u8]) -> Result<(usize, usize)> {
+        let (offset, size) = KeyStore::find_section(binary_data, Self::LENGTH_SECTION)?;
+        if size < 8 {
+            return Err(Error::SizeMismatch {
+                expected: 8,
+                actual: size,
+            });
+        }
+        Ok((offset, size))
+    }
+}
+
+impl KeyStore {
+    /// 打开当前可执行文件的固定布局密钥存储
+    ///
+    /// 用于 `init_key_storage!(fixed)` 生成的二进制：不读取、也不需要 `.key_meta`，
+    /// 按序号使用全部8个分片
+    ///
+    /// # 返回
+    ///
+    /// 成功返回FixedKeyStore实例，二进制中没有 `.key_len` section时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new_fixed()?;
+    /// store.update_bytes(&[0x42; 32])?;
+    /// assert_eq!(store.read_bytes()?, [0x42; 32]);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn new_fixed() -> Result<FixedKeyStore> {
+        FixedKeyStore::open(env::current_exe()?)
+    }
+
+    /// 打开指定路径二进制的固定布局密钥存储，参见 [`new_fixed`](Self::new_fixed)
+    pub fn fixed_from_path<P: Into<PathBuf>>(path: P) -> Result<FixedKeyStore> {
+        FixedKeyStore::open(path.into())
+    }
+}
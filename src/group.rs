@@ -0,0 +1,217 @@
+//! 多个二进制文件的批量密钥更新
+
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::sidecar;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 将同一个密钥写入一组二进制文件（全部成功或全部不变）
+///
+/// 先为每个二进制生成写入新密钥后的临时文件（已fsync），只有所有临时文件都写入成功后，
+/// 才依次rename到目标位置。任何一个文件准备失败时，会删除已生成的临时文件，
+/// 所有原始文件保持不变。列表中有指向同一个文件的路径时直接返回 `Error::Config`。
+/// 替换后会删除该二进制残留的旁路文件。
+///
+/// rename之前会为每个原始文件（及其旁路文件）建立备份；某次rename失败时，
+/// 已替换的文件会从备份恢复，所有文件回到调用前的状态。备份只在全部替换成功后删除。
+///
+/// # 参数
+///
+/// * `paths` - 目标二进制文件路径列表
+/// * `key` - 新的密钥数据
+///
+/// # 返回
+///
+/// 成功返回Ok(())，失败返回第一个遇到的Error
+///
+/// # 注意
+///
+/// 回滚本身也依赖rename，若恢复备份时再次失败，备份文件会保留在原目录中（`*.bak.*`）
+///
+/// # 示例
+///
+/// ```no_run
+/// use std::path::PathBuf;
+///
+/// let paths = vec![PathBuf::from("/opt/app/a"), PathBuf::from("/opt/app/b")];
+/// self_crypto_key::update_group(&paths, b"shared-secret")?;
+/// # Ok::<(), self_crypto_key::Error>(())
+/// ```
+pub fn update_group(paths: &[PathBuf], key: &[u8]) -> Result<()> {
+    update_group_inner(paths, key, |_| {})
+}
+
+/// 与 [`update_group`] 相同，但在每次rename之前以目标路径调用 `before_rename`（测试用）
+///
+/// 用于在测试中确定性地模拟rename阶段的失败
+#[cfg(feature = "test-utils")]
+pub fn update_group_with_hook<F>(paths: &[PathBuf], key: &[u8], before_rename: F) -> Result<()>
+where
+    F: FnMut(&Path),
+{
+    update_group_inner(paths, key, before_rename)
+}
+
+fn update_group_inner<F>(paths: &[PathBuf], key: &[u8], mut before_rename: F) -> Result<()>
+where
+    F: FnMut(&Path),
+{
+    // 同一个文件出现两次时，后写入的临时文件会覆盖先rename的结果
+    let mut canonical = Vec::with_capacity(paths.len());
+    for path in paths {
+        let resolved = fs::canonicalize(path).map_err(Error::io_at(path))?;
+        if canonical.contains(&resolved) {
+            return Err(Error::Config(format!("目标文件重复: {}", path.display())));
+        }
+        canonical.push(resolved);
+    }
+
+    // 第一阶段：为每个二进制写入临时文件
+    let mut staged: Vec<(PathBuf, &PathBuf)> = Vec::with_capacity(paths.len());
+    for path in paths {
+        let temp_path = KeyStore::from_path(path.clone())
+            .and_then(|store| store.prepare_update(key))
+            .and_then(|(binary_data, _)| KeyStore::write_temp(path, &binary_data, true));
+
+        match temp_path {
+            Ok(temp_path) => staged.push((temp_path, path)),
+            Err(e) => {
+                discard_temps(&staged);
+                return Err(e);
+            }
+        }
+    }
+
+    // 第二阶段：备份原始文件，失败时恢复需要用到
+    let mut backups: Vec<Backup> = Vec::with_capacity(staged.len());
+    for (_, path) in &staged {
+        match Backup::create(path) {
+            Ok(backup) => backups.push(backup),
+            Err(e) => {
+                discard_backups(&backups);
+                discard_temps(&staged);
+                return Err(e);
+            }
+        }
+    }
+
+    // 第三阶段：依次替换，任何一步失败时恢复已替换的文件
+    for (i, (temp_path, path)) in staged.iter().enumerate() {
+        before_rename(path);
+
+        let replaced = fs::rename(temp_path, path)
+            .map_err(Error::io_at(path))
+            // 二进制中的密钥为最新值，删除可能残留的旁路文件，否则读取时仍会优先使用旧密钥
+            .and_then(|()| sidecar::remove(path));
+
+        if let Err(e) = replaced {
+            // 第i个文件的rename可能已经成功（sidecar删除失败），一并恢复
+            let restored = backups.iter().zip(&staged).take(i + 1);
+            let unrestored: Vec<usize> = restored
+                .enumerate()
+                .filter(|(_, (backup, (_, path)))| !backup.restore(path))
+                .map(|(j, _)| j)
+                .collect();
+            for (j, backup) in backups.iter().enumerate() {
+                if !unrestored.contains(&j) {
+                    backup.discard();
+                }
+            }
+            discard_temps(&staged[i..]);
+            return Err(e);
+        }
+    }
+
+    discard_backups(&backups);
+
+    // 使所有rename持久化
+    for path in paths {
+        KeyStore::sync_parent_dir(path)?;
+    }
+
+    Ok(())
+}
+
+/// rename之前为原始二进制及其旁路文件建立的备份
+struct Backup {
+    binary: PathBuf,
+    sidecar: Option<PathBuf>,
+}
+
+impl Backup {
+    /// 以硬链接备份原始文件（不支持硬链接时复制），旁路文件不存在时不备份
+    fn create(path: &Path) -> Result<Self> {
+        let binary = link_or_copy(path)?;
+
+        let sidecar_path = sidecar::sidecar_path(path);
+        let sidecar = if sidecar_path.exists() {
+            match link_or_copy(&sidecar_path) {
+                Ok(backup) => Some(backup),
+                Err(e) => {
+                    let _ = fs::remove_file(&binary);
+                    return Err(e);
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Backup { binary, sidecar })
+    }
+
+    /// 将备份恢复到原位置，返回是否全部恢复成功
+    fn restore(&self, path: &Path) -> bool {
+        let binary = fs::rename(&self.binary, path).is_ok();
+        let sidecar = match &self.sidecar {
+            Some(sidecar) => fs::rename(sidecar, sidecar::sidecar_path(path)).is_ok(),
+            None => true,
+        };
+        binary && sidecar
+    }
+
+    /// 删除备份文件（忽略删除错误，已恢复的备份不再存在）
+    fn discard(&self) {
+        let _ = fs::remove_file(&self.binary);
+        if let Some(sidecar) = &self.sidecar {
+            let _ = fs::remove_file(sidecar);
+        }
+    }
+}
+
+/// 在同一目录下为 `path` 建立备份，返回备份文件路径
+fn link_or_copy(path: &Path) -> Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(
+        ".bak.{}.{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let backup_path = path.with_file_name(file_name);
+
+    if fs::hard_link(path, &backup_path).is_err() {
+        if let Err(e) = fs::copy(path, &backup_path) {
+            let _ = fs::remove_file(&backup_path);
+            return Err(Error::io_at(&backup_path)(e));
+        }
+    }
+
+    Ok(backup_path)
+}
+
+/// 删除全部备份文件
+fn discard_backups(backups: &[Backup]) {
+    for backup in backups {
+        backup.discard();
+    }
+}
+
+/// 删除已生成的临时文件（忽略删除错误）
+fn discard_temps(staged: &[(PathBuf, &PathBuf)]) {
+    for (temp_path, _) in staged {
+        let _ = fs::remove_file(temp_path);
+    }
+}
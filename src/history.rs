@@ -0,0 +1,135 @@
+//! 保留最近几次写入的密钥历史版本
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+
+/// 每个版本前记录长度的字节数
+const LEN_SIZE: usize = 4;
+
+/// 将当前密钥和历史版本依次打包，每个版本前是4字节小端长度
+pub(crate) fn pack(versions: &[&[u8]]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(versions.iter().map(|v| LEN_SIZE + v.len()).sum::<usize>());
+    for version in versions {
+        frame.extend_from_slice(&(version.len() as u32).to_le_bytes());
+        frame.extend_from_slice(version);
+    }
+    frame
+}
+
+/// 保留 `history` 个历史版本、且每个版本都是 `key_len` 字节时，打包后的长度
+pub(crate) fn packed_len(key_len: usize, history: usize) -> usize {
+    (LEN_SIZE.saturating_add(key_len)).saturating_mul(history.saturating_add(1))
+}
+
+/// 拆分 [`pack`] 打包的数据，第一个元素为当前密钥，之后由新到旧
+pub(crate) fn unpack(mut frame: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut versions = Vec::new();
+    while !frame.is_empty() {
+        let len = frame
+            .get(..LEN_SIZE)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+        match len {
+            Some(len) if frame.len() - LEN_SIZE >= len => {
+                versions.push(frame[LEN_SIZE..LEN_SIZE + len].to_vec());
+                frame = &frame[LEN_SIZE + len..];
+            }
+            _ => {
+                for version in &mut versions {
+                    zeroize(version);
+                }
+                return Err(Error::IntegrityCheckFailed(
+                    "密钥历史版本的记录已损坏".to_string(),
+                ));
+            }
+        }
+    }
+
+    if versions.is_empty() {
+        return Err(Error::IntegrityCheckFailed(
+            "密钥历史版本的记录为空".to_string(),
+        ));
+    }
+    Ok(versions)
+}
+
+impl KeyStore {
+    /// 读取历史版本
+    ///
+    /// # 参数
+    ///
+    /// * `back` - 往回的版本数，0为当前密钥，1为上一次写入的值，依此类推
+    ///
+    /// # 返回
+    ///
+    /// 成功返回该版本的bytes；没有这么多历史版本时返回 `Error::Config`，其他失败返回Error
+    pub fn read_history(&self, back: usize) -> Result<Vec<u8>> {
+        let mut binary_data = self.read_binary()?;
+        let result = Self::decode_versions(
+            &binary_data,
+            self.meta_section(),
+            self.metadata(),
+            self.constant_time(),
+        );
+        zeroize(&mut binary_data);
+
+        let mut versions = result?;
+        let available = versions.len() - 1;
+        let version = (back < versions.len()).then(|| versions.swap_remove(back));
+        for other in &mut versions {
+            zeroize(other);
+        }
+        version.ok_or_else(|| {
+            Error::Config(format!(
+                "没有第{}个历史版本，当前只保存了{}个",
+                back, available
+            ))
+        })
+    }
+
+    /// 把新密钥和二进制中已有的版本打包，最多保留 `history` 个历史版本
+    pub(crate) fn pack_with_history(&self, binary_data: &[u8], new_key: &[u8]) -> Result<Vec<u8>> {
+        let stored = Self::read_metadata(binary_data, self.meta_section())
+            .map(|metadata| metadata.initialized)
+            .unwrap_or(false);
+        let mut previous = if stored {
+            Self::decode_versions(
+                binary_data,
+                self.meta_section(),
+                self.metadata(),
+                self.constant_time(),
+            )?
+        } else {
+            Vec::new()
+        };
+
+        for old in previous.iter_mut().skip(self.metadata().history) {
+            zeroize(old);
+        }
+        previous.truncate(self.metadata().history);
+
+        let mut versions = vec![new_key];
+        versions.extend(previous.iter().map(Vec::as_slice));
+        let frame = pack(&versions);
+        for old in &mut previous {
+            zeroize(old);
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_round_trip() {
+        let versions: [&[u8]; 3] = [b"current", b"", b"oldest"];
+        assert_eq!(unpack(&pack(&versions)).unwrap(), versions);
+
+        let frame = pack(&versions);
+        assert!(unpack(&frame[..frame.len() - 1]).is_err());
+        assert!(unpack(&frame[..2]).is_err());
+        assert!(unpack(&[]).is_err());
+    }
+}
@@ -1,17 +1,75 @@
 //! 密钥存储核心实现
 
-use crate::crypto::{decrypt_shard, derive_key_from_section, encrypt_shard};
+use crate::audit_log::{AuditEvent, AuditOperation};
+use crate::crypto::{
+    constant_time_eq, constants_fingerprint, decrypt_shard, derive_key_from_section, encrypt_shard,
+    hmac_sha256, hmac_sha256_verify, parse_binary, pbkdf2_hmac_sha256, zeroize,
+};
 use crate::error::{Error, Result};
-use crate::metadata::KeyMetadata;
+use crate::history;
+use crate::limits::Limits;
+use crate::metadata::{
+    CompressionCodec, ExternalFactor, InterleaveStride, KeyMetadata, MetaEncoding, Padding,
+    ShardLayout,
+};
+use crate::note::{self, StorageFormat};
+use crate::readonly::ReadOnlyKeyStore;
+use crate::sections::SectionMap;
+use crate::sidecar;
+use crate::stale::StaleKeyStore;
 use object::{Object, ObjectSection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::{self, File};
-use std::io::Read;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 // 引入编译时生成的加密常量
 include!(concat!(env!("OUT_DIR"), "/crypto_constants.rs"));
 
+/// 密钥字节的来源：`(section名称, 密钥中的字节范围)` 列表，参见 [`KeyStore::read_with_provenance`]
+pub type Provenance = Vec<(String, Range<usize>)>;
+
+/// 密钥的实际存储方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageMode {
+    /// 直接写入二进制文件的sections
+    InPlace,
+    /// 写入二进制旁边的旁路文件
+    Sidecar,
+}
+
+/// [`KeyStore::key_sections`] 返回的单个section状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionStatus {
+    /// section名称（`.key_meta` 或 `.key_data_xx`）
+    pub name: String,
+    /// section在文件中的大小（字节）
+    pub size: usize,
+    /// 是否正在使用：元数据section总是在使用，分片section在当前布局中时为true
+    pub active: bool,
+    /// 内容是否全为0
+    pub zeroed: bool,
+}
+
+/// 密钥变更后调用的审计回调，参见 [`KeyStore::set_audit_hook`]
+pub type AuditHook = Box<dyn Fn(&AuditEvent) + Send + Sync>;
+
+/// 长时间操作的进度回调，参数为 `(已完成的操作数, 总操作数)`，参见 [`KeyStore::set_progress`]
+pub type ProgressCallback = Box<dyn Fn(usize, usize) + Send + Sync>;
+
+/// 测试用的提交前回调，参数为二进制文件路径
+#[cfg(feature = "test-utils")]
+type CommitHook = Box<dyn FnMut(&Path) + Send>;
+
 /// 密钥存储管理器
 ///
 /// 提供密钥的读取、更新等操作，支持任意长度的bytes数据
@@ -20,14 +78,106 @@ pub struct KeyStore {
     exe_path: PathBuf,
     /// 密钥元数据
     metadata: KeyMetadata,
+    /// 写入时是否fsync临时文件和所在目录
+    durable: bool,
+    /// 读取时是否总是处理全部物理分片
+    constant_time: bool,
+    /// 期望的存储格式
+    format: StorageFormat,
+    /// 打开时识别出的二进制实际存储格式
+    detected_format: StorageFormat,
+    /// 显式设置的KDF迭代次数，读取时要求与二进制中记录的一致
+    kdf_iterations: Option<u32>,
+    /// 打开时（以及本实例每次写入后）二进制文件的 (设备号, inode)
+    identity: Option<(u64, u64)>,
+    /// 写入前是否检查进程正被调试器跟踪
+    anti_debug: bool,
+    /// 允许使用的分片序号，None表示全部8个
+    allowed_shards: Option<Vec<usize>>,
+    /// 每次读写前检查的输入限制
+    limits: Option<Limits>,
+    /// 乐观并发检查失败时的重试次数，None表示不检查
+    optimistic_retries: Option<u32>,
+    /// 测试用：计算出新二进制之后、检查写入代数之前调用
+    #[cfg(feature = "test-utils")]
+    before_commit: Option<CommitHook>,
+    /// 延迟写入模式下尚未写回文件的二进制内容
+    pending: Option<Vec<u8>>,
+    /// 是否延迟写入，直到 `flush` 或实例被丢弃
+    deferred: bool,
+    /// 读取时确认完整性校验失败后是否销毁分片
+    wipe_on_tamper: bool,
+    /// 写入时是否遮盖头部中的密钥长度
+    obfuscate_length: bool,
+    /// 每次成功修改密钥后调用的审计回调
+    audit_hook: Option<AuditHook>,
+    /// 长时间操作的进度回调
+    progress: Option<ProgressCallback>,
+    /// 写入时是否同时用随机数据刷新未使用的shard section
+    decoy_writes: bool,
+    /// 写入时是否保留明文未变的分片的nonce，使其密文保持不变
+    partial_writes: bool,
+    /// 是否只在内存中操作二进制内容，不对应任何文件
+    in_memory: bool,
+    /// 每次写入时是否重新随机决定分片的逻辑顺序
+    remap_on_write: bool,
+    /// `read_bytes_checked` 是否检查解密结果的可疑迹象
+    sanity_warnings: bool,
+    /// 写入时是否为每个分片记录绑定 .text 段的标签
+    section_data_digest: bool,
+    /// 元数据section的名称
+    meta_section: String,
+    /// 调用方指定的shard section名称，None表示使用 `KeyMetadata::SHARD_NAMES`
+    explicit_shards: Option<Vec<String>>,
+}
+
+/// [`KeyStore::capture_raw_state`] 保存的原始存储状态，丢弃时清零
+pub(crate) struct RawState {
+    binary: Vec<u8>,
+    /// 保存时是否有延迟写入模式下尚未写回的修改
+    pending: bool,
+    sidecar: Option<Vec<u8>>,
+    metadata: KeyMetadata,
+}
+
+impl Drop for RawState {
+    fn drop(&mut self) {
+        zeroize(&mut self.binary);
+        if let Some(content) = &mut self.sidecar {
+            zeroize(content);
+        }
+    }
 }
 
 impl KeyStore {
     /// 元数据section的名称（固定）
-    const METADATA_SECTION: &'static str = ".key_meta";
+    pub(crate) const METADATA_SECTION: &'static str = ".key_meta";
 
     /// 用于派生加密密钥的代码段（.text段不会被密钥更新修改）
-    const DERIVE_SECTION: &'static str = ".text";
+    pub(crate) const DERIVE_SECTION: &'static str = ".text";
+
+    /// 用PBKDF2拉伸派生密钥时使用的盐
+    const KDF_SALT: &'static [u8] = b"self_crypto_key/kdf";
+
+    /// 计算外部因子校验值时使用的标签
+    const FACTOR_CHECK_LABEL: &'static [u8] = b"self_crypto_key/factor-check";
+
+    /// 计算存储数据完整性校验值时使用的标签
+    const PAYLOAD_CHECK_LABEL: &'static [u8] = b"self_crypto_key/payload-check";
+
+    /// 计算派生sections绑定校验值时使用的标签
+    const BINDING_CHECK_LABEL: &'static [u8] = b"self_crypto_key/binding-check";
+
+    /// 计算密钥长度掩码时使用的标签
+    const LENGTH_MASK_LABEL: &'static [u8] = b"self_crypto_key/length-mask";
+
+    /// 每个分片绑定标签的长度（字节）
+    const SHARD_TAG_SIZE: usize = 4;
+
+    /// [`read_bytes_timeout`](Self::read_bytes_timeout) 同时存在的辅助读取线程上限
+    ///
+    /// 超时后辅助线程仍阻塞在文件读取中，达到上限后新的调用直接返回超时错误，不再创建线程
+    pub const MAX_TIMEOUT_READERS: usize = 4;
 
     /// 创建新的KeyStore实例
     ///
@@ -39,368 +189,4933 @@ impl KeyStore {
     ///
     /// 首次使用时会自动生成配置，在第一次update时写入元数据
     pub fn new() -> Result<Self> {
-        let exe_path = env::current_exe()?;
-
-        let mut file = File::open(&exe_path)?;
-        let mut binary_data = Vec::new();
-        file.read_to_end(&mut binary_data)?;
-        drop(file);
-
-        // 尝试从二进制中读取现有元数据
-        let metadata = Self::read_metadata(&binary_data).unwrap_or_else(|_| {
-            // 如果没有元数据，生成新的配置（首次使用时会在update时写入）
-            KeyMetadata::generate()
-        });
-
-        metadata.validate()?;
-
-        Ok(Self { exe_path, metadata })
+        Self::from_path(env::current_exe()?)
     }
 
-    /// 更新密钥（bytes版本）
+    /// 基于指定路径的二进制文件创建KeyStore实例
     ///
-    /// 将新密钥加密后写入二进制文件，支持任意长度的数据
+    /// 与 [`KeyStore::new`] 相同，但操作的是任意一个包含密钥存储sections的
+    /// 二进制文件，而不是当前可执行文件
     ///
     /// # 参数
     ///
-    /// * `new_key` - 新的密钥数据（bytes）
+    /// * `path` - 二进制文件路径
     ///
     /// # 返回
     ///
-    /// 成功返回Ok(())，失败返回Error
+    /// 成功返回KeyStore实例，失败返回Error
+    pub fn from_path<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        Self::open_path(path.into(), true)
+    }
+
+    /// 打开当前可执行文件的密钥存储，以便用当前构建重新写入
+    ///
+    /// 密钥由使用不同加密常量的构建写入时，[`new`](Self::new) 会返回Error，且存储的
+    /// 数据已无法解密。此方法跳过这项检查，返回的 [`StaleKeyStore`] 只能重新写入或
+    /// 清除密钥，完成后得到正常的KeyStore
+    ///
+    /// # 返回
+    ///
+    /// 成功返回StaleKeyStore实例，失败返回Error
     ///
     /// # 示例
     ///
     /// ```no_run
     /// # use self_crypto_key::KeyStore;
-    /// let mut store = KeyStore::new()?;
-    /// store.update_bytes(b"my-secret-key")?;
+    /// let store = match KeyStore::new() {
+    ///     Ok(store) => store,
+    ///     Err(_) => KeyStore::open_for_rewrite()?.update_bytes(b"my-secret-key")?,
+    /// };
     /// # Ok::<(), self_crypto_key::Error>(())
     /// ```
-    pub fn update_bytes(&mut self, new_key: &[u8]) -> Result<()> {
-        // 读取二进制文件
-        let mut binary_data = fs::read(&self.exe_path)?;
-
-        // 首次使用时，写入元数据JSON
-        let needs_metadata_init = Self::read_metadata(&binary_data).is_err();
-        if needs_metadata_init {
-            self.write_metadata_to_binary(&mut binary_data)?;
-        }
-
-        // 获取总容量
-        let total_capacity = self.metadata.total_capacity();
-
-        // 检查密钥长度是否超出容量
-        if new_key.len() > total_capacity {
-            return Err(Error::Config(format!(
-                "密钥长度({})超出总容量({}), 请考虑重新编译以增加容量",
-                new_key.len(),
-                total_capacity
-            )));
-        }
-
-        // 如果密钥长度小于总容量，填充零字节
-        let mut padded_key = new_key.to_vec();
-        padded_key.resize(total_capacity, 0);
-
-        // 分片并加密
-        let mut offset_in_key = 0;
-        for (i, &shard_size) in self.metadata.shard_sizes.iter().enumerate() {
-            let shard_data = &padded_key[offset_in_key..offset_in_key + shard_size];
-            offset_in_key += shard_size;
-
-            // 找到对应的section
-            let section_name = &self.metadata.shard_names[i];
-            let (section_offset, section_size) = Self::find_section(&binary_data, section_name)?;
-
-            if section_size < shard_size {
-                return Err(Error::SizeMismatch {
-                    expected: shard_size,
-                    actual: section_size,
-                });
-            }
-
-            // 从.text段派生加密密钥
-            let derive_key =
-                derive_key_from_section(&binary_data, Self::DERIVE_SECTION, shard_size)?;
+    pub fn open_for_rewrite() -> Result<StaleKeyStore> {
+        Self::from_path_for_rewrite(env::current_exe()?)
+    }
 
-            // 使用编译时生成的随机种子偏移量
-            let shard_seed = SHARD_SEED_OFFSETS[i % SHARD_SEED_OFFSETS.len()];
+    /// 与 [`open_for_rewrite`](Self::open_for_rewrite) 相同，但操作指定路径的二进制文件
+    ///
+    /// # 参数
+    ///
+    /// * `path` - 二进制文件路径
+    ///
+    /// # 返回
+    ///
+    /// 成功返回StaleKeyStore实例，失败返回Error
+    pub fn from_path_for_rewrite<P: Into<PathBuf>>(path: P) -> Result<StaleKeyStore> {
+        Self::open_path(path.into(), false).map(StaleKeyStore::new)
+    }
 
-            // 加密：混淆 -> 异或
-            let encrypted =
-                encrypt_shard(shard_data, &derive_key, shard_seed.wrapping_add(i as u8));
+    /// 读取二进制文件并创建KeyStore实例，`check_constants` 为false时不检查加密常量指纹
+    fn open_path(exe_path: PathBuf, check_constants: bool) -> Result<Self> {
+        let mut file = File::open(&exe_path).map_err(Error::io_at(&exe_path))?;
+        let mut binary_data = Vec::new();
+        file.read_to_end(&mut binary_data)
+            .map_err(Error::io_at(&exe_path))?;
+        drop(file);
 
-            // 写入二进制数据
-            binary_data[section_offset..section_offset + shard_size].copy_from_slice(&encrypted);
+        let metadata = Self::load_metadata_unchecked(&binary_data, Self::METADATA_SECTION)?;
+        if check_constants {
+            Self::check_constants_fingerprint(&metadata)?;
         }
-
-        // 更新元数据中的实际密钥长度（存储在元数据section的前8个字节）
-        let (meta_offset, _) = Self::find_section(&binary_data, Self::METADATA_SECTION)?;
-        let key_len_bytes = (new_key.len() as u64).to_le_bytes();
-        binary_data[meta_offset..meta_offset + 8].copy_from_slice(&key_len_bytes);
-
-        // 原子写入
-        Self::atomic_write(&self.exe_path, &binary_data)?;
-
-        Ok(())
+        Self::open_parts(
+            exe_path,
+            &binary_data,
+            metadata,
+            Self::METADATA_SECTION,
+            None,
+        )
     }
 
-    /// 更新密钥（字符串版本）
+    /// 从内存中的二进制内容创建KeyStore实例，不对应任何文件
     ///
-    /// 便捷方法，用于更新字符串类型的密钥
+    /// 适合在工具中处理下载得到或即将上传的二进制：之后的写入只修改内存中的内容，
+    /// 不会写入任何文件（也不使用旁路文件），[`flush`](Self::flush) 不做任何操作。
+    /// 完成后用 [`into_binary`](Self::into_binary) 取出修改后的完整二进制。
+    /// 依赖文件的功能（如 [`read_bytes_timeout`](Self::read_bytes_timeout)、
+    /// [`cached_reader`](Self::cached_reader)、[`stored_checksum`](Self::stored_checksum)）不可用，
+    /// [`exe_path`](Self::exe_path) 为空路径
     ///
     /// # 参数
     ///
-    /// * `new_key` - 新的密钥字符串
+    /// * `binary_data` - 完整的ELF二进制内容
     ///
     /// # 返回
     ///
-    /// 成功返回Ok(())，失败返回Error
+    /// 成功返回KeyStore实例，失败返回Error
     ///
     /// # 示例
     ///
     /// ```no_run
     /// # use self_crypto_key::KeyStore;
-    /// let mut store = KeyStore::new()?;
+    /// let mut store = KeyStore::from_bytes(std::fs::read("app")?)?;
     /// store.update("my-secret-key")?;
+    /// let patched = store.into_binary()?;
     /// # Ok::<(), self_crypto_key::Error>(())
     /// ```
-    pub fn update(&mut self, new_key: &str) -> Result<()> {
-        self.update_bytes(new_key.as_bytes())
+    pub fn from_bytes(binary_data: Vec<u8>) -> Result<Self> {
+        let metadata = Self::load_metadata(&binary_data, Self::METADATA_SECTION)?;
+        let detected_format = Self::detect_format(&binary_data, Self::METADATA_SECTION)?;
+
+        let mut store = Self::assemble(
+            PathBuf::new(),
+            None,
+            detected_format,
+            metadata,
+            Self::METADATA_SECTION,
+            None,
+        );
+        store.in_memory = true;
+        store.deferred = true;
+        store.pending = Some(binary_data);
+        Ok(store)
     }
 
-    /// 读取当前密钥（bytes版本）
+    /// 创建KeyStore实例，但不为尚未写入元数据的二进制生成配置
     ///
-    /// 从二进制文件中读取并解密密钥，返回原始bytes
+    /// [`new`](Self::new) 在二进制中没有元数据时会在内存中随机生成布局；需要严格区分
+    /// “尚未初始化”的场景可以使用此方法。不会生成配置，也不会写入任何内容
     ///
     /// # 返回
     ///
-    /// 成功返回密钥的bytes，失败返回Error
+    /// 二进制中已写入有效元数据时返回 `Ok(Some(store))`，尚未写入时返回 `Ok(None)`，
+    /// 其他失败返回Error
     ///
     /// # 示例
     ///
     /// ```no_run
     /// # use self_crypto_key::KeyStore;
-    /// let store = KeyStore::new()?;
-    /// let key_bytes = store.read_bytes()?;
+    /// match KeyStore::try_new()? {
+    ///     Some(store) => println!("密钥长度: {}", store.stored_len()?),
+    ///     None => println!("尚未配置密钥"),
+    /// }
     /// # Ok::<(), self_crypto_key::Error>(())
     /// ```
-    pub fn read_bytes(&self) -> Result<Vec<u8>> {
-        let binary_data = fs::read(&self.exe_path)?;
-
-        // 读取实际密钥长度
-        let (meta_offset, _) = Self::find_section(&binary_data, Self::METADATA_SECTION)?;
-        let key_len_bytes = &binary_data[meta_offset..meta_offset + 8];
-        let actual_key_len = u64::from_le_bytes([
-            key_len_bytes[0],
-            key_len_bytes[1],
-            key_len_bytes[2],
-            key_len_bytes[3],
-            key_len_bytes[4],
-            key_len_bytes[5],
-            key_len_bytes[6],
-            key_len_bytes[7],
-        ]) as usize;
-
-        // 如果密钥长度为0，返回空vec
-        if actual_key_len == 0 {
-            return Ok(Vec::new());
-        }
+    pub fn try_new() -> Result<Option<Self>> {
+        Self::try_from_path(env::current_exe()?)
+    }
 
-        let total_capacity = self.metadata.total_capacity();
-        if actual_key_len > total_capacity {
-            return Err(Error::Config(format!(
-                "存储的密钥长度异常: {} > {}",
-                actual_key_len, total_capacity
-            )));
-        }
+    /// 与 [`try_new`](Self::try_new) 相同，但操作指定路径的二进制文件
+    ///
+    /// # 参数
+    ///
+    /// * `path` - 二进制文件路径
+    ///
+    /// # 返回
+    ///
+    /// 二进制中已写入有效元数据时返回 `Ok(Some(store))`，尚未写入时返回 `Ok(None)`，
+    /// 其他失败返回Error
+    pub fn try_from_path<P: Into<PathBuf>>(path: P) -> Result<Option<Self>> {
+        let exe_path = path.into();
+        let binary_data = fs::read(&exe_path).map_err(Error::io_at(&exe_path))?;
 
-        // 读取并解密所有分片
-        let mut decrypted_bytes = Vec::new();
-        let mut bytes_needed = actual_key_len;
+        let (_, meta_size) = Self::find_section(&binary_data, Self::METADATA_SECTION)
+            .map_err(|e| Self::explain_missing_section(&binary_data, e))?;
+        Self::check_meta_size(meta_size)?;
 
-        for (i, &shard_size) in self.metadata.shard_sizes.iter().enumerate() {
-            if bytes_needed == 0 {
-                break;
-            }
+        let metadata = match Self::read_metadata(&binary_data, Self::METADATA_SECTION) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+        metadata.validate()?;
+        Self::check_constants_fingerprint(&metadata)?;
+        Self::validate_against_binary(&binary_data, &metadata, Self::METADATA_SECTION)?;
+
+        Self::open_parts(
+            exe_path,
+            &binary_data,
+            metadata,
+            Self::METADATA_SECTION,
+            None,
+        )
+        .map(Some)
+    }
+
+    /// 使用调用方指定的section名称创建当前可执行文件的KeyStore实例
+    ///
+    /// 不使用 `init_key_storage!` 的命名约定和预定义的 `.key_data_xx` 名称，
+    /// 元数据和分片都存放在调用方声明的section中，适合需要自定义section名称
+    /// 或与其他工具约定名称的场景。分片按 `shards` 中的顺序全部使用，
+    /// 每个分片的容量等于对应section的大小
+    ///
+    /// # 参数
+    ///
+    /// * `meta` - 元数据section的名称，大小至少为1296字节
+    /// * `shards` - shard section的名称（1-8个，不能重复）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回KeyStore实例；任一section在二进制中不存在或名称无效时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::with_explicit_sections(".app_meta", &[".app_a", ".app_b"])?;
+    /// store.update_bytes(b"my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_explicit_sections(meta: &str, shards: &[&str]) -> Result<Self> {
+        Self::from_path_with_sections(env::current_exe()?, meta, shards)
+    }
 
-            let section_name = &self.metadata.shard_names[i];
-            let (section_offset, section_size) = Self::find_section(&binary_data, section_name)?;
+    /// 使用调用方指定的section名称创建指定二进制文件的KeyStore实例
+    ///
+    /// 与 [`with_explicit_sections`](Self::with_explicit_sections) 相同，但操作的是
+    /// 任意一个二进制文件
+    ///
+    /// # 参数
+    ///
+    /// * `path` - 二进制文件路径
+    /// * `meta` - 元数据section的名称
+    /// * `shards` - shard section的名称（1-8个，不能重复）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回KeyStore实例；任一section在二进制中不存在或名称无效时返回Error
+    pub fn from_path_with_sections<P: Into<PathBuf>>(
+        path: P,
+        meta: &str,
+        shards: &[&str],
+    ) -> Result<Self> {
+        let exe_path = path.into();
 
-            if section_size < shard_size {
-                return Err(Error::SizeMismatch {
-                    expected: shard_size,
-                    actual: section_size,
-                });
+        if shards.is_empty() || shards.len() > KeyMetadata::SHARD_NAMES.len() {
+            return Err(Error::Config(format!(
+                "shard section数量({})必须在1到{}之间",
+                shards.len(),
+                KeyMetadata::SHARD_NAMES.len()
+            )));
+        }
+        for (i, name) in shards.iter().enumerate() {
+            if *name == meta || shards[..i].contains(name) {
+                return Err(Error::Config(format!("section名称重复: {}", name)));
             }
+        }
 
-            let encrypted_data = &binary_data[section_offset..section_offset + shard_size];
+        let binary_data = fs::read(&exe_path).map_err(Error::io_at(&exe_path))?;
 
-            // 从.text段派生解密密钥
-            let derive_key =
-                derive_key_from_section(&binary_data, Self::DERIVE_SECTION, shard_size)?;
+        let (_, meta_size) = Self::find_section(&binary_data, meta)?;
+        Self::check_meta_size(meta_size)?;
+        let sizes = shards
+            .iter()
+            .map(|name| Self::find_section(&binary_data, name).map(|(_, size)| size))
+            .collect::<Result<Vec<_>>>()?;
 
-            // 使用编译时生成的随机种子偏移量（必须与加密时相同）
-            let shard_seed = SHARD_SEED_OFFSETS[i % SHARD_SEED_OFFSETS.len()];
+        let metadata = match Self::read_metadata(&binary_data, meta) {
+            Ok(metadata) => {
+                if metadata
+                    .shard_names
+                    .iter()
+                    .any(|n| !shards.contains(&n.as_str()))
+                {
+                    return Err(Error::Config(format!(
+                        "二进制中已写入的布局与指定的shard section不一致: {:?}",
+                        metadata.shard_names
+                    )));
+                }
+                metadata
+            }
+            Err(_) => KeyMetadata::explicit(shards, &sizes),
+        };
+        metadata.validate()?;
+        Self::check_constants_fingerprint(&metadata)?;
 
-            // 解密：异或 -> 反混淆
-            let decrypted = decrypt_shard(
-                encrypted_data,
-                &derive_key,
-                shard_seed.wrapping_add(i as u8),
-            );
+        let explicit = shards.iter().map(|name| name.to_string()).collect();
+        Self::open_parts(exe_path, &binary_data, metadata, meta, Some(explicit))
+    }
 
-            // 只取需要的字节数
-            let bytes_to_take = bytes_needed.min(decrypted.len());
-            decrypted_bytes.extend(&decrypted[..bytes_to_take]);
-            bytes_needed -= bytes_to_take;
-        }
+    /// 由已加载的元数据组装KeyStore实例，其余选项均为默认值
+    fn open_parts(
+        exe_path: PathBuf,
+        binary_data: &[u8],
+        metadata: KeyMetadata,
+        meta_section: &str,
+        explicit_shards: Option<Vec<String>>,
+    ) -> Result<Self> {
+        let detected_format = Self::detect_format(binary_data, meta_section)?;
+        let identity = Self::file_identity(&exe_path)?;
+        Ok(Self::assemble(
+            exe_path,
+            identity,
+            detected_format,
+            metadata,
+            meta_section,
+            explicit_shards,
+        ))
+    }
 
-        Ok(decrypted_bytes)
+    /// 组装KeyStore实例，其余选项均为默认值
+    fn assemble(
+        exe_path: PathBuf,
+        identity: Option<(u64, u64)>,
+        detected_format: StorageFormat,
+        metadata: KeyMetadata,
+        meta_section: &str,
+        explicit_shards: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            identity,
+            exe_path,
+            metadata,
+            durable: true,
+            constant_time: false,
+            format: StorageFormat::default(),
+            detected_format,
+            kdf_iterations: None,
+            anti_debug: false,
+            allowed_shards: None,
+            limits: None,
+            optimistic_retries: None,
+            #[cfg(feature = "test-utils")]
+            before_commit: None,
+            pending: None,
+            deferred: false,
+            wipe_on_tamper: false,
+            obfuscate_length: false,
+            audit_hook: None,
+            progress: None,
+            decoy_writes: false,
+            partial_writes: false,
+            section_data_digest: false,
+            meta_section: meta_section.to_string(),
+            explicit_shards,
+            in_memory: false,
+            remap_on_write: false,
+            sanity_warnings: false,
+        }
     }
 
-    /// 读取当前密钥（字符串版本）
+    /// 依次尝试多个候选路径，使用第一个包含有效密钥存储的二进制
     ///
-    /// 便捷方法，尝试将密钥解析为UTF-8字符串
+    /// 某些打包方式下 `env::current_exe()` 指向的是启动器而不是包含密钥存储的二进制，
+    /// 此时可以列出可能的路径。候选二进制需要能够解析出元数据，且元数据引用的
+    /// shard section全部存在、大小足够
     ///
-    /// # 返回
+    /// # 参数
     ///
-    /// 成功返回密钥字符串，失败返回Error
+    /// * `paths` - 候选路径，按优先级排列
     ///
-    /// # 注意
+    /// # 返回
     ///
-    /// 如果密钥不是有效的UTF-8，将返回错误。对于二进制密钥，请使用`read_bytes()`
+    /// 成功返回第一个合格候选的KeyStore；没有合格的候选时返回 `Error::Config`，
+    /// 其中列出每个路径不合格的原因
     ///
     /// # 示例
     ///
     /// ```no_run
     /// # use self_crypto_key::KeyStore;
-    /// let store = KeyStore::new()?;
-    /// let key = store.read()?;
-    /// println!("密钥: {}", key);
+    /// # use std::path::PathBuf;
+    /// let store = KeyStore::from_candidates(&[
+    ///     std::env::current_exe()?,
+    ///     PathBuf::from("/opt/app/lib/app-real"),
+    /// ])?;
     /// # Ok::<(), self_crypto_key::Error>(())
     /// ```
-    pub fn read(&self) -> Result<String> {
-        let bytes = self.read_bytes()?;
-        String::from_utf8(bytes).map_err(|e| Error::Parse(format!("密钥不是有效的UTF-8: {}", e)))
+    pub fn from_candidates<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut reasons = Vec::new();
+
+        for path in paths {
+            let path = path.as_ref();
+            match Self::from_path(path).and_then(|store| {
+                // 尚未写入元数据时布局是随机生成的，同样要求引用的sections存在
+                let binary_data = store.read_binary()?;
+                Self::validate_against_binary(&binary_data, &store.metadata, &store.meta_section)
+                    .map(|_| store)
+            }) {
+                Ok(store) => return Ok(store),
+                Err(e) => reasons.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+
+        Err(Error::Config(format!(
+            "没有包含有效密钥存储的候选二进制 [{}]",
+            reasons.join("; ")
+        )))
     }
 
-    /// 获取密钥存储的总容量
-    ///
-    /// # 返回
+    /// 确认元数据引用的sections在当前二进制中全部存在
     ///
-    /// 总容量（字节）
-    pub fn capacity(&self) -> usize {
-        self.metadata.total_capacity()
+    /// `validate` 只检查元数据自身的一致性；这里在打开时确认元数据section、派生密钥所用的
+    /// sections存在，且每个分片section存在、大小足够，避免问题拖到第一次读写时才暴露。
+    /// 缺少section的错误与读取时一样经过 `explain_missing_section`：静态链接的二进制中
+    /// 分片被链接器回收时，打开就会返回建议重新构建的 `Error::Config`
+    fn validate_against_binary(
+        binary_data: &[u8],
+        metadata: &KeyMetadata,
+        meta_section: &str,
+    ) -> Result<()> {
+        let sections = SectionMap::parse(binary_data)?;
+        sections
+            .find(meta_section)
+            .map_err(|e| Self::explain_missing_section(binary_data, e))?;
+
+        let derive_sections = if metadata.derive_sections.is_empty() {
+            vec![Self::DERIVE_SECTION]
+        } else {
+            metadata
+                .derive_sections
+                .iter()
+                .map(String::as_str)
+                .collect()
+        };
+        for name in derive_sections {
+            sections
+                .find(name)
+                .map_err(|e| Self::explain_missing_section(binary_data, e))?;
+        }
+
+        for (name, &size) in metadata.shard_names.iter().zip(&metadata.shard_sizes) {
+            Self::shard_range(&sections, name, size)?;
+        }
+
+        Ok(())
     }
 
-    /// 生成随机密钥字符串
+    /// 打开来源不可信的二进制，在做任何耗时解析之前检查输入限制
+    ///
+    /// 与 [`from_path`](Self::from_path) 相同，但先只读取文件大小和ELF头部，超出
+    /// `limits` 时直接返回 `Error::Parse`。之后每次读写前都会重新检查，
+    /// 防止文件在打开后被替换为异常的输入
     ///
     /// # 参数
     ///
-    /// * `length` - 密钥长度（字符数）
+    /// * `path` - 二进制文件路径
+    /// * `limits` - 文件大小和section数量的上限
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, Limits};
+    /// let store = KeyStore::from_path_with_limits("/tmp/upload.bin", Limits::default())?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn from_path_with_limits<P: Into<PathBuf>>(path: P, limits: Limits) -> Result<Self> {
+        let path = path.into();
+        limits.check_file(&path)?;
+
+        let mut store = Self::from_path(path)?;
+        store.limits = Some(limits);
+        Ok(store)
+    }
+
+    /// 设置了输入限制时检查当前文件
+    fn check_limits(&self) -> Result<()> {
+        match &self.limits {
+            Some(limits) => limits.check_file(&self.exe_path),
+            None => Ok(()),
+        }
+    }
+
+    /// 以只读方式打开当前可执行文件的密钥存储
+    ///
+    /// 返回的 [`ReadOnlyKeyStore`] 只提供读取操作，不会以写方式打开文件，
+    /// 也不会执行首次使用时的元数据初始化，因此可用于只读挂载的二进制
     ///
     /// # 返回
     ///
-    /// 随机生成的可打印ASCII字符串（字符范围 33-126）
-    pub fn generate_random_key(length: usize) -> String {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        (0..length)
-            .map(|_| rng.gen_range(33..=126) as u8 as char)
-            .collect()
+    /// 成功返回ReadOnlyKeyStore实例，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::open_readonly()?;
+    /// let key = store.read_bytes()?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn open_readonly() -> Result<ReadOnlyKeyStore> {
+        ReadOnlyKeyStore::open(env::current_exe()?)
     }
 
-    /// 生成随机密钥bytes
+    /// 以只读方式打开指定路径的二进制文件的密钥存储
+    ///
+    /// 与 [`open_readonly`](Self::open_readonly) 相同，但读取指定的二进制，用于检查或
+    /// 校验只有读权限的二进制（例如已签名的发布产物）。不会尝试任何写入，也不会执行
+    /// 首次使用时的元数据初始化
     ///
     /// # 参数
     ///
-    /// * `length` - 密钥长度（字节数）
+    /// * `path` - 二进制文件的路径
     ///
     /// # 返回
     ///
-    /// 随机生成的bytes
-    pub fn generate_random_bytes(length: usize) -> Vec<u8> {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        (0..length).map(|_| rng.gen()).collect()
+    /// 成功返回ReadOnlyKeyStore实例，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::from_path_readonly("/usr/local/bin/my-app")?;
+    /// assert!(store.verify_against(b"my-secret-key")?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn from_path_readonly<P: Into<PathBuf>>(path: P) -> Result<ReadOnlyKeyStore> {
+        ReadOnlyKeyStore::open(path.into())
     }
 
-    /// 从二进制数据中读取元数据
-    fn read_metadata(binary_data: &[u8]) -> Result<KeyMetadata> {
-        let (offset, size) = Self::find_section(binary_data, Self::METADATA_SECTION)?;
-
-        if size < 8 {
-            return Err(Error::Config(format!("元数据section太小: {} < 8", size)));
-        }
-
-        // 跳过前8个字节（密钥长度），读取JSON元数据
-        let metadata_bytes = &binary_data[offset + 8..offset + size];
-
-        KeyMetadata::from_bytes(metadata_bytes)
+    /// 从二进制数据中加载元数据
+    ///
+    /// 元数据不存在时生成新的配置（仅在内存中，首次使用时会在update时写入）
+    pub(crate) fn load_metadata(binary_data: &[u8], meta_section: &str) -> Result<KeyMetadata> {
+        let metadata = Self::load_metadata_unchecked(binary_data, meta_section)?;
+        Self::check_constants_fingerprint(&metadata)?;
+        Ok(metadata)
     }
 
-    /// 将元数据写入二进制数据的.key_meta section
-    fn write_metadata_to_binary(&self, binary_data: &mut Vec<u8>) -> Result<()> {
-        let (meta_offset, meta_size) = Self::find_section(binary_data, Self::METADATA_SECTION)?;
+    /// 从二进制数据中加载元数据，不检查加密常量指纹
+    fn load_metadata_unchecked(binary_data: &[u8], meta_section: &str) -> Result<KeyMetadata> {
+        // 确认元数据section足以容纳头部和JSON
+        let (_, meta_size) = Self::find_section(binary_data, meta_section)
+            .map_err(|e| Self::explain_missing_section(binary_data, e))?;
+        Self::check_meta_size(meta_size)?;
 
-        // 序列化元数据为JSON
-        let json_bytes = self.metadata.to_bytes()?;
+        // 尝试从二进制中读取现有元数据
+        let (metadata, stored) = match Self::read_metadata(binary_data, meta_section) {
+            Ok(metadata) => (metadata, true),
+            // 如果没有元数据，生成新的配置（首次使用时会在update时写入）
+            Err(_) => (KeyMetadata::generate(), false),
+        };
 
-        // 检查空间是否足够（前8字节留给密钥长度）
-        if json_bytes.len() + 8 > meta_size {
-            return Err(Error::Config(format!(
-                "元数据section空间不足: {} + 8 > {}",
-                json_bytes.len(),
-                meta_size
-            )));
+        metadata.validate()?;
+        // 二进制中记录的布局必须与当前二进制的sections对应
+        if stored {
+            Self::validate_against_binary(binary_data, &metadata, meta_section)?;
         }
 
-        // 写入JSON（从偏移8开始，前8字节保留给密钥长度）
-        binary_data[meta_offset + 8..meta_offset + 8 + json_bytes.len()]
-            .copy_from_slice(&json_bytes);
-
-        Ok(())
+        Ok(metadata)
     }
 
-    /// 查找section的文件偏移和大小
-    fn find_section(binary_data: &[u8], section_name: &str) -> Result<(usize, usize)> {
-        let obj_file = object::File::parse(binary_data)
-            .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
+    /// 确认密钥是由使用相同编译时加密常量的二进制写入的
+    ///
+    /// 加密常量在每次编译时随机生成。增量构建复用了旧的 `OUT_DIR`，或重新编译后
+    /// 修补了二进制时，代码中的常量可能与写入密钥时不同，此时解密只会得到无意义的数据
+    fn check_constants_fingerprint(metadata: &KeyMetadata) -> Result<()> {
+        let current = constants_fingerprint();
+        if metadata.constants_fingerprint.is_empty() || metadata.constants_fingerprint == current {
+            return Ok(());
+        }
 
-        for section in obj_file.sections() {
-            if let Ok(name) = section.name() {
-                if name == section_name {
-                    let (offset, size) = section.file_range().ok_or_else(|| {
-                        Error::Parse(format!("无法获取section {}的文件偏移", section_name))
-                    })?;
-                    return Ok((offset as usize, size as usize));
+        Err(Error::Crypto(format!(
+            "该密钥由使用不同加密常量的二进制写入（写入时 {}，当前 {}），\
+             可能复用了旧的构建缓存或在重新编译后修补了二进制，\
+             请通过 KeyStore::open_for_rewrite 用当前构建重新写入密钥",
+            metadata.constants_fingerprint, current
+        )))
+    }
+
+    /// 更新密钥（bytes版本）
+    ///
+    /// 将新密钥加密后写入二进制文件，支持任意长度的数据
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 新的密钥数据（bytes）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.update_bytes(b"my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update_bytes(&mut self, new_key: &[u8]) -> Result<()> {
+        let layout = self.metadata.clone();
+        self.update_in_layout(&layout, new_key)
+    }
+
+    /// 按给定的分片布局写入新密钥，写入成功后才采用对应的元数据
+    fn update_in_layout(&mut self, layout: &KeyMetadata, new_key: &[u8]) -> Result<()> {
+        let old_fingerprint = self.audit_fingerprint();
+        self.write_key(layout, new_key)?;
+
+        if self.audit_hook.is_some() {
+            let new_fingerprint = self.key_fingerprint(new_key).ok();
+            self.emit_audit(AuditOperation::Update, old_fingerprint, new_fingerprint);
+        }
+        Ok(())
+    }
+
+    /// 更新密钥，并返回本次写入修改的section名称
+    ///
+    /// 与 [`update_bytes`](Self::update_bytes) 相同，额外返回内容实际发生变化的section：
+    /// 先是元数据section，之后是按布局顺序排列的活动分片；开启
+    /// [`with_decoy_writes`](Self::with_decoy_writes) 时还包括被随机数据刷新的其余shard section。
+    /// 开启 [`with_partial_writes`](Self::with_partial_writes) 时，沿用原有密文的分片不会列出。
+    /// 可用于审计，或只把这些区域同步到远端
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 新的密钥数据（bytes）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回被修改的section名称，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// for name in store.update_bytes_detailed(b"my-secret-key")? {
+    ///     println!("modified: {}", name);
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update_bytes_detailed(&mut self, new_key: &[u8]) -> Result<Vec<String>> {
+        let mut before = self.read_binary()?;
+        let result = self.update_bytes(new_key).and_then(|_| {
+            let mut after = self.read_binary()?;
+            let modified = self.changed_sections(&before, &after);
+            zeroize(&mut after);
+            modified
+        });
+        zeroize(&mut before);
+        result
+    }
+
+    /// 比较写入前后的二进制，按元数据section、活动分片、其余shard section的顺序列出内容变化的section
+    fn changed_sections(&self, before: &[u8], after: &[u8]) -> Result<Vec<String>> {
+        let active = self.metadata.shard_names.iter().map(String::as_str);
+        let others = self
+            .shard_universe()
+            .into_iter()
+            .filter(|name| !self.metadata.shard_names.iter().any(|n| n == name));
+
+        let mut modified = Vec::new();
+        for name in std::iter::once(self.meta_section.as_str())
+            .chain(active)
+            .chain(others)
+        {
+            let range = match Self::find_section(after, name) {
+                Ok((offset, size)) => offset..offset + size,
+                Err(Error::SectionNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            if before.get(range.clone()) != Some(&after[range]) {
+                modified.push(name.to_string());
+            }
+        }
+        Ok(modified)
+    }
+
+    /// 按给定的布局写入新密钥（不调用审计回调）
+    fn write_key(&mut self, layout: &KeyMetadata, new_key: &[u8]) -> Result<()> {
+        let mut attempts = 0;
+        let (binary_data, metadata) = loop {
+            let (binary_data, metadata) = self.prepare_update_in(layout, new_key)?;
+            if self.generation_unchanged(metadata.write_generation)? {
+                break (binary_data, metadata);
+            }
+
+            // 其他进程在此期间写入了密钥，基于最新的二进制重新计算
+            let retries = self.optimistic_retries.unwrap_or(0);
+            if attempts >= retries {
+                return Err(Error::Config(format!(
+                    "二进制在写入期间被其他进程修改，已重试{}次",
+                    retries
+                )));
+            }
+            attempts += 1;
+        };
+
+        if self.deferred {
+            self.pending = Some(binary_data);
+            self.metadata = metadata;
+            return Ok(());
+        }
+
+        // 原子写入，成功后才采用新的元数据，失败时内存中的状态与磁盘保持一致
+        self.write_binary(&binary_data)?;
+        self.metadata = metadata;
+
+        // 二进制中的密钥为最新值，删除可能残留的旁路文件
+        self.remove_sidecar()?;
+
+        Ok(())
+    }
+
+    /// 按需更新密钥：由闭包根据当前存储的值决定是否写入以及写入什么
+    ///
+    /// 先读取当前存储的密钥（未写入过时为 `None`）传给 `f`，`f` 返回 `None` 表示无需更新。
+    /// 适合新密钥计算代价较高、只在确实需要时才计算的场景：在闭包中先检查当前值，
+    /// 只有需要更新时才计算新密钥。返回的新密钥与当前值相同时也不会写入（以恒定时间比较）。
+    /// 读取出的当前值和闭包返回的新密钥在返回前清零
+    ///
+    /// # 参数
+    ///
+    /// * `f` - 接收当前密钥，返回需要写入的新密钥或 `None`
+    ///
+    /// # 返回
+    ///
+    /// 实际写入返回 `Ok(true)`，未写入返回 `Ok(false)`，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// # fn expensive_derivation() -> Vec<u8> { vec![0x42; 32] }
+    /// let mut store = KeyStore::new()?;
+    /// // 只有尚未配置密钥时才计算
+    /// let written = store.update_bytes_with(|current| match current {
+    ///     Some(_) => None,
+    ///     None => Some(expensive_derivation()),
+    /// })?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update_bytes_with<F>(&mut self, f: F) -> Result<bool>
+    where
+        F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>,
+    {
+        let mut current = self.try_read_bytes()?;
+        let new_key = f(current.as_deref());
+
+        let result = match new_key {
+            Some(mut key) => {
+                // 与当前值的比较以恒定时间进行，不通过耗时泄露两者相同的前缀长度
+                let unchanged = current
+                    .as_deref()
+                    .is_some_and(|current| constant_time_eq(current, &key));
+                let result = match unchanged {
+                    true => Ok(false),
+                    false => self.update_bytes(&key).map(|_| true),
+                };
+                zeroize(&mut key);
+                result
+            }
+            None => Ok(false),
+        };
+
+        if let Some(current) = current.as_mut() {
+            zeroize(current);
+        }
+        result
+    }
+
+    /// 预先完成首次使用时的元数据初始化
+    ///
+    /// 将元数据写入 `.key_meta` section，但不写入任何密钥数据。
+    /// 调用后，后续的update只会写入密钥数据本身，避免首次写入时元数据和
+    /// 分片同时写入带来的不确定状态。元数据已存在时不做任何修改。
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.reserve()?;
+    /// store.update_bytes(&vec![0x42; store.capacity()])?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn reserve(&mut self) -> Result<()> {
+        let mut binary_data = self.read_binary()?;
+
+        if Self::read_metadata(&binary_data, &self.meta_section).is_ok() {
+            return Ok(());
+        }
+
+        self.write_metadata_to_binary(&mut binary_data, &self.metadata)?;
+        self.write_binary(&binary_data)
+    }
+
+    /// 采用另一个KeyStore的分片布局
+    ///
+    /// 批量配置多个二进制时，可以让它们使用完全相同的分片布局（分片名称、大小和顺序），
+    /// 而不是各自随机生成。布局会立即写入本二进制的元数据；若本二进制已存储密钥，
+    /// 会按新布局重新写入，存储的值保持不变
+    ///
+    /// # 参数
+    ///
+    /// * `source` - 提供布局的KeyStore
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；本二进制缺少布局需要的section时返回Error，且不做任何修改
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let template = KeyStore::from_path("/opt/app/template")?;
+    /// let mut target = KeyStore::from_path("/opt/app/node-01")?;
+    /// target.copy_layout_from(&template)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn copy_layout_from(&mut self, source: &KeyStore) -> Result<()> {
+        let mut binary_data = self.read_binary()?;
+
+        for (name, &size) in source
+            .metadata
+            .shard_names
+            .iter()
+            .zip(&source.metadata.shard_sizes)
+        {
+            Self::find_shard_section(&binary_data, name, size)?;
+        }
+
+        let existing = if self.is_initialized()? {
+            Some(self.read_bytes()?)
+        } else {
+            None
+        };
+
+        // 新布局在副本上构建，写入成功后才采用
+        let mut metadata = self.metadata.clone();
+        metadata.num_shards = source.metadata.num_shards;
+        metadata.shard_sizes = source.metadata.shard_sizes.clone();
+        metadata.shard_names = source.metadata.shard_names.clone();
+        metadata.logical_index = source.metadata.logical_index.clone();
+        metadata.shard_nonces.clear();
+
+        self.commit_layout(metadata, &mut binary_data, existing)
+    }
+
+    /// 采用新的分片布局：已存储密钥时按新布局重新写入，否则只写入元数据
+    ///
+    /// 写入成功后才替换本实例的元数据，失败时保持原有布局不变。`existing` 在返回前清零
+    fn commit_layout(
+        &mut self,
+        metadata: KeyMetadata,
+        binary_data: &mut [u8],
+        existing: Option<Vec<u8>>,
+    ) -> Result<()> {
+        match existing {
+            Some(mut key) => {
+                let result = self.update_in_layout(&metadata, &key);
+                zeroize(&mut key);
+                result
+            }
+            None => {
+                self.write_metadata_to_binary(binary_data, &metadata)?;
+                self.write_binary(binary_data)?;
+                self.metadata = metadata;
+                Ok(())
+            }
+        }
+    }
+
+    /// 扩大分片布局以获得更大的容量，已存储的密钥保持不变
+    ///
+    /// 从二进制中尚未使用的shard section里选取新的分片追加到布局末尾，然后按新布局
+    /// 重新写入当前密钥。要求二进制编译时已包含足够的物理section（`init_key_storage!`
+    /// 总是生成全部8个）。只从 [`with_allowed_shards`](Self::with_allowed_shards) 允许的分片，
+    /// 或使用指定section时从指定的section中选取
+    ///
+    /// # 参数
+    ///
+    /// * `new_shard_count` - 新的分片数量，不能小于当前分片数量，且不能超过8
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；分片数量无效或缺少所需的物理section时返回Error，且不做任何修改
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.grow(8)?;
+    /// assert_eq!(store.capacity(), 8 * 1024);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn grow(&mut self, new_shard_count: usize) -> Result<()> {
+        let current = self.metadata.num_shards;
+        if new_shard_count < current || new_shard_count > KeyMetadata::SHARD_NAMES.len() {
+            return Err(Error::Config(format!(
+                "无效的分片数量: {} (当前 {}, 最大 {})",
+                new_shard_count,
+                current,
+                KeyMetadata::SHARD_NAMES.len()
+            )));
+        }
+
+        let mut binary_data = self.read_binary()?;
+
+        let candidates: Vec<&str> = match &self.explicit_shards {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => KeyMetadata::SHARD_NAMES
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| self.shard_allowed(*i))
+                .map(|(_, name)| *name)
+                .collect(),
+        };
+        let added: Vec<String> = candidates
+            .into_iter()
+            .filter(|name| !self.metadata.shard_names.iter().any(|n| n == name))
+            .take(new_shard_count - current)
+            .map(str::to_string)
+            .collect();
+        if added.len() < new_shard_count - current {
+            return Err(Error::Config(format!(
+                "可用的shard section不足：需要新增{}个，只有{}个可用",
+                new_shard_count - current,
+                added.len()
+            )));
+        }
+
+        // 指定的section按实际大小使用，预定义的section使用固定大小
+        let mut added_sizes = Vec::with_capacity(added.len());
+        for name in &added {
+            let size = match &self.explicit_shards {
+                Some(_) => Self::find_section(&binary_data, name)?.1,
+                None => KeyMetadata::SHARD_SIZE,
+            };
+            Self::find_shard_section(&binary_data, name, size)?;
+            added_sizes.push(size);
+        }
+
+        let existing = if self.is_initialized()? {
+            Some(self.read_bytes()?)
+        } else {
+            None
+        };
+
+        // 新布局在副本上构建，新增的分片排在逻辑顺序的最后
+        let mut metadata = self.metadata.clone();
+        if metadata.logical_index.is_empty() {
+            metadata.logical_index = (0..current).collect();
+        }
+        metadata
+            .logical_index
+            .extend(current..current + added.len());
+        metadata.num_shards = new_shard_count;
+        metadata.shard_sizes.extend(added_sizes);
+        metadata.shard_names.extend(added);
+        metadata.shard_nonces.clear();
+
+        self.commit_layout(metadata, &mut binary_data, existing)
+    }
+
+    /// 恢复出厂状态：清除密钥和元数据
+    ///
+    /// 将 `.key_meta` section（包括头部和JSON元数据）和全部shard section清零，
+    /// 并删除可能存在的旁路文件。之后二进制与刚编译出来时相同，下次写入会重新
+    /// 随机生成分片布局
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.reset()?;
+    /// assert!(store.needs_init()?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn reset(&mut self) -> Result<()> {
+        let old_fingerprint = self.audit_fingerprint();
+        let mut binary_data = self.read_binary()?;
+
+        for name in self.storage_sections() {
+            match Self::find_section(&binary_data, &name) {
+                Ok((offset, size)) => binary_data[offset..offset + size].fill(0),
+                Err(Error::SectionNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.write_binary(&binary_data)?;
+        self.remove_sidecar()?;
+
+        // 与新二进制一样使用新生成的配置（保留用户设置的选项）
+        let mut metadata = self.generate_metadata()?;
+        metadata.compression_threshold = self.metadata.compression_threshold;
+        metadata.derive_sections = self.metadata.derive_sections.clone();
+        metadata.layout = self.metadata.layout;
+        metadata.interleave_stride = self.metadata.interleave_stride;
+        metadata.padding = self.metadata.padding;
+        metadata.history = self.metadata.history;
+        metadata.external_factor = self.metadata.external_factor.clone();
+        metadata.compression_dictionary = self.metadata.compression_dictionary.clone();
+        metadata.dictionary_codec = self.metadata.dictionary_codec;
+        metadata.encoding = self.metadata.encoding;
+        self.metadata = metadata;
+
+        self.emit_audit(AuditOperation::Reset, old_fingerprint, None);
+        Ok(())
+    }
+
+    /// 用随机数据覆盖全部shard section，销毁其中存储的密钥
+    ///
+    /// 与 [`reset`](Self::reset) 不同，元数据保持不变，之后的读取会因完整性校验失败
+    /// 而返回Error；覆盖为随机数据而不是清零，使分片看起来与正常写入的密文无法区分。
+    /// 同时删除可能存在的旁路文件。需要重新写入密钥才能继续使用
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    pub fn shred(&mut self) -> Result<()> {
+        self.shred_passes(1)
+    }
+
+    /// 多次用随机数据覆盖全部shard section
+    ///
+    /// 与 [`shred`](Self::shred) 相同，但覆盖 `passes` 遍。每遍都在文件中原地写入并 `sync_data`，
+    /// 不经过临时文件 + rename，否则旧的密文只是随原inode被解除链接，仍留在磁盘上。
+    /// 旁路文件同样先原地覆盖再删除；延迟写入模式下尚未写回的修改会被丢弃。
+    /// 设置了 [`set_progress`](Self::set_progress) 时，每覆盖一个section调用一次进度回调，
+    /// 总操作数为 `passes × 存在的shard section数量`
+    ///
+    /// # 参数
+    ///
+    /// * `passes` - 覆盖的遍数，必须大于0
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；`passes` 为0或写入失败时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.set_progress(Box::new(|done, total| eprintln!("{}/{}", done, total)));
+    /// store.shred_passes(3)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn shred_passes(&mut self, passes: usize) -> Result<()> {
+        use rand::RngCore;
+
+        if passes == 0 {
+            return Err(Error::Config("覆盖遍数必须大于0".to_string()));
+        }
+
+        let old_fingerprint = self.audit_fingerprint();
+
+        if self.in_memory {
+            // 内存实例没有磁盘上的旧数据，直接修改内存中的二进制
+            let mut binary_data = self.read_binary()?;
+            let ranges = Self::shard_ranges(&binary_data, &self.shard_universe())?;
+            let total = passes * ranges.len();
+            let mut rng = rand::thread_rng();
+            for pass in 0..passes {
+                for (i, range) in ranges.iter().enumerate() {
+                    rng.fill_bytes(&mut binary_data[range.clone()]);
+                    self.report_progress(pass * ranges.len() + i + 1, total);
+                }
+            }
+            self.write_binary(&binary_data)?;
+        } else {
+            // 未写回的修改中可能包含密钥，丢弃后不会在flush或drop时重新写入文件
+            if let Some(mut pending) = self.pending.take() {
+                zeroize(&mut pending);
+            }
+
+            let binary_data = fs::read(&self.exe_path).map_err(Error::io_at(&self.exe_path))?;
+            let ranges = Self::shard_ranges(&binary_data, &self.shard_universe())?;
+            let total = passes * ranges.len();
+            for pass in 0..passes {
+                for (i, range) in ranges.iter().enumerate() {
+                    Self::overwrite_in_place(&self.exe_path, std::slice::from_ref(range))?;
+                    self.report_progress(pass * ranges.len() + i + 1, total);
                 }
             }
+            self.wipe_sidecar()?;
+        }
+
+        self.emit_audit(AuditOperation::Shred, old_fingerprint, None);
+        Ok(())
+    }
+
+    /// 用随机数据覆盖二进制数据中存在的、不在 `skip` 中的shard section
+    fn randomize_shards(binary_data: &mut [u8], names: &[&str], skip: &[String]) -> Result<()> {
+        use rand::RngCore;
+        let mut rng = rand::thread_rng();
+        let names: Vec<&str> = names
+            .iter()
+            .copied()
+            .filter(|name| !skip.iter().any(|n| n == name))
+            .collect();
+        for range in Self::shard_ranges(binary_data, &names)? {
+            rng.fill_bytes(&mut binary_data[range]);
+        }
+        Ok(())
+    }
+
+    /// 二进制中存在的、名称在 `names` 中的shard section的文件范围
+    fn shard_ranges(binary_data: &[u8], names: &[&str]) -> Result<Vec<Range<usize>>> {
+        let mut ranges = Vec::new();
+        for &name in names {
+            match Self::find_section(binary_data, name) {
+                Ok((offset, size)) => ranges.push(offset..offset + size),
+                Err(Error::SectionNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// 检查二进制是否尚未写入元数据（即处于刚编译出来或 [`reset`](Self::reset) 之后的状态）
+    ///
+    /// # 返回
+    ///
+    /// 元数据尚未写入返回 `Ok(true)`，否则返回 `Ok(false)`
+    pub fn needs_init(&self) -> Result<bool> {
+        let binary_data = self.read_binary()?;
+        Ok(Self::read_metadata(&binary_data, &self.meta_section).is_err())
+    }
+
+    /// 更新密钥，二进制不可写时自动改用旁路文件
+    ///
+    /// 优先直接写入二进制；当二进制文件为只读（或写入因权限/只读文件系统失败）时，
+    /// 将密钥加密写入二进制旁边的 `<二进制名>.key` 旁路文件。
+    /// 旁路文件与二进制内的存储使用相同的派生sections、KDF迭代次数和外部因子，
+    /// 读取时会优先使用旁路文件。
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 新的密钥数据（bytes）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回实际使用的存储方式，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, StorageMode};
+    /// let mut store = KeyStore::new()?;
+    /// if store.update_bytes_auto(b"my-secret-key")? == StorageMode::Sidecar {
+    ///     println!("二进制不可写，密钥已写入旁路文件");
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update_bytes_auto(&mut self, new_key: &[u8]) -> Result<StorageMode> {
+        let permissions = fs::metadata(&self.exe_path)
+            .map_err(Error::io_at(&self.exe_path))?
+            .permissions();
+        if !permissions.readonly() {
+            match self.update_bytes(new_key) {
+                Err(Error::Io(e) | Error::IoAt { source: e, .. })
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+                    ) => {}
+                result => return result.map(|_| StorageMode::InPlace),
+            }
+        }
+
+        let content = self.encode_sidecar(new_key)?;
+        Self::atomic_write(
+            &sidecar::sidecar_path(&self.exe_path),
+            &content,
+            self.durable,
+        )?;
+
+        Ok(StorageMode::Sidecar)
+    }
+
+    /// 按当前配置的派生参数生成旁路文件内容
+    ///
+    /// 与写入二进制相同：基础密钥由派生sections、KDF迭代次数和外部因子（每次写入使用新的盐）
+    /// 计算，派生参数记录在旁路文件中
+    fn encode_sidecar(&self, new_key: &[u8]) -> Result<Vec<u8>> {
+        let binary_data = self.read_binary()?;
+        let mut metadata = self.metadata.clone();
+        metadata.factor_check.clear();
+        if metadata.external_factor.is_some() {
+            metadata.factor_salt = KeyStore::generate_random_bytes(16);
+        } else {
+            metadata.factor_salt.clear();
+        }
+
+        let mut base_key = Self::derive_base_key(&SectionMap::parse(&binary_data)?, &metadata)?;
+        if metadata.external_factor.is_some() {
+            metadata.factor_check = Self::factor_check(&base_key);
+        }
+        let content = sidecar::encode(
+            &base_key,
+            &sidecar::DeriveParams::from_metadata(&metadata),
+            new_key,
+        );
+        zeroize(&mut base_key);
+        content
+    }
+
+    /// 解密旁路文件内容，按其中记录的派生参数计算基础密钥，外部因子取自 `current`
+    fn decode_sidecar(
+        content: &[u8],
+        binary_data: &[u8],
+        current: &KeyMetadata,
+    ) -> Result<Vec<u8>> {
+        let mut metadata = current.clone();
+        sidecar::params(content)?.apply_to(&mut metadata);
+
+        let mut base_key = Self::derive_base_key(&SectionMap::parse(binary_data)?, &metadata)?;
+        let key = sidecar::decode(content, &base_key);
+        zeroize(&mut base_key);
+        key
+    }
+
+    /// 设置写入时是否保证持久化（默认开启）
+    ///
+    /// 开启时，每次写入会在rename前fsync临时文件，并在rename后fsync所在目录，
+    /// 保证断电后二进制要么是旧内容、要么是完整的新内容，而不会被截断。
+    ///
+    /// # 性能
+    ///
+    /// fsync需要等待数据真正写入存储设备，单次写入会增加数毫秒到数十毫秒
+    /// （取决于文件系统和磁盘）。对于测试或可以容忍数据丢失的场景，可以关闭。
+    ///
+    /// # 参数
+    ///
+    /// * `durable` - 是否开启持久化保证
+    pub fn set_durable(&mut self, durable: bool) {
+        self.durable = durable;
+    }
+
+    /// 开启延迟写入模式
+    ///
+    /// 之后的 [`update_bytes`](Self::update_bytes) 只在内存中更新二进制内容，直到调用
+    /// [`flush`](Self::flush) 才写回文件，连续多次更新只需一次写入。本实例的读取和其他
+    /// 操作都基于未写回的内容，[`stored_checksum`](Self::stored_checksum) 和其他实例
+    /// 看到的仍是文件中的内容。延迟写入模式下不做乐观并发检查
+    ///
+    /// 实例被丢弃时会尝试写回未写回的修改，但 `Drop` 无法返回错误，写回失败时修改会
+    /// 丢失且不会有任何提示，因此应当显式调用 [`flush`](Self::flush) 并检查结果
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_deferred_writes();
+    /// store.update("first")?;
+    /// store.update("second")?;
+    /// store.flush()?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_deferred_writes(mut self) -> Self {
+        self.deferred = true;
+        self
+    }
+
+    /// 将延迟写入模式下未写回的修改写回文件
+    ///
+    /// 没有未写回的修改时不做任何操作
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；写入失败时返回Error，未写回的修改保留，可以再次调用
+    pub fn flush(&mut self) -> Result<()> {
+        if self.in_memory {
+            return Ok(());
+        }
+
+        let binary_data = match self.pending.take() {
+            Some(binary_data) => binary_data,
+            None => return Ok(()),
+        };
+
+        if let Err(e) = self.write_binary(&binary_data) {
+            self.pending = Some(binary_data);
+            return Err(e);
+        }
+        self.remove_sidecar()
+    }
+
+    /// 删除可能残留的旁路文件，内存实例没有旁路文件
+    fn remove_sidecar(&self) -> Result<()> {
+        if self.in_memory {
+            return Ok(());
+        }
+        sidecar::remove(&self.exe_path)
+    }
+
+    /// 是否有延迟写入模式下尚未写回文件的修改
+    pub fn has_pending_writes(&self) -> bool {
+        !self.in_memory && self.pending.is_some()
+    }
+
+    /// 取出包含全部修改的完整二进制内容
+    ///
+    /// 对 [`from_bytes`](Self::from_bytes) 创建的实例返回内存中修改后的二进制；
+    /// 对文件实例返回当前的二进制内容（延迟写入模式下包括尚未写回的修改，
+    /// 这些修改仍会在实例被丢弃时写回文件）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回完整的二进制内容，读取文件失败时返回Error
+    pub fn into_binary(mut self) -> Result<Vec<u8>> {
+        match self.pending.take() {
+            Some(binary_data) if self.in_memory => Ok(binary_data),
+            pending => {
+                self.pending = pending;
+                self.read_binary()
+            }
+        }
+    }
+
+    /// 开启乐观并发检查，设置检测到并发写入时的重试次数（默认不检查）
+    ///
+    /// 每次写入都会把元数据中的写入代数加1。开启后，[`update_bytes`](Self::update_bytes)
+    /// 在替换文件之前重新读取磁盘上的写入代数，若与计算新二进制时读到的不同，说明其他
+    /// 进程在此期间写入了密钥，此时基于最新的二进制重新计算，最多重试 `retries` 次，
+    /// 仍然冲突时返回 `Error::Config`。这比文件锁更轻量，但检查与rename之间仍有很短的
+    /// 时间窗口，需要严格互斥时应使用文件锁
+    ///
+    /// # 参数
+    ///
+    /// * `retries` - 最多重试的次数，0表示检测到冲突时直接返回错误
+    pub fn set_optimistic_retries(&mut self, retries: u32) {
+        self.optimistic_retries = Some(retries);
+    }
+
+    /// 设置每次成功修改密钥后调用的审计回调
+    ///
+    /// 回调在 [`update_bytes`](Self::update_bytes)（以及基于它的 `update`、`grow` 等）、
+    /// [`reset`](Self::reset) 和 [`shred`](Self::shred) 成功写入之后调用，失败的操作
+    /// 不会触发。事件中只有密钥指纹，可以直接写入合规审计日志。延迟写入模式下，
+    /// `update_bytes` 在更新内存中的内容后即触发回调，而不是等到 `flush`。
+    ///
+    /// 设置回调后，每次修改需要额外解密一次旧密钥来计算指纹
+    ///
+    /// # 参数
+    ///
+    /// * `hook` - 审计回调
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.set_audit_hook(Box::new(|event| {
+    ///     eprintln!("{:?} {:?} -> {:?}", event.operation, event.old_fingerprint, event.new_fingerprint);
+    /// }));
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn set_audit_hook(&mut self, hook: AuditHook) {
+        self.audit_hook = Some(hook);
+    }
+
+    /// 设置长时间操作的进度回调
+    ///
+    /// 回调参数为 `(已完成的操作数, 总操作数)`，最后一次调用时两者相等。
+    /// 目前由 [`shred_passes`](Self::shred_passes)（以及 [`shred`](Self::shred)）调用，
+    /// 适合在命令行工具中显示进度条
+    ///
+    /// # 参数
+    ///
+    /// * `callback` - 进度回调
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.set_progress(Box::new(|done, total| eprintln!("{}/{}", done, total)));
+    /// store.shred_passes(3)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn set_progress(&mut self, callback: ProgressCallback) {
+        self.progress = Some(callback);
+    }
+
+    /// 设置了进度回调时报告进度
+    fn report_progress(&self, completed: usize, total: usize) {
+        if let Some(callback) = &self.progress {
+            callback(completed, total);
+        }
+    }
+
+    /// 设置了审计回调时，计算当前存储的密钥指纹
+    pub(crate) fn audit_fingerprint(&self) -> Option<String> {
+        self.audit_hook.as_ref()?;
+        self.stored_key_fingerprint()
+            .ok()
+            .filter(|fingerprint| !fingerprint.is_empty())
+    }
+
+    /// 设置了审计回调时，以给定的操作和指纹调用回调
+    pub(crate) fn emit_audit(
+        &self,
+        operation: AuditOperation,
+        old_fingerprint: Option<String>,
+        new_fingerprint: Option<String>,
+    ) {
+        if let Some(hook) = &self.audit_hook {
+            hook(&AuditEvent {
+                operation,
+                timestamp: SystemTime::now(),
+                old_fingerprint,
+                new_fingerprint,
+                path: self.exe_path().to_path_buf(),
+            });
+        }
+    }
+
+    /// 替换审计回调，返回原来的回调，用于在内部操作期间暂停审计
+    pub(crate) fn replace_audit_hook(&mut self, hook: Option<AuditHook>) -> Option<AuditHook> {
+        std::mem::replace(&mut self.audit_hook, hook)
+    }
+
+    /// 设置一个在计算出新二进制之后、检查写入代数之前调用的函数（测试用）
+    ///
+    /// 用于在测试中确定性地模拟其他进程的并发写入，参数为二进制文件路径
+    #[cfg(feature = "test-utils")]
+    pub fn set_before_commit_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&Path) + Send + 'static,
+    {
+        self.before_commit = Some(Box::new(hook));
+    }
+
+    /// 开启乐观并发检查时，确认磁盘上的写入代数仍是计算新二进制时读到的值
+    ///
+    /// `write_generation` 是新二进制中记录的写入代数
+    fn generation_unchanged(&mut self, write_generation: u64) -> Result<bool> {
+        #[cfg(feature = "test-utils")]
+        if let Some(hook) = self.before_commit.as_mut() {
+            hook(&self.exe_path);
+        }
+
+        if self.optimistic_retries.is_none() || self.deferred {
+            return Ok(true);
+        }
+
+        let binary_data = self.read_binary()?;
+        Ok(Self::generation_in(&binary_data, &self.meta_section) + 1 == write_generation)
+    }
+
+    /// 二进制中记录的写入代数，尚未写入元数据时为0
+    fn generation_in(binary_data: &[u8], meta_section: &str) -> u64 {
+        Self::read_metadata(binary_data, meta_section)
+            .map(|metadata| metadata.write_generation)
+            .unwrap_or(0)
+    }
+
+    /// 设置读取时是否使用恒定工作量模式（默认关闭）
+    ///
+    /// 默认情况下，读取只解密实际使用的分片，并在取够密钥长度后提前结束，
+    /// 读取耗时会暴露密钥长度和分片数量。开启后，每次读取都会解密全部8个
+    /// 物理shard section，耗时与存储的内容无关，代价是读取变慢。
+    ///
+    /// # 注意
+    ///
+    /// 此模式要求二进制中存在全部8个shard section
+    ///
+    /// # 参数
+    ///
+    /// * `constant_time` - 是否开启恒定工作量模式
+    pub fn set_constant_time(&mut self, constant_time: bool) {
+        self.constant_time = constant_time;
+    }
+
+    /// 设置密钥存储的组织形式（默认为 [`StorageFormat::NamedSections`]）
+    ///
+    /// 二进制使用 `init_key_storage!(note_vendor = "...")` 生成note格式的sections时，
+    /// 需要设置为 [`StorageFormat::ElfNote`] 并指定相同的厂商名称。写入和读取时会检查
+    /// 二进制的实际格式（见 [`storage_format`](Self::storage_format)），不一致时返回 `Error::Config`
+    ///
+    /// # 参数
+    ///
+    /// * `format` - 存储格式
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, StorageFormat};
+    /// let mut store = KeyStore::new()?.with_storage_format(StorageFormat::ElfNote {
+    ///     vendor: "ACME".to_string(),
+    /// });
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_storage_format(mut self, format: StorageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// 获取二进制中密钥存储的实际组织形式
+    pub fn storage_format(&self) -> &StorageFormat {
+        &self.detected_format
+    }
+
+    /// 设置压缩阈值（需要 `compression` feature）
+    ///
+    /// 只有长度达到阈值的密钥才会尝试压缩；压缩后没有变小的数据按原样存储。
+    /// 每次写入是否压缩会记录在元数据中，读取时据此决定是否解压。
+    ///
+    /// # 参数
+    ///
+    /// * `threshold` - 压缩阈值（字节）
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_compression_threshold(128);
+    /// store.update_bytes(&[b'a'; 4096])?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    #[cfg(feature = "compression")]
+    pub fn with_compression_threshold(mut self, threshold: usize) -> Self {
+        self.metadata.compression_threshold = threshold;
+        self
+    }
+
+    /// 设置压缩时使用的预置字典（需要 `compression` feature）
+    ///
+    /// 大量结构相似的密钥（例如字段相同的JSON）单独压缩时效果有限；把一份典型样本作为字典后，
+    /// 与字典相同的片段只需记录回溯引用，压缩率会明显提高。DEFLATE只能回溯32KB，
+    /// 字典超出时只使用最后32KB。字典本身不写入二进制，元数据中只记录其标识，
+    /// 之后读取时必须提供相同的字典，否则返回 `Error::Config`
+    ///
+    /// # 参数
+    ///
+    /// * `dictionary` - 字典内容，通常是一份或几份典型的密钥样本
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let dictionary = std::fs::read("/etc/app/secrets.dict")?;
+    /// let mut store = KeyStore::new()?.with_compression_dictionary(dictionary);
+    /// store.update(r#"{"user":"svc","token":"..."}"#)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    #[cfg(feature = "compression")]
+    pub fn with_compression_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.metadata.compression_dictionary = Some(dictionary);
+        self.metadata.dictionary_codec = CompressionCodec::Deflate;
+        self
+    }
+
+    /// 设置使用Zstandard压缩时的预置字典（需要 `zstd` feature）
+    ///
+    /// 与 [`with_compression_dictionary`](Self::with_compression_dictionary) 相同，
+    /// 但使用Zstandard代替DEFLATE，整个字典都可被引用，不受32KB窗口的限制。
+    /// 元数据中记录压缩算法和字典的标识，之后读取时必须提供相同的字典，否则返回 `Error::Config`
+    ///
+    /// # 参数
+    ///
+    /// * `dictionary` - 字典内容，通常是一份或几份典型的密钥样本
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let dictionary = std::fs::read("/etc/app/secrets.dict")?;
+    /// let mut store = KeyStore::new()?.with_zstd_dictionary(dictionary);
+    /// store.update(r#"{"user":"svc","token":"..."}"#)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd_dictionary(mut self, dictionary: Vec<u8>) -> Self {
+        self.metadata.compression_dictionary = Some(dictionary);
+        self.metadata.dictionary_codec = CompressionCodec::Zstd;
+        self
+    }
+
+    /// 设置用于派生加密密钥的sections（默认只使用 .text 段）
+    ///
+    /// 部分加固构建中 .text 段会被拆分或重定位，即使源码相同，重新编译后哈希也不稳定。
+    /// 此时可以改为绑定 `.rodata` 或其他内容稳定的section。多个section按给定顺序
+    /// 依次计算哈希。设置会在下次写入时记录到元数据中，读取时自动使用相同的sections。
+    ///
+    /// 可以先用 [`suggest_derive_section`](Self::suggest_derive_section) 查看候选section
+    ///
+    /// # 注意
+    ///
+    /// 不能选择会被密钥写入修改的section（`.key_meta`、`.key_data_xx`，note格式下的
+    /// `.note.key_meta`、`.note.key_data_xx`，以及指定的存储sections），否则下次写入时
+    /// 返回 `Error::Config`；旁路文件始终绑定 .text 段
+    ///
+    /// # 参数
+    ///
+    /// * `sections` - section名称列表，为空时恢复默认
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_derive_sections(&[".rodata"]);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_derive_sections(mut self, sections: &[&str]) -> Self {
+        self.metadata.derive_sections = sections.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// 设置密钥字节在分片间的排列方式（默认为 [`ShardLayout::Contiguous`]）
+    ///
+    /// 默认情况下密钥被切成连续的块依次放入各分片，恢复出一个分片和分片顺序的分析者
+    /// 就能得到一段连续的明文。改为 [`ShardLayout::Interleaved`] 后字节轮流放入各分片，
+    /// 单个分片只包含间隔的字节。布局会在下次写入时记录到元数据中，读取时自动使用
+    ///
+    /// # 参数
+    ///
+    /// * `layout` - 排列方式
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, ShardLayout};
+    /// let mut store = KeyStore::new()?.with_shard_layout(ShardLayout::Interleaved);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_shard_layout(mut self, layout: ShardLayout) -> Self {
+        self.metadata.layout = layout;
+        self
+    }
+
+    /// 设置交错布局下每次放入一个分片的字节数（默认为1）
+    ///
+    /// 只在 [`ShardLayout::Interleaved`] 下起作用：密钥按 `stride` 字节一组轮流放入各分片，
+    /// 方便下游按字对齐地处理分片。步长会在下次写入时记录到元数据中，读取时自动使用
+    ///
+    /// # 参数
+    ///
+    /// * `stride` - 步长（字节），不能为0
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{InterleaveStride, KeyStore, ShardLayout};
+    /// let mut store = KeyStore::new()?
+    ///     .with_shard_layout(ShardLayout::Interleaved)
+    ///     .with_interleave_stride(InterleaveStride(4));
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_interleave_stride(mut self, stride: InterleaveStride) -> Self {
+        self.metadata.interleave_stride = stride;
+        self
+    }
+
+    /// 设置密钥长度的记录方式（默认为 [`Padding::HeaderLength`]）
+    ///
+    /// 默认情况下密钥长度记录在 `.key_meta` 头部。改为 [`Padding::SelfDescribing`] 后，
+    /// 数据之后追加结束标记再填充到总容量，长度从解密结果中还原，头部只记录总容量，
+    /// 不再暴露密钥长度，[`stored_len`](Self::stored_len) 也随之返回总容量。可用容量减少1字节。
+    /// 填充方式会在下次写入时记录到元数据中，读取时自动使用
+    ///
+    /// # 参数
+    ///
+    /// * `padding` - 长度的记录方式
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, Padding};
+    /// let mut store = KeyStore::new()?.with_padding_scheme(Padding::SelfDescribing);
+    /// store.update("my-secret-key")?;
+    /// assert_eq!(store.read()?, "my-secret-key");
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_padding_scheme(mut self, padding: Padding) -> Self {
+        self.metadata.padding = padding;
+        self
+    }
+
+    /// 每次写入时保留之前的 `n` 个密钥值，用于轮换出错后回滚
+    ///
+    /// 新密钥写入时，原来的当前值和历史版本一起打包存入分片，超过 `n` 个时丢弃最旧的。
+    /// 历史版本与当前密钥共用总容量，每个版本额外占用4字节；放不下时写入失败，
+    /// 不会静默丢弃历史。设置会在下次写入时记录到元数据中，读取时自动使用。
+    /// 历史版本只保存在二进制中，写入旁路文件时不保留
+    ///
+    /// # 参数
+    ///
+    /// * `n` - 保留的历史版本数量，0表示不保留
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_history(2);
+    /// store.update("key-v1")?;
+    /// store.update("key-v2")?;
+    /// assert_eq!(store.read_history(1)?, b"key-v1");
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_history(mut self, n: usize) -> Self {
+        self.metadata.history = n;
+        self
+    }
+
+    /// 设置元数据在 `.key_meta` 中的编码方式（默认为 [`MetaEncoding::Json`]）
+    ///
+    /// JSON元数据会重复每个字段名，配置较多时可能占满 `.key_meta` section。
+    /// [`MetaEncoding::Cbor`] 更紧凑。编码会在下次写入时生效，并记录在头部中，
+    /// 读取时自动识别，无需再次设置
+    ///
+    /// # 参数
+    ///
+    /// * `encoding` - 元数据编码
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, MetaEncoding};
+    /// let mut store = KeyStore::new()?.with_meta_encoding(MetaEncoding::Cbor);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_meta_encoding(mut self, encoding: MetaEncoding) -> Self {
+        self.metadata.encoding = encoding;
+        self
+    }
+
+    /// 检查分片所在的PT_LOAD段在运行时是否可写
+    ///
+    /// 写入密钥修改的是磁盘上的二进制，当前进程映射的内存不会随之变化。若链接器把
+    /// shard section放在只读段中，运行时的副本更不可能被就地更新，磁盘与内存中的内容
+    /// 会不一致。返回false时，需要重新执行（re-exec）程序才能在内存中看到更新后的密钥；
+    /// 通过 `read_bytes` 读取磁盘内容则不受影响
+    ///
+    /// # 返回
+    ///
+    /// 所有分片都位于带写权限（PF_W）的PT_LOAD段中时返回 `Ok(true)`，否则返回 `Ok(false)`
+    pub fn shards_are_writable_segment(&self) -> Result<bool> {
+        use object::{ObjectSegment, SegmentFlags};
+
+        const PF_W: u32 = 0x2;
+
+        let binary_data = self.read_binary()?;
+        let obj_file = object::File::parse(binary_data.as_slice())
+            .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
+
+        for name in &self.metadata.shard_names {
+            let (offset, size) = Self::find_section(&binary_data, name)?;
+            let (start, end) = (offset as u64, (offset + size) as u64);
+
+            let writable = obj_file.segments().any(|segment| {
+                let (seg_start, seg_size) = segment.file_range();
+                // 伪造的程序头中偏移和大小相加可能溢出，这样的段视为不包含分片
+                let contains = seg_start
+                    .checked_add(seg_size)
+                    .is_some_and(|seg_end| seg_start <= start && end <= seg_end);
+                let flags_writable =
+                    matches!(segment.flags(), SegmentFlags::Elf { p_flags } if p_flags & PF_W != 0);
+                contains && flags_writable
+            });
+
+            if !writable {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// 设置用PBKDF2拉伸派生密钥的迭代次数（默认0，即不拉伸）
+    ///
+    /// 很小的程序 .text 段也很小，其哈希容易被重现。开启后，派生密钥会再经过
+    /// `iterations` 轮PBKDF2-HMAC-SHA256，提高暴力破解绑定关系的成本，代价是
+    /// 每次读写都要多花相应的计算时间。迭代次数会记录到元数据中，读取时使用相同的值；
+    /// 显式设置后，若二进制中记录的迭代次数与设置不一致，读取会返回 `Error::Config`
+    ///
+    /// # 参数
+    ///
+    /// * `iterations` - 迭代次数，0表示不拉伸
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_kdf_iterations(100_000);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_kdf_iterations(mut self, iterations: u32) -> Self {
+        self.metadata.kdf_iterations = iterations;
+        self.kdf_iterations = Some(iterations);
+        self
+    }
+
+    /// 限制随机生成布局时可以使用的分片（默认可以使用全部8个）
+    ///
+    /// 二进制中部分 `.key_data_xx` section另有用途时，可以只允许使用其余的section。
+    /// 二进制尚未写入元数据时，会立即在允许的范围内重新生成布局；之后的
+    /// [`reset`](Self::reset) 和 [`grow`](Self::grow) 也只会使用允许的分片
+    ///
+    /// # 参数
+    ///
+    /// * `indices` - 允许使用的分片序号（0-7），至少4个
+    ///
+    /// # 返回
+    ///
+    /// 成功返回设置后的KeyStore；序号无效、允许的分片少于4个、二进制中已写入的
+    /// 布局使用了不允许的分片，或实例使用调用方指定的section时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_allowed_shards(&[0, 1, 2, 3, 4])?;
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_allowed_shards(mut self, indices: &[usize]) -> Result<Self> {
+        if self.explicit_shards.is_some() {
+            return Err(Error::Config(
+                "使用指定的section时不能限制分片序号，请直接调整传入的section名称".to_string(),
+            ));
+        }
+        let generated = KeyMetadata::generate_within(indices)?;
+        self.allowed_shards = Some(indices.to_vec());
+
+        if self.needs_init()? {
+            self.metadata.num_shards = generated.num_shards;
+            self.metadata.shard_sizes = generated.shard_sizes;
+            self.metadata.shard_names = generated.shard_names;
+            self.metadata.logical_index = generated.logical_index;
+            return Ok(self);
+        }
+
+        let disallowed = self.metadata.shard_names.iter().find(|name| {
+            KeyMetadata::SHARD_NAMES
+                .iter()
+                .position(|n| n == name)
+                .is_none_or(|i| !self.shard_allowed(i))
+        });
+        if let Some(name) = disallowed {
+            return Err(Error::Config(format!(
+                "二进制中已写入的布局使用了不允许的分片: {}",
+                name
+            )));
+        }
+
+        Ok(self)
+    }
+
+    /// 检查分片序号是否允许使用
+    fn shard_allowed(&self, index: usize) -> bool {
+        self.allowed_shards
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&index))
+    }
+
+    /// 可用作分片的全部shard section名称：指定了section时为指定的名称，否则为预定义的8个
+    fn shard_universe(&self) -> Vec<&str> {
+        match &self.explicit_shards {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => KeyMetadata::SHARD_NAMES.to_vec(),
+        }
+    }
+
+    /// 是否为存放密钥存储内容的section（包括note格式下对应的 `.note` 前缀section）
+    fn is_storage_section(&self, name: &str) -> bool {
+        self.storage_sections()
+            .iter()
+            .any(|s| s == name || note::note_section_name(s) == name)
+    }
+
+    /// 存放密钥存储内容的全部section名称：元数据section在前，之后是全部shard section
+    pub(crate) fn storage_sections(&self) -> Vec<String> {
+        std::iter::once(self.meta_section.as_str())
+            .chain(self.shard_universe())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// 在允许的分片范围内生成新的元数据
+    fn generate_metadata(&self) -> Result<KeyMetadata> {
+        if let Some(names) = &self.explicit_shards {
+            let binary_data = self.read_binary()?;
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let sizes = names
+                .iter()
+                .map(|name| Self::find_section(&binary_data, name).map(|(_, size)| size))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(KeyMetadata::explicit(&names, &sizes));
+        }
+
+        match &self.allowed_shards {
+            Some(allowed) => KeyMetadata::generate_within(allowed),
+            None => Ok(KeyMetadata::generate()),
+        }
+    }
+
+    /// 缩小每个分片实际使用的大小（仅用于测试，需要 `test-utils` feature）
+    ///
+    /// 用于在不重新编译的情况下模拟小容量的二进制，以便确定性地测试超出容量的路径。
+    /// 设置后 [`capacity`](Self::capacity) 随之减小，新的分片大小会在下次写入时记录到元数据中
+    ///
+    /// # 参数
+    ///
+    /// * `size` - 每个分片使用的字节数，必须大于0且不超过物理section大小（1KB）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；`size` 为0或超过物理section大小时返回Error
+    #[cfg(feature = "test-utils")]
+    pub fn set_usable_shard_size(&mut self, size: usize) -> Result<()> {
+        if size == 0 || size > KeyMetadata::SHARD_SIZE {
+            return Err(Error::Config(format!(
+                "分片可用大小({})必须在1到物理section大小({})之间",
+                size,
+                KeyMetadata::SHARD_SIZE
+            )));
+        }
+
+        self.metadata.shard_sizes = vec![size; self.metadata.num_shards];
+        Ok(())
+    }
+
+    /// 设置外部因子，使二进制中的密钥单独无法解密
+    ///
+    /// 外部因子（例如U盘上的文件内容，或运行时从服务器获取的值）与 .text 段派生的
+    /// 密钥材料一起经HMAC混合后用于加解密。二进制中只记录随机盐和一个校验值，
+    /// 不记录因子本身；读取时因子不正确会返回 `Error::Crypto`，未设置因子则返回
+    /// `Error::Config`。写入时设置了因子，之后的每次读写都必须提供相同的因子
+    ///
+    /// # 参数
+    ///
+    /// * `factor` - 外部因子
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let factor = std::fs::read("/media/usb/app.factor")?;
+    /// let mut store = KeyStore::new()?.with_external_factor(&factor);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_external_factor(mut self, factor: &[u8]) -> Self {
+        self.metadata.external_factor = Some(ExternalFactor(factor.to_vec()));
+        self
+    }
+
+    /// 设置读取时确认被篡改后是否销毁分片（默认关闭）
+    ///
+    /// 开启后，[`read_bytes`](Self::read_bytes) 遇到 `Error::IntegrityCheckFailed`
+    /// （解密结果与写入时记录的校验值不一致，说明分片或代码段已被修改）时，先调用
+    /// [`shred`](Self::shred) 用随机数据覆盖全部分片，再返回该错误，使攻击者无法
+    /// 在篡改失败后继续尝试。其他错误（如文件无法读取、格式不匹配）不会触发销毁。
+    /// 分片在文件中原地覆盖，旁路文件先覆盖再删除，磁盘上不会留下旧的密文；
+    /// 销毁失败时仍返回 `Error::IntegrityCheckFailed`，失败原因附加在错误说明中。
+    ///
+    /// 这会让密钥不可恢复，只建议在能够重新下发密钥的高安全部署中开启
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?.with_wipe_on_tamper(true);
+    /// let key = store.read_bytes()?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_wipe_on_tamper(mut self, enabled: bool) -> Self {
+        self.wipe_on_tamper = enabled;
+        self
+    }
+
+    /// 设置写入时是否遮盖头部中的密钥长度（默认关闭）
+    ///
+    /// `.key_meta` 头部的前8字节默认以明文小端序记录密钥长度，直接暴露了密钥有多长。
+    /// 开启后，每次写入生成新的nonce并记录在元数据中，长度与由 .text 段哈希和nonce
+    /// 派生的掩码异或后再写入头部；读取时自动还原，无需再次设置。还原出的长度超出容量时
+    /// （头部损坏或代码段被修改）返回 `Error::IntegrityCheckFailed`
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_obfuscated_length(true);
+    /// store.update("my-secret-key")?;
+    /// assert_eq!(store.stored_len()?, 13);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_obfuscated_length(mut self, enabled: bool) -> Self {
+        self.obfuscate_length = enabled;
+        self
+    }
+
+    /// 设置写入时是否把每个分片绑定到 .text 段（默认关闭）
+    ///
+    /// 开启后，每次写入为每个分片计算4字节的绑定标签：以 .text 段的哈希为密钥，
+    /// 对分片序号和密文计算HMAC后截断，记录在元数据中。读取时先校验标签，从另一个
+    /// 二进制（.text 段不同）复制过来的分片会被明确识别，返回指出该分片的
+    /// `Error::IntegrityCheckFailed`，而不只是解密出错误的数据。标签记录在元数据中，
+    /// 读取时自动校验，无需再次设置
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_section_data_digest(true);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_section_data_digest(mut self, enabled: bool) -> Self {
+        self.section_data_digest = enabled;
+        self
+    }
+
+    /// 设置写入时是否同时刷新未使用的shard section（默认关闭）
+    ///
+    /// 开启后，每次写入密钥都会用CSPRNG生成的随机数据覆盖当前布局没有使用的
+    /// `.key_data_xx` section，使它们与真正的分片一样随每次写入而变化，静态分析或
+    /// 比较两个版本的二进制时难以区分哪些section存储了密钥。这些诱饵section不被元数据引用，
+    /// 读取时不会用到
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_decoy_writes(true);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_decoy_writes(mut self, enabled: bool) -> Self {
+        self.decoy_writes = enabled;
+        self
+    }
+
+    /// 设置 [`read_bytes_checked`](Self::read_bytes_checked) 是否检查解密结果（默认关闭）
+    ///
+    /// 开启后，解密出的数据开头有大量零字节等常见的损坏迹象会以 [`SanityWarning`](crate::SanityWarning) 的形式
+    /// 随数据一起返回，不影响 [`read_bytes`](Self::read_bytes)
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    pub fn with_sanity_warnings(mut self, enabled: bool) -> Self {
+        self.sanity_warnings = enabled;
+        self
+    }
+
+    /// 设置每次写入时是否重新打乱分片的逻辑顺序（默认关闭）
+    ///
+    /// 使用哪些section在二进制首次写入时随机决定，之后保持不变；默认情况下密钥的第几段
+    /// 存放在哪个section也不变。开启后每次写入都会重新随机决定逻辑顺序并记录在元数据中，
+    /// 同一个二进制在两次写入相同密钥后，各段数据也位于不同的section。读取时按元数据中的
+    /// 记录还原，无需额外设置
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_remap_on_write(true);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_remap_on_write(mut self, enabled: bool) -> Self {
+        self.remap_on_write = enabled;
+        self
+    }
+
+    /// 设置写入时是否只改动明文有变化的分片（默认关闭）
+    ///
+    /// 默认每次写入都为所有分片生成新的nonce，即使明文相同，全部分片的密文也会改变。
+    /// 开启后，明文（以及派生参数）未变的分片沿用之前的nonce，密文与磁盘上完全相同，
+    /// 只有变化的分片获得新的nonce。例如只修改大块数据的末尾时，前面分片的字节保持不变，
+    /// 减少磁盘写入差异和杀毒软件的告警。代价是相同的分片内容在多次写入之间可以被识别出来。
+    /// 二进制仍然整体原子替换，元数据每次都会更新
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_partial_writes(true);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_partial_writes(mut self, enabled: bool) -> Self {
+        self.partial_writes = enabled;
+        self
+    }
+
+    /// 设置写入前是否拒绝在调试器下运行（默认关闭）
+    ///
+    /// 开启后，写入二进制前会读取 `/proc/self/status` 中的 `TracerPid`，进程正被
+    /// ptrace跟踪（如gdb、strace）时返回 `Error::Config`，不做任何修改。
+    /// 这只是尽力而为的加固措施：仅在Linux上生效，且可以被有经验的攻击者绕过
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否开启检查
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?.with_anti_debug(true);
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_anti_debug(mut self, enabled: bool) -> Self {
+        self.anti_debug = enabled;
+        self
+    }
+
+    /// 打开后立即确认二进制的代码段与写入密钥时一致（严格绑定）
+    ///
+    /// 每次写入时元数据会记录派生sections（默认为 `.text` 段）的校验值。代码段被修改
+    /// （例如为绕过许可证检查打了补丁）后密钥将无法解密，默认要到第一次读取时才会发现；
+    /// 开启后在构造时就比对校验值，不一致时返回 `Error::Crypto("binary tampered…")`。
+    /// 尚未写入密钥时没有需要比对的内容，直接通过；旧版本写入、没有记录校验值时返回
+    /// `Error::Config`，重新写入一次密钥即可
+    ///
+    /// # 参数
+    ///
+    /// * `enabled` - 是否进行检查
+    ///
+    /// # 返回
+    ///
+    /// 检查通过（或未开启）返回实例，否则返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?.with_strict_binding(true)?;
+    /// let license = store.read()?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn with_strict_binding(self, enabled: bool) -> Result<Self> {
+        if enabled {
+            self.verify_binding()?;
+        }
+        Ok(self)
+    }
+
+    /// 比对二进制中记录的绑定校验值与当前派生sections
+    fn verify_binding(&self) -> Result<()> {
+        let binary_data = self.read_binary()?;
+        let metadata = match Self::read_metadata(&binary_data, &self.meta_section) {
+            Ok(metadata) if metadata.initialized => metadata,
+            _ => return Ok(()),
+        };
+        if metadata.binding_check.is_empty() {
+            return Err(Error::Config(
+                "二进制中没有记录绑定校验值，请重新写入密钥后再启用严格绑定".to_string(),
+            ));
+        }
+
+        let sections = SectionMap::parse(&binary_data)?;
+        let mut hash = Self::derive_sections_hash(&sections, &metadata)?;
+        let valid = hmac_sha256_verify(&hash, Self::BINDING_CHECK_LABEL, &metadata.binding_check);
+        zeroize(&mut hash);
+        if !valid {
+            return Err(Error::Crypto(
+                "binary tampered: 派生sections（默认为 .text 段）与写入密钥时不一致".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 列出二进制中所有与密钥存储相关的section及其状态
+    ///
+    /// 包括 `.key_meta` 和二进制中存在的全部 `.key_data_xx`，可用于确认
+    /// `init_key_storage!` 生成了预期的sections，以及查看哪些分片正在使用
+    ///
+    /// # 返回
+    ///
+    /// 成功返回section状态列表（元数据section在前），失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// for section in store.key_sections()? {
+    ///     println!("{} {}B active={}", section.name, section.size, section.active);
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn key_sections(&self) -> Result<Vec<SectionStatus>> {
+        let binary_data = self.read_binary()?;
+
+        let mut sections = Vec::new();
+        for name in self.storage_sections() {
+            let (offset, size) = match Self::find_section(&binary_data, &name) {
+                Ok(range) => range,
+                Err(Error::SectionNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+
+            let active = name == self.meta_section || self.metadata.shard_names.contains(&name);
+            sections.push(SectionStatus {
+                name: name.to_string(),
+                size,
+                active,
+                zeroed: binary_data[offset..offset + size].iter().all(|&b| b == 0),
+            });
+        }
+
+        Ok(sections)
+    }
+
+    /// 计算 `.key_meta` 和全部 `.key_data_xx` 当前内容的CRC32
+    ///
+    /// 供外部监控在两次轮询之间比较，确定具体是哪个section发生了变化。
+    /// CRC32只用于诊断，不能防篡改；二进制中不存在的分片section会被跳过
+    ///
+    /// # 返回
+    ///
+    /// 成功返回section名称到CRC32的映射，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// for (name, crc) in store.section_checksums()? {
+    ///     println!("{name} {crc:08x}");
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn section_checksums(&self) -> Result<BTreeMap<String, u32>> {
+        let binary_data = self.read_binary()?;
+
+        let mut checksums = BTreeMap::new();
+        for name in self.storage_sections() {
+            let (offset, size) = match Self::find_section(&binary_data, &name) {
+                Ok(range) => range,
+                Err(Error::SectionNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let crc = crc32fast::hash(&binary_data[offset..offset + size]);
+            checksums.insert(name, crc);
+        }
+
+        Ok(checksums)
+    }
+
+    /// 检查分片与元数据是否一致（不解密）
+    ///
+    /// 手工修改二进制或写入中断后，分片和元数据可能不再对应。该检查只做廉价的结构校验：
+    /// 元数据能否解析且有效、记录的密钥长度不超过容量、nonce数量与分片数量一致、
+    /// 每个活动分片的section存在且大小足够，以及写入过密钥时活动分片不是全零
+    ///
+    /// # 返回
+    ///
+    /// 一致（或尚未写入元数据）返回 `Ok(true)`，不一致返回 `Ok(false)`，读取文件失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// if !store.binding_consistent()? {
+    ///     eprintln!("分片与元数据不一致，请重新写入密钥");
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn binding_consistent(&self) -> Result<bool> {
+        let binary_data = self.read_binary()?;
+
+        // 元数据section全为零：刚编译出来或reset之后，没有需要对应的内容
+        let (meta_offset, meta_size) = Self::find_section(&binary_data, &self.meta_section)?;
+        if binary_data[meta_offset..meta_offset + meta_size]
+            .iter()
+            .all(|&b| b == 0)
+        {
+            return Ok(true);
+        }
+
+        let metadata = match Self::read_metadata(&binary_data, &self.meta_section) {
+            Ok(metadata) if metadata.validate().is_ok() => metadata,
+            _ => return Ok(false),
+        };
+
+        if Self::stored_len_in(&binary_data, &self.meta_section)? > metadata.total_capacity() {
+            return Ok(false);
+        }
+        if !metadata.shard_nonces.is_empty() && metadata.shard_nonces.len() != metadata.num_shards {
+            return Ok(false);
+        }
+
+        for (name, &size) in metadata.shard_names.iter().zip(&metadata.shard_sizes) {
+            let (offset, used) = match Self::find_shard_section(&binary_data, name, size) {
+                Ok(range) => range,
+                Err(Error::SectionNotFound(_) | Error::SizeMismatch { .. } | Error::Config(_)) => {
+                    return Ok(false)
+                }
+                Err(e) => return Err(e),
+            };
+
+            // 加密后的分片（包括填充部分）几乎不可能全为零
+            if metadata.initialized && binary_data[offset..offset + used].iter().all(|&b| b == 0) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// 列出适合作为派生来源的section，供选择 [`with_derive_sections`](Self::with_derive_sections) 参考
+    ///
+    /// 只包含在文件中有内容、且不会被密钥写入修改的section。每项附带一个0到1之间的
+    /// 稳定性评分：只读数据（如 `.rodata`）最高，代码段次之（加固构建中可能被拆分或重定位），
+    /// 其他section最低。这只是启发式评估，实际稳定性需要对比多次构建确认
+    ///
+    /// # 返回
+    ///
+    /// 成功返回 `(section名称, 稳定性评分)` 列表，按section大小降序排列
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// for (name, score) in store.suggest_derive_section()? {
+    ///     println!("{}: {:.1}", name, score);
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn suggest_derive_section(&self) -> Result<Vec<(String, f32)>> {
+        use object::SectionKind;
+
+        let binary_data = self.read_binary()?;
+        let obj_file = object::File::parse(binary_data.as_slice())
+            .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
+
+        let mut candidates: Vec<(String, u64, f32)> = Vec::new();
+        for section in obj_file.sections() {
+            let name = match section.name() {
+                Ok(name)
+                    if !name.is_empty()
+                        && !name.starts_with(".key_")
+                        && !self.is_storage_section(name) =>
+                {
+                    name
+                }
+                _ => continue,
+            };
+            let size = match section.file_range() {
+                Some((_, size)) if size > 0 => size,
+                _ => continue,
+            };
+
+            let score = match section.kind() {
+                SectionKind::ReadOnlyData | SectionKind::ReadOnlyString => 0.9,
+                SectionKind::Text => 0.6,
+                SectionKind::Data | SectionKind::UninitializedData => continue,
+                _ => 0.3,
+            };
+            candidates.push((name.to_string(), size, score));
+        }
+
+        candidates.sort_by_key(|c| std::cmp::Reverse(c.1));
+        Ok(candidates
+            .into_iter()
+            .map(|(name, _, score)| (name, score))
+            .collect())
+    }
+
+    /// 将密钥转换为实际写入分片的数据，并记录是否压缩
+    fn encode_payload(metadata: &mut KeyMetadata, new_key: &[u8]) -> Result<Vec<u8>> {
+        metadata.compressed = false;
+        metadata.dictionary_id.clear();
+
+        #[cfg(feature = "compression")]
+        if new_key.len() >= metadata.compression_threshold {
+            let (compressed, dictionary_id) = match &metadata.compression_dictionary {
+                #[cfg(feature = "zstd")]
+                Some(dictionary) if metadata.dictionary_codec == CompressionCodec::Zstd => (
+                    crate::compression::zstd_compress(new_key, dictionary)?,
+                    crate::compression::zstd_dictionary_id(dictionary),
+                ),
+                Some(dictionary) => (
+                    crate::compression::compress_with_dictionary(new_key, dictionary)?,
+                    crate::compression::dictionary_id(dictionary),
+                ),
+                None => (crate::compression::compress(new_key)?, String::new()),
+            };
+            if compressed.len() < new_key.len() {
+                metadata.compressed = true;
+                metadata.dictionary_id = dictionary_id;
+                return Ok(compressed);
+            }
+        }
+
+        Ok(new_key.to_vec())
+    }
+
+    /// 生成写入新密钥后的完整二进制数据和对应的元数据（不写回磁盘）
+    ///
+    /// 新的元数据在副本上构建，本实例的元数据保持不变，由调用方在写入成功后再采用
+    pub(crate) fn prepare_update(&self, new_key: &[u8]) -> Result<(Vec<u8>, KeyMetadata)> {
+        self.prepare_update_in(&self.metadata, new_key)
+    }
+
+    /// 与 [`prepare_update`](Self::prepare_update) 相同，但新的元数据以 `layout` 为基础构建
+    fn prepare_update_in(
+        &self,
+        layout: &KeyMetadata,
+        new_key: &[u8],
+    ) -> Result<(Vec<u8>, KeyMetadata)> {
+        if self.anti_debug {
+            Self::check_not_traced()?;
+        }
+        self.check_limits()?;
+        if layout.interleave_stride.0 == 0 {
+            return Err(Error::Config("交错步长不能为0".to_string()));
+        }
+        // 派生sections在每次写入时都会变化，之前写入的密钥将无法再解密
+        if let Some(name) = layout
+            .derive_sections
+            .iter()
+            .find(|name| self.is_storage_section(name))
+        {
+            return Err(Error::Config(format!(
+                "不能使用存放密钥的section {} 派生加密密钥",
+                name
+            )));
+        }
+
+        // 读取二进制文件
+        let mut binary_data = self.read_binary()?;
+        self.check_format()?;
+        let mut metadata = layout.clone();
+        metadata.write_generation = Self::generation_in(&binary_data, &self.meta_section) + 1;
+        if self.remap_on_write {
+            metadata.reshuffle_logical_order();
+        }
+
+        // 保留历史版本时与之前的值一起打包
+        let mut framed = match metadata.history {
+            0 => None,
+            _ => Some(self.pack_with_history(&binary_data, new_key)?),
+        };
+
+        // 按需压缩，压缩决定记录在元数据中
+        let encoded = Self::encode_payload(&mut metadata, framed.as_deref().unwrap_or(new_key));
+        if let Some(framed) = framed.as_mut() {
+            zeroize(framed);
+        }
+        let payload = encoded?;
+        if metadata.history > 0 && payload.len() > metadata.total_capacity() {
+            return Err(Error::Config(format!(
+                "密钥连同{}个历史版本共需{}字节，超出总容量({})，请减少保留的历史版本数量",
+                metadata.history,
+                payload.len(),
+                metadata.total_capacity()
+            )));
+        }
+        let payload = metadata.padding.apply(payload, metadata.total_capacity())?;
+        metadata.initialized = true;
+        metadata.pipeline = KeyMetadata::pipeline_for(metadata.compressed);
+
+        // 每次写入为每个分片生成新的nonce，相同的密钥也会得到不同的密文
+        metadata.shard_nonces = (0..metadata.num_shards).map(|_| rand::random()).collect();
+
+        // 设置了外部因子时每次写入使用新的盐，并记录校验值
+        metadata.factor_check.clear();
+        if metadata.external_factor.is_some() {
+            metadata.factor_salt = KeyStore::generate_random_bytes(16);
+        } else {
+            metadata.factor_salt.clear();
+        }
+        if self.partial_writes {
+            self.reuse_unchanged_nonces(&mut metadata, &binary_data, &payload)?;
+        }
+        let sections = SectionMap::parse(&binary_data)?;
+        let base_key = Self::derive_base_key(&sections, &metadata)?;
+        metadata.binding_check = Self::binding_check(&sections, &metadata)?;
+        if metadata.external_factor.is_some() {
+            metadata.factor_check = Self::factor_check(&base_key);
+        }
+        metadata.payload_check = Self::payload_check(&base_key, &payload);
+        metadata.constants_fingerprint = constants_fingerprint();
+        metadata.length_nonce = if self.obfuscate_length {
+            KeyStore::generate_random_bytes(8)
+        } else {
+            Vec::new()
+        };
+
+        // 加密并写入各分片
+        Self::encode_shards(&mut binary_data, &metadata, &payload)?;
+        if self.decoy_writes {
+            Self::randomize_shards(
+                &mut binary_data,
+                &self.shard_universe(),
+                &metadata.shard_names,
+            )?;
+        }
+
+        // 绑定标签覆盖分片密文，需要在分片写入之后计算
+        metadata.shard_tags = if self.section_data_digest {
+            Self::shard_tags(&SectionMap::parse(&binary_data)?, &metadata)?
+        } else {
+            Vec::new()
+        };
+
+        // 写入元数据JSON（首次使用时初始化，之后每次更新写入的状态）
+        self.write_metadata_to_binary(&mut binary_data, &metadata)?;
+
+        // 更新元数据头部中的实际密钥长度
+        let (meta_offset, _) = Self::find_section(&binary_data, &self.meta_section)?;
+        let key_len_bytes = Self::encode_length(&binary_data, &metadata, payload.len())?;
+        binary_data[meta_offset..meta_offset + key_len_bytes.len()].copy_from_slice(&key_len_bytes);
+
+        Ok((binary_data, metadata))
+    }
+
+    /// 为明文未变的分片沿用之前的nonce（以及外部因子的盐），使其密文与磁盘上完全相同
+    ///
+    /// 先用磁盘上记录的nonce试加密一遍：某个分片的试加密结果与现有密文相同，说明明文和
+    /// 派生参数都没有变化，沿用nonce不会泄露任何信息；其余分片保留新生成的nonce
+    fn reuse_unchanged_nonces(
+        &self,
+        metadata: &mut KeyMetadata,
+        binary_data: &[u8],
+        payload: &[u8],
+    ) -> Result<()> {
+        let previous = match Self::read_metadata(binary_data, &self.meta_section) {
+            Ok(previous)
+                if previous.initialized && previous.shard_nonces.len() == metadata.num_shards =>
+            {
+                previous
+            }
+            _ => return Ok(()),
+        };
+
+        let mut trial_metadata = metadata.clone();
+        trial_metadata.shard_nonces = previous.shard_nonces.clone();
+        if metadata.external_factor.is_some() && !previous.factor_salt.is_empty() {
+            trial_metadata.factor_salt = previous.factor_salt.clone();
+        }
+
+        let mut trial = binary_data.to_vec();
+        let encoded = Self::encode_shards(&mut trial, &trial_metadata, payload);
+        let unchanged = encoded.and_then(|_| {
+            metadata
+                .shard_names
+                .iter()
+                .zip(&metadata.shard_sizes)
+                .map(|(name, &size)| {
+                    let (offset, used) = Self::find_shard_section(binary_data, name, size)?;
+                    Ok(trial[offset..offset + used] == binary_data[offset..offset + used])
+                })
+                .collect::<Result<Vec<bool>>>()
+        });
+        zeroize(&mut trial);
+        let unchanged = match unchanged {
+            Ok(unchanged) => unchanged,
+            // 外部因子不匹配等原因无法试加密时，全部使用新的nonce
+            Err(_) => return Ok(()),
+        };
+
+        if unchanged.iter().any(|&same| same) {
+            metadata.factor_salt = trial_metadata.factor_salt;
+            for (i, same) in unchanged.into_iter().enumerate() {
+                if same {
+                    metadata.shard_nonces[i] = previous.shard_nonces[i];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 将数据填充到总容量后按元数据分片加密，写入对应的shard section
+    pub(crate) fn encode_shards(
+        binary_data: &mut [u8],
+        metadata: &KeyMetadata,
+        payload: &[u8],
+    ) -> Result<()> {
+        let total_capacity = metadata.total_capacity();
+
+        // 检查密钥长度是否超出容量
+        if payload.len() > total_capacity {
+            return Err(Error::Config(format!(
+                "密钥长度({})超出总容量({}), 请考虑重新编译以增加容量",
+                payload.len(),
+                total_capacity
+            )));
+        }
+
+        // 如果密钥长度小于总容量，填充零字节
+        let mut padded_key = payload.to_vec();
+        padded_key.resize(total_capacity, 0);
+
+        // 只解析一次二进制：从.text段（或配置的派生sections）派生基础密钥，所有分片共用，
+        // 并找到各分片的section
+        let (base_key, ranges) = {
+            let sections = SectionMap::parse(binary_data)?;
+            let base_key = Self::derive_base_key(&sections, metadata)?;
+            let ranges = metadata
+                .shard_names
+                .iter()
+                .zip(&metadata.shard_sizes)
+                .map(|(name, &size)| Self::shard_range(&sections, name, size))
+                .collect::<Result<Vec<_>>>()?;
+            (base_key, ranges)
+        };
+
+        // 按布局分片并加密
+        let shards = metadata.split_payload(&padded_key);
+        for (i, shard_data) in shards.iter().enumerate() {
+            let (section_offset, shard_size) = ranges[i];
+
+            // 混入本分片的nonce
+            let derive_key = Self::derive_shard_key(&base_key, metadata, i);
+
+            // 加密：混淆 -> 异或
+            let encrypted = encrypt_shard(shard_data, &derive_key, Self::shard_seed(metadata, i));
+
+            // 写入二进制数据
+            binary_data[section_offset..section_offset + shard_size].copy_from_slice(&encrypted);
+        }
+
+        Ok(())
+    }
+
+    /// 更新密钥（字符串版本）
+    ///
+    /// 便捷方法，用于更新字符串类型的密钥
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 新的密钥字符串
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.update("my-secret-key")?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update(&mut self, new_key: &str) -> Result<()> {
+        self.update_bytes(new_key.as_bytes())
+    }
+
+    /// 从文件读取密钥并写入
+    ///
+    /// 读取前先按文件大小检查容量，超出总容量的文件不会被读入内存（即使开启压缩后
+    /// 可能放得下）。读取的内容在写入后清零
+    ///
+    /// # 参数
+    ///
+    /// * `path` - 密钥文件路径
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；文件超出总容量或读写失败时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// # use std::path::Path;
+    /// let mut store = KeyStore::new()?;
+    /// store.update_from_file(Path::new("/run/secrets/api-key"))?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update_from_file(&mut self, path: &Path) -> Result<()> {
+        let len = fs::metadata(path).map_err(Error::io_at(path))?.len();
+        if len > self.capacity() as u64 {
+            return Err(Error::Config(format!(
+                "密钥文件长度({})超出总容量({})",
+                len,
+                self.capacity()
+            )));
+        }
+
+        let mut new_key = fs::read(path).map_err(Error::io_at(path))?;
+        let result = self.update_bytes(&new_key);
+        zeroize(&mut new_key);
+        result
+    }
+
+    /// 读取当前密钥（bytes版本）
+    ///
+    /// 从二进制文件中读取并解密密钥，返回原始bytes
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let key_bytes = store.read_bytes()?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_bytes(&self) -> Result<Vec<u8>> {
+        self.check_limits()?;
+        self.check_format()?;
+        self.check_kdf_iterations()?;
+        let result = match &self.pending {
+            Some(binary_data) => Self::decode_key(
+                binary_data,
+                &self.meta_section,
+                &self.metadata,
+                self.constant_time,
+            ),
+            None => Self::load_key(
+                &self.exe_path,
+                &self.meta_section,
+                &self.metadata,
+                self.constant_time,
+            ),
+        };
+
+        self.wipe_if_tampered(result)
+    }
+
+    /// 读取当前密钥，文件读取超过 `timeout` 时放弃等待
+    ///
+    /// 二进制位于NFS等网络文件系统上时，挂载异常可能使读取无限期阻塞。此方法在辅助线程中
+    /// 读取二进制和旁路文件，超时后返回 `ErrorKind::TimedOut` 的 `Error::Io`，调用方不会被卡住。
+    /// 超时只限制文件读取，不包括之后的解密；超时后辅助线程会在读取结束时清零读到的内容并退出。
+    /// 仍在阻塞的辅助线程达到 [`MAX_TIMEOUT_READERS`](Self::MAX_TIMEOUT_READERS) 个时，
+    /// 直接返回超时错误而不再创建新线程
+    ///
+    /// # 参数
+    ///
+    /// * `timeout` - 等待文件读取完成的最长时间
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes；读取超时返回 `Error::Io`，其他失败返回对应的Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// # use std::time::Duration;
+    /// let store = KeyStore::new()?;
+    /// let key_bytes = store.read_bytes_timeout(Duration::from_secs(5))?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_bytes_timeout(&self, timeout: Duration) -> Result<Vec<u8>> {
+        if self.pending.is_some() {
+            return self.read_bytes();
+        }
+        self.check_limits()?;
+        self.check_format()?;
+        self.check_kdf_iterations()?;
+
+        // 尚未退出的辅助线程数量（包括超时后仍阻塞在读取中的线程）
+        static READERS: AtomicUsize = AtomicUsize::new(0);
+        let reserved = READERS.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+            (n < Self::MAX_TIMEOUT_READERS).then_some(n + 1)
+        });
+        if reserved.is_err() {
+            return Err(Error::Io(std::io::Error::new(
+                ErrorKind::TimedOut,
+                format!(
+                    "读取 {} 超时：已有{}个读取线程阻塞未返回",
+                    self.exe_path.display(),
+                    Self::MAX_TIMEOUT_READERS
+                ),
+            )));
+        }
+
+        let exe_path = self.exe_path.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let files = fs::read(&exe_path)
+                .map_err(Error::io_at(&exe_path))
+                .and_then(|binary_data| Ok((sidecar::read_content(&exe_path)?, binary_data)));
+            // 调用方已超时返回时，读到的内容不会再被使用
+            if let Err(mpsc::SendError(files)) = sender.send(files) {
+                Self::discard_files(files);
+            }
+            READERS.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        let (sidecar_content, binary_data) = match receiver.recv_timeout(timeout) {
+            Ok(files) => files?,
+            Err(_) => {
+                // 辅助线程可能恰好在超时之后发送了结果
+                if let Ok(files) = receiver.try_recv() {
+                    Self::discard_files(files);
+                }
+                return Err(Error::Io(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!("读取 {} 超时 ({:?})", self.exe_path.display(), timeout),
+                )));
+            }
+        };
+
+        let result = match sidecar_content {
+            Some(content) => Self::decode_sidecar(&content, &binary_data, &self.metadata),
+            None => Self::decode_key(
+                &binary_data,
+                &self.meta_section,
+                &self.metadata,
+                self.constant_time,
+            ),
+        };
+        self.wipe_if_tampered(result)
+    }
+
+    /// 清零辅助线程读到但不再使用的旁路文件和二进制内容
+    fn discard_files(files: Result<(Option<Vec<u8>>, Vec<u8>)>) {
+        if let Ok((sidecar_content, mut binary_data)) = files {
+            zeroize(&mut binary_data);
+            if let Some(mut content) = sidecar_content {
+                zeroize(&mut content);
+            }
+        }
+    }
+
+    /// 读取结果为完整性校验失败且开启了 `wipe_on_tamper` 时销毁分片
+    ///
+    /// 总是返回原来的读取结果；销毁本身失败时，失败原因附加在完整性校验错误的说明中
+    fn wipe_if_tampered(&self, result: Result<Vec<u8>>) -> Result<Vec<u8>> {
+        // 内存实例只持有不可变引用时无法修改内容
+        if !self.wipe_on_tamper || self.in_memory {
+            return result;
+        }
+
+        match result {
+            Err(Error::IntegrityCheckFailed(message)) => match self.wipe_shards() {
+                Ok(()) => Err(Error::IntegrityCheckFailed(message)),
+                Err(e) => Err(Error::IntegrityCheckFailed(format!(
+                    "{}（销毁分片失败: {}）",
+                    message, e
+                ))),
+            },
+            result => result,
+        }
+    }
+
+    /// 在文件中原地用随机数据覆盖全部分片，并销毁旁路文件
+    ///
+    /// 不使用临时文件 + rename：替换文件只会解除原inode的链接，其中的密文仍留在磁盘上。
+    /// 文件标识保持不变
+    fn wipe_shards(&self) -> Result<()> {
+        let binary_data = fs::read(&self.exe_path).map_err(Error::io_at(&self.exe_path))?;
+        let ranges = Self::shard_ranges(&binary_data, &self.shard_universe())?;
+        Self::overwrite_in_place(&self.exe_path, &ranges)?;
+        self.wipe_sidecar()
+    }
+
+    /// 原地用随机数据覆盖旁路文件后删除，旁路文件不存在时什么也不做
+    fn wipe_sidecar(&self) -> Result<()> {
+        let sidecar_path = sidecar::sidecar_path(&self.exe_path);
+        match fs::metadata(&sidecar_path) {
+            Ok(meta) => {
+                let whole = 0..meta.len() as usize;
+                Self::overwrite_in_place(&sidecar_path, std::slice::from_ref(&whole))?;
+                sidecar::remove(&self.exe_path)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::io_at(&sidecar_path)(e)),
+        }
+    }
+
+    /// 用随机数据原地覆盖文件中的指定范围，完成后 `sync_data`
+    pub(crate) fn overwrite_in_place(path: &Path, ranges: &[Range<usize>]) -> Result<()> {
+        use rand::RngCore;
+        let mut rng = rand::thread_rng();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(Error::io_at(path))?;
+
+        for range in ranges {
+            let mut noise = vec![0u8; range.len()];
+            rng.fill_bytes(&mut noise);
+            file.seek(SeekFrom::Start(range.start as u64))
+                .and_then(|_| file.write_all(&noise))
+                .map_err(Error::io_at(path))?;
+        }
+        file.sync_data().map_err(Error::io_at(path))
+    }
+
+    /// 读取密钥，并给出每段字节来自哪个shard section
+    ///
+    /// 用于排查部分损坏：解密结果中某个字节异常时，可以据此定位到具体的section。
+    /// 只读取二进制中的分片，不使用旁路文件
+    ///
+    /// # 返回
+    ///
+    /// 成功返回 `(密钥, [(section名称, 密钥中的字节范围)])`，范围首尾相接，
+    /// 合起来恰好覆盖整个密钥（交错布局下每个范围只有1字节）；密钥以压缩形式存储时无法对应到分片，返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let (key, provenance) = store.read_with_provenance()?;
+    /// for (section, range) in provenance {
+    ///     println!("{}: {:?}", section, range);
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_with_provenance(&self) -> Result<(Vec<u8>, Provenance)> {
+        self.check_format()?;
+        self.check_kdf_iterations()?;
+
+        let binary_data = self.read_binary()?;
+        let on_disk =
+            Self::read_metadata_with_factor(&binary_data, &self.meta_section, &self.metadata);
+        let metadata = on_disk.as_ref().unwrap_or(&self.metadata);
+        if metadata.compressed {
+            return Err(Error::Config(
+                "密钥以压缩形式存储，无法对应到分片".to_string(),
+            ));
+        }
+
+        let key = Self::decode_payload(&binary_data, &self.meta_section, metadata, false)?;
+
+        // 相邻且来自同一分片的字节合并为一个范围
+        let mut provenance: Provenance = Vec::new();
+        for (j, index) in metadata.byte_order(key.len()).into_iter().enumerate() {
+            let name = &metadata.shard_names[index];
+            match provenance.last_mut() {
+                Some((last, range)) if last == name && range.end == j => range.end = j + 1,
+                _ => provenance.push((name.clone(), j..j + 1)),
+            }
+        }
+
+        Ok((key, provenance))
+    }
+
+    /// 读取密钥，未写入过密钥时返回None
+    ///
+    /// 与 [`read_bytes`](Self::read_bytes) 不同，可以区分未写入过密钥（`None`）
+    /// 和显式写入的空密钥（`Some(vec![])`）
+    ///
+    /// # 返回
+    ///
+    /// 写入过密钥时返回 `Ok(Some(key))`，未写入过返回 `Ok(None)`，读取失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// match store.try_read_bytes()? {
+    ///     Some(key) => println!("密钥长度: {}", key.len()),
+    ///     None => println!("尚未配置密钥"),
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn try_read_bytes(&self) -> Result<Option<Vec<u8>>> {
+        if !self.is_initialized()? {
+            return Ok(None);
+        }
+
+        self.read_bytes().map(Some)
+    }
+
+    /// 读取指定二进制的密钥，存在旁路文件时优先使用旁路文件
+    pub(crate) fn load_key(
+        exe_path: &Path,
+        meta_section: &str,
+        fallback: &KeyMetadata,
+        constant_time: bool,
+    ) -> Result<Vec<u8>> {
+        let binary_data = fs::read(exe_path).map_err(Error::io_at(exe_path))?;
+        match sidecar::read_content(exe_path)? {
+            Some(content) => Self::decode_sidecar(&content, &binary_data, fallback),
+            None => Self::decode_key(&binary_data, meta_section, fallback, constant_time),
+        }
+    }
+
+    /// 读取指定二进制存储的密钥长度，存在旁路文件时优先使用旁路文件
+    pub(crate) fn load_stored_len(exe_path: &Path, meta_section: &str) -> Result<usize> {
+        match sidecar::stored_len(exe_path)? {
+            Some(len) => Ok(len),
+            None => Self::stored_len_in(
+                &fs::read(exe_path).map_err(Error::io_at(exe_path))?,
+                meta_section,
+            ),
+        }
+    }
+
+    /// 校验存储的密钥是否与期望值一致
+    ///
+    /// 解密后以恒定时间与 `expected` 比较，只返回比较结果，解密出的副本在返回前清零。
+    /// 用于部署验证：确认二进制中存储的正是预期的密钥，而不必打印或返回明文
+    ///
+    /// # 参数
+    ///
+    /// * `expected` - 期望的密钥
+    ///
+    /// # 返回
+    ///
+    /// 一致返回 `Ok(true)`，不一致返回 `Ok(false)`，读取失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// assert!(store.verify_against(b"my-secret-key")?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn verify_against(&self, expected: &[u8]) -> Result<bool> {
+        let mut stored = self.read_bytes()?;
+        let matches = constant_time_eq(&stored, expected);
+        zeroize(&mut stored);
+        Ok(matches)
+    }
+
+    /// 计算当前存储密钥的指纹，用于审计日志
+    ///
+    /// 指纹为以二进制 .text 段哈希为密钥、对密钥明文计算的HMAC-SHA256（十六进制），
+    /// 不可逆推出密钥，且同一密钥在不同 .text 的二进制中指纹不同。可用于记录密钥
+    /// 何时发生变化，而不在日志中暴露密钥本身
+    ///
+    /// # 返回
+    ///
+    /// 成功返回64个字符的十六进制指纹；未写入过密钥时返回空字符串
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// println!("当前密钥指纹: {}", store.stored_key_fingerprint()?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn stored_key_fingerprint(&self) -> Result<String> {
+        let mut key = match self.try_read_bytes()? {
+            Some(key) => key,
+            None => return Ok(String::new()),
+        };
+
+        let fingerprint = self.key_fingerprint(&key);
+        zeroize(&mut key);
+        fingerprint
+    }
+
+    /// 计算给定密钥在本二进制中的指纹，参见 [`stored_key_fingerprint`](Self::stored_key_fingerprint)
+    pub(crate) fn key_fingerprint(&self, key: &[u8]) -> Result<String> {
+        let binary_data = self.read_binary()?;
+        let mut text_hash =
+            derive_key_from_section(&parse_binary(&binary_data)?, Self::DERIVE_SECTION, 32)?;
+        let mac = hmac_sha256(&text_hash, key);
+        zeroize(&mut text_hash);
+
+        Ok(mac.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// 获取当前存储的密钥长度（字节）
+    ///
+    /// 通常只读取元数据头部，不解密分片。密钥以压缩形式存储或带有历史版本时，
+    /// 头部记录的是实际写入分片的数据长度，此时解密一次以得到密钥本身的长度
+    /// （解密出的副本立即清零）；只需要头部中的长度时使用 [`stored_payload_len`](Self::stored_payload_len)
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥长度，未写入过密钥时为0
+    pub fn stored_len(&self) -> Result<usize> {
+        let len = self.stored_payload_len()?;
+        let differs = match &self.pending {
+            Some(binary_data) => Self::payload_len_differs(binary_data, &self.meta_section),
+            None => Self::load_payload_len_differs(&self.exe_path, &self.meta_section)?,
+        };
+        if len == 0 || !differs {
+            return Ok(len);
+        }
+        Self::decrypted_len(self.read_bytes())
+    }
+
+    /// 获取实际写入分片的数据长度（字节）
+    ///
+    /// 只读取元数据头部，不解密分片。密钥以压缩形式存储时为压缩后的长度，
+    /// 带有历史版本时包含历史版本，其余情况与 [`stored_len`](Self::stored_len) 相同
+    ///
+    /// # 返回
+    ///
+    /// 成功返回数据长度，未写入过密钥时为0
+    pub fn stored_payload_len(&self) -> Result<usize> {
+        match &self.pending {
+            Some(binary_data) => Self::stored_len_in(binary_data, &self.meta_section),
+            None => Self::load_stored_len(&self.exe_path, &self.meta_section),
+        }
+    }
+
+    /// 头部记录的长度是否不等于密钥本身的长度（以压缩形式存储或带有历史版本）
+    ///
+    /// 自描述填充下头部记录的总是总容量，不视为不同
+    fn payload_len_differs(binary_data: &[u8], meta_section: &str) -> bool {
+        Self::read_metadata(binary_data, meta_section).is_ok_and(|metadata| {
+            metadata.padding != Padding::SelfDescribing
+                && (metadata.compressed || metadata.history > 0)
+        })
+    }
+
+    /// 读取文件判断头部记录的长度是否不等于密钥本身的长度，旁路文件记录的总是密钥本身的长度
+    pub(crate) fn load_payload_len_differs(exe_path: &Path, meta_section: &str) -> Result<bool> {
+        if sidecar::stored_len(exe_path)?.is_some() {
+            return Ok(false);
+        }
+        let binary_data = fs::read(exe_path).map_err(Error::io_at(exe_path))?;
+        Ok(Self::payload_len_differs(&binary_data, meta_section))
+    }
+
+    /// 取解密结果的长度，并清零解密出的副本
+    pub(crate) fn decrypted_len(key: Result<Vec<u8>>) -> Result<usize> {
+        let mut key = key?;
+        let len = key.len();
+        zeroize(&mut key);
+        Ok(len)
+    }
+
+    /// 从调用方给出的文件偏移处直接读取密钥长度，不解析ELF
+    ///
+    /// 与 [`stored_payload_len`](Self::stored_payload_len) 相同，但跳过查找元数据section的ELF解析，只读取
+    /// 偏移处的8字节，用于高频轮询长度。偏移应通过 [`on_disk_offsets`](Self::on_disk_offsets)
+    /// 获取一次后复用；二进制被重新链接或替换后偏移可能失效。不读取旁路文件；
+    /// 长度被遮盖（[`with_obfuscated_length`](Self::with_obfuscated_length)）时还原需要代码段的哈希，
+    /// 无法跳过解析，返回Error
+    ///
+    /// # 参数
+    ///
+    /// * `meta_offset` - 元数据section在文件中的起始偏移
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥长度；偏移超出文件范围或读到的值超出总容量时返回 `Error::Parse`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let meta_offset = store.on_disk_offsets()?[".key_meta"].start;
+    /// loop {
+    ///     println!("密钥长度: {}", store.stored_len_at(meta_offset)?);
+    ///     # break;
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn stored_len_at(&self, meta_offset: usize) -> Result<usize> {
+        if !self.metadata.length_nonce.is_empty() {
+            return Err(Error::Config(
+                "密钥长度被遮盖，无法跳过解析直接读取，请使用 stored_len".to_string(),
+            ));
+        }
+
+        let out_of_range = || Error::Parse("元数据头部超出文件范围".to_string());
+        let end = meta_offset.checked_add(8).ok_or_else(out_of_range)?;
+
+        let mut len_bytes = [0u8; 8];
+        match &self.pending {
+            Some(binary_data) => len_bytes
+                .copy_from_slice(binary_data.get(meta_offset..end).ok_or_else(out_of_range)?),
+            None => {
+                use std::io::{Seek, SeekFrom};
+
+                // 偏移超出文件系统支持的范围时seek本身就会失败
+                let mut file = File::open(&self.exe_path).map_err(Error::io_at(&self.exe_path))?;
+                file.seek(SeekFrom::Start(meta_offset as u64))
+                    .and_then(|_| file.read_exact(&mut len_bytes))
+                    .map_err(|e| match e.kind() {
+                        ErrorKind::UnexpectedEof | ErrorKind::InvalidInput => out_of_range(),
+                        _ => Error::Io(e),
+                    })?;
+            }
+        }
+
+        // 偏移不对时读到的多半是无关数据，通常远超总容量
+        let len = u64::from_le_bytes(len_bytes) as usize;
+        if len > self.metadata.total_capacity() {
+            return Err(Error::Parse(format!(
+                "偏移 {} 处读到的密钥长度({})超出总容量({})，偏移可能已失效",
+                meta_offset,
+                len,
+                self.metadata.total_capacity()
+            )));
+        }
+        Ok(len)
+    }
+
+    /// 列出 `.key_meta` 和全部 `.key_data_xx` 在文件中的字节范围
+    ///
+    /// 供 [`stored_len_at`](Self::stored_len_at) 等需要固定偏移的场景获取一次后复用；
+    /// 二进制中不存在的分片section会被跳过
+    ///
+    /// # 返回
+    ///
+    /// 成功返回section名称到文件范围的映射，失败返回Error
+    pub fn on_disk_offsets(&self) -> Result<BTreeMap<String, Range<usize>>> {
+        let binary_data = self.read_binary()?;
+
+        let mut offsets = BTreeMap::new();
+        for name in self.storage_sections() {
+            match Self::find_section(&binary_data, &name) {
+                Ok((offset, size)) => {
+                    offsets.insert(name, offset..offset + size);
+                }
+                Err(Error::SectionNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(offsets)
+    }
+
+    /// 检查是否已经写入过密钥
+    ///
+    /// 未写入过密钥和显式写入空密钥时，[`read_bytes`](Self::read_bytes) 都返回空数据，
+    /// 可以通过此方法区分两者。`reserve` 只写入元数据，不算写入过密钥
+    ///
+    /// # 返回
+    ///
+    /// 写入过密钥（二进制或旁路文件中）返回 `Ok(true)`，否则返回 `Ok(false)`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// if !store.is_initialized()? {
+    ///     println!("尚未配置密钥");
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn is_initialized(&self) -> Result<bool> {
+        if !self.in_memory && sidecar::stored_len(&self.exe_path)?.is_some() {
+            return Ok(true);
+        }
+
+        let binary_data = self.read_binary()?;
+        match Self::read_metadata(&binary_data, &self.meta_section) {
+            // 旧版本元数据没有该标记，存储了非空密钥即视为已写入
+            Ok(metadata) => {
+                Ok(metadata.initialized
+                    || Self::stored_len_in(&binary_data, &self.meta_section)? > 0)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// 计算当前存储内容的校验和
+    ///
+    /// 覆盖 `.key_meta`、全部shard section以及旁路文件（如存在）的原始字节，
+    /// 不解密密钥。任何一次写入都会改变校验和，可用于低成本地检测密钥是否被其他进程更新
+    ///
+    /// # 返回
+    ///
+    /// 成功返回SHA256校验和，失败返回Error
+    pub fn stored_checksum(&self) -> Result<[u8; 32]> {
+        Self::load_checksum(&self.exe_path, &self.storage_sections())
+    }
+
+    /// 计算指定二进制存储内容的校验和，覆盖 `sections` 中存在的section
+    pub(crate) fn load_checksum(exe_path: &Path, sections: &[String]) -> Result<[u8; 32]> {
+        let binary_data = fs::read(exe_path).map_err(Error::io_at(exe_path))?;
+        let mut hasher = Sha256::new();
+
+        for name in sections {
+            match Self::find_section(&binary_data, name) {
+                Ok((offset, size)) => hasher.update(&binary_data[offset..offset + size]),
+                Err(Error::SectionNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if let Some(content) = sidecar::read_content(exe_path)? {
+            hasher.update(&content);
+        }
+
+        Ok(hasher.finalize().into())
+    }
+
+    /// 从二进制数据的元数据头部读取密钥长度
+    pub(crate) fn stored_len_in(binary_data: &[u8], meta_section: &str) -> Result<usize> {
+        // 实际密钥长度位于元数据头部的前8字节
+        let (meta_offset, meta_size) = Self::find_section(binary_data, meta_section)?;
+        let header = binary_data
+            .get(meta_offset..meta_offset + KeyMetadata::HEADER_SIZE)
+            .filter(|_| meta_size >= KeyMetadata::HEADER_SIZE)
+            .ok_or_else(|| Error::Parse("元数据头部超出文件范围".to_string()))?;
+
+        let mut key_len_bytes = [0u8; 8];
+        key_len_bytes.copy_from_slice(&header[..8]);
+
+        // 元数据中记录了nonce时，长度是遮盖后存储的
+        let metadata = match Self::read_metadata(binary_data, meta_section) {
+            Ok(metadata) if !metadata.length_nonce.is_empty() => metadata,
+            _ => return Ok(u64::from_le_bytes(key_len_bytes) as usize),
+        };
+        let mask = Self::length_mask(binary_data, &metadata.length_nonce)?;
+        let len = (u64::from_le_bytes(key_len_bytes) ^ u64::from_le_bytes(mask)) as usize;
+
+        // 遮盖后的长度字段损坏或 .text 段被修改时，还原出的长度几乎不可能在容量以内
+        if len > metadata.total_capacity() {
+            return Err(Error::IntegrityCheckFailed(
+                "无法还原密钥长度，元数据头部或代码段可能已被修改".to_string(),
+            ));
+        }
+        Ok(len)
+    }
+
+    /// 按元数据计算写入头部的密钥长度字段，记录了nonce时遮盖长度
+    fn encode_length(binary_data: &[u8], metadata: &KeyMetadata, len: usize) -> Result<[u8; 8]> {
+        let len_bytes = (len as u64).to_le_bytes();
+        if metadata.length_nonce.is_empty() {
+            return Ok(len_bytes);
+        }
+
+        let mask = Self::length_mask(binary_data, &metadata.length_nonce)?;
+        Ok((u64::from_le_bytes(len_bytes) ^ u64::from_le_bytes(mask)).to_le_bytes())
+    }
+
+    /// 由 .text 段的哈希和nonce计算密钥长度的掩码
+    fn length_mask(binary_data: &[u8], nonce: &[u8]) -> Result<[u8; 8]> {
+        let mut text_hash =
+            derive_key_from_section(&parse_binary(binary_data)?, Self::DERIVE_SECTION, 32)?;
+        let mut message = Self::LENGTH_MASK_LABEL.to_vec();
+        message.extend_from_slice(nonce);
+        let mac = hmac_sha256(&text_hash, &message);
+        zeroize(&mut text_hash);
+
+        let mut mask = [0u8; 8];
+        mask.copy_from_slice(&mac[..8]);
+        Ok(mask)
+    }
+
+    /// 从二进制数据中解密出密钥
+    ///
+    /// 优先使用二进制中已写入的元数据（其中记录了最近一次写入的状态），
+    /// 尚未写入元数据时使用 `fallback`
+    pub(crate) fn decode_key(
+        binary_data: &[u8],
+        meta_section: &str,
+        fallback: &KeyMetadata,
+        constant_time: bool,
+    ) -> Result<Vec<u8>> {
+        let on_disk = Self::read_metadata_with_factor(binary_data, meta_section, fallback);
+        let metadata = on_disk.as_ref().unwrap_or(fallback);
+
+        let payload = Self::decode_payload(binary_data, meta_section, metadata, constant_time)?;
+        Self::finish_payload(metadata, payload)
+    }
+
+    /// 与 [`decode_key`](Self::decode_key) 相同，但返回当前密钥和全部历史版本（由新到旧）
+    pub(crate) fn decode_versions(
+        binary_data: &[u8],
+        meta_section: &str,
+        fallback: &KeyMetadata,
+        constant_time: bool,
+    ) -> Result<Vec<Vec<u8>>> {
+        let on_disk = Self::read_metadata_with_factor(binary_data, meta_section, fallback);
+        let metadata = on_disk.as_ref().unwrap_or(fallback);
+
+        let payload = Self::decode_payload(binary_data, meta_section, metadata, constant_time)?;
+        let mut payload = Self::unwrap_payload(metadata, payload)?;
+        if metadata.history == 0 {
+            return Ok(vec![payload]);
+        }
+        let versions = history::unpack(&payload);
+        zeroize(&mut payload);
+        versions
+    }
+
+    /// 按元数据中的记录还原解密出的数据（以压缩形式存储时解压，保留历史版本时只取当前密钥）
+    pub(crate) fn finish_payload(metadata: &KeyMetadata, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let mut payload = Self::unwrap_payload(metadata, payload)?;
+        if metadata.history == 0 {
+            return Ok(payload);
+        }
+
+        let versions = history::unpack(&payload);
+        zeroize(&mut payload);
+        let mut versions = versions?;
+        let current = versions.remove(0);
+        for old in &mut versions {
+            zeroize(old);
+        }
+        Ok(current)
+    }
+
+    /// 去掉填充，以压缩形式存储时解压
+    fn unwrap_payload(metadata: &KeyMetadata, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let payload = metadata.padding.strip(payload)?;
+        if metadata.compressed {
+            #[cfg(feature = "compression")]
+            return Self::decompress_payload(metadata, &payload);
+
+            #[cfg(not(feature = "compression"))]
+            return Err(Error::Config(
+                match CompressionCodec::of_dictionary_id(&metadata.dictionary_id) {
+                    CompressionCodec::Deflate => "密钥以压缩形式存储，需要启用 compression feature",
+                    CompressionCodec::Zstd => "密钥使用Zstandard压缩存储，需要启用 zstd feature",
+                }
+                .to_string(),
+            ));
+        }
+
+        Ok(payload)
+    }
+
+    /// 解压数据，写入时使用了字典则要求内存中的字典与记录的标识一致
+    #[cfg(feature = "compression")]
+    fn decompress_payload(metadata: &KeyMetadata, payload: &[u8]) -> Result<Vec<u8>> {
+        if metadata.dictionary_id.is_empty() {
+            return crate::compression::decompress(payload);
+        }
+
+        let codec = CompressionCodec::of_dictionary_id(&metadata.dictionary_id);
+        #[cfg(not(feature = "zstd"))]
+        if codec == CompressionCodec::Zstd {
+            return Err(Error::Config(
+                "密钥使用Zstandard压缩存储，需要启用 zstd feature".to_string(),
+            ));
+        }
+
+        match &metadata.compression_dictionary {
+            #[cfg(feature = "zstd")]
+            Some(dictionary)
+                if codec == CompressionCodec::Zstd
+                    && crate::compression::zstd_dictionary_id(dictionary)
+                        == metadata.dictionary_id =>
+            {
+                crate::compression::zstd_decompress(payload, dictionary)
+            }
+            Some(dictionary)
+                if codec == CompressionCodec::Deflate
+                    && crate::compression::dictionary_id(dictionary) == metadata.dictionary_id =>
+            {
+                crate::compression::decompress_with_dictionary(payload, dictionary)
+            }
+            Some(_) => Err(Error::Config(format!(
+                "压缩字典与写入时使用的字典（{}）不一致",
+                metadata.dictionary_id
+            ))),
+            None => Err(Error::Config(format!(
+                "密钥使用压缩字典（{}）存储，请先调用 {}",
+                metadata.dictionary_id,
+                match codec {
+                    CompressionCodec::Deflate => "with_compression_dictionary",
+                    CompressionCodec::Zstd => "with_zstd_dictionary",
+                }
+            ))),
+        }
+    }
+
+    /// 按照元数据解密出分片中存储的原始数据
+    fn decode_payload(
+        binary_data: &[u8],
+        meta_section: &str,
+        metadata: &KeyMetadata,
+        constant_time: bool,
+    ) -> Result<Vec<u8>> {
+        let actual_key_len = Self::stored_len_in(binary_data, meta_section)?;
+        Self::decode_shards(binary_data, metadata, actual_key_len, constant_time)
+    }
+
+    /// 按照元数据解密分片，取出前 `actual_key_len` 字节
+    pub(crate) fn decode_shards(
+        binary_data: &[u8],
+        metadata: &KeyMetadata,
+        actual_key_len: usize,
+        constant_time: bool,
+    ) -> Result<Vec<u8>> {
+        let total_capacity = metadata.total_capacity();
+        if actual_key_len > total_capacity {
+            return Err(Error::Config(format!(
+                "存储的密钥长度异常: {} > {}",
+                actual_key_len, total_capacity
+            )));
+        }
+
+        // 只解析一次二进制，之后派生密钥和查找各分片都使用同一份section表
+        let sections = SectionMap::parse(binary_data)?;
+
+        if actual_key_len > 0 {
+            Self::check_shards_written(&sections, metadata)?;
+        }
+
+        // 如果密钥长度为0，返回空vec（恒定工作量模式仍然解密全部分片）
+        if actual_key_len == 0 && !constant_time {
+            return Ok(Vec::new());
+        }
+
+        // 读取并解密所有分片
+        let base_key = Self::derive_base_key(&sections, metadata)?;
+        Self::decrypt_with_key(
+            &sections,
+            metadata,
+            &base_key,
+            actual_key_len,
+            constant_time,
+        )
+    }
+
+    /// 使用已派生的基础密钥解密分片，取出前 `actual_key_len` 字节并校验完整性
+    pub(crate) fn decrypt_with_key(
+        sections: &SectionMap,
+        metadata: &KeyMetadata,
+        base_key: &[u8],
+        actual_key_len: usize,
+        constant_time: bool,
+    ) -> Result<Vec<u8>> {
+        metadata.check_pipeline()?;
+        Self::check_shard_tags(sections, metadata)?;
+
+        let payload = if constant_time {
+            Self::decode_payload_constant_time(sections, metadata, base_key, actual_key_len)?
+        } else {
+            Self::decrypt_payload(sections, metadata, base_key, actual_key_len)?
+        };
+
+        Self::check_payload(base_key, metadata, &payload)?;
+        Ok(payload)
+    }
+
+    /// 解密活动分片，按布局取出前 `actual_key_len` 字节
+    fn decrypt_payload(
+        sections: &SectionMap,
+        metadata: &KeyMetadata,
+        base_key: &[u8],
+        actual_key_len: usize,
+    ) -> Result<Vec<u8>> {
+        // 交错布局下每个分片都包含密钥的一部分，需要全部解密后重新拼接
+        if metadata.layout == ShardLayout::Interleaved {
+            let shards = metadata
+                .shard_names
+                .iter()
+                .zip(&metadata.shard_sizes)
+                .enumerate()
+                .map(|(i, (name, &size))| {
+                    Self::decrypt_section(sections, metadata, base_key, name, size, i)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(metadata.join_shards(&shards, actual_key_len));
+        }
+
+        let mut decrypted_bytes = Vec::new();
+        let mut bytes_needed = actual_key_len;
+
+        // 按逻辑顺序依次解密，与分片在元数据数组和文件中的顺序无关
+        for i in metadata.logical_order() {
+            if bytes_needed == 0 {
+                break;
+            }
+
+            let decrypted = Self::decrypt_section(
+                sections,
+                metadata,
+                base_key,
+                &metadata.shard_names[i],
+                metadata.shard_sizes[i],
+                i,
+            )?;
+
+            // 只取需要的字节数
+            let bytes_to_take = bytes_needed.min(decrypted.len());
+            decrypted_bytes.extend(&decrypted[..bytes_to_take]);
+            bytes_needed -= bytes_to_take;
+        }
+
+        Ok(decrypted_bytes)
+    }
+
+    /// 记录了非零密钥长度、但活动分片全为零时返回错误
+    ///
+    /// 加密后的分片（包括填充部分）几乎不可能全为零，这种状态说明长度已写入而分片
+    /// 没有写入（例如写入过程中崩溃），此时解密只会得到无意义的数据
+    pub(crate) fn check_shards_written(
+        sections: &SectionMap,
+        metadata: &KeyMetadata,
+    ) -> Result<()> {
+        let binary_data = sections.binary_data();
+        for (name, &size) in metadata.shard_names.iter().zip(&metadata.shard_sizes) {
+            let (offset, used) = Self::shard_range(sections, name, size)?;
+            if binary_data[offset..offset + used].iter().any(|&b| b != 0) {
+                return Ok(());
+            }
+        }
+
+        Err(Error::Parse(
+            "记录了密钥长度，但所有分片均未写入，密钥数据不完整".to_string(),
+        ))
+    }
+
+    /// 以固定的工作量解密密钥
+    ///
+    /// 总是处理全部8个物理shard section（未使用的section解密后丢弃），
+    /// 且不会因为已取够字节而提前结束，读取耗时与密钥长度和分片数量无关
+    fn decode_payload_constant_time(
+        sections: &SectionMap,
+        metadata: &KeyMetadata,
+        base_key: &[u8],
+        actual_key_len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut shards: Vec<Vec<u8>> = vec![Vec::new(); metadata.num_shards];
+
+        for (i, name) in metadata.shard_names.iter().enumerate() {
+            shards[i] = Self::decrypt_section(
+                sections,
+                metadata,
+                base_key,
+                name,
+                metadata.shard_sizes[i],
+                i,
+            )?;
+        }
+
+        // 使用调用方指定的section时，布局之外没有预定义的shard section
+        let predefined = metadata
+            .shard_names
+            .iter()
+            .all(|n| KeyMetadata::SHARD_NAMES.contains(&n.as_str()));
+        for (physical, name) in KeyMetadata::SHARD_NAMES.iter().enumerate() {
+            if !predefined || metadata.shard_names.iter().any(|n| n == name) {
+                continue;
+            }
+            let discarded = Self::decrypt_section(
+                sections,
+                metadata,
+                base_key,
+                name,
+                KeyMetadata::SHARD_SIZE,
+                physical,
+            )?;
+            std::hint::black_box(discarded);
+        }
+
+        Ok(metadata.join_shards(&shards, actual_key_len))
+    }
+
+    /// 计算所有分片共用的基础密钥
+    ///
+    /// 由元数据中配置的派生sections（未配置时为 .text 段）计算哈希；
+    /// 元数据中记录了KDF迭代次数时，再用PBKDF2拉伸
+    pub(crate) fn derive_base_key(
+        sections: &SectionMap,
+        metadata: &KeyMetadata,
+    ) -> Result<Vec<u8>> {
+        let hash = Self::derive_sections_hash(sections, metadata)?;
+
+        let stretched = if metadata.kdf_iterations == 0 {
+            hash
+        } else {
+            pbkdf2_hmac_sha256(&hash, Self::KDF_SALT, metadata.kdf_iterations).to_vec()
+        };
+
+        if metadata.factor_salt.is_empty() {
+            return Ok(stretched);
+        }
+
+        // 混入外部因子，并用校验值确认因子正确
+        let factor = metadata.external_factor.as_ref().ok_or_else(|| {
+            Error::Config("密钥绑定了外部因子，请先调用 with_external_factor".to_string())
+        })?;
+        let mut material = metadata.factor_salt.clone();
+        material.extend_from_slice(&factor.0);
+        let base_key = hmac_sha256(&stretched, &material).to_vec();
+        zeroize(&mut material);
+
+        if !metadata.factor_check.is_empty()
+            && !hmac_sha256_verify(&base_key, Self::FACTOR_CHECK_LABEL, &metadata.factor_check)
+        {
+            return Err(Error::Crypto("外部因子不正确".to_string()));
+        }
+
+        Ok(base_key)
+    }
+
+    /// 计算元数据中配置的派生sections（未配置时为 .text 段）的哈希
+    fn derive_sections_hash(sections: &SectionMap, metadata: &KeyMetadata) -> Result<Vec<u8>> {
+        if metadata.derive_sections.is_empty() {
+            return sections.derive_key(&[Self::DERIVE_SECTION], 32);
+        }
+
+        let names: Vec<&str> = metadata
+            .derive_sections
+            .iter()
+            .map(String::as_str)
+            .collect();
+        sections.derive_key(&names, 32)
+    }
+
+    /// 由派生sections的哈希计算绑定校验值，不需要解密即可确认二进制的代码段未被修改
+    fn binding_check(sections: &SectionMap, metadata: &KeyMetadata) -> Result<Vec<u8>> {
+        let mut hash = Self::derive_sections_hash(sections, metadata)?;
+        let check = hmac_sha256(&hash, Self::BINDING_CHECK_LABEL)[..16].to_vec();
+        zeroize(&mut hash);
+        Ok(check)
+    }
+
+    /// 由混入外部因子后的基础密钥计算校验值
+    fn factor_check(base_key: &[u8]) -> Vec<u8> {
+        hmac_sha256(base_key, Self::FACTOR_CHECK_LABEL)[..16].to_vec()
+    }
+
+    /// 计算存储数据的完整性校验值
+    ///
+    /// 以基础密钥为HMAC密钥，分片被篡改或 .text 段被修改（基础密钥随之改变）都会导致不匹配
+    fn payload_check(base_key: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut message = Self::payload_check_message(payload);
+        let check = hmac_sha256(base_key, &message)[..16].to_vec();
+        zeroize(&mut message);
+        check
+    }
+
+    /// 完整性校验值认证的消息：标签与存储数据拼接
+    fn payload_check_message(payload: &[u8]) -> Vec<u8> {
+        let mut message = Self::PAYLOAD_CHECK_LABEL.to_vec();
+        message.extend_from_slice(payload);
+        message
+    }
+
+    /// 校验解密出的数据，元数据中没有记录校验值（旧版本写入）时跳过
+    fn check_payload(base_key: &[u8], metadata: &KeyMetadata, payload: &[u8]) -> Result<()> {
+        if metadata.payload_check.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = Self::payload_check_message(payload);
+        let valid = hmac_sha256_verify(base_key, &message, &metadata.payload_check);
+        zeroize(&mut message);
+        if valid {
+            return Ok(());
+        }
+
+        Err(Error::IntegrityCheckFailed(
+            "解密结果与写入时不一致，分片或代码段可能已被修改".to_string(),
+        ))
+    }
+
+    /// 计算各分片的绑定标签
+    ///
+    /// 以 .text 段的哈希为HMAC密钥，对分片序号（小端序u32）和分片密文计算HMAC，
+    /// 每个分片取前 `SHARD_TAG_SIZE` 字节，按元数据中的分片顺序拼接
+    fn shard_tags(sections: &SectionMap, metadata: &KeyMetadata) -> Result<Vec<u8>> {
+        let mut text_hash = sections.derive_key(&[Self::DERIVE_SECTION], 32)?;
+        let mut tags = Vec::with_capacity(metadata.num_shards * Self::SHARD_TAG_SIZE);
+
+        for (i, (name, &size)) in metadata
+            .shard_names
+            .iter()
+            .zip(&metadata.shard_sizes)
+            .enumerate()
+        {
+            let (offset, size) = Self::shard_range(sections, name, size)?;
+            let mut message = (i as u32).to_le_bytes().to_vec();
+            message.extend_from_slice(&sections.binary_data()[offset..offset + size]);
+            tags.extend_from_slice(&hmac_sha256(&text_hash, &message)[..Self::SHARD_TAG_SIZE]);
+        }
+
+        zeroize(&mut text_hash);
+        Ok(tags)
+    }
+
+    /// 校验各分片的绑定标签，元数据中没有记录标签时跳过
+    fn check_shard_tags(sections: &SectionMap, metadata: &KeyMetadata) -> Result<()> {
+        if metadata.shard_tags.is_empty() {
+            return Ok(());
+        }
+
+        let tags = Self::shard_tags(sections, metadata)?;
+        if tags.len() != metadata.shard_tags.len() {
+            return Err(Error::IntegrityCheckFailed(format!(
+                "分片绑定标签数量不符: 期望 {}, 实际 {}",
+                metadata.shard_tags.len() / Self::SHARD_TAG_SIZE,
+                metadata.num_shards
+            )));
+        }
+
+        let mismatched = tags
+            .chunks(Self::SHARD_TAG_SIZE)
+            .zip(metadata.shard_tags.chunks(Self::SHARD_TAG_SIZE))
+            .position(|(actual, expected)| !constant_time_eq(actual, expected));
+        match mismatched {
+            Some(i) => Err(Error::IntegrityCheckFailed(format!(
+                "分片 {} 的绑定标签不匹配，该分片可能是从其他二进制复制而来或已被修改",
+                metadata.shard_names[i]
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// 读取二进制中的元数据，并带上内存中的外部因子和压缩字典（两者从不写入二进制）
+    pub(crate) fn read_metadata_with_factor(
+        binary_data: &[u8],
+        meta_section: &str,
+        current: &KeyMetadata,
+    ) -> Option<KeyMetadata> {
+        let mut metadata = Self::read_metadata(binary_data, meta_section).ok()?;
+        metadata.external_factor = current.external_factor.clone();
+        metadata.compression_dictionary = current.compression_dictionary.clone();
+        metadata.dictionary_codec = current.dictionary_codec;
+        Some(metadata)
+    }
+
+    /// 计算分片的加解密密钥
+    ///
+    /// 元数据中记录了该分片的nonce时，将基础密钥与nonce一起哈希，使每次写入的密文都不同
+    pub(crate) fn derive_shard_key(
+        base_key: &[u8],
+        metadata: &KeyMetadata,
+        index: usize,
+    ) -> Vec<u8> {
+        match metadata.shard_nonces.get(index) {
+            Some(nonce) => {
+                let mut hasher = Sha256::new();
+                hasher.update(base_key);
+                hasher.update(Self::nonce_bytes(*nonce));
+                hasher.finalize()[..base_key.len()].to_vec()
+            }
+            // 旧版本元数据没有nonce
+            None => base_key.to_vec(),
+        }
+    }
+
+    /// 将nonce序列化为参与派生的字节
+    ///
+    /// 多字节的种子材料一律显式按小端序处理，与运行平台的字节序无关，
+    /// 在x86_64上写入的密钥可以在大端平台上读取同一二进制时正确解密
+    fn nonce_bytes(nonce: u32) -> [u8; 4] {
+        nonce.to_le_bytes()
+    }
+
+    /// 计算分片的混淆种子
+    ///
+    /// 由编译时生成的随机种子偏移量和分片序号决定，记录了nonce时再混入nonce
+    fn shard_seed(metadata: &KeyMetadata, index: usize) -> u8 {
+        let seed = SHARD_SEED_OFFSETS[index % SHARD_SEED_OFFSETS.len()].wrapping_add(index as u8);
+        match metadata.shard_nonces.get(index) {
+            Some(&nonce) => seed ^ Self::nonce_bytes(nonce).iter().fold(0, |acc, b| acc ^ b),
+            None => seed,
+        }
+    }
+
+    /// 解密单个shard section
+    ///
+    /// `index` 为分片的逻辑序号，用于选择混淆种子
+    fn decrypt_section(
+        sections: &SectionMap,
+        metadata: &KeyMetadata,
+        base_key: &[u8],
+        section_name: &str,
+        shard_size: usize,
+        index: usize,
+    ) -> Result<Vec<u8>> {
+        let (section_offset, shard_size) = Self::shard_range(sections, section_name, shard_size)?;
+
+        let encrypted_data = &sections.binary_data()[section_offset..section_offset + shard_size];
+
+        // 基础密钥混入本分片的nonce
+        let derive_key = Self::derive_shard_key(base_key, metadata, index);
+
+        // 解密：异或 -> 反混淆（种子必须与加密时相同）
+        Ok(decrypt_shard(
+            encrypted_data,
+            &derive_key,
+            Self::shard_seed(metadata, index),
+        ))
+    }
+
+    /// 读取当前密钥（字符串版本）
+    ///
+    /// 便捷方法，尝试将密钥解析为UTF-8字符串
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥字符串，失败返回Error
+    ///
+    /// # 注意
+    ///
+    /// 如果密钥不是有效的UTF-8，将返回错误。对于二进制密钥，请使用`read_bytes()`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let key = store.read()?;
+    /// println!("密钥: {}", key);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read(&self) -> Result<String> {
+        Self::bytes_to_string(self.read_bytes()?)
+    }
+
+    /// 读取密钥并写入文件
+    ///
+    /// 文件不存在时创建，存在时覆盖。Unix上文件权限设为 `0600`（仅所有者可读写），
+    /// 已存在的文件也会改为该权限。读取出的密钥在写入后清零
+    ///
+    /// # 参数
+    ///
+    /// * `path` - 输出文件路径
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// # use std::path::Path;
+    /// let store = KeyStore::new()?;
+    /// store.read_to_file(Path::new("/run/app/key"))?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_to_file(&self, path: &Path) -> Result<()> {
+        let mut key = self.read_bytes()?;
+        let result = Self::write_private_file(path, &key);
+        zeroize(&mut key);
+        result
+    }
+
+    /// 写入只有所有者可读写（0600）的文件，已存在时覆盖
+    pub(crate) fn write_private_file(path: &Path, data: &[u8]) -> Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // mode只作用于新建的文件，已存在的文件需要单独修改权限
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                    .map_err(Error::io_at(path))?;
+            }
+        }
+
+        options
+            .open(path)
+            .and_then(|mut file| file.write_all(data))
+            .map_err(Error::io_at(path))
+    }
+
+    /// 更新密钥（UTF-16版本）
+    ///
+    /// 以UTF-16LE字节存储，适用于Windows凭据API返回的UTF-16字符串，
+    /// 避免与UTF-8之间的有损转换
+    ///
+    /// # 参数
+    ///
+    /// * `s` - UTF-16码元序列
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// let credential: Vec<u16> = "密码".encode_utf16().collect();
+    /// store.update_utf16(&credential)?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn update_utf16(&mut self, s: &[u16]) -> Result<()> {
+        let bytes: Vec<u8> = s.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+        self.update_bytes(&bytes)
+    }
+
+    /// 读取当前密钥（UTF-16版本）
+    ///
+    /// 将存储的UTF-16LE字节还原为码元序列，不校验是否为有效的UTF-16
+    ///
+    /// # 返回
+    ///
+    /// 成功返回UTF-16码元序列；存储的数据长度为奇数时返回 `Error::Parse`
+    pub fn read_utf16(&self) -> Result<Vec<u16>> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() % 2 != 0 {
+            return Err(Error::Parse(format!(
+                "密钥长度({})为奇数，不是UTF-16数据",
+                bytes.len()
+            )));
+        }
+
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect())
+    }
+
+    /// 以JSON形式存储一个可序列化的值
+    ///
+    /// 便捷方法，将值用serde_json序列化后作为密钥写入
+    ///
+    /// # 参数
+    ///
+    /// * `value` - 要存储的值
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，序列化失败返回 `Error::Parse`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// #[derive(serde::Serialize)]
+    /// struct Credential {
+    ///     username: String,
+    ///     token: String,
+    /// }
+    ///
+    /// let mut store = KeyStore::new()?;
+    /// store.store_json(&Credential {
+    ///     username: "admin".into(),
+    ///     token: "secret".into(),
+    /// })?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn store_json<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        let bytes = serde_json::to_vec(value)?;
+        self.update_bytes(&bytes)
+    }
+
+    /// 读取以JSON形式存储的值
+    ///
+    /// 与 [`KeyStore::store_json`] 对应，读取密钥并用serde_json反序列化
+    ///
+    /// # 返回
+    ///
+    /// 成功返回反序列化后的值，反序列化失败返回 `Error::Parse`
+    pub fn load_json<T: DeserializeOwned>(&self) -> Result<T> {
+        let bytes = self.read_bytes()?;
+        serde_json::from_slice(&bytes).map_err(Error::from)
+    }
+
+    /// 将密钥bytes转换为UTF-8字符串
+    pub(crate) fn bytes_to_string(bytes: Vec<u8>) -> Result<String> {
+        String::from_utf8(bytes).map_err(|e| Error::Parse(format!("密钥不是有效的UTF-8: {}", e)))
+    }
+
+    /// 计算存储指定长度的密钥需要的总容量（字节）
+    ///
+    /// 加密不改变数据长度，密钥长度和布局记录在独立的 `.key_meta` section中，不占用分片容量。
+    /// 额外占用来自存储方案：[`Padding::SelfDescribing`] 需要1字节结束标记；
+    /// [`with_history`](Self::with_history) 保留 `history` 个历史版本时，按历史版本与新密钥
+    /// 等长、且已经保存满计算，每个版本另需4字节长度。启用压缩时实际占用可能更小，
+    /// 这里按未压缩计算
+    ///
+    /// # 参数
+    ///
+    /// * `key_len` - 密钥长度（字节）
+    /// * `padding` - 使用的填充方式
+    /// * `history` - 保留的历史版本数量，不保留时为0
+    ///
+    /// # 示例
+    ///
+    /// ```
+    /// # use self_crypto_key::{KeyStore, Padding};
+    /// assert_eq!(KeyStore::required_capacity(32, Padding::HeaderLength, 0), 32);
+    /// assert_eq!(KeyStore::required_capacity(32, Padding::SelfDescribing, 0), 33);
+    /// assert_eq!(KeyStore::required_capacity(32, Padding::HeaderLength, 2), 3 * (4 + 32));
+    /// ```
+    pub fn required_capacity(key_len: usize, padding: Padding, history: usize) -> usize {
+        let payload = match history {
+            0 => key_len,
+            _ => history::packed_len(key_len, history),
+        };
+        payload.saturating_add(padding.overhead())
+    }
+
+    /// 计算存储指定长度的密钥需要多少个给定大小的分片
+    ///
+    /// 所需容量按 [`required_capacity`](Self::required_capacity) 计算
+    ///
+    /// # 参数
+    ///
+    /// * `key_len` - 密钥长度（字节）
+    /// * `shard_size` - 每个分片可用的字节数
+    /// * `padding` - 使用的填充方式
+    /// * `history` - 保留的历史版本数量，不保留时为0
+    ///
+    /// # 返回
+    ///
+    /// 成功返回分片数量；`shard_size` 为0时返回 `Error::Config`
+    ///
+    /// # 示例
+    ///
+    /// ```
+    /// # use self_crypto_key::{KeyStore, Padding};
+    /// assert_eq!(KeyStore::required_shards(4096, 1024, Padding::HeaderLength, 0)?, 4);
+    /// assert_eq!(KeyStore::required_shards(4097, 1024, Padding::HeaderLength, 0)?, 5);
+    /// assert_eq!(KeyStore::required_shards(4096, 1024, Padding::SelfDescribing, 0)?, 5);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn required_shards(
+        key_len: usize,
+        shard_size: usize,
+        padding: Padding,
+        history: usize,
+    ) -> Result<usize> {
+        if shard_size == 0 {
+            return Err(Error::Config("分片大小必须大于0".to_string()));
+        }
+        Ok(Self::required_capacity(key_len, padding, history).div_ceil(shard_size))
+    }
+
+    /// 获取密钥存储的总容量
+    ///
+    /// # 返回
+    ///
+    /// 总容量（字节）
+    pub fn capacity(&self) -> usize {
+        self.metadata.total_capacity()
+    }
+
+    /// 读取 `.key_meta` section的原始字节（调试用）
+    ///
+    /// 返回整个元数据section的内容，包括头部和JSON元数据，不做任何解析。
+    /// 用于在元数据损坏、无法解析时排查问题
+    ///
+    /// # 返回
+    ///
+    /// 成功返回section的原始字节，失败返回Error
+    pub fn peek_raw_meta(&self) -> Result<Vec<u8>> {
+        let binary_data = self.read_binary()?;
+        let (offset, size) = Self::find_section(&binary_data, &self.meta_section)?;
+        Ok(binary_data[offset..offset + size].to_vec())
+    }
+
+    /// 获取元数据section的空间使用情况
+    ///
+    /// 返回 `(已使用, 总大小)`，已使用部分包括头部和当前元数据序列化后的JSON。
+    /// 接近上限时，应通过 `init_key_storage!(meta_size = ...)` 增大元数据section，
+    /// 以免写入时出现空间不足的错误
+    ///
+    /// # 返回
+    ///
+    /// 成功返回 `(used, total)`（字节），失败返回Error
+    pub fn metadata_usage(&self) -> Result<(usize, usize)> {
+        let binary_data = self.read_binary()?;
+        let (_, meta_size) = Self::find_section(&binary_data, &self.meta_section)?;
+        let used = KeyMetadata::HEADER_SIZE + self.metadata.to_bytes()?.len();
+        Ok((used, meta_size))
+    }
+
+    /// 以最紧凑的编码重写二进制中的元数据，释放 `.key_meta` 中的空间
+    ///
+    /// 分别按JSON和CBOR序列化已写入的元数据，使用较小的一种重新写入，并清空其后的
+    /// 剩余空间。只改写元数据section，分片和存储的密钥保持不变。二进制尚未写入元数据时
+    /// 只修改之后写入使用的编码
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// store.compact_metadata()?;
+    /// let (used, total) = store.metadata_usage()?;
+    /// println!("metadata: {}/{}", used, total);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn compact_metadata(&mut self) -> Result<()> {
+        let mut binary_data = self.read_binary()?;
+        let on_disk = Self::read_metadata(&binary_data, &self.meta_section).ok();
+
+        let mut metadata = on_disk.clone().unwrap_or_else(|| self.metadata.clone());
+        let mut smallest = None;
+        for encoding in [MetaEncoding::Json, MetaEncoding::Cbor] {
+            metadata.encoding = encoding;
+            let len = metadata.to_bytes()?.len();
+            if smallest.is_none_or(|(_, best)| len < best) {
+                smallest = Some((encoding, len));
+            }
         }
+        let (encoding, _) = smallest.expect("至少比较了一种编码");
+        self.metadata.encoding = encoding;
 
-        Err(Error::SectionNotFound(section_name.to_string()))
+        let Some(mut on_disk) = on_disk else {
+            return Ok(());
+        };
+        on_disk.encoding = encoding;
+        let (meta_offset, meta_size) = Self::find_section(&binary_data, &self.meta_section)?;
+        on_disk.write_to_section(&mut binary_data[meta_offset..meta_offset + meta_size])?;
+        self.write_binary(&binary_data)
     }
 
-    /// 原子写入文件（使用临时文件 + rename）
-    fn atomic_write(path: &PathBuf, data: &[u8]) -> Result<()> {
-        let temp_path = path.with_extension("tmp");
+    /// 生成随机密钥字符串
+    ///
+    /// # 参数
+    ///
+    /// * `length` - 密钥长度（字符数）
+    ///
+    /// # 返回
+    ///
+    /// 随机生成的可打印ASCII字符串（字符范围 33-126）
+    pub fn generate_random_key(length: usize) -> String {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        (0..length)
+            .map(|_| rng.gen_range(33..=126) as u8 as char)
+            .collect()
+    }
+
+    /// 生成随机密钥bytes
+    ///
+    /// # 参数
+    ///
+    /// * `length` - 密钥长度（字节数）
+    ///
+    /// # 返回
+    ///
+    /// 随机生成的bytes
+    pub fn generate_random_bytes(length: usize) -> Vec<u8> {
+        use rand::Rng;
+        let mut rng = rand::thread_rng();
+        (0..length).map(|_| rng.gen()).collect()
+    }
 
-        // 写入临时文件
-        fs::write(&temp_path, data)?;
+    /// 获取所管理的二进制文件路径
+    pub fn exe_path(&self) -> &Path {
+        &self.exe_path
+    }
 
-        // 复制权限
-        #[cfg(unix)]
+    /// 获取当前使用的元数据
+    pub(crate) fn metadata(&self) -> &KeyMetadata {
+        &self.metadata
+    }
+
+    /// 读取时是否使用恒定工作量模式
+    pub(crate) fn constant_time(&self) -> bool {
+        self.constant_time
+    }
+
+    /// 是否开启了解密结果检查
+    pub(crate) fn sanity_warnings(&self) -> bool {
+        self.sanity_warnings
+    }
+
+    /// 元数据section的名称
+    pub(crate) fn meta_section(&self) -> &str {
+        &self.meta_section
+    }
+
+    /// 替换当前使用的元数据（不写入二进制）
+    pub(crate) fn set_metadata(&mut self, metadata: KeyMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// 检查二进制路径是否已被替换为另一个文件
+    ///
+    /// 打开时会记录二进制文件的设备号和inode。部署新版本时通常会原子替换可执行文件，
+    /// 此时路径指向新文件，读取到的是新二进制中的密钥，而不是正在运行的代码对应的密钥。
+    /// 本实例自身的写入会更新记录的标识，不会被视为替换
+    ///
+    /// # 返回
+    ///
+    /// 路径已指向其他文件或文件已被删除返回 `Ok(true)`，否则返回 `Ok(false)`。
+    /// 非Unix平台无法获取inode，总是返回 `Ok(false)`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// if store.binary_replaced()? {
+    ///     eprintln!("二进制已被替换，请重启进程");
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn binary_replaced(&self) -> Result<bool> {
+        let Some(identity) = self.identity else {
+            return Ok(false);
+        };
+
+        match Self::file_identity(&self.exe_path) {
+            Ok(current) => Ok(current != Some(identity)),
+            Err(Error::IoAt { source, .. }) if source.kind() == ErrorKind::NotFound => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 保存当前的原始存储状态：二进制内容、旁路文件和内存中的元数据
+    pub(crate) fn capture_raw_state(&self) -> Result<RawState> {
+        Ok(RawState {
+            binary: self.read_binary()?,
+            pending: self.pending.is_some(),
+            sidecar: match self.in_memory {
+                true => None,
+                false => sidecar::read_content(&self.exe_path)?,
+            },
+            metadata: self.metadata.clone(),
+        })
+    }
+
+    /// 逐字节恢复 [`capture_raw_state`](Self::capture_raw_state) 保存的状态
+    ///
+    /// 不经过写入流程，因此不会产生历史版本、审计事件或新的nonce
+    pub(crate) fn restore_raw_state(&mut self, state: &RawState) -> Result<()> {
+        if state.pending || self.in_memory {
+            self.pending = Some(state.binary.clone());
+        } else if self.deferred {
+            // 延迟写入模式下期间的修改只在内存中，文件本身没有变化
+            self.pending = None;
+        } else {
+            self.write_binary(&state.binary)?;
+        }
+
+        if !self.in_memory {
+            match &state.sidecar {
+                Some(content) => Self::atomic_write(
+                    &sidecar::sidecar_path(&self.exe_path),
+                    content,
+                    self.durable,
+                )?,
+                None => self.remove_sidecar()?,
+            }
+        }
+
+        self.metadata = state.metadata.clone();
+        Ok(())
+    }
+
+    /// 从二进制数据中读取元数据
+    pub(crate) fn read_metadata(binary_data: &[u8], meta_section: &str) -> Result<KeyMetadata> {
+        let (offset, size) = Self::find_section(binary_data, meta_section)?;
+
+        Self::check_meta_size(size)?;
+
+        KeyMetadata::from_section(&binary_data[offset..offset + size])
+    }
+
+    /// 检查元数据section是否能容纳头部和最小的JSON元数据
+    pub(crate) fn check_meta_size(size: usize) -> Result<()> {
+        if size < KeyMetadata::MIN_SECTION_SIZE {
+            return Err(Error::Config(format!(
+                "元数据section太小: {} < {}",
+                size,
+                KeyMetadata::MIN_SECTION_SIZE
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// 将元数据写入二进制数据的.key_meta section
+    fn write_metadata_to_binary(
+        &self,
+        binary_data: &mut [u8],
+        metadata: &KeyMetadata,
+    ) -> Result<()> {
+        let (meta_offset, meta_size) = Self::find_section(binary_data, &self.meta_section)?;
+
+        metadata.write_to_section(&mut binary_data[meta_offset..meta_offset + meta_size])
+    }
+
+    /// 查找section的文件偏移和大小
+    ///
+    /// 不存在同名section时，查找note格式对应的 `.note` 前缀section，返回其描述数据的范围
+    pub(crate) fn find_section(binary_data: &[u8], section_name: &str) -> Result<(usize, usize)> {
+        SectionMap::parse(binary_data)?.find(section_name)
+    }
+
+    /// 识别二进制中密钥存储的组织形式
+    fn detect_format(binary_data: &[u8], meta_section: &str) -> Result<StorageFormat> {
+        if SectionMap::parse(binary_data)?
+            .named(meta_section)?
+            .is_some()
         {
-            let metadata = fs::metadata(path)?;
-            let permissions = metadata.permissions();
-            fs::set_permissions(&temp_path, permissions)?;
+            return Ok(StorageFormat::NamedSections);
+        }
+
+        match note::find_note(binary_data, meta_section)? {
+            Some((_, _, vendor)) => Ok(StorageFormat::ElfNote { vendor }),
+            None => Err(Error::SectionNotFound(meta_section.to_string())),
+        }
+    }
+
+    /// 进程正被调试器跟踪时返回错误（仅Linux）
+    fn check_not_traced() -> Result<()> {
+        if !cfg!(target_os = "linux") {
+            return Ok(());
+        }
+
+        let status_path = Path::new("/proc/self/status");
+        let status = fs::read_to_string(status_path).map_err(Error::io_at(status_path))?;
+        match Self::tracer_pid(&status) {
+            Some(pid) if pid != 0 => Err(Error::Config(format!(
+                "进程正被调试器跟踪 (TracerPid: {})，拒绝写入",
+                pid
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// 从 `/proc/<pid>/status` 的内容中解析 `TracerPid`
+    fn tracer_pid(status: &str) -> Option<u32> {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("TracerPid:"))
+            .and_then(|value| value.trim().parse().ok())
+    }
+
+    /// 检查二进制中记录的KDF迭代次数是否与显式设置的一致
+    fn check_kdf_iterations(&self) -> Result<()> {
+        let expected = match self.kdf_iterations {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+
+        let binary_data = self.read_binary()?;
+        if let Ok(metadata) = Self::read_metadata(&binary_data, &self.meta_section) {
+            if metadata.kdf_iterations != expected {
+                return Err(Error::Config(format!(
+                    "KDF迭代次数不匹配: 设置为 {}, 二进制中为 {}",
+                    expected, metadata.kdf_iterations
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 检查二进制的存储格式是否与设置的格式一致
+    fn check_format(&self) -> Result<()> {
+        if self.detected_format != self.format {
+            return Err(Error::Config(format!(
+                "存储格式不匹配: 期望 {:?}, 实际 {:?}",
+                self.format, self.detected_format
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// 查找shard section，返回文件偏移和实际使用的字节数
+    ///
+    /// 部分链接器会把section对齐填充到更大的大小，此时 `file_range` 返回的是填充后的大小。
+    /// 分片只使用前 `min(section_size, shard_size)` 字节，填充部分既不写入也不读取；
+    /// section大小为0时返回说明原因的 `Error::Config`，其余小于逻辑分片大小的情况返回 `SizeMismatch`
+    pub(crate) fn find_shard_section(
+        binary_data: &[u8],
+        section_name: &str,
+        shard_size: usize,
+    ) -> Result<(usize, usize)> {
+        Self::shard_range(&SectionMap::parse(binary_data)?, section_name, shard_size)
+    }
+
+    /// 与 [`find_shard_section`](Self::find_shard_section) 相同，但使用已解析的section表
+    fn shard_range(
+        sections: &SectionMap,
+        section_name: &str,
+        shard_size: usize,
+    ) -> Result<(usize, usize)> {
+        let (section_offset, section_size) = sections
+            .find(section_name)
+            .map_err(|e| Self::explain_missing_section(sections.binary_data(), e))?;
+
+        // section存在但大小为0，通常是声明的static没有被保留下来
+        if section_size == 0 && shard_size > 0 {
+            return Err(Error::Config(format!(
+                "shard section {} 的大小为0，链接器可能只保留了空的占位section。\
+                 请确认通过 init_key_storage! 声明存储sections；手动声明时需要为static\
+                 加上 #[used] 并使用非零大小的数组",
+                section_name
+            )));
+        }
+
+        let used = section_size.min(shard_size);
+        if used < shard_size {
+            return Err(Error::SizeMismatch {
+                expected: shard_size,
+                actual: section_size,
+            });
+        }
+
+        Ok((section_offset, used))
+    }
+
+    /// 为静态链接二进制中缺失的密钥section给出针对性的错误
+    ///
+    /// musl静态构建时，链接器的section回收可能会丢弃全零的密钥存储static。
+    /// 对于静态链接（没有PT_INTERP）的二进制，将 `SectionNotFound` 转换为带有
+    /// 解决建议的 `Error::Config`；其他错误原样返回
+    pub(crate) fn explain_missing_section(binary_data: &[u8], err: Error) -> Error {
+        let name = match &err {
+            Error::SectionNotFound(name) => name,
+            _ => return err,
+        };
+
+        if Self::is_static_binary(binary_data) {
+            return Error::Config(format!(
+                "静态链接的二进制中缺少section {}，可能已被链接器回收。\
+                 请确认调用了 init_key_storage!，并尝试使用 \
+                 RUSTFLAGS=\"-C link-args=-Wl,--no-gc-sections\" 重新构建",
+                name
+            ));
+        }
+
+        err
+    }
+
+    /// 判断二进制是否为静态链接（没有PT_INTERP程序头）
+    fn is_static_binary(binary_data: &[u8]) -> bool {
+        use object::elf::PT_INTERP;
+        use object::read::elf::{ElfFile64, ProgramHeader};
+
+        match ElfFile64::<object::Endianness>::parse(binary_data) {
+            Ok(elf) => !elf
+                .raw_segments()
+                .iter()
+                .any(|segment| segment.p_type(elf.endian()) == PT_INTERP),
+            Err(_) => false,
         }
+    }
+
+    /// 原子写入文件（使用临时文件 + rename）
+    ///
+    /// `durable` 为true时，rename前会fsync临时文件，rename后会fsync所在目录
+    pub(crate) fn atomic_write(path: &Path, data: &[u8], durable: bool) -> Result<()> {
+        let temp_path = Self::write_temp(path, data, durable)?;
 
         // 原子重命名
-        fs::rename(&temp_path, path)?;
+        if let Err(e) = fs::rename(&temp_path, path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(Error::io_at(path)(e));
+        }
+
+        if durable {
+            Self::sync_parent_dir(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// 原子写入本实例的二进制，并记录写入后的文件标识
+    ///
+    /// rename会让路径指向新的inode，记录下来避免自身的写入被
+    /// [`binary_replaced`](Self::binary_replaced) 误判为外部替换
+    pub(crate) fn write_binary(&mut self, data: &[u8]) -> Result<()> {
+        if self.in_memory {
+            self.pending = Some(data.to_vec());
+            return Ok(());
+        }
+
+        Self::atomic_write(&self.exe_path, data, self.durable)?;
+        self.identity = Self::file_identity(&self.exe_path)?;
+        // 写入的是完整的二进制，之前延迟的修改已包含在内
+        self.pending = None;
+        Ok(())
+    }
+
+    /// 读取二进制内容，延迟写入模式下有未写回的修改时返回修改后的内容
+    pub(crate) fn read_binary(&self) -> Result<Vec<u8>> {
+        match &self.pending {
+            Some(binary_data) => Ok(binary_data.clone()),
+            None => fs::read(&self.exe_path).map_err(Error::io_at(&self.exe_path)),
+        }
+    }
+
+    /// 获取文件的 (设备号, inode)，非Unix平台返回None
+    fn file_identity(path: &Path) -> Result<Option<(u64, u64)>> {
+        let metadata = fs::metadata(path).map_err(Error::io_at(path))?;
+        Ok(Self::metadata_identity(&metadata))
+    }
+
+    /// 从已查询的文件元数据中取出 (设备号, inode)，非Unix平台返回None
+    #[cfg(unix)]
+    pub(crate) fn metadata_identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+        use std::os::unix::fs::MetadataExt;
+
+        Some((metadata.dev(), metadata.ino()))
+    }
+
+    /// 从已查询的文件元数据中取出 (设备号, inode)，非Unix平台返回None
+    #[cfg(not(unix))]
+    pub(crate) fn metadata_identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+        None
+    }
+
+    /// 将数据写入目标文件旁的临时文件，并复制目标文件的权限
+    ///
+    /// 临时文件名为 `<文件名>.tmp.<pid>.<序号>`，同一目录下扩展名不同的目标文件
+    /// （例如 `app.v1` 和 `app.v2`）以及同一进程中的多次写入都不会共用临时文件。
+    /// 返回临时文件路径，由调用者负责rename或清理
+    pub(crate) fn write_temp(path: &Path, data: &[u8], durable: bool) -> Result<PathBuf> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(
+            ".tmp.{}.{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(file_name);
+
+        if let Err(e) = Self::fill_temp(&temp_path, path, data, durable) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        Ok(temp_path)
+    }
+
+    /// 写入临时文件内容、复制权限，并按需fsync
+    fn fill_temp(temp_path: &Path, path: &Path, data: &[u8], durable: bool) -> Result<()> {
+        let mut file = File::create(temp_path).map_err(Error::io_at(temp_path))?;
+        file.write_all(data).map_err(Error::io_at(temp_path))?;
+
+        // 复制权限（目标文件尚不存在时使用默认权限）
+        #[cfg(unix)]
+        match fs::metadata(path) {
+            Ok(metadata) => file
+                .set_permissions(metadata.permissions())
+                .map_err(Error::io_at(temp_path))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::io_at(path)(e)),
+        }
+
+        // 确保数据落盘后再rename，避免断电后留下空文件或截断的文件
+        if durable {
+            file.sync_all().map_err(Error::io_at(temp_path))?;
+        }
+
+        Ok(())
+    }
 
+    /// fsync文件所在的目录，使rename本身持久化
+    pub(crate) fn sync_parent_dir(path: &Path) -> Result<()> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .map_err(Error::io_at(parent))?;
         Ok(())
     }
 }
+
+/// 计算 `KeyStore` 加解密指定分片时使用的密钥
+///
+/// 供外部的预配置工具使用：对目标二进制离线计算出与 `KeyStore` 完全相同的密钥，
+/// 而无需自行实现派生逻辑。二进制中已写入元数据时，会使用其中记录的派生sections
+/// 、KDF迭代次数和该分片的nonce；尚未写入元数据时，返回由 .text 段派生的基础密钥
+///
+/// # 参数
+///
+/// * `binary` - 目标二进制的完整数据
+/// * `shard_name` - 分片的section名称（`.key_data_00` 到 `.key_data_07`）
+/// * `len` - 需要的密钥长度（最多32字节）
+///
+/// # 返回
+///
+/// 成功返回派生密钥，分片名称无效或找不到派生section时返回Error
+///
+/// # 示例
+///
+/// ```no_run
+/// let binary = std::fs::read("/usr/local/bin/app")?;
+/// let key = self_crypto_key::derive_key(&binary, ".key_data_00", 32)?;
+/// assert_eq!(key.len(), 32);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn derive_key(binary: &[u8], shard_name: &str, len: usize) -> Result<Vec<u8>> {
+    if !KeyMetadata::SHARD_NAMES.contains(&shard_name) {
+        return Err(Error::Config(format!("无效的分片名称: {}", shard_name)));
+    }
+
+    match KeyStore::read_metadata(binary, KeyStore::METADATA_SECTION) {
+        Ok(metadata) => {
+            // 不属于当前布局的分片没有nonce，使用基础密钥
+            let index = metadata
+                .shard_names
+                .iter()
+                .position(|name| name == shard_name)
+                .unwrap_or(usize::MAX);
+            let base_key = KeyStore::derive_base_key(&SectionMap::parse(binary)?, &metadata)?;
+            let mut key = KeyStore::derive_shard_key(&base_key, &metadata, index);
+            key.truncate(len);
+            Ok(key)
+        }
+        Err(_) => derive_key_from_section(&parse_binary(binary)?, KeyStore::DERIVE_SECTION, len),
+    }
+}
+
+impl Drop for KeyStore {
+    /// 尽力写回延迟写入模式下未写回的修改，错误被忽略
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{obfuscate, xor_cipher};
+
+    // 读写测试二进制的副本需要存储sections
+    crate::init_key_storage!();
+
+    #[test]
+    fn test_tracer_pid() {
+        let status = "Name:\tserver\nState:\tS (sleeping)\nTracerPid:\t4242\nUid:\t0\t0\t0\t0\n";
+        assert_eq!(KeyStore::tracer_pid(status), Some(4242));
+        assert_eq!(KeyStore::tracer_pid("TracerPid:\t0\n"), Some(0));
+        assert_eq!(KeyStore::tracer_pid("Name:\tserver\n"), None);
+
+        // 测试进程本身通常没有被跟踪，开启检查后仍可正常写入
+        #[cfg(target_os = "linux")]
+        if KeyStore::tracer_pid(&fs::read_to_string("/proc/self/status").unwrap()) == Some(0) {
+            KeyStore::check_not_traced().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_public_derive_key_round_trip() {
+        // 外部工具用公开的派生密钥加密，KeyStore按内部方式解密应得到原文
+        let binary_data = fs::read("/proc/self/exe").unwrap();
+        let tool_key = derive_key(&binary_data, ".key_data_03", 1024).unwrap();
+
+        let metadata = KeyMetadata::generate();
+        let base_key =
+            KeyStore::derive_base_key(&SectionMap::parse(&binary_data).unwrap(), &metadata)
+                .unwrap();
+        let internal_key = KeyStore::derive_shard_key(&base_key, &metadata, 3);
+        assert_eq!(tool_key, internal_key);
+
+        let seed = KeyStore::shard_seed(&metadata, 3);
+        let encrypted = encrypt_shard(b"provisioned", &tool_key, seed);
+        assert_eq!(
+            decrypt_shard(&encrypted, &internal_key, seed),
+            b"provisioned"
+        );
+
+        assert!(derive_key(&binary_data, ".text", 32).is_err());
+    }
+
+    #[test]
+    fn test_public_derive_key_decrypts_written_shard() {
+        // 写入密钥后，外部工具用公开的派生密钥和元数据中的nonce解密分片，应得到read_bytes的结果
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("derive_key");
+        fs::copy("/proc/self/exe", &path).unwrap();
+        let mut store = KeyStore::from_path(&path).unwrap();
+        // 每个字节都相同，任何分片都对应密钥中同样内容的一段，不受逻辑顺序和交错布局影响；
+        // 这样的数据会被压缩，因此关闭压缩
+        store.metadata.compression_threshold = usize::MAX;
+        let key = vec![0x5a; store.capacity()];
+        store.update_bytes(&key).unwrap();
+        let stored = store.read_bytes().unwrap();
+        assert_eq!(stored, key);
+
+        let binary_data = fs::read(&path).unwrap();
+        let metadata = KeyStore::read_metadata(&binary_data, KeyStore::METADATA_SECTION).unwrap();
+        assert_eq!(metadata.shard_nonces.len(), metadata.num_shards);
+
+        let name = &metadata.shard_names[0];
+        let (offset, size) =
+            KeyStore::find_shard_section(&binary_data, name, metadata.shard_sizes[0]).unwrap();
+        let tool_key = derive_key(&binary_data, name, 32).unwrap();
+        let shard = decrypt_shard(
+            &binary_data[offset..offset + size],
+            &tool_key,
+            KeyStore::shard_seed(&metadata, 0),
+        );
+        assert_eq!(shard, stored[..size]);
+    }
+
+    #[test]
+    fn test_cross_endian_seed_material() {
+        // 在小端平台上写入，nonce按小端序参与派生
+        let binary_data = fs::read("/proc/self/exe").unwrap();
+        let mut metadata = KeyMetadata::generate();
+        metadata.shard_nonces = vec![0x1234_5678; metadata.num_shards];
+        let base_key =
+            KeyStore::derive_base_key(&SectionMap::parse(&binary_data).unwrap(), &metadata)
+                .unwrap();
+
+        let plaintext = KeyStore::generate_random_bytes(KeyMetadata::SHARD_SIZE);
+        let key = KeyStore::derive_shard_key(&base_key, &metadata, 1);
+        let encrypted = encrypt_shard(&plaintext, &key, KeyStore::shard_seed(&metadata, 1));
+
+        // 模拟大端平台：内存中的数值字节是翻转的，按大端序取出后得到相同的字节序列
+        let swapped = 0x1234_5678u32.swap_bytes();
+        let be_bytes = swapped.to_be_bytes();
+        assert_eq!(be_bytes, KeyStore::nonce_bytes(0x1234_5678));
+
+        let mut hasher = Sha256::new();
+        hasher.update(&base_key);
+        hasher.update(be_bytes);
+        let be_key = hasher.finalize()[..base_key.len()].to_vec();
+        let be_seed =
+            (SHARD_SEED_OFFSETS[1].wrapping_add(1)) ^ be_bytes.iter().fold(0, |acc, b| acc ^ b);
+
+        assert_eq!(decrypt_shard(&encrypted, &be_key, be_seed), plaintext);
+    }
+
+    /// 构造只包含 .text 和 .shstrtab 两个section的最小ELF64（小端序）
+    fn minimal_elf(text: &[u8]) -> Vec<u8> {
+        let shstrtab = b"\0.text\0.shstrtab\0";
+        let text_offset = 64;
+        let shstrtab_offset = text_offset + text.len();
+        let shoff = (shstrtab_offset + shstrtab.len()).next_multiple_of(8);
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        elf.resize(16, 0);
+        elf.extend_from_slice(&1u16.to_le_bytes()); // ET_REL
+        elf.extend_from_slice(&62u16.to_le_bytes()); // EM_X86_64
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&[0; 16]); // e_entry, e_phoff
+        elf.extend_from_slice(&(shoff as u64).to_le_bytes());
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        for field in [64u16, 0, 0, 64, 3, 2] {
+            elf.extend_from_slice(&field.to_le_bytes());
+        }
+        elf.extend_from_slice(text);
+        elf.extend_from_slice(shstrtab);
+        elf.resize(shoff + 64, 0);
+
+        // (名称偏移, 类型, 标志, 文件偏移, 大小)
+        let headers = [
+            (1u32, 1u32, 6u64, text_offset, text.len()),
+            (7, 3, 0, shstrtab_offset, shstrtab.len()),
+        ];
+        for (name, kind, flags, offset, size) in headers {
+            elf.extend_from_slice(&name.to_le_bytes());
+            elf.extend_from_slice(&kind.to_le_bytes());
+            elf.extend_from_slice(&flags.to_le_bytes());
+            elf.extend_from_slice(&0u64.to_le_bytes());
+            elf.extend_from_slice(&(offset as u64).to_le_bytes());
+            elf.extend_from_slice(&(size as u64).to_le_bytes());
+            elf.extend_from_slice(&[0; 8]);
+            elf.extend_from_slice(&1u64.to_le_bytes());
+            elf.extend_from_slice(&0u64.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn test_fixed_cipher_vectors() {
+        let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+        let elf = minimal_elf(b"self_crypto_key fixed .text vector");
+        let sections = SectionMap::parse(&elf).unwrap();
+        let plaintext = b"fixed plaintext";
+
+        // 已知的 .text 和nonce得到固定的基础密钥、分片密钥和异或层输出
+        let mut metadata = KeyMetadata::fixed();
+        metadata.shard_nonces = vec![0x0102_0304; metadata.num_shards];
+        let base_key = KeyStore::derive_base_key(&sections, &metadata).unwrap();
+        assert_eq!(
+            hex(&base_key),
+            "5a08b4d0a869c003a9dcd79a1b0023eb4c00e120882903b5d8f49bb95b8b619d"
+        );
+        let key = KeyStore::derive_shard_key(&base_key, &metadata, 0);
+        assert_eq!(
+            hex(&key),
+            "f768ff62421ad36370756553442b97fd9798143ebcd173d8207e2174906e5641"
+        );
+        assert_eq!(
+            hex(&xor_cipher(plaintext, &key)),
+            "91018707263aa30f111c0b272153e3"
+        );
+
+        // 混淆层的常量每次编译随机生成，完整密文只能按固定的组合方式校验
+        let seed = KeyStore::shard_seed(&metadata, 0);
+        let ciphertext = encrypt_shard(plaintext, &key, seed);
+        assert_eq!(ciphertext, xor_cipher(&obfuscate(plaintext, seed), &key));
+
+        // 字节序翻转的nonce得到不同的分片密钥和密文，但仍能正确解密
+        let mut swapped = metadata.clone();
+        swapped.shard_nonces = vec![0x0403_0201; swapped.num_shards];
+        let swapped_key = KeyStore::derive_shard_key(&base_key, &swapped, 0);
+        assert_eq!(
+            hex(&swapped_key),
+            "0c69202f389a6a124558cb982ed70ece61ea5934ad56e3eb5c578049b545d06e"
+        );
+        let swapped_seed = KeyStore::shard_seed(&swapped, 0);
+        let swapped_ciphertext = encrypt_shard(plaintext, &swapped_key, swapped_seed);
+        assert_ne!(swapped_ciphertext, ciphertext);
+        assert_eq!(
+            decrypt_shard(&swapped_ciphertext, &swapped_key, swapped_seed),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn test_shard_nonce_changes_key_and_seed() {
+        let binary_data = fs::read("/proc/self/exe").unwrap();
+        let mut metadata = KeyMetadata::generate();
+        let base_key =
+            KeyStore::derive_base_key(&SectionMap::parse(&binary_data).unwrap(), &metadata)
+                .unwrap();
+        let base = KeyStore::derive_shard_key(&base_key, &metadata, 0);
+
+        metadata.shard_nonces = vec![1; metadata.num_shards];
+        let salted = KeyStore::derive_shard_key(&base_key, &metadata, 0);
+        assert_ne!(base, salted);
+        assert_eq!(salted.len(), 32);
+    }
+}
@@ -10,6 +10,11 @@
 //! - **无长度限制**: 支持任意长度的密钥（受限于编译时分配的总容量）
 //! - **Bytes支持**: 同时支持字符串和二进制数据
 //! - **自修改**: 程序可以在运行时修改自身二进制中的密钥数据
+//! - **可选压缩**: 启用 `compression` feature 后，较大的密钥会先压缩再加密存储
+//! - **变更通知**: 启用 `watch` feature 后，可以在其他进程更新密钥时收到通知
+//! - **RustCrypto互操作**: 启用 `rustcrypto` feature 后，可以直接把密钥读取为 `GenericArray`
+//! - **秘密值类型**: 启用 `secrecy` feature 后，可以用 `secrecy` crate 的 `Secret` 类型读写密钥，避免密钥被意外打印
+//! - **安全审计**: 启用 `audit` feature 后，可以导出分片密钥流的样本，用于随机性检验
 //!
 //! ## 安全说明
 //!
@@ -49,14 +54,86 @@
 //! ```
 
 // 内部模块
+#[cfg(feature = "audit")]
+mod audit;
+mod audit_log;
+mod bench;
+mod blob;
+mod cached_reader;
+mod compare;
+#[cfg(feature = "compression")]
+mod compression;
 mod crypto;
+mod dump;
 mod error;
+mod estimate;
+mod fixed;
+mod group;
+mod history;
 mod key_store;
+mod limits;
 mod metadata;
+mod migrate;
+mod note;
+mod preflight;
+mod read_snapshot;
+mod readonly;
+#[cfg(feature = "rustcrypto")]
+mod rustcrypto;
+mod sanity;
+#[cfg(feature = "secrecy")]
+mod secret;
+mod sections;
+mod self_test;
+mod shard_dir;
+mod sidecar;
+mod snapshot;
+mod stale;
+#[cfg(feature = "watch")]
+mod watch;
 
 // 公开导出
+pub use audit_log::{AuditEvent, AuditOperation};
+pub use bench::BenchResult;
+pub use cached_reader::CachedReader;
+pub use compare::{binaries_match, MatchReport};
 pub use error::{Error, Result};
-pub use key_store::KeyStore;
+pub use estimate::CrackEstimate;
+pub use fixed::FixedKeyStore;
+#[cfg(feature = "rustcrypto")]
+pub use generic_array;
+pub use group::update_group;
+#[cfg(feature = "test-utils")]
+pub use group::update_group_with_hook;
+pub use key_store::{
+    derive_key, AuditHook, KeyStore, ProgressCallback, Provenance, SectionStatus, StorageMode,
+};
+pub use limits::Limits;
+pub use metadata::{InterleaveStride, MetaEncoding, Padding, PipelineStage, ShardLayout};
+pub use migrate::MigrationOutcome;
+pub use note::StorageFormat;
+pub use preflight::{preflight, preflight_at};
+pub use read_snapshot::ReadSnapshot;
+pub use readonly::ReadOnlyKeyStore;
+pub use sanity::SanityWarning;
+#[cfg(feature = "secrecy")]
+pub use secrecy::{self, ExposeSecret, Secret};
+pub use self_test::self_test;
+pub use snapshot::Snapshot;
+pub use stale::StaleKeyStore;
+#[cfg(feature = "secrecy")]
+pub use subtle;
+#[cfg(feature = "watch")]
+pub use watch::ChangeEvent;
+
+/// 供 `init_key_storage!` 宏内部使用，不属于公开API
+#[doc(hidden)]
+pub mod __private {
+    /// `.key_meta` section的最小大小
+    pub const MIN_META_SECTION_SIZE: usize = crate::metadata::KeyMetadata::MIN_SECTION_SIZE;
+
+    pub use crate::note::{note_bytes, note_size, Note, NOTE_TYPE_META, NOTE_TYPE_SHARD};
+}
 
 /// 用于在编译时初始化密钥存储空间的宏
 ///
@@ -72,21 +149,73 @@ pub use key_store::KeyStore;
 /// init_key_storage!();
 /// ```
 ///
+/// 元数据较多时可以增大 `.key_meta` section（默认 4KB）：
+///
+/// ```rust
+/// use self_crypto_key::init_key_storage;
+///
+/// init_key_storage!(meta_size = 8192);
+/// ```
+///
+/// 过小的 `meta_size` 会在编译期报错：
+///
+/// ```compile_fail
+/// use self_crypto_key::init_key_storage;
+///
+/// init_key_storage!(meta_size = 64);
+/// ```
+///
+/// 需要与读取ELF note的工具兼容时，可以改为生成带厂商名称的 `SHT_NOTE` sections
+/// （`.note.key_meta`、`.note.key_data_00` 等），读写时需配合
+/// [`KeyStore::with_storage_format`] 使用：
+///
+/// ```rust
+/// use self_crypto_key::init_key_storage;
+///
+/// init_key_storage!(note_vendor = "ACME");
+/// ```
+///
+/// 只存储单个密钥、对体积敏感的场景可以使用固定布局：不生成 `.key_meta`，
+/// 8个分片按序号全部使用，只用8字节的 `.key_len` section记录密钥长度。
+/// 固定布局需要通过 [`KeyStore::new_fixed`] 读写：
+///
+/// ```rust
+/// use self_crypto_key::init_key_storage;
+///
+/// init_key_storage!(fixed);
+/// ```
+///
 /// # 注意
 ///
 /// - 此宏只能在程序中调用一次
 /// - 生成的 sections 命名为 `.key_data_00` 到 `.key_data_07`
-/// - 元数据 section 命名为 `.key_meta`，大小为 4KB
+/// - 元数据 section 命名为 `.key_meta`，默认大小为 4KB，开头为固定大小的头部
+///   （`KeyMetadata::HEADER_SIZE` 字节，存储实际密钥长度和JSON长度），其后为JSON元数据
+/// - 可以通过 [`KeyStore::metadata_usage`] 查看元数据空间的使用情况
 /// - 总容量为 8KB（8个1KB的shards）
+/// - 生成的 static 带有 `#[used]` 和 `#[no_mangle]`，以免被链接器回收；若在 musl
+///   静态构建中仍然报告缺少section，可以使用 `-C link-args=-Wl,--no-gc-sections` 构建
+/// - 链接器可能按对齐要求把 shard section 填充得比 1KB 更大，此时每个分片仍只使用
+///   section 开头的 1KB，填充字节保持不变，不计入容量
 #[macro_export]
 macro_rules! init_key_storage {
     () => {
-        // 元数据section（固定名称，4KB）
-        // 前8字节存储实际密钥长度，后续存储JSON元数据
+        $crate::init_key_storage!(meta_size = 4096);
+    };
+
+    (meta_size = $meta_size:expr) => {
+        // 编译期检查元数据section能容纳头部和JSON元数据
+        const _: () = assert!(
+            $meta_size >= $crate::__private::MIN_META_SECTION_SIZE,
+            "meta_size 过小，无法容纳元数据头部和JSON"
+        );
+
+        // 元数据section（固定名称）
+        // 头部存储实际密钥长度，后续存储JSON元数据
         #[link_section = ".key_meta"]
         #[used]
         #[no_mangle]
-        static KEY_METADATA: [u8; 4096] = [0u8; 4096];
+        static KEY_METADATA: [u8; $meta_size] = [0u8; $meta_size];
 
         // 数据存储sections（8个，每个1KB）
         #[link_section = ".key_data_00"]
@@ -129,4 +258,148 @@ macro_rules! init_key_storage {
         #[no_mangle]
         static SHARD_07: [u8; 1024] = [0u8; 1024];
     };
+
+    (note_vendor = $vendor:expr) => {
+        // 元数据note section，描述数据部分的布局与 `.key_meta` 相同
+        #[link_section = ".note.key_meta"]
+        #[used]
+        #[no_mangle]
+        static KEY_METADATA: $crate::__private::Note<
+            { $crate::__private::note_size($vendor, 4096) },
+        > = $crate::__private::Note($crate::__private::note_bytes(
+            $vendor,
+            $crate::__private::NOTE_TYPE_META,
+            4096,
+        ));
+
+        // 数据存储note sections（8个，描述数据各1KB）
+        #[link_section = ".note.key_data_00"]
+        #[used]
+        #[no_mangle]
+        static SHARD_00: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_01"]
+        #[used]
+        #[no_mangle]
+        static SHARD_01: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_02"]
+        #[used]
+        #[no_mangle]
+        static SHARD_02: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_03"]
+        #[used]
+        #[no_mangle]
+        static SHARD_03: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_04"]
+        #[used]
+        #[no_mangle]
+        static SHARD_04: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_05"]
+        #[used]
+        #[no_mangle]
+        static SHARD_05: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_06"]
+        #[used]
+        #[no_mangle]
+        static SHARD_06: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+
+        #[link_section = ".note.key_data_07"]
+        #[used]
+        #[no_mangle]
+        static SHARD_07: $crate::__private::Note<{ $crate::__private::note_size($vendor, 1024) }> =
+            $crate::__private::Note($crate::__private::note_bytes(
+                $vendor,
+                $crate::__private::NOTE_TYPE_SHARD,
+                1024,
+            ));
+    };
+
+    (fixed) => {
+        // 固定布局没有元数据section，只有8字节的密钥长度头部
+        #[link_section = ".key_len"]
+        #[used]
+        #[no_mangle]
+        static KEY_LENGTH: [u8; 8] = [0u8; 8];
+
+        // 数据存储sections（8个，每个1KB，按序号全部使用）
+        #[link_section = ".key_data_00"]
+        #[used]
+        #[no_mangle]
+        static SHARD_00: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_01"]
+        #[used]
+        #[no_mangle]
+        static SHARD_01: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_02"]
+        #[used]
+        #[no_mangle]
+        static SHARD_02: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_03"]
+        #[used]
+        #[no_mangle]
+        static SHARD_03: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_04"]
+        #[used]
+        #[no_mangle]
+        static SHARD_04: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_05"]
+        #[used]
+        #[no_mangle]
+        static SHARD_05: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_06"]
+        #[used]
+        #[no_mangle]
+        static SHARD_06: [u8; 1024] = [0u8; 1024];
+
+        #[link_section = ".key_data_07"]
+        #[used]
+        #[no_mangle]
+        static SHARD_07: [u8; 1024] = [0u8; 1024];
+    };
 }
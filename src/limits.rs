@@ -0,0 +1,134 @@
+//! 处理不可信二进制时的输入大小限制
+
+use crate::error::{Error, Result};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// 打开不可信二进制时的合理性限制
+///
+/// 解析section需要完整解析ELF结构，读写一次密钥会多次解析。对于来源不可信的文件，
+/// 超大的文件或数量异常的section会让这些操作变得非常慢。设置限制后，只读取文件大小
+/// 和ELF头部就能在做任何耗时工作之前拒绝这类输入
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// 二进制文件的最大字节数
+    pub max_binary_size: u64,
+    /// section的最大数量
+    pub max_sections: u64,
+}
+
+impl Default for Limits {
+    /// 默认限制：256MB、4096个section，对正常的程序足够宽松
+    fn default() -> Self {
+        Self {
+            max_binary_size: 256 * 1024 * 1024,
+            max_sections: 4096,
+        }
+    }
+}
+
+impl Limits {
+    /// 检查文件是否在限制之内，只读取文件大小和ELF头部
+    ///
+    /// 超出限制时返回 `Error::Parse`
+    pub fn check_file(&self, path: &Path) -> Result<()> {
+        let size = fs::metadata(path).map_err(Error::io_at(path))?.len();
+        if size > self.max_binary_size {
+            return Err(Error::Parse(format!(
+                "二进制文件过大: {} 字节 (上限 {})",
+                size, self.max_binary_size
+            )));
+        }
+
+        let mut file = File::open(path).map_err(Error::io_at(path))?;
+        let sections = section_count(&mut file).map_err(Error::io_at(path))?;
+        if let Some(count) = sections {
+            if count > self.max_sections {
+                return Err(Error::Parse(format!(
+                    "section数量过多: {} (上限 {})",
+                    count, self.max_sections
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 从ELF头部读取section数量，不是ELF文件时返回None（交给后续解析报错）
+fn section_count<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<u64>> {
+    let mut ident = [0u8; 64];
+    let read = reader.read(&mut ident)?;
+    if read < 52 || &ident[..4] != b"\x7fELF" {
+        return Ok(None);
+    }
+
+    let is_64 = ident[4] == 2;
+    let little = ident[5] == 1;
+    let u16_at = |o: usize| {
+        let bytes = [ident[o], ident[o + 1]];
+        if little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    };
+    let uint_at = |buf: &[u8], o: usize, len: usize| {
+        let mut value = 0u64;
+        for i in 0..len {
+            let byte = if little {
+                buf[o + len - 1 - i]
+            } else {
+                buf[o + i]
+            };
+            value = (value << 8) | byte as u64;
+        }
+        value
+    };
+
+    let (shoff, shnum) = if is_64 {
+        if read < 64 {
+            return Ok(None);
+        }
+        (uint_at(&ident, 0x28, 8), u16_at(0x3c))
+    } else {
+        (uint_at(&ident, 0x20, 4), u16_at(0x30))
+    };
+
+    if shnum != 0 || shoff == 0 {
+        return Ok(Some(shnum as u64));
+    }
+
+    // section数量超过0xff00时，实际数量记录在第0个section头部的sh_size中
+    let (size_offset, size_len) = if is_64 { (32, 8) } else { (20, 4) };
+    let mut field = [0u8; 8];
+    reader.seek(SeekFrom::Start(shoff + size_offset))?;
+    reader.read_exact(&mut field[..size_len])?;
+    Ok(Some(uint_at(&field, 0, size_len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_section_count_from_header() {
+        let exe = fs::read("/proc/self/exe").unwrap();
+        let count = section_count(&mut Cursor::new(&exe)).unwrap().unwrap();
+        let parsed = {
+            use object::Object;
+            object::File::parse(exe.as_slice())
+                .unwrap()
+                .sections()
+                .count() as u64
+        };
+        assert_eq!(count, parsed);
+
+        assert_eq!(
+            section_count(&mut Cursor::new(b"not an elf")).unwrap(),
+            None
+        );
+    }
+}
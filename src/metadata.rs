@@ -19,11 +19,305 @@ pub struct KeyMetadata {
 
     /// 版本信息
     pub version: u32,
+
+    /// 压缩阈值：密钥长度达到该值时才尝试压缩（需要 `compression` feature）
+    #[serde(default = "KeyMetadata::default_compression_threshold")]
+    pub compression_threshold: usize,
+
+    /// 最近一次写入的数据是否经过压缩
+    #[serde(default)]
+    pub compressed: bool,
+
+    /// 压缩时使用的字典的标识，为空表示没有使用字典；以 `zstd:` 开头表示使用Zstandard压缩
+    #[serde(default)]
+    pub dictionary_id: String,
+
+    /// 是否已经写入过密钥（包括显式写入的空密钥）
+    #[serde(default)]
+    pub initialized: bool,
+
+    /// 用于派生加密密钥的sections，为空时使用 .text 段
+    #[serde(default)]
+    pub derive_sections: Vec<String>,
+
+    /// 每个分片最近一次写入时使用的随机nonce
+    #[serde(default)]
+    pub shard_nonces: Vec<u32>,
+
+    /// 用PBKDF2拉伸派生密钥的迭代次数，0表示不拉伸
+    #[serde(default)]
+    pub kdf_iterations: u32,
+
+    /// 密钥字节在分片间的排列方式
+    #[serde(default)]
+    pub layout: ShardLayout,
+
+    /// 交错布局下每次放入一个分片的字节数
+    #[serde(default)]
+    pub interleave_stride: InterleaveStride,
+
+    /// 密钥长度的记录方式
+    #[serde(default)]
+    pub padding: Padding,
+
+    /// 与当前密钥一起保存的历史版本数量，0表示不保存
+    #[serde(default)]
+    pub history: usize,
+
+    /// 混入外部因子时使用的盐，为空表示没有使用外部因子
+    #[serde(default)]
+    pub factor_salt: Vec<u8>,
+
+    /// 用于确认外部因子正确的校验值
+    #[serde(default)]
+    pub factor_check: Vec<u8>,
+
+    /// 存储数据的完整性校验值，为空表示不校验（旧版本写入）
+    #[serde(default)]
+    pub payload_check: Vec<u8>,
+
+    /// 写入时派生sections（默认为 .text 段）的校验值，为空表示没有记录（旧版本写入）
+    #[serde(default)]
+    pub binding_check: Vec<u8>,
+
+    /// 遮盖头部中密钥长度时使用的nonce，为空表示长度以明文存储
+    #[serde(default)]
+    pub length_nonce: Vec<u8>,
+
+    /// 写入时密钥数据依次经过的处理步骤，读取时按相反顺序还原；为空表示未记录（旧版本写入）
+    #[serde(default)]
+    pub pipeline: Vec<PipelineStage>,
+
+    /// 每个分片4字节的绑定标签，依次拼接；为空表示没有开启分片绑定
+    #[serde(default)]
+    pub shard_tags: Vec<u8>,
+
+    /// 写入密钥的二进制所用编译时加密常量的指纹，为空表示未记录（旧版本写入）
+    #[serde(default)]
+    pub constants_fingerprint: String,
+
+    /// 每个分片在密钥中的逻辑顺序：`logical_index[i]` 为第i个分片是密钥的第几段
+    ///
+    /// 为空时（旧版本元数据）逻辑顺序与数组顺序相同
+    #[serde(default)]
+    pub logical_index: Vec<usize>,
+
+    /// 写入代数：每次写入密钥时加1，用于检测其他进程的并发写入
+    #[serde(default)]
+    pub write_generation: u64,
+
+    /// 外部因子本身，只保存在内存中，从不写入二进制
+    #[serde(skip)]
+    pub external_factor: Option<ExternalFactor>,
+
+    /// 压缩字典本身，只保存在内存中，从不写入二进制
+    #[serde(skip)]
+    pub compression_dictionary: Option<Vec<u8>>,
+
+    /// 写入时使用压缩字典的算法，只保存在内存中；读取时以 `dictionary_id` 为准
+    #[serde(skip)]
+    pub dictionary_codec: CompressionCodec,
+
+    /// 写入 `.key_meta` 时使用的编码，读取时由头部中的标记决定
+    #[serde(skip)]
+    pub encoding: MetaEncoding,
+}
+
+/// 元数据在 `.key_meta` section中的编码方式
+///
+/// 编码标记保存在头部JSON长度字段的最高字节中，读取时自动识别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetaEncoding {
+    /// JSON（默认），便于用 `strings` 等工具直接查看
+    #[default]
+    Json,
+    /// CBOR（RFC 8949），字段名和数字更紧凑，适合需要较多配置的场景
+    ///
+    /// 不提供bincode等非自描述的编码：元数据的很多字段是后续版本加入的（`#[serde(default)]`），
+    /// 这类编码按字段顺序读取，无法解码缺少字段的旧版本元数据
+    Cbor,
+}
+
+impl MetaEncoding {
+    /// 头部中记录的编码标记
+    ///
+    /// JSON使用0，与未记录编码的旧版本头部兼容
+    const fn tag(self) -> u8 {
+        match self {
+            MetaEncoding::Json => 0,
+            MetaEncoding::Cbor => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self> {
+        match tag {
+            0 => Ok(MetaEncoding::Json),
+            1 => Ok(MetaEncoding::Cbor),
+            _ => Err(Error::Parse(format!("未知的元数据编码: {}", tag))),
+        }
+    }
+}
+
+/// 外部因子（例如U盘上的文件或运行时获取的值）
+///
+/// `Debug` 输出不包含内容，丢弃时清零内存
+#[derive(Clone)]
+pub struct ExternalFactor(pub Vec<u8>);
+
+impl std::fmt::Debug for ExternalFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ExternalFactor([REDACTED])")
+    }
+}
+
+impl Drop for ExternalFactor {
+    fn drop(&mut self) {
+        crate::crypto::zeroize(&mut self.0);
+    }
+}
+
+/// 写入密钥时数据经过的处理步骤
+///
+/// 唯一支持的顺序为 压缩 → 填充到总容量 → 混淆 → 异或加密：先压缩再加密，
+/// 并且压缩后的数据总是填充到全部分片的容量，分片中看不出压缩后的长度。
+/// 先加密再压缩既无法压缩也会泄露信息，读取时会被拒绝
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PipelineStage {
+    /// 压缩（需要 `compression` feature，只在压缩后更小时使用）
+    Compress,
+    /// 用零字节填充到全部分片的总容量
+    Pad,
+    /// 按分片种子混淆字节
+    Obfuscate,
+    /// 与派生密钥异或
+    Xor,
+}
+
+/// 密钥字节在分片间的排列方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShardLayout {
+    /// 按顺序切成连续的块，依次放入各分片（默认）
+    #[default]
+    Contiguous,
+    /// 轮流放入各分片：第 `j` 个字节放入第 `j % 分片数` 个分片，
+    /// 单个分片只包含间隔的字节，而不是一段连续的明文
+    Interleaved,
+}
+
+/// 交错布局（[`ShardLayout::Interleaved`]）下每次放入一个分片的字节数，默认为1
+///
+/// 为 `n` 时密钥按 `n` 字节一组轮流放入各分片，下游按字（例如4字节）读取分片时
+/// 每组不会被拆开。连续布局下不起作用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InterleaveStride(pub usize);
+
+impl Default for InterleaveStride {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+/// 使用压缩字典时的压缩算法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionCodec {
+    /// DEFLATE（默认），字典只能回溯最后32KB
+    #[default]
+    Deflate,
+    /// Zstandard（需要 `zstd` feature），整个字典都可被引用
+    Zstd,
+}
+
+/// 密钥长度的记录方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Padding {
+    /// 长度记录在 `.key_meta` 头部，之后用零字节填充到总容量（默认）
+    #[default]
+    HeaderLength,
+    /// 自描述填充（ISO/IEC 7816-4）：数据之后追加一个 `0x80` 再用零字节填充到总容量，
+    /// 读取时从解密结果的末尾还原长度。头部只记录总容量，不再暴露密钥长度；
+    /// 代价是可用容量减少1字节
+    SelfDescribing,
+}
+
+impl CompressionCodec {
+    /// Zstandard字典标识的前缀
+    pub const ZSTD_PREFIX: &'static str = "zstd:";
+
+    /// 根据元数据中记录的字典标识判断写入时使用的压缩算法
+    pub fn of_dictionary_id(dictionary_id: &str) -> Self {
+        if dictionary_id.starts_with(Self::ZSTD_PREFIX) {
+            CompressionCodec::Zstd
+        } else {
+            CompressionCodec::Deflate
+        }
+    }
+}
+
+impl Padding {
+    /// 自描述填充的结束标记
+    const MARKER: u8 = 0x80;
+
+    /// 填充方式在数据之外额外占用的容量（字节）
+    pub(crate) fn overhead(self) -> usize {
+        match self {
+            Padding::HeaderLength => 0,
+            Padding::SelfDescribing => 1,
+        }
+    }
+
+    /// 按填充方式处理写入的数据：自描述填充时追加结束标记并填充到 `capacity`
+    ///
+    /// # 返回
+    ///
+    /// 成功返回填充后的数据；数据加上结束标记超出容量时返回Error
+    pub(crate) fn apply(self, mut payload: Vec<u8>, capacity: usize) -> Result<Vec<u8>> {
+        if self == Padding::HeaderLength {
+            return Ok(payload);
+        }
+
+        if payload.len() >= capacity {
+            return Err(Error::Config(format!(
+                "密钥长度({})超出总容量({}), 自描述填充需要额外1字节",
+                payload.len(),
+                capacity
+            )));
+        }
+        payload.push(Self::MARKER);
+        payload.resize(capacity, 0);
+        Ok(payload)
+    }
+
+    /// 去除填充，还原写入时的数据
+    ///
+    /// # 返回
+    ///
+    /// 成功返回原始数据；自描述填充的末尾没有结束标记时返回 `Error::IntegrityCheckFailed`
+    pub(crate) fn strip(self, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+        if self == Padding::HeaderLength {
+            return Ok(payload);
+        }
+
+        match payload.iter().rposition(|&b| b != 0) {
+            Some(end) if payload[end] == Self::MARKER => {
+                payload.truncate(end);
+                Ok(payload)
+            }
+            _ => Err(Error::IntegrityCheckFailed(
+                "自描述填充中没有找到结束标记".to_string(),
+            )),
+        }
+    }
 }
 
 impl KeyMetadata {
     /// 当前版本号
-    pub const VERSION: u32 = 1;
+    ///
+    /// 版本2起头部记录JSON长度和编码标记；版本1的JSON紧跟在8字节密钥长度之后
+    pub const VERSION: u32 = 2;
+
+    /// 没有JSON长度字段的旧版本头部中记录的版本号
+    const LEGACY_VERSION: u32 = 1;
 
     /// 预定义的shard section名称（固定8个）
     pub const SHARD_NAMES: [&'static str; 8] = [
@@ -37,22 +331,78 @@ impl KeyMetadata {
         ".key_data_07",
     ];
 
+    /// 随机生成配置时使用的最小分片数量
+    pub const MIN_SHARDS: usize = 4;
+
     /// 每个shard的标准大小（1KB）
     pub const SHARD_SIZE: usize = 1024;
 
+    /// `.key_meta` section头部的大小：8字节密钥长度 + 8字节JSON长度
+    ///
+    /// JSON元数据从该偏移处开始存放。JSON长度字段的最高字节记录元数据的编码
+    pub const HEADER_SIZE: usize = 16;
+
+    /// 头部中编码标记的偏移（JSON长度字段的最高字节）
+    const ENCODING_TAG_OFFSET: usize = Self::HEADER_SIZE - 1;
+
+    /// 旧版本头部的大小（只有8字节密钥长度，JSON紧随其后且没有记录长度）
+    const LEGACY_HEADER_SIZE: usize = 8;
+
+    /// 为JSON元数据预留的最小空间（足以容纳8个分片的完整配置）
+    pub const MIN_JSON_SIZE: usize = 1280;
+
+    /// 默认压缩阈值（字节），更小的密钥压缩后通常反而变大
+    pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 64;
+
+    /// `.key_meta` section的最小大小
+    pub const MIN_SECTION_SIZE: usize = Self::HEADER_SIZE + Self::MIN_JSON_SIZE;
+
     /// 生成新的元数据配置
     ///
     /// 随机决定使用4-8个分片
     pub fn generate() -> Self {
+        Self::generate_within(&[0, 1, 2, 3, 4, 5, 6, 7]).expect("全部8个分片总是满足最小分片数量")
+    }
+
+    /// 只从允许的分片序号中随机选择，生成新的元数据配置
+    ///
+    /// 随机决定使用 `MIN_SHARDS` 到允许数量（最多8个）之间的分片
+    ///
+    /// # 参数
+    ///
+    /// * `allowed` - 允许使用的分片序号（0-7），重复的序号只计一次
+    ///
+    /// # 返回
+    ///
+    /// 成功返回元数据；序号超出范围或允许的分片少于 `MIN_SHARDS` 个时返回Error
+    pub fn generate_within(allowed: &[usize]) -> Result<Self> {
+        use rand::seq::SliceRandom;
         use rand::Rng;
+
+        let mut available_indices = allowed.to_vec();
+        available_indices.sort_unstable();
+        available_indices.dedup();
+
+        if let Some(&index) = available_indices
+            .iter()
+            .find(|&&i| i >= Self::SHARD_NAMES.len())
+        {
+            return Err(Error::Config(format!("无效的分片序号: {}", index)));
+        }
+        if available_indices.len() < Self::MIN_SHARDS {
+            return Err(Error::Config(format!(
+                "允许的分片数量({})少于最小分片数量({})",
+                available_indices.len(),
+                Self::MIN_SHARDS
+            )));
+        }
+
         let mut rng = rand::thread_rng();
 
-        // 随机选择4-8个分片
-        let num_shards = rng.gen_range(4..=8);
+        // 随机选择分片数量
+        let num_shards = rng.gen_range(Self::MIN_SHARDS..=available_indices.len());
 
         // 使用预定义的section名称
-        use rand::seq::SliceRandom;
-        let mut available_indices: Vec<usize> = (0..8).collect();
         available_indices.shuffle(&mut rng);
 
         let shard_names: Vec<String> = available_indices
@@ -63,38 +413,277 @@ impl KeyMetadata {
 
         let shard_sizes = vec![Self::SHARD_SIZE; num_shards];
 
-        Self {
+        // 逻辑顺序也随机决定，与数组顺序和section在文件中的顺序都无关
+        let mut logical_index: Vec<usize> = (0..num_shards).collect();
+        logical_index.shuffle(&mut rng);
+
+        Ok(Self {
             num_shards,
             shard_sizes,
             shard_names,
             version: Self::VERSION,
+            compression_threshold: Self::DEFAULT_COMPRESSION_THRESHOLD,
+            compressed: false,
+            dictionary_id: String::new(),
+            initialized: false,
+            derive_sections: Vec::new(),
+            shard_nonces: Vec::new(),
+            kdf_iterations: 0,
+            layout: ShardLayout::default(),
+            interleave_stride: InterleaveStride::default(),
+            padding: Padding::default(),
+            history: 0,
+            factor_salt: Vec::new(),
+            factor_check: Vec::new(),
+            payload_check: Vec::new(),
+            binding_check: Vec::new(),
+            length_nonce: Vec::new(),
+            pipeline: Vec::new(),
+            shard_tags: Vec::new(),
+            constants_fingerprint: String::new(),
+            logical_index,
+            write_generation: 0,
+            external_factor: None,
+            compression_dictionary: None,
+            dictionary_codec: CompressionCodec::default(),
+            encoding: MetaEncoding::default(),
+        })
+    }
+
+    /// 固定布局：按序号使用全部8个分片，不记录nonce
+    ///
+    /// 用于 `init_key_storage!(fixed)`，布局在编译时即已确定，无需存储元数据
+    pub fn fixed() -> Self {
+        let num_shards = Self::SHARD_NAMES.len();
+
+        Self {
+            num_shards,
+            shard_sizes: vec![Self::SHARD_SIZE; num_shards],
+            shard_names: Self::SHARD_NAMES.iter().map(|n| n.to_string()).collect(),
+            version: Self::VERSION,
+            compression_threshold: Self::DEFAULT_COMPRESSION_THRESHOLD,
+            compressed: false,
+            dictionary_id: String::new(),
+            initialized: false,
+            derive_sections: Vec::new(),
+            shard_nonces: Vec::new(),
+            kdf_iterations: 0,
+            layout: ShardLayout::default(),
+            interleave_stride: InterleaveStride::default(),
+            padding: Padding::default(),
+            history: 0,
+            factor_salt: Vec::new(),
+            factor_check: Vec::new(),
+            payload_check: Vec::new(),
+            binding_check: Vec::new(),
+            length_nonce: Vec::new(),
+            pipeline: Vec::new(),
+            shard_tags: Vec::new(),
+            constants_fingerprint: String::new(),
+            logical_index: (0..num_shards).collect(),
+            write_generation: 0,
+            external_factor: None,
+            compression_dictionary: None,
+            dictionary_codec: CompressionCodec::default(),
+            encoding: MetaEncoding::default(),
         }
     }
 
-    /// 从JSON字节反序列化
-    pub fn from_bytes(data: &[u8]) -> Result<Self> {
-        // 查找JSON的开始和结束位置
-        let json_start = data
-            .iter()
-            .position(|&b| b == b'{')
-            .ok_or_else(|| Error::Parse("未找到元数据JSON开始标记".to_string()))?;
+    /// 写入时使用的处理步骤
+    ///
+    /// # 参数
+    ///
+    /// * `compressed` - 数据是否经过压缩
+    pub fn pipeline_for(compressed: bool) -> Vec<PipelineStage> {
+        let mut stages = vec![
+            PipelineStage::Pad,
+            PipelineStage::Obfuscate,
+            PipelineStage::Xor,
+        ];
+        if compressed {
+            stages.insert(0, PipelineStage::Compress);
+        }
+        stages
+    }
+
+    /// 检查记录的处理步骤是否为支持的顺序，并与压缩标记一致
+    ///
+    /// 没有记录处理步骤（旧版本写入）时跳过
+    pub fn check_pipeline(&self) -> Result<()> {
+        if self.pipeline.is_empty() {
+            return Ok(());
+        }
 
-        let json_end = data
+        let compress = self
+            .pipeline
             .iter()
-            .rposition(|&b| b == b'}')
-            .ok_or_else(|| Error::Parse("未找到元数据JSON结束标记".to_string()))?;
+            .position(|s| *s == PipelineStage::Compress);
+        let xor = self.pipeline.iter().position(|s| *s == PipelineStage::Xor);
+        if let (Some(compress), Some(xor)) = (compress, xor) {
+            if compress > xor {
+                return Err(Error::Config(format!(
+                    "拒绝先加密后压缩的处理流程: {:?}",
+                    self.pipeline
+                )));
+            }
+        }
+
+        if self.pipeline != Self::pipeline_for(self.compressed) {
+            return Err(Error::Config(format!(
+                "不支持的处理流程: {:?}，期望 {:?}",
+                self.pipeline,
+                Self::pipeline_for(self.compressed)
+            )));
+        }
+
+        Ok(())
+    }
 
-        if json_start > json_end {
-            return Err(Error::Parse("无效的JSON范围".to_string()));
+    /// 调用方指定的布局：按给定顺序使用全部分片
+    ///
+    /// 用于 [`KeyStore::with_explicit_sections`](crate::KeyStore::with_explicit_sections)，
+    /// 分片名称和大小来自调用方声明的section，而不是 `SHARD_NAMES`
+    ///
+    /// # 参数
+    ///
+    /// * `names` - shard section名称，同时也是逻辑顺序
+    /// * `sizes` - 每个shard section的大小
+    pub fn explicit(names: &[&str], sizes: &[usize]) -> Self {
+        Self {
+            num_shards: names.len(),
+            shard_sizes: sizes.to_vec(),
+            shard_names: names.iter().map(|n| n.to_string()).collect(),
+            logical_index: (0..names.len()).collect(),
+            ..Self::fixed()
         }
+    }
+
+    /// serde默认值：旧版本元数据中没有压缩阈值字段
+    fn default_compression_threshold() -> usize {
+        Self::DEFAULT_COMPRESSION_THRESHOLD
+    }
 
-        let json_bytes = &data[json_start..=json_end];
-        serde_json::from_slice(json_bytes).map_err(Error::from)
+    /// 按指定编码反序列化，返回的元数据之后也使用该编码写入
+    ///
+    /// `data` 必须恰好是一个完整的值，前后不能有其他内容
+    pub fn from_bytes(data: &[u8], encoding: MetaEncoding) -> Result<Self> {
+        let mut metadata: Self = match encoding {
+            MetaEncoding::Json => serde_json::from_slice(data)?,
+            MetaEncoding::Cbor => Self::from_cbor(data)?,
+        };
+        metadata.encoding = encoding;
+        Ok(metadata)
     }
 
-    /// 序列化为JSON字节
+    /// 从完整的 `.key_meta` section内容解析元数据
+    ///
+    /// 按头部记录的JSON长度精确读取 `HEADER_SIZE` 之后的JSON，长度之后的内容被忽略。
+    /// 兼容旧版本写入的没有JSON长度的头部
+    pub fn from_section(section: &[u8]) -> Result<Self> {
+        if section.len() < Self::HEADER_SIZE {
+            return Err(Error::Parse("元数据section小于头部大小".to_string()));
+        }
+
+        // 新格式的JSON从HEADER_SIZE开始；旧格式的JSON从8字节处开始，
+        // 且该处总是 `{"num_sh`，因此HEADER_SIZE处不会是 `{`，编码标记处也不是有效的标记
+        let tag = section[Self::ENCODING_TAG_OFFSET];
+        if MetaEncoding::from_tag(tag).is_err()
+            && section[Self::HEADER_SIZE] != b'{'
+            && section[Self::LEGACY_HEADER_SIZE] == b'{'
+        {
+            return Self::from_legacy_bytes(&section[Self::LEGACY_HEADER_SIZE..]);
+        }
+        let encoding = MetaEncoding::from_tag(tag)?;
+
+        let mut len_bytes = [0u8; 8];
+        len_bytes[..7]
+            .copy_from_slice(&section[Self::LEGACY_HEADER_SIZE..Self::ENCODING_TAG_OFFSET]);
+        let json_len = u64::from_le_bytes(len_bytes) as usize;
+
+        if json_len == 0 {
+            return Err(Error::Parse("元数据尚未写入".to_string()));
+        }
+
+        let json = section
+            .get(Self::HEADER_SIZE..)
+            .and_then(|rest| rest.get(..json_len))
+            .ok_or_else(|| Error::Parse(format!("元数据JSON长度超出section范围: {}", json_len)))?;
+
+        Self::from_bytes(json, encoding)
+    }
+
+    /// 解析旧版本格式：JSON紧跟在8字节密钥长度之后，没有长度字段
+    ///
+    /// 旧版本写入时不会清除之前更长的JSON留下的尾部，因此只解析开头的一个JSON对象。
+    /// 旧版本的元数据中没有嵌套对象，之后再出现 `{` 或版本号不是1时，说明并非旧版本写入，直接拒绝
+    fn from_legacy_bytes(data: &[u8]) -> Result<Self> {
+        let mut stream = serde_json::Deserializer::from_slice(data).into_iter::<Self>();
+        let metadata = stream
+            .next()
+            .ok_or_else(|| Error::Parse("未找到元数据JSON".to_string()))??;
+
+        if data[stream.byte_offset()..].contains(&b'{') {
+            return Err(Error::Parse("旧版本元数据之后还有其他JSON对象".to_string()));
+        }
+        if metadata.version != Self::LEGACY_VERSION {
+            return Err(Error::Parse(format!(
+                "旧版本头部中的元数据版本不正确: {}",
+                metadata.version
+            )));
+        }
+
+        Ok(metadata)
+    }
+
+    /// 将JSON长度和JSON元数据写入 `.key_meta` section（不修改头部中的密钥长度）
+    ///
+    /// JSON之后的旧内容会被清零
+    pub fn write_to_section(&self, section: &mut [u8]) -> Result<()> {
+        let json_bytes = self.to_bytes()?;
+
+        // 检查空间是否足够
+        if json_bytes.len() + Self::HEADER_SIZE > section.len() {
+            return Err(Error::Config(format!(
+                "元数据section空间不足: {} + {} > {}",
+                json_bytes.len(),
+                Self::HEADER_SIZE,
+                section.len()
+            )));
+        }
+
+        section[Self::LEGACY_HEADER_SIZE..Self::HEADER_SIZE]
+            .copy_from_slice(&(json_bytes.len() as u64).to_le_bytes());
+        section[Self::ENCODING_TAG_OFFSET] = self.encoding.tag();
+
+        // 清空旧的JSON后写入（紧跟在头部之后）
+        let json_start = Self::HEADER_SIZE;
+        section[json_start..].fill(0);
+        section[json_start..json_start + json_bytes.len()].copy_from_slice(&json_bytes);
+
+        Ok(())
+    }
+
+    /// 按 `encoding` 序列化（默认为JSON）
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
-        serde_json::to_vec(self).map_err(Error::from)
+        match self.encoding {
+            MetaEncoding::Json => serde_json::to_vec(self).map_err(Error::from),
+            MetaEncoding::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(self, &mut out)
+                    .map_err(|e| Error::Parse(format!("CBOR编码失败: {}", e)))?;
+                Ok(out)
+            }
+        }
+    }
+
+    /// 解码CBOR，`data` 之后不能有多余内容
+    fn from_cbor(mut data: &[u8]) -> Result<Self> {
+        let metadata = ciborium::from_reader(&mut data)
+            .map_err(|e| Error::Parse(format!("CBOR解码失败: {}", e)))?;
+        if !data.is_empty() {
+            return Err(Error::Parse("CBOR数据之后有多余内容".to_string()));
+        }
+        Ok(metadata)
     }
 
     /// 计算总容量（所有shard的大小之和）
@@ -102,6 +691,92 @@ impl KeyMetadata {
         self.shard_sizes.iter().sum()
     }
 
+    /// 按布局把填充到总容量的数据拆分到各分片
+    pub fn split_payload(&self, padded: &[u8]) -> Vec<Vec<u8>> {
+        let mut shards: Vec<Vec<u8>> = self
+            .shard_sizes
+            .iter()
+            .map(|&size| Vec::with_capacity(size))
+            .collect();
+
+        for (j, index) in self.byte_order(padded.len()).into_iter().enumerate() {
+            shards[index].push(padded[j]);
+        }
+
+        shards
+    }
+
+    /// 按布局把各分片的数据重新拼接，只取前 `len` 字节
+    pub fn join_shards(&self, shards: &[Vec<u8>], len: usize) -> Vec<u8> {
+        let mut cursors = vec![0usize; shards.len()];
+        self.byte_order(len)
+            .into_iter()
+            .map(|index| {
+                let byte = shards[index][cursors[index]];
+                cursors[index] += 1;
+                byte
+            })
+            .collect()
+    }
+
+    /// 重新随机决定分片的逻辑顺序，使用的section不变
+    ///
+    /// 分片多于1个时保证与原来的顺序不同
+    pub fn reshuffle_logical_order(&mut self) {
+        use rand::seq::SliceRandom;
+
+        let previous = self.logical_order();
+        let mut order = previous.clone();
+        if self.num_shards > 1 {
+            while order == previous {
+                order.shuffle(&mut rand::thread_rng());
+            }
+        }
+
+        // order[k] 为第k段所在的分片，反过来得到每个分片是第几段
+        self.logical_index = vec![0; self.num_shards];
+        for (segment, &index) in order.iter().enumerate() {
+            self.logical_index[index] = segment;
+        }
+    }
+
+    /// 按逻辑顺序排列的分片序号（数组下标）
+    pub fn logical_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.num_shards).collect();
+        if self.logical_index.len() == self.num_shards {
+            order.sort_by_key(|&index| self.logical_index[index]);
+        }
+        order
+    }
+
+    /// 计算前 `len` 个字节各自所在的分片序号
+    ///
+    /// 按分片的逻辑顺序排列。交错布局下每次为一个分片选择 `interleave_stride` 个字节，
+    /// 轮流选择分片，已满的分片会被跳过（不足一组时取剩余的字节），因此分片大小不同时也能填满总容量
+    pub fn byte_order(&self, len: usize) -> Vec<usize> {
+        let logical = self.logical_order();
+        let mut order = Vec::with_capacity(len);
+        match self.layout {
+            ShardLayout::Contiguous => {
+                for &index in &logical {
+                    order.extend(std::iter::repeat_n(index, self.shard_sizes[index]));
+                }
+            }
+            ShardLayout::Interleaved => {
+                let mut remaining = self.shard_sizes.clone();
+                while order.len() < len && remaining.iter().any(|&r| r > 0) {
+                    for &index in &logical {
+                        let take = remaining[index].min(self.interleave_stride.0);
+                        order.extend(std::iter::repeat_n(index, take));
+                        remaining[index] -= take;
+                    }
+                }
+            }
+        }
+        order.truncate(len);
+        order
+    }
+
     /// 验证元数据的有效性
     pub fn validate(&self) -> Result<()> {
         if self.num_shards == 0 {
@@ -131,6 +806,23 @@ impl KeyMetadata {
             )));
         }
 
+        self.check_pipeline()?;
+
+        if self.interleave_stride.0 == 0 {
+            return Err(Error::Config("交错步长不能为0".to_string()));
+        }
+
+        if !self.logical_index.is_empty() {
+            let mut sorted = self.logical_index.clone();
+            sorted.sort_unstable();
+            if !sorted.iter().copied().eq(0..self.num_shards) {
+                return Err(Error::Config(format!(
+                    "分片逻辑顺序无效: {:?}",
+                    self.logical_index
+                )));
+            }
+        }
+
         Ok(())
     }
 }
@@ -139,6 +831,172 @@ impl KeyMetadata {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_reshuffle_logical_order() {
+        let mut meta = KeyMetadata::generate();
+        let names = meta.shard_names.clone();
+        for _ in 0..10 {
+            let previous = meta.logical_order();
+            meta.reshuffle_logical_order();
+            assert_ne!(meta.logical_order(), previous);
+            assert_eq!(meta.shard_names, names);
+            meta.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_self_describing_padding() {
+        let padding = Padding::SelfDescribing;
+        // 包括空数据、以0x80或0结尾的数据，以及恰好占满可用容量的数据
+        let cases: [&[u8]; 5] = [b"", b"key", &[0x80, 0x80], &[1, 0, 0], &[0x42; 15]];
+        for data in cases {
+            let padded = padding.apply(data.to_vec(), 16).unwrap();
+            assert_eq!(padded.len(), 16);
+            assert_eq!(padding.strip(padded).unwrap(), data);
+        }
+
+        assert!(padding.apply(vec![0x42; 16], 16).is_err());
+        assert!(padding.strip(vec![0x42, 0, 0]).is_err());
+        assert!(padding.strip(vec![0; 4]).is_err());
+        assert_eq!(
+            Padding::HeaderLength.apply(b"key".to_vec(), 16).unwrap(),
+            b"key"
+        );
+    }
+
+    #[test]
+    fn test_generate_within_allowed_shards() {
+        let allowed = [0, 1, 2, 3];
+        for _ in 0..200 {
+            let meta = KeyMetadata::generate_within(&allowed).unwrap();
+            assert_eq!(meta.num_shards, 4);
+            assert!(meta
+                .shard_names
+                .iter()
+                .all(|n| KeyMetadata::SHARD_NAMES[..4].contains(&n.as_str())));
+        }
+
+        assert!(KeyMetadata::generate_within(&[0, 1, 2]).is_err());
+        assert!(KeyMetadata::generate_within(&[0, 1, 1, 2]).is_err());
+        assert!(KeyMetadata::generate_within(&[0, 1, 2, 8]).is_err());
+    }
+
+    #[test]
+    fn test_cbor_metadata_round_trip() {
+        let mut meta = KeyMetadata::fixed();
+        meta.derive_sections = vec![".text".to_string(), ".rodata".to_string()];
+        meta.shard_nonces = (0..8).map(|i| 0x9e37_79b9u32.wrapping_mul(i + 1)).collect();
+        meta.factor_salt = vec![0xa5; 16];
+        meta.factor_check = vec![0x5a; 16];
+        let json = meta.to_bytes().unwrap();
+
+        meta.encoding = MetaEncoding::Cbor;
+        let cbor = meta.to_bytes().unwrap();
+        assert!(cbor.len() < json.len(), "{} >= {}", cbor.len(), json.len());
+
+        let mut section = vec![0u8; KeyMetadata::MIN_SECTION_SIZE];
+        meta.write_to_section(&mut section).unwrap();
+        let parsed = KeyMetadata::from_section(&section).unwrap();
+        assert_eq!(parsed.encoding, MetaEncoding::Cbor);
+        assert_eq!(parsed.to_bytes().unwrap(), cbor);
+        assert_eq!(parsed.shard_nonces, meta.shard_nonces);
+
+        // 截断或之后有多余内容时拒绝
+        assert!(KeyMetadata::from_bytes(&cbor[..cbor.len() - 1], MetaEncoding::Cbor).is_err());
+        let mut trailing = cbor.clone();
+        trailing.push(0x01);
+        assert!(KeyMetadata::from_bytes(&trailing, MetaEncoding::Cbor).is_err());
+        assert!(KeyMetadata::from_bytes(&[0x81; 64], MetaEncoding::Cbor).is_err());
+    }
+
+    #[test]
+    fn test_logical_order_independent_of_array_order() {
+        let mut meta = KeyMetadata::fixed();
+        meta.num_shards = 4;
+        meta.shard_sizes = vec![4, 4, 4, 4];
+        meta.shard_names = [
+            ".key_data_05",
+            ".key_data_01",
+            ".key_data_07",
+            ".key_data_02",
+        ]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+        meta.logical_index = vec![2, 0, 3, 1];
+        meta.validate().unwrap();
+
+        let padded: Vec<u8> = (0..16).collect();
+        let shards = meta.split_payload(&padded);
+        // 逻辑上的第一段在数组下标1，第二段在下标3
+        assert_eq!(shards[1], [0, 1, 2, 3]);
+        assert_eq!(shards[3], [4, 5, 6, 7]);
+        assert_eq!(shards[0], [8, 9, 10, 11]);
+        assert_eq!(meta.join_shards(&shards, 16), padded);
+
+        meta.layout = ShardLayout::Interleaved;
+        let shards = meta.split_payload(&padded);
+        assert_eq!(shards[1], [0, 4, 8, 12]);
+        assert_eq!(meta.join_shards(&shards, 10), padded[..10]);
+
+        meta.logical_index = vec![0, 0, 1, 2];
+        assert!(meta.validate().is_err());
+    }
+
+    #[test]
+    fn test_interleaved_split_and_join() {
+        let mut meta = KeyMetadata::generate();
+        meta.layout = ShardLayout::Interleaved;
+        let n = meta.num_shards;
+
+        let padded: Vec<u8> = (0..meta.total_capacity()).map(|i| i as u8).collect();
+        let shards = meta.split_payload(&padded);
+
+        // 单个分片只包含每隔n个的字节，逻辑上的第一个分片从第0个字节开始
+        let every_nth: Vec<u8> = padded.iter().step_by(n).copied().collect();
+        assert_eq!(shards[meta.logical_order()[0]], every_nth);
+        assert!(shards.iter().all(|s| s.len() == KeyMetadata::SHARD_SIZE));
+
+        assert_eq!(meta.join_shards(&shards, 100), padded[..100]);
+        assert_eq!(meta.join_shards(&shards, padded.len()), padded);
+    }
+
+    #[test]
+    fn test_interleave_stride() {
+        let mut meta = KeyMetadata::generate();
+        meta.layout = ShardLayout::Interleaved;
+        meta.interleave_stride = InterleaveStride(4);
+        meta.validate().unwrap();
+        let n = meta.num_shards;
+
+        let padded: Vec<u8> = (0..meta.total_capacity()).map(|i| i as u8).collect();
+        let shards = meta.split_payload(&padded);
+
+        // 逻辑上的第k个分片依次包含第k、k+n、k+2n……个4字节组
+        for (k, &index) in meta.logical_order().iter().enumerate() {
+            let chunks: Vec<u8> = padded
+                .chunks(4)
+                .skip(k)
+                .step_by(n)
+                .flatten()
+                .copied()
+                .collect();
+            assert_eq!(shards[index], chunks);
+        }
+        assert_eq!(meta.join_shards(&shards, 101), padded[..101]);
+        assert_eq!(meta.join_shards(&shards, padded.len()), padded);
+
+        // 分片大小不是步长的整数倍时取剩余的字节
+        meta.shard_sizes[0] += 2;
+        let padded: Vec<u8> = (0..meta.total_capacity()).map(|i| i as u8).collect();
+        let shards = meta.split_payload(&padded);
+        assert_eq!(shards[0].len(), meta.shard_sizes[0]);
+        assert_eq!(meta.join_shards(&shards, padded.len()), padded);
+
+        meta.interleave_stride = InterleaveStride(0);
+        assert!(meta.validate().is_err());
+    }
+
     #[test]
     fn test_metadata_generation() {
         let meta = KeyMetadata::generate();
@@ -152,11 +1010,136 @@ mod tests {
     fn test_metadata_serialization() {
         let meta = KeyMetadata::generate();
         let bytes = meta.to_bytes().unwrap();
-        let meta2 = KeyMetadata::from_bytes(&bytes).unwrap();
+        let meta2 = KeyMetadata::from_bytes(&bytes, MetaEncoding::Json).unwrap();
 
         assert_eq!(meta.num_shards, meta2.num_shards);
         assert_eq!(meta.shard_sizes, meta2.shard_sizes);
         assert_eq!(meta.shard_names, meta2.shard_names);
+        assert_eq!(meta.compression_threshold, meta2.compression_threshold);
+        assert_eq!(meta.compressed, meta2.compressed);
+    }
+
+    #[test]
+    fn test_metadata_without_compression_fields() {
+        // 旧版本写入的元数据没有压缩相关字段
+        let json = br#"{"num_shards":4,"shard_sizes":[1024,1024,1024,1024],"shard_names":[".key_data_00",".key_data_01",".key_data_02",".key_data_03"],"version":1}"#;
+        let meta = KeyMetadata::from_bytes(json, MetaEncoding::Json).unwrap();
+
+        assert_eq!(
+            meta.compression_threshold,
+            KeyMetadata::DEFAULT_COMPRESSION_THRESHOLD
+        );
+        assert!(!meta.compressed);
+        assert!(!meta.initialized);
+    }
+
+    #[test]
+    fn test_min_json_size_fits_full_layout() {
+        // 8个分片的配置必须能放进预留的JSON空间
+        let mut meta = KeyMetadata::generate();
+        meta.num_shards = 8;
+        meta.shard_sizes = vec![KeyMetadata::SHARD_SIZE; 8];
+        meta.shard_names = KeyMetadata::SHARD_NAMES
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        meta.shard_nonces = vec![u32::MAX; 8];
+        meta.kdf_iterations = u32::MAX;
+        meta.initialized = true;
+        meta.derive_sections = vec![".text".to_string()];
+        meta.layout = ShardLayout::Interleaved;
+        meta.padding = Padding::SelfDescribing;
+        meta.history = usize::MAX;
+        meta.factor_salt = vec![u8::MAX; 16];
+        meta.factor_check = vec![u8::MAX; 16];
+        meta.payload_check = vec![u8::MAX; 16];
+        meta.binding_check = vec![u8::MAX; 16];
+        meta.length_nonce = vec![u8::MAX; 8];
+        meta.shard_tags = vec![u8::MAX; 8 * 4];
+        meta.pipeline = KeyMetadata::pipeline_for(true);
+        meta.constants_fingerprint = "f".repeat(16);
+        meta.dictionary_id = format!("zstd:{}", "f".repeat(16));
+        meta.write_generation = u64::MAX;
+        assert!(meta.to_bytes().unwrap().len() <= KeyMetadata::MIN_JSON_SIZE);
+    }
+
+    /// 构造一个写入了指定元数据的section
+    fn section_with(meta: &KeyMetadata) -> Vec<u8> {
+        let mut section = vec![0u8; 1024];
+        meta.write_to_section(&mut section).unwrap();
+        section
+    }
+
+    #[test]
+    fn test_section_ignores_trailing_garbage() {
+        let meta = KeyMetadata::generate();
+        let mut section = section_with(&meta);
+
+        // JSON之后的垃圾数据（包括花括号）不影响解析
+        let json_end = KeyMetadata::HEADER_SIZE + meta.to_bytes().unwrap().len();
+        section[json_end..json_end + 12].copy_from_slice(b"}{garbage}}}");
+
+        let parsed = KeyMetadata::from_section(&section).unwrap();
+        assert_eq!(parsed.shard_names, meta.shard_names);
+    }
+
+    #[test]
+    fn test_section_rejects_multiple_objects() {
+        let meta = KeyMetadata::generate();
+        let json = meta.to_bytes().unwrap();
+        let doubled = [json.as_slice(), json.as_slice()].concat();
+
+        let mut section = vec![0u8; KeyMetadata::HEADER_SIZE + doubled.len()];
+        section[8..16].copy_from_slice(&(doubled.len() as u64).to_le_bytes());
+        section[16..16 + doubled.len()].copy_from_slice(&doubled);
+
+        assert!(KeyMetadata::from_section(&section).is_err());
+        assert!(KeyMetadata::from_bytes(&doubled, MetaEncoding::Json).is_err());
+    }
+
+    #[test]
+    fn test_section_legacy_layout() {
+        // 旧版本：JSON紧跟在8字节密钥长度之后，没有长度字段
+        let mut meta = KeyMetadata::generate();
+        meta.version = 1;
+        let json = meta.to_bytes().unwrap();
+        let mut section = vec![0u8; 1024];
+        section[8..8 + json.len()].copy_from_slice(&json);
+
+        // 旧版本写入时不清除之前更长的JSON留下的尾部
+        let tail = 8 + json.len();
+        section[tail..tail + 14].copy_from_slice(b"00\"],\"version\"");
+
+        let parsed = KeyMetadata::from_section(&section).unwrap();
+        assert_eq!(parsed.shard_names, meta.shard_names);
+    }
+
+    #[test]
+    fn test_section_legacy_rejects_multiple_objects() {
+        let mut meta = KeyMetadata::generate();
+        meta.version = 1;
+        let json = meta.to_bytes().unwrap();
+        let doubled = [json.as_slice(), json.as_slice()].concat();
+        let mut section = vec![0u8; 8 + doubled.len() + 8];
+        section[8..8 + doubled.len()].copy_from_slice(&doubled);
+
+        assert!(KeyMetadata::from_section(&section).is_err());
+    }
+
+    #[test]
+    fn test_section_legacy_rejects_current_version() {
+        // 当前版本的元数据总是带有长度头部，出现在旧版本位置时不是旧版本写入的
+        let meta = KeyMetadata::generate();
+        let json = meta.to_bytes().unwrap();
+        let mut section = vec![0u8; 1024];
+        section[8..8 + json.len()].copy_from_slice(&json);
+
+        assert!(KeyMetadata::from_section(&section).is_err());
+    }
+
+    #[test]
+    fn test_empty_section_rejected() {
+        assert!(KeyMetadata::from_section(&[0u8; 1024]).is_err());
     }
 
     #[test]
@@ -0,0 +1,104 @@
+//! 从环境变量迁移密钥
+
+use crate::crypto::{constant_time_eq, zeroize};
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use std::env;
+
+/// [`KeyStore::migrate_from_env`] 的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationOutcome {
+    /// 已将环境变量的值写入二进制并验证通过，可以删除环境变量
+    Migrated,
+    /// 二进制中已经存储了密钥，没有做任何修改
+    Skipped,
+    /// 写入后无法读回，或读回的值与环境变量不一致，二进制已恢复为迁移前的内容
+    VerificationFailed,
+}
+
+impl KeyStore {
+    /// 将环境变量中的明文密钥一次性迁移到二进制中
+    ///
+    /// 二进制尚未存储密钥时，读取环境变量 `var` 的值写入二进制，再读回验证；
+    /// 读回失败或验证失败时把二进制恢复为迁移前的内容。迁移成功后应提示运维人员删除该环境变量
+    ///
+    /// # 参数
+    ///
+    /// * `var` - 环境变量名称
+    ///
+    /// # 返回
+    ///
+    /// 成功返回迁移结果；二进制未存储密钥且环境变量不存在（或不是有效的UTF-8）时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{KeyStore, MigrationOutcome};
+    /// let mut store = KeyStore::new()?;
+    /// if store.migrate_from_env("APP_SECRET")? == MigrationOutcome::Migrated {
+    ///     eprintln!("密钥已写入二进制，请删除环境变量 APP_SECRET 后重启");
+    ///     std::process::exit(0);
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn migrate_from_env(&mut self, var: &str) -> Result<MigrationOutcome> {
+        self.migrate_from_env_with(var, constant_time_eq)
+    }
+
+    /// 与 [`migrate_from_env`](Self::migrate_from_env) 相同，但使用自定义的验证
+    ///
+    /// 写入后读回存储的密钥，与环境变量的值一起传给 `verify`，返回false时恢复迁移前的
+    /// 二进制。可用于额外确认密钥可用，例如用读回的密钥解密一个已知的令牌
+    ///
+    /// # 参数
+    ///
+    /// * `var` - 环境变量名称
+    /// * `verify` - 接收 `(读回的密钥, 环境变量的值)`，验证通过返回true
+    ///
+    /// # 返回
+    ///
+    /// 同 [`migrate_from_env`](Self::migrate_from_env)
+    pub fn migrate_from_env_with<F>(&mut self, var: &str, verify: F) -> Result<MigrationOutcome>
+    where
+        F: FnOnce(&[u8], &[u8]) -> bool,
+    {
+        if self.is_initialized()? {
+            return Ok(MigrationOutcome::Skipped);
+        }
+
+        let mut value = env::var(var)
+            .map_err(|e| Error::Config(format!("无法读取环境变量 {}: {}", var, e)))?
+            .into_bytes();
+
+        let outcome = self.migrate_value(&value, verify);
+        zeroize(&mut value);
+        outcome
+    }
+
+    /// 写入密钥并读回验证，验证失败时恢复迁移前的二进制
+    fn migrate_value<F>(&mut self, value: &[u8], verify: F) -> Result<MigrationOutcome>
+    where
+        F: FnOnce(&[u8], &[u8]) -> bool,
+    {
+        let original = self.read_binary()?;
+        let original_metadata = self.metadata().clone();
+
+        self.update_bytes(value)?;
+        // 无法读回同样视为验证失败
+        let verified = match self.read_bytes() {
+            Ok(mut stored) => {
+                let verified = verify(&stored, value);
+                zeroize(&mut stored);
+                verified
+            }
+            Err(_) => false,
+        };
+        if verified {
+            return Ok(MigrationOutcome::Migrated);
+        }
+
+        self.write_binary(&original)?;
+        self.set_metadata(original_metadata);
+        Ok(MigrationOutcome::VerificationFailed)
+    }
+}
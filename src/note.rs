@@ -0,0 +1,194 @@
+//! ELF note格式的密钥存储
+//!
+//! 使用 `init_key_storage!(note_vendor = "...")` 时，元数据和分片存放在名为
+//! `.note.key_meta`、`.note.key_data_00` 等的 `SHT_NOTE` section中。每个section
+//! 是一条标准的ELF note（namesz、descsz、type、厂商名称、描述数据），
+//! 密钥存储使用其中的描述数据部分，因此 `readelf -n` 等工具可以正常解析。
+
+use crate::error::{Error, Result};
+use object::{Object, ObjectSection, SectionKind};
+
+/// 密钥存储在二进制中的组织形式
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum StorageFormat {
+    /// 使用自定义名称的section（`.key_meta`、`.key_data_xx`，默认）
+    #[default]
+    NamedSections,
+    /// 使用带厂商名称的ELF note section（`.note.key_meta`、`.note.key_data_xx`）
+    ElfNote {
+        /// note的厂商名称（owner）
+        vendor: String,
+    },
+}
+
+/// note头部大小：namesz、descsz、type各4字节
+const NOTE_HEADER_SIZE: usize = 12;
+
+/// 元数据note的类型
+pub const NOTE_TYPE_META: u32 = 0x5343_4b4d;
+
+/// 分片note的类型
+pub const NOTE_TYPE_SHARD: u32 = 0x5343_4b44;
+
+/// 对齐到4字节
+const fn align4(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+/// 指定厂商名称和描述数据大小的note总大小
+pub const fn note_size(vendor: &str, desc_size: usize) -> usize {
+    NOTE_HEADER_SIZE + align4(vendor.len() + 1) + align4(desc_size)
+}
+
+/// 编译期生成一条描述数据全为0的note
+///
+/// ELF要求note头部使用目标平台的字节序，宏在目标平台的编译期求值，因此使用本机字节序
+pub const fn note_bytes<const N: usize>(vendor: &str, note_type: u32, desc_size: usize) -> [u8; N] {
+    assert!(N == note_size(vendor, desc_size), "note大小不匹配");
+
+    let mut bytes = [0u8; N];
+    let namesz = ((vendor.len() + 1) as u32).to_ne_bytes();
+    let descsz = (desc_size as u32).to_ne_bytes();
+    let kind = note_type.to_ne_bytes();
+
+    let mut i = 0;
+    while i < 4 {
+        bytes[i] = namesz[i];
+        bytes[4 + i] = descsz[i];
+        bytes[8 + i] = kind[i];
+        i += 1;
+    }
+
+    let name = vendor.as_bytes();
+    let mut i = 0;
+    while i < name.len() {
+        bytes[NOTE_HEADER_SIZE + i] = name[i];
+        i += 1;
+    }
+
+    bytes
+}
+
+/// 4字节对齐的note数据，ELF要求note section按4字节对齐
+#[repr(C, align(4))]
+pub struct Note<const N: usize>(pub [u8; N]);
+
+/// 获取命名section对应的note section名称
+pub fn note_section_name(section_name: &str) -> String {
+    format!(".note{}", section_name)
+}
+
+/// 查找note格式的section，返回描述数据的文件偏移、大小以及厂商名称
+///
+/// 不存在同名的 `SHT_NOTE` section时返回None
+pub fn find_note(binary_data: &[u8], section_name: &str) -> Result<Option<(usize, usize, String)>> {
+    let obj_file = object::File::parse(binary_data)
+        .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
+
+    let note_name = note_section_name(section_name);
+    let section = match obj_file
+        .sections()
+        .find(|s| s.kind() == SectionKind::Note && s.name().is_ok_and(|n| n == note_name))
+    {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+
+    let (offset, size) = section
+        .file_range()
+        .ok_or_else(|| Error::Parse(format!("无法获取section {}的文件偏移", note_name)))?;
+    // 伪造的section头部中偏移和大小相加可能溢出
+    let data = (offset as usize)
+        .checked_add(size as usize)
+        .and_then(|end| binary_data.get(offset as usize..end))
+        .ok_or_else(|| Error::Parse(format!("section {}超出文件范围", note_name)))?;
+
+    let (desc_offset, desc_size, vendor) = parse_note(data, obj_file.is_little_endian())
+        .ok_or_else(|| Error::Parse(format!("无效的note section: {}", note_name)))?;
+    Ok(Some((offset as usize + desc_offset, desc_size, vendor)))
+}
+
+/// 解析note头部，返回描述数据相对偏移、大小和厂商名称
+///
+/// 头部字段按ELF文件声明的字节序解析，`little_endian` 由ELF头部决定
+pub(crate) fn parse_note(data: &[u8], little_endian: bool) -> Option<(usize, usize, String)> {
+    let u32_at = |o: usize| -> Option<usize> {
+        let bytes = data.get(o..o + 4)?.try_into().ok()?;
+        let value = if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        };
+        Some(value as usize)
+    };
+    let namesz = u32_at(0)?;
+    let descsz = u32_at(4)?;
+
+    let name = data.get(NOTE_HEADER_SIZE..NOTE_HEADER_SIZE + namesz)?;
+    let vendor = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(name)).into_owned();
+
+    let desc_offset = NOTE_HEADER_SIZE + align4(namesz);
+    data.get(desc_offset..desc_offset + descsz)?;
+    Some((desc_offset, descsz, vendor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_bytes_round_trip() {
+        const SIZE: usize = note_size("ACME", 64);
+        let bytes: [u8; SIZE] = note_bytes("ACME", NOTE_TYPE_SHARD, 64);
+
+        assert_eq!(SIZE, 12 + 8 + 64);
+        let native = cfg!(target_endian = "little");
+        assert_eq!(
+            parse_note(&bytes, native),
+            Some((20, 64, "ACME".to_string()))
+        );
+        assert_eq!(
+            u32::from_ne_bytes(bytes[8..12].try_into().unwrap()),
+            NOTE_TYPE_SHARD
+        );
+    }
+
+    #[test]
+    fn test_parse_note_other_endianness() {
+        const SIZE: usize = note_size("ACME", 64);
+        let mut bytes: [u8; SIZE] = note_bytes("ACME", NOTE_TYPE_SHARD, 64);
+
+        // 模拟另一字节序的目标生成的note：头部三个字段逐个翻转字节
+        for field in bytes[..NOTE_HEADER_SIZE].chunks_mut(4) {
+            field.reverse();
+        }
+        let foreign = !cfg!(target_endian = "little");
+        assert_eq!(
+            parse_note(&bytes, foreign),
+            Some((20, 64, "ACME".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_find_note_rejects_overflowing_range() {
+        // 伪造section头部，使偏移加大小溢出
+        let mut data = std::fs::read("/proc/self/exe").unwrap();
+        let index = object::File::parse(data.as_slice())
+            .unwrap()
+            .section_by_name(".note.ABI-tag")
+            .unwrap()
+            .index()
+            .0;
+        let shoff = u64::from_le_bytes(data[0x28..0x30].try_into().unwrap()) as usize;
+        let shentsize = u16::from_le_bytes(data[0x3a..0x3c].try_into().unwrap()) as usize;
+        let size_field = shoff + index * shentsize + 32;
+        data[size_field..size_field + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        assert!(matches!(find_note(&data, ".ABI-tag"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_note_rejects_truncated() {
+        assert_eq!(parse_note(&[5, 0, 0, 0, 64, 0, 0, 0], true), None);
+    }
+}
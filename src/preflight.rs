@@ -0,0 +1,81 @@
+//! 程序启动时的环境检查
+
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// 检查当前可执行文件能否使用密钥存储
+///
+/// 建议在程序启动时调用，以便在环境不满足时立即给出明确的错误，而不是在第一次
+/// `update` 深处才失败。检查内容参见 [`preflight_at`]
+///
+/// # 返回
+///
+/// 全部检查通过返回Ok(())，否则返回第一个发现的问题
+///
+/// # 示例
+///
+/// ```no_run
+/// self_crypto_key::init_key_storage!();
+///
+/// fn main() -> Result<(), self_crypto_key::Error> {
+///     self_crypto_key::preflight()?;
+///     Ok(())
+/// }
+/// ```
+pub fn preflight() -> Result<()> {
+    preflight_at(&env::current_exe()?)
+}
+
+/// 检查指定的二进制能否使用密钥存储
+///
+/// 依次检查：
+///
+/// - 文件可以读取，且能解析为支持的二进制格式
+/// - 存在足够大的 `.key_meta` section（即调用了 `init_key_storage!`）
+/// - 8个 `.key_data_xx` section全部存在（具名section或note格式均可）
+/// - 存在用于派生密钥的 .text 段
+/// - 文件不是只读的（只读的二进制应使用 [`KeyStore::open_readonly`] 或旁路文件）
+///
+/// 只读取文件，不做任何修改
+///
+/// # 参数
+///
+/// * `path` - 二进制文件的路径
+///
+/// # 返回
+///
+/// 全部检查通过返回Ok(())，否则返回第一个发现的问题
+pub fn preflight_at(path: &Path) -> Result<()> {
+    let binary_data = fs::read(path).map_err(Error::io_at(path))?;
+    object::File::parse(binary_data.as_slice())
+        .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
+
+    let (_, meta_size) = KeyStore::find_section(&binary_data, KeyStore::METADATA_SECTION)
+        .map_err(|e| KeyStore::explain_missing_section(&binary_data, e))?;
+    KeyStore::check_meta_size(meta_size)?;
+
+    for name in KeyMetadata::SHARD_NAMES {
+        KeyStore::find_section(&binary_data, name)
+            .map_err(|e| KeyStore::explain_missing_section(&binary_data, e))?;
+    }
+
+    KeyStore::find_section(&binary_data, KeyStore::DERIVE_SECTION)?;
+
+    if fs::metadata(path)
+        .map_err(Error::io_at(path))?
+        .permissions()
+        .readonly()
+    {
+        return Err(Error::Config(format!(
+            "二进制文件为只读，无法写入密钥: {}。只需读取时可以使用 KeyStore::open_readonly，\
+             需要写入时可以使用 update_bytes_auto 改用旁路文件",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! 可在多个线程间共享的只读快照
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+use crate::sections::{SectionMap, SectionTable};
+use std::fmt;
+use std::sync::Arc;
+
+/// [`KeyStore::snapshot_readonly`] 创建的只读快照
+///
+/// 创建时读取并解析一次二进制，缓存二进制内容、section表和由派生sections计算出的
+/// 基础密钥，之后的 [`read_bytes`](Self::read_bytes) 只需解密分片。快照不可变，
+/// 可以放在 `Arc` 中被多个线程同时读取，无需加锁。
+///
+/// 快照不会看到创建之后的任何写入（包括本进程和其他进程的写入），需要时重新调用
+/// [`KeyStore::snapshot_readonly`]。快照只读取二进制中的分片，不使用旁路文件。
+/// 释放时清零缓存的基础密钥
+pub struct ReadSnapshot {
+    binary_data: Vec<u8>,
+    table: SectionTable,
+    metadata: KeyMetadata,
+    /// 存储的数据长度（压缩时为压缩后的长度）
+    stored_len: usize,
+    /// 基础密钥，没有需要解密的数据时为空
+    base_key: Vec<u8>,
+    constant_time: bool,
+}
+
+impl fmt::Debug for ReadSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadSnapshot")
+            .field("binary_len", &self.binary_data.len())
+            .field("stored_len", &self.stored_len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ReadSnapshot {
+    fn drop(&mut self) {
+        zeroize(&mut self.base_key);
+    }
+}
+
+impl ReadSnapshot {
+    /// 从缓存的二进制内容中解密密钥
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes，失败返回Error
+    pub fn read_bytes(&self) -> Result<Vec<u8>> {
+        if self.stored_len == 0 && !self.constant_time {
+            return Ok(Vec::new());
+        }
+
+        let sections = SectionMap::with_table(&self.binary_data, &self.table);
+        let payload = KeyStore::decrypt_with_key(
+            &sections,
+            &self.metadata,
+            &self.base_key,
+            self.stored_len,
+            self.constant_time,
+        )?;
+        KeyStore::finish_payload(&self.metadata, payload)
+    }
+
+    /// 读取密钥（字符串版本）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥字符串，密钥不是有效的UTF-8时返回Error
+    pub fn read(&self) -> Result<String> {
+        KeyStore::bytes_to_string(self.read_bytes()?)
+    }
+}
+
+impl KeyStore {
+    /// 创建可在多个线程间共享的只读快照
+    ///
+    /// 只读取和解析一次二进制，并预先计算派生sections的哈希。此后每次
+    /// [`ReadSnapshot::read_bytes`] 都基于缓存的内容解密，不再读取文件，适合高并发的
+    /// 读取场景。快照是创建时的状态，不会反映之后的写入，需要时重新创建
+    ///
+    /// # 返回
+    ///
+    /// 成功返回共享的快照，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let snapshot = KeyStore::new()?.snapshot_readonly()?;
+    /// let handles: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let snapshot = snapshot.clone();
+    ///         std::thread::spawn(move || snapshot.read_bytes())
+    ///     })
+    ///     .collect();
+    /// for handle in handles {
+    ///     handle.join().unwrap()?;
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn snapshot_readonly(&self) -> Result<Arc<ReadSnapshot>> {
+        let binary_data = self.read_binary()?;
+        let metadata =
+            Self::read_metadata_with_factor(&binary_data, self.meta_section(), self.metadata())
+                .unwrap_or_else(|| self.metadata().clone());
+        let stored_len = Self::stored_len_in(&binary_data, self.meta_section())?;
+        let constant_time = self.constant_time();
+
+        let total_capacity = metadata.total_capacity();
+        if stored_len > total_capacity {
+            return Err(Error::Config(format!(
+                "存储的密钥长度异常: {} > {}",
+                stored_len, total_capacity
+            )));
+        }
+
+        let sections = SectionMap::parse(&binary_data)?;
+        if stored_len > 0 {
+            Self::check_shards_written(&sections, &metadata)?;
+        }
+        let base_key = if stored_len > 0 || constant_time {
+            Self::derive_base_key(&sections, &metadata)?
+        } else {
+            Vec::new()
+        };
+        let table = sections.into_table();
+
+        Ok(Arc::new(ReadSnapshot {
+            binary_data,
+            table,
+            metadata,
+            stored_len,
+            base_key,
+            constant_time,
+        }))
+    }
+}
@@ -0,0 +1,103 @@
+//! 只读密钥存储
+
+use crate::crypto::{constant_time_eq, zeroize};
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+use std::fs;
+use std::path::PathBuf;
+
+/// 只读密钥存储
+///
+/// 由 [`KeyStore::open_readonly`] 或 [`KeyStore::from_path_readonly`] 创建，只提供读取相关操作。
+/// 从不以写方式打开二进制文件，也不会执行首次使用时的元数据初始化，
+/// 适用于只需要读取密钥的最小权限服务，以及只读挂载的二进制。
+///
+/// 只读存储没有任何写入方法：
+///
+/// ```compile_fail
+/// # fn main() -> Result<(), self_crypto_key::Error> {
+/// let mut store = self_crypto_key::KeyStore::open_readonly()?;
+/// store.update_bytes(b"new-key")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReadOnlyKeyStore {
+    /// 二进制文件的路径
+    exe_path: PathBuf,
+    /// 密钥元数据
+    metadata: KeyMetadata,
+}
+
+impl ReadOnlyKeyStore {
+    /// 以只读方式打开指定路径的二进制文件
+    pub(crate) fn open(exe_path: PathBuf) -> Result<Self> {
+        let binary_data = fs::read(&exe_path).map_err(Error::io_at(&exe_path))?;
+        let metadata = KeyStore::load_metadata(&binary_data, KeyStore::METADATA_SECTION)?;
+
+        Ok(Self { exe_path, metadata })
+    }
+
+    /// 读取当前密钥（bytes版本）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes，失败返回Error
+    pub fn read_bytes(&self) -> Result<Vec<u8>> {
+        KeyStore::load_key(
+            &self.exe_path,
+            KeyStore::METADATA_SECTION,
+            &self.metadata,
+            false,
+        )
+    }
+
+    /// 读取当前密钥（字符串版本）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥字符串，密钥不是有效的UTF-8时返回Error
+    pub fn read(&self) -> Result<String> {
+        KeyStore::bytes_to_string(self.read_bytes()?)
+    }
+
+    /// 校验存储的密钥是否与期望值一致
+    ///
+    /// 与 [`KeyStore::verify_against`] 相同：以恒定时间比较，解密出的副本在返回前清零
+    ///
+    /// # 参数
+    ///
+    /// * `expected` - 期望的密钥
+    ///
+    /// # 返回
+    ///
+    /// 一致返回 `Ok(true)`，不一致返回 `Ok(false)`，读取失败返回Error
+    pub fn verify_against(&self, expected: &[u8]) -> Result<bool> {
+        let mut stored = self.read_bytes()?;
+        let matches = constant_time_eq(&stored, expected);
+        zeroize(&mut stored);
+        Ok(matches)
+    }
+
+    /// 获取密钥存储的总容量（字节）
+    pub fn capacity(&self) -> usize {
+        self.metadata.total_capacity()
+    }
+
+    /// 获取当前存储的密钥长度（字节）
+    ///
+    /// 与 [`KeyStore::stored_len`] 相同：密钥以压缩形式存储或带有历史版本时解密一次以得到密钥本身的长度
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥长度，未写入过密钥时为0
+    pub fn stored_len(&self) -> Result<usize> {
+        let len = KeyStore::load_stored_len(&self.exe_path, KeyStore::METADATA_SECTION)?;
+        if len == 0
+            || !KeyStore::load_payload_len_differs(&self.exe_path, KeyStore::METADATA_SECTION)?
+        {
+            return Ok(len);
+        }
+        KeyStore::decrypted_len(self.read_bytes())
+    }
+}
@@ -0,0 +1,44 @@
+//! 与RustCrypto生态的互操作（需要 `rustcrypto` feature）
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use generic_array::{ArrayLength, GenericArray};
+
+impl KeyStore {
+    /// 读取密钥到定长的 `GenericArray`（需要 `rustcrypto` feature）
+    ///
+    /// RustCrypto的分组密码和MAC（如 `aes::Aes256`）使用 `GenericArray` 作为密钥类型，
+    /// 可以直接传入返回值，无需经过 `Vec` 再复制。中间的 `Vec` 在返回前清零
+    ///
+    /// # 返回
+    ///
+    /// 成功返回定长数组；存储的密钥长度与 `N` 不一致时返回 `Error::SizeMismatch`
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// use self_crypto_key::generic_array::{typenum::U32, GenericArray};
+    ///
+    /// let store = KeyStore::new()?;
+    /// let key: GenericArray<u8, U32> = store.read_key()?;
+    /// // let cipher = aes::Aes256::new(&key);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_key<N: ArrayLength<u8>>(&self) -> Result<GenericArray<u8, N>> {
+        let mut bytes = self.read_bytes()?;
+
+        let result = if bytes.len() == N::USIZE {
+            Ok(GenericArray::clone_from_slice(&bytes))
+        } else {
+            Err(Error::SizeMismatch {
+                expected: N::USIZE,
+                actual: bytes.len(),
+            })
+        };
+
+        zeroize(&mut bytes);
+        result
+    }
+}
@@ -0,0 +1,92 @@
+//! 对解密结果做启发式检查，提示可能的损坏
+
+use crate::error::Result;
+use crate::key_store::KeyStore;
+use std::fmt;
+
+/// 检查开头全零时比较的字节数
+const ZERO_PREFIX_LEN: usize = 16;
+
+/// [`KeyStore::read_bytes_checked`] 发现的可疑迹象
+///
+/// 只是启发式判断，不代表数据一定损坏：例如密钥本身恰好以16个零字节开头时同样会产生警告
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanityWarning {
+    /// 解密出的数据开头的 `len` 个字节全为零，常见于派生密钥或编译时常量不匹配
+    ZeroPrefix {
+        /// 检查的字节数
+        len: usize,
+    },
+}
+
+impl fmt::Display for SanityWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanityWarning::ZeroPrefix { len } => write!(
+                f,
+                "解密出的数据前{}个字节全为零，派生密钥或加密常量可能不匹配",
+                len
+            ),
+        }
+    }
+}
+
+/// 检查解密出的数据，返回发现的可疑迹象
+fn inspect(key: &[u8]) -> Vec<SanityWarning> {
+    let mut warnings = Vec::new();
+    if key.len() >= ZERO_PREFIX_LEN && key[..ZERO_PREFIX_LEN].iter().all(|&b| b == 0) {
+        warnings.push(SanityWarning::ZeroPrefix {
+            len: ZERO_PREFIX_LEN,
+        });
+    }
+    warnings
+}
+
+impl KeyStore {
+    /// 读取密钥，并返回对解密结果的启发式检查结果
+    ///
+    /// 与 [`read_bytes`](Self::read_bytes) 相同，开启 [`with_sanity_warnings`](Self::with_sanity_warnings)
+    /// 后额外检查解密出的数据：长度不少于16字节且前16个字节全为零时返回
+    /// [`SanityWarning::ZeroPrefix`]，调用方可以据此提示数据可能已损坏。未开启时警告列表总是为空
+    ///
+    /// # 返回
+    ///
+    /// 成功返回密钥的bytes和警告列表，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?.with_sanity_warnings(true);
+    /// let (key, warnings) = store.read_bytes_checked()?;
+    /// for warning in &warnings {
+    ///     eprintln!("警告: {}", warning);
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_bytes_checked(&self) -> Result<(Vec<u8>, Vec<SanityWarning>)> {
+        let key = self.read_bytes()?;
+        let warnings = if self.sanity_warnings() {
+            inspect(&key)
+        } else {
+            Vec::new()
+        };
+        Ok((key, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_zero_prefix() {
+        let mut key = vec![0u8; 32];
+        assert_eq!(inspect(&key), [SanityWarning::ZeroPrefix { len: 16 }]);
+
+        key[15] = 1;
+        assert!(inspect(&key).is_empty());
+        // 短于检查长度的数据不做判断
+        assert!(inspect(&[0u8; 8]).is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+//! 使用 `secrecy` crate 的秘密值类型读写密钥（需要 `secrecy` feature）
+//!
+//! 读取返回 [`secrecy::Secret`]，写入和比较接受任何实现了 [`ExposeSecret`] 的类型，
+//! 可以直接与使用 `secrecy` 的其他库交换密钥。开启此feature后，crate内部的恒定时间比较
+//! 改用 `subtle` 实现
+
+use crate::error::Result;
+use crate::key_store::KeyStore;
+use secrecy::{ExposeSecret, Secret};
+
+impl KeyStore {
+    /// 读取当前密钥并包装为 [`secrecy::Secret`]（需要 `secrecy` feature）
+    ///
+    /// 返回的 `Secret<Vec<u8>>` 不实现 `Debug`，无法被意外打印到日志中：
+    ///
+    /// ```compile_fail
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// println!("{:?}", store.read_secret()?);
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    ///
+    /// # 返回
+    ///
+    /// 成功返回包装后的密钥，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::{ExposeSecret, KeyStore};
+    /// let store = KeyStore::new()?;
+    /// let key = store.read_secret()?;
+    /// let bytes: &Vec<u8> = key.expose_secret();
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn read_secret(&self) -> Result<Secret<Vec<u8>>> {
+        self.read_bytes().map(Secret::new)
+    }
+
+    /// 写入秘密值（需要 `secrecy` feature）
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 任何实现了 [`ExposeSecret`] 的秘密值
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())，失败返回Error
+    pub fn update_secret<T, S>(&mut self, new_key: &S) -> Result<()>
+    where
+        T: AsRef<[u8]>,
+        S: ExposeSecret<T>,
+    {
+        self.update_bytes(new_key.expose_secret().as_ref())
+    }
+
+    /// 以恒定时间比较存储的密钥与秘密值（需要 `secrecy` feature）
+    ///
+    /// 与 [`verify_against`](Self::verify_against) 相同，但接受 [`ExposeSecret`] 类型
+    pub fn verify_secret<T, S>(&self, expected: &S) -> Result<bool>
+    where
+        T: AsRef<[u8]>,
+        S: ExposeSecret<T>,
+    {
+        self.verify_against(expected.expose_secret().as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::constant_time_eq;
+
+    #[test]
+    fn test_constant_time_eq_with_subtle() {
+        assert!(constant_time_eq(b"hunter2", b"hunter2"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter3"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
@@ -0,0 +1,187 @@
+//! 一次解析得到的section表
+//!
+//! 一次读取要查找元数据、每个分片以及派生密钥用的section，逐个查找时每次都会完整
+//! 解析一遍ELF结构。[`SectionMap`] 只解析一次，之后的查找只是遍历已记录的section表
+
+use crate::error::{Error, Result};
+use crate::note;
+use object::{Object, ObjectSection, SectionKind};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+/// 解析一次二进制后得到的section名称到文件范围的映射
+pub(crate) struct SectionMap<'a> {
+    binary_data: &'a [u8],
+    table: Cow<'a, SectionTable>,
+}
+
+/// 与二进制数据分开保存的section表，可以缓存后对同一份数据重复使用
+#[derive(Clone)]
+pub(crate) struct SectionTable {
+    little_endian: bool,
+    /// section通常只有几十个，线性查找比建立哈希表更快
+    sections: Vec<SectionEntry>,
+}
+
+/// section表中的一项
+#[derive(Clone)]
+struct SectionEntry {
+    name: String,
+    /// 文件范围，没有文件内容的section（如 .bss）为None
+    range: Option<(u64, u64)>,
+    is_note: bool,
+}
+
+impl<'a> SectionMap<'a> {
+    /// 解析二进制并记录全部section
+    pub(crate) fn parse(binary_data: &'a [u8]) -> Result<Self> {
+        let obj_file = object::File::parse(binary_data)
+            .map_err(|e| Error::Parse(format!("无法解析二进制格式: {}", e)))?;
+
+        let sections = obj_file
+            .sections()
+            .filter_map(|section| {
+                let name = section.name().ok()?;
+                Some(SectionEntry {
+                    name: name.to_string(),
+                    range: section.file_range(),
+                    is_note: name.starts_with(".note") && section.kind() == SectionKind::Note,
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            binary_data,
+            table: Cow::Owned(SectionTable {
+                little_endian: obj_file.is_little_endian(),
+                sections,
+            }),
+        })
+    }
+
+    /// 使用之前从同一份二进制数据解析出的section表，不再重新解析
+    pub(crate) fn with_table(binary_data: &'a [u8], table: &'a SectionTable) -> Self {
+        Self {
+            binary_data,
+            table: Cow::Borrowed(table),
+        }
+    }
+
+    /// 取出section表，供之后对同一份数据使用 [`with_table`](Self::with_table)
+    pub(crate) fn into_table(self) -> SectionTable {
+        self.table.into_owned()
+    }
+
+    /// 解析时使用的二进制数据
+    pub(crate) fn binary_data(&self) -> &'a [u8] {
+        self.binary_data
+    }
+
+    /// 查找指定名称的section，不存在时返回None
+    pub(crate) fn named(&self, section_name: &str) -> Result<Option<(usize, usize)>> {
+        match self.get(section_name) {
+            Some(entry) => self.checked_range(section_name, entry.range).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 查找section的文件偏移和大小
+    ///
+    /// 不存在同名section时，查找note格式对应的 `.note` 前缀section，返回其描述数据的范围
+    pub(crate) fn find(&self, section_name: &str) -> Result<(usize, usize)> {
+        if let Some(range) = self.named(section_name)? {
+            return Ok(range);
+        }
+
+        let note_name = note::note_section_name(section_name);
+        match self.get(&note_name) {
+            Some(entry) if entry.is_note => {
+                let (offset, size) = self.checked_range(&note_name, entry.range)?;
+                let (desc_offset, desc_size, _) = note::parse_note(
+                    &self.binary_data[offset..offset + size],
+                    self.table.little_endian,
+                )
+                .ok_or_else(|| Error::Parse(format!("无效的note section: {}", note_name)))?;
+                Ok((offset + desc_offset, desc_size))
+            }
+            _ => Err(Error::SectionNotFound(section_name.to_string())),
+        }
+    }
+
+    /// 按顺序对多个section计算SHA256哈希，返回前 `key_len` 字节（最多32字节）
+    pub(crate) fn derive_key(&self, section_names: &[&str], key_len: usize) -> Result<Vec<u8>> {
+        let mut hasher = Sha256::new();
+        for &section_name in section_names {
+            let (offset, size) = self
+                .named(section_name)?
+                .ok_or_else(|| Error::SectionNotFound(section_name.to_string()))?;
+            // 直接在已读入内存的二进制数据上计算哈希，不额外复制section数据
+            hasher.update(&self.binary_data[offset..offset + size]);
+        }
+
+        let hash: [u8; 32] = hasher.finalize().into();
+        Ok(hash[..key_len.min(32)].to_vec())
+    }
+
+    /// 按名称查找section，同名section以第一个为准（与逐个查找时一致）
+    fn get(&self, section_name: &str) -> Option<&SectionEntry> {
+        self.table
+            .sections
+            .iter()
+            .find(|entry| entry.name == section_name)
+    }
+
+    /// 检查section有文件内容且范围不超出文件
+    fn checked_range(
+        &self,
+        section_name: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<(usize, usize)> {
+        let (offset, size) = range
+            .ok_or_else(|| Error::Parse(format!("无法获取section {}的文件偏移", section_name)))?;
+
+        // 截断或伪造的二进制中，section头部声明的范围可能超出文件
+        let (offset, size) = (offset as usize, size as usize);
+        if offset
+            .checked_add(size)
+            .is_none_or(|end| end > self.binary_data.len())
+        {
+            return Err(Error::Parse(format!(
+                "section {}超出文件范围",
+                section_name
+            )));
+        }
+
+        Ok((offset, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::derive_key_from_sections;
+
+    #[test]
+    fn test_section_map_matches_full_parse() {
+        let binary_data = std::fs::read("/proc/self/exe").unwrap();
+        let map = SectionMap::parse(&binary_data).unwrap();
+        let obj_file = object::File::parse(binary_data.as_slice()).unwrap();
+
+        for name in [".text", ".rodata", ".data", ".key_meta", ".missing"] {
+            let expected = obj_file
+                .section_by_name(name)
+                .and_then(|s| s.file_range())
+                .map(|(offset, size)| (offset as usize, size as usize));
+            assert_eq!(map.named(name).unwrap(), expected, "{}", name);
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(obj_file.section_by_name(".text").unwrap().data().unwrap());
+        let text_hash: [u8; 32] = hasher.finalize().into();
+        assert_eq!(map.derive_key(&[".text"], 32).unwrap(), text_hash);
+        assert_eq!(
+            map.derive_key(&[".text", ".rodata"], 16).unwrap(),
+            derive_key_from_sections(&binary_data, &[".text", ".rodata"], 16).unwrap()
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! 检查当前构建的加密常量能否正确往返
+
+use crate::crypto::{decrypt_shard, deobfuscate, encrypt_shard, obfuscate};
+use crate::error::{Error, Result};
+
+/// 自检使用的数据长度：单字节、不足一个块、恰好一个分片和跨越多个分片
+const LENGTHS: [usize; 4] = [1, 31, 1024, 3000];
+
+/// 自检使用的混淆种子，覆盖边界值
+const SEEDS: [u8; 4] = [0, 1, 0x7f, 0xff];
+
+/// 确认本次构建的混淆和加密能够无损往返
+///
+/// 加密常量在每次编译时随机生成，生成出错的构建可能写入之后再也无法读回。
+/// 此函数对全零、全0xFF、递增和随机四种数据，在多个长度和混淆种子下分别执行
+/// 混淆/反混淆和加密/解密，要求精确还原。不读写任何文件，适合放在应用的测试中
+///
+/// # 返回
+///
+/// 全部通过返回Ok(())，否则返回描述第一个失败项的 `Error::Crypto`
+///
+/// # 示例
+///
+/// ```
+/// self_crypto_key::self_test().expect("当前构建的加密常量有问题");
+/// ```
+pub fn self_test() -> Result<()> {
+    let derive_key: Vec<u8> = (0..32).map(|_| rand::random()).collect();
+
+    for len in LENGTHS {
+        let patterns: [(&str, Vec<u8>); 4] = [
+            ("全零", vec![0u8; len]),
+            ("全0xFF", vec![0xffu8; len]),
+            ("递增", (0..len).map(|i| i as u8).collect()),
+            ("随机", (0..len).map(|_| rand::random()).collect()),
+        ];
+
+        for (name, data) in &patterns {
+            for seed in SEEDS {
+                let fail = |step: &str| {
+                    Err(Error::Crypto(format!(
+                        "自检失败：{}数据（长度{}，种子{}）{}后无法还原",
+                        name, len, seed, step
+                    )))
+                };
+
+                if deobfuscate(&obfuscate(data, seed), seed) != *data {
+                    return fail("混淆");
+                }
+                if decrypt_shard(&encrypt_shard(data, &derive_key, seed), &derive_key, seed)
+                    != *data
+                {
+                    return fail("加密");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! 将加密分片导出为单独的文件
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 导出目录中元数据文件的名称
+const META_FILE: &str = "metadata.bin";
+
+/// section在导出目录中对应的分片文件，例如 `.key_data_03` 对应 `shard_key_data_03.bin`
+fn shard_file(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("shard_{}.bin", name.trim_start_matches('.')))
+}
+
+impl KeyStore {
+    /// 将当前存储的加密分片导出到目录，每个分片一个文件
+    ///
+    /// 写入元数据section的原始字节（`metadata.bin`）和每个活动分片section的原始字节
+    /// （`shard_<section名称>.bin`），文件权限为0600。分片内容仍是密文，解密依然需要
+    /// 原二进制的 `.text` 等sections，因此可以把分片文件分散存放在不同的卷上，
+    /// 再用 [`import_shards_from_dir`](Self::import_shards_from_dir) 写回
+    ///
+    /// # 参数
+    ///
+    /// * `dir` - 导出目录，必须已存在，同名文件会被覆盖
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；尚未写入密钥或写入文件失败时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// # use std::path::Path;
+    /// let store = KeyStore::new()?;
+    /// store.export_shards_to_dir(Path::new("/mnt/shards"))?;
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn export_shards_to_dir(&self, dir: &Path) -> Result<()> {
+        let mut binary_data = self.read_binary()?;
+        let result = self.export_shards_from(&binary_data, dir);
+        zeroize(&mut binary_data);
+        result
+    }
+
+    fn export_shards_from(&self, binary_data: &[u8], dir: &Path) -> Result<()> {
+        let metadata = Self::read_metadata(binary_data, self.meta_section())?;
+        if !metadata.initialized {
+            return Err(Error::Config("尚未写入密钥，没有可导出的分片".to_string()));
+        }
+
+        let (offset, size) = Self::find_section(binary_data, self.meta_section())?;
+        Self::write_private_file(&dir.join(META_FILE), &binary_data[offset..offset + size])?;
+
+        for name in &metadata.shard_names {
+            let (offset, size) = Self::find_section(binary_data, name)?;
+            Self::write_private_file(&shard_file(dir, name), &binary_data[offset..offset + size])?;
+        }
+
+        Ok(())
+    }
+
+    /// 从 [`export_shards_to_dir`](Self::export_shards_to_dir) 导出的目录中读取分片并写回二进制
+    ///
+    /// 先写回元数据，再按其中记录的活动分片读取对应的文件，最后原子替换二进制并更新内存中的配置。
+    /// 密钥仍与导出时二进制的 `.text` 等sections绑定，导入到其他二进制后无法解密
+    ///
+    /// # 参数
+    ///
+    /// * `dir` - 导出目录
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；文件缺失、文件大小与section大小不同或元数据无效时返回Error，
+    /// 此时二进制不会被修改
+    pub fn import_shards_from_dir(&mut self, dir: &Path) -> Result<()> {
+        let mut binary_data = self.read_binary()?;
+        let result = self.import_shards_into(&mut binary_data, dir);
+        zeroize(&mut binary_data);
+        result
+    }
+
+    fn import_shards_into(&mut self, binary_data: &mut [u8], dir: &Path) -> Result<()> {
+        let meta_section = self.meta_section().to_string();
+        Self::copy_file_into(binary_data, &meta_section, &dir.join(META_FILE))?;
+
+        let mut metadata = Self::read_metadata(binary_data, &meta_section)?;
+        metadata.validate()?;
+        // 外部因子和压缩字典从不写入二进制，沿用内存中的配置
+        metadata.external_factor = self.metadata().external_factor.clone();
+        metadata.compression_dictionary = self.metadata().compression_dictionary.clone();
+        metadata.dictionary_codec = self.metadata().dictionary_codec;
+
+        for name in &metadata.shard_names {
+            Self::copy_file_into(binary_data, name, &shard_file(dir, name))?;
+        }
+
+        self.write_binary(binary_data)?;
+        self.set_metadata(metadata);
+        Ok(())
+    }
+
+    /// 把文件内容写入同样大小的section
+    fn copy_file_into(binary_data: &mut [u8], name: &str, path: &Path) -> Result<()> {
+        let mut data = fs::read(path).map_err(Error::io_at(path))?;
+        let result = Self::find_section(binary_data, name).and_then(|(offset, size)| {
+            if size != data.len() {
+                return Err(Error::SizeMismatch {
+                    expected: size,
+                    actual: data.len(),
+                });
+            }
+            binary_data[offset..offset + size].copy_from_slice(&data);
+            Ok(())
+        });
+        zeroize(&mut data);
+        result
+    }
+}
@@ -0,0 +1,258 @@
+//! 旁路文件（sidecar）存储
+//!
+//! 当二进制文件不可写时，密钥以加密形式存放在二进制旁边的 `<二进制名>.key` 文件中。
+//! 加密使用与二进制内存储相同的基础密钥（派生sections、KDF迭代次数和外部因子），
+//! 因此旁路文件离开对应的二进制无法解密。派生参数记录在旁路文件中，不依赖二进制中
+//! 可能已过期的元数据。每次写入使用新的随机nonce，并记录明文的HMAC校验值，读取时
+//! 校验不通过返回 `Error::IntegrityCheckFailed`。
+
+use crate::crypto::{decrypt_shard, encrypt_shard, hmac_sha256, hmac_sha256_verify, zeroize};
+use crate::error::{Error, Result};
+use crate::metadata::KeyMetadata;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// 旁路文件的魔数
+const MAGIC: &[u8; 4] = b"SCK3";
+
+/// nonce的长度（字节）
+const NONCE_SIZE: usize = 8;
+
+/// 校验值的长度（字节）
+const CHECK_SIZE: usize = 16;
+
+/// 旁路文件头部大小：魔数 + nonce + 校验值 + 4字节派生参数长度，
+/// 之后依次是JSON格式的派生参数和与密钥等长的密文
+const HEADER_SIZE: usize = MAGIC.len() + NONCE_SIZE + CHECK_SIZE + 4;
+
+/// 旁路文件加密使用的混淆种子，每次写入再混入nonce
+const SIDECAR_SEED: u8 = 0xa5;
+
+/// 计算校验值时使用的标签
+const CHECK_LABEL: &[u8] = b"self_crypto_key/sidecar-check";
+
+/// 旁路文件中记录的密钥派生参数，含义与元数据中的同名字段相同
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeriveParams {
+    pub derive_sections: Vec<String>,
+    pub kdf_iterations: u32,
+    pub factor_salt: Vec<u8>,
+    pub factor_check: Vec<u8>,
+}
+
+impl DeriveParams {
+    /// 取出元数据中的派生参数
+    pub fn from_metadata(metadata: &KeyMetadata) -> Self {
+        Self {
+            derive_sections: metadata.derive_sections.clone(),
+            kdf_iterations: metadata.kdf_iterations,
+            factor_salt: metadata.factor_salt.clone(),
+            factor_check: metadata.factor_check.clone(),
+        }
+    }
+
+    /// 用记录的派生参数替换元数据中的对应字段
+    pub fn apply_to(self, metadata: &mut KeyMetadata) {
+        metadata.derive_sections = self.derive_sections;
+        metadata.kdf_iterations = self.kdf_iterations;
+        metadata.factor_salt = self.factor_salt;
+        metadata.factor_check = self.factor_check;
+    }
+}
+
+/// 旁路文件内容的各个部分
+struct Parts<'a> {
+    nonce: &'a [u8],
+    check: &'a [u8],
+    params: &'a [u8],
+    encrypted: &'a [u8],
+}
+
+/// 获取二进制对应的旁路文件路径
+pub fn sidecar_path(exe_path: &Path) -> PathBuf {
+    let mut name = exe_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".key");
+    exe_path.with_file_name(name)
+}
+
+/// 用基础密钥加密密钥并生成旁路文件内容，`params` 为计算基础密钥时使用的派生参数
+pub fn encode(base_key: &[u8], params: &DeriveParams, key: &[u8]) -> Result<Vec<u8>> {
+    let params = serde_json::to_vec(params)?;
+    let nonce: [u8; NONCE_SIZE] = rand::random();
+
+    let mut content = Vec::with_capacity(HEADER_SIZE + params.len() + key.len());
+    content.extend_from_slice(MAGIC);
+    content.extend_from_slice(&nonce);
+    content.extend_from_slice(&check(base_key, key));
+    content.extend_from_slice(&(params.len() as u32).to_le_bytes());
+    content.extend_from_slice(&params);
+    content.extend(encrypt_shard(
+        key,
+        &file_key(base_key, &nonce),
+        seed(&nonce),
+    ));
+    Ok(content)
+}
+
+/// 读取旁路文件中的密钥长度，旁路文件不存在时返回None
+pub fn stored_len(exe_path: &Path) -> Result<Option<usize>> {
+    match read_content(exe_path)? {
+        Some(content) => Ok(Some(split(&content)?.encrypted.len())),
+        None => Ok(None),
+    }
+}
+
+/// 解析已读取的旁路文件内容中记录的派生参数
+pub fn params(content: &[u8]) -> Result<DeriveParams> {
+    serde_json::from_slice(split(content)?.params).map_err(Error::from)
+}
+
+/// 用基础密钥解密已读取的旁路文件内容，校验值不匹配时返回 `Error::IntegrityCheckFailed`
+pub fn decode(content: &[u8], base_key: &[u8]) -> Result<Vec<u8>> {
+    let parts = split(content)?;
+    let mut key = decrypt_shard(
+        parts.encrypted,
+        &file_key(base_key, parts.nonce),
+        seed(parts.nonce),
+    );
+    let mut message = check_message(&key);
+    let valid = hmac_sha256_verify(base_key, &message, parts.check);
+    zeroize(&mut message);
+    if !valid {
+        zeroize(&mut key);
+        return Err(Error::IntegrityCheckFailed(
+            "旁路文件已被修改或不属于该二进制".to_string(),
+        ));
+    }
+    Ok(key)
+}
+
+/// 删除旁路文件（不存在时忽略）
+pub fn remove(exe_path: &Path) -> Result<()> {
+    let path = sidecar_path(exe_path);
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(Error::io_at(&path)(e)),
+        _ => Ok(()),
+    }
+}
+
+/// 读取旁路文件的原始内容
+pub fn read_content(exe_path: &Path) -> Result<Option<Vec<u8>>> {
+    let path = sidecar_path(exe_path);
+    match fs::read(&path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::io_at(&path)(e)),
+    }
+}
+
+/// 校验魔数并拆分旁路文件内容
+fn split(content: &[u8]) -> Result<Parts<'_>> {
+    if content.len() < HEADER_SIZE || &content[..MAGIC.len()] != MAGIC {
+        return Err(Error::Parse("无效的旁路文件".to_string()));
+    }
+
+    let (nonce, rest) = content[MAGIC.len()..].split_at(NONCE_SIZE);
+    let (check, rest) = rest.split_at(CHECK_SIZE);
+    let (len, rest) = rest.split_at(4);
+    let mut len_bytes = [0u8; 4];
+    len_bytes.copy_from_slice(len);
+    let params_len = u32::from_le_bytes(len_bytes) as usize;
+    if params_len > rest.len() {
+        return Err(Error::Parse("旁路文件的派生参数超出文件范围".to_string()));
+    }
+    let (params, encrypted) = rest.split_at(params_len);
+
+    Ok(Parts {
+        nonce,
+        check,
+        params,
+        encrypted,
+    })
+}
+
+/// 由基础密钥和nonce计算本次写入的加密密钥，每次写入的密文都不同
+fn file_key(base_key: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(base_key);
+    hasher.update(nonce);
+    hasher.finalize().to_vec()
+}
+
+/// 计算混淆种子：固定种子与nonce的各字节异或
+fn seed(nonce: &[u8]) -> u8 {
+    nonce.iter().fold(SIDECAR_SEED, |acc, b| acc ^ b)
+}
+
+/// 计算明文的校验值，以基础密钥为HMAC密钥
+fn check(base_key: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut message = check_message(key);
+    let check = hmac_sha256(base_key, &message)[..CHECK_SIZE].to_vec();
+    zeroize(&mut message);
+    check
+}
+
+/// 校验值认证的消息：标签与明文拼接
+fn check_message(key: &[u8]) -> Vec<u8> {
+    let mut message = CHECK_LABEL.to_vec();
+    message.extend_from_slice(key);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("/opt/app/server")),
+            PathBuf::from("/opt/app/server.key")
+        );
+    }
+
+    #[test]
+    fn test_io_errors_name_sidecar_path() {
+        // 旁路文件位置是一个目录：读取和删除都失败，错误中带有旁路文件的路径
+        let dir = tempfile::tempdir().unwrap();
+        let exe_path = dir.path().join("server");
+        fs::create_dir(sidecar_path(&exe_path)).unwrap();
+
+        for result in [read_content(&exe_path).map(|_| ()), remove(&exe_path)] {
+            match result {
+                Err(Error::IoAt { path, .. }) => assert_eq!(path, sidecar_path(&exe_path)),
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_rejects_bad_header() {
+        let header = [0u8; NONCE_SIZE + CHECK_SIZE];
+        let params = b"{}";
+        let len = (params.len() as u32).to_le_bytes();
+        let content = [MAGIC.as_slice(), &header, &len, params, b"hello"].concat();
+        assert_eq!(split(&content).unwrap().params, params);
+        assert_eq!(split(&content).unwrap().encrypted, b"hello");
+
+        assert!(split(&[b"XXXX".as_slice(), &content[MAGIC.len()..]].concat()).is_err());
+        assert!(split(b"SCK3").is_err());
+
+        // 派生参数长度超出文件范围
+        let len = 100u32.to_le_bytes();
+        assert!(split(&[MAGIC.as_slice(), &header, &len, params].concat()).is_err());
+    }
+
+    #[test]
+    fn test_decode_rejects_wrong_base_key() {
+        let content = encode(&[1u8; 32], &DeriveParams::default(), b"secret").unwrap();
+        assert_eq!(decode(&content, &[1u8; 32]).unwrap(), b"secret");
+        assert!(matches!(
+            decode(&content, &[2u8; 32]),
+            Err(Error::IntegrityCheckFailed(_))
+        ));
+    }
+}
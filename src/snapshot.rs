@@ -0,0 +1,106 @@
+//! 密钥存储的快照与恢复
+
+use crate::crypto::zeroize;
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::metadata::KeyMetadata;
+use std::fmt;
+
+/// [`KeyStore::snapshot`] 保存的密钥存储状态
+///
+/// 包含 `.key_meta` 和全部分片section的原始字节以及内存中的配置，与存储的值无关，
+/// 用于粗粒度的回滚。分片内容仍是密文，释放时会清零
+#[derive(Clone)]
+pub struct Snapshot {
+    sections: Vec<(String, Vec<u8>)>,
+    metadata: KeyMetadata,
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.sections.iter().map(|(name, data)| (name, data.len())))
+            .finish()
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        for (_, data) in &mut self.sections {
+            zeroize(data);
+        }
+    }
+}
+
+impl KeyStore {
+    /// 保存当前磁盘上的密钥存储状态
+    ///
+    /// 记录 `.key_meta` 和全部分片section的原始字节，之后可以用
+    /// [`restore`](Self::restore) 整体恢复，适合尝试多种配置、失败时回滚的场景
+    ///
+    /// # 返回
+    ///
+    /// 成功返回快照，失败返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let mut store = KeyStore::new()?;
+    /// let snap = store.snapshot()?;
+    /// if store.update("experimental-key").is_err() {
+    ///     store.restore(&snap)?;
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let binary_data = self.read_binary()?;
+
+        let mut sections = Vec::new();
+        for name in self.storage_sections() {
+            match Self::find_section(&binary_data, &name) {
+                Ok((offset, size)) => {
+                    sections.push((name, binary_data[offset..offset + size].to_vec()))
+                }
+                Err(Error::SectionNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Snapshot {
+            sections,
+            metadata: self.metadata().clone(),
+        })
+    }
+
+    /// 将密钥存储恢复为快照时的状态
+    ///
+    /// 把快照中的各section原样写回二进制（原子替换），并恢复内存中的配置
+    ///
+    /// # 参数
+    ///
+    /// * `snap` - [`snapshot`](Self::snapshot) 返回的快照
+    ///
+    /// # 返回
+    ///
+    /// 成功返回Ok(())；快照中的section在当前二进制中不存在或大小不同时返回Error，
+    /// 此时二进制不会被修改
+    pub fn restore(&mut self, snap: &Snapshot) -> Result<()> {
+        let mut binary_data = self.read_binary()?;
+
+        for (name, data) in &snap.sections {
+            let (offset, size) = Self::find_section(&binary_data, name)?;
+            if size != data.len() {
+                return Err(Error::SizeMismatch {
+                    expected: data.len(),
+                    actual: size,
+                });
+            }
+            binary_data[offset..offset + size].copy_from_slice(data);
+        }
+
+        self.write_binary(&binary_data)?;
+        self.set_metadata(snap.metadata.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! 加密常量不匹配时的恢复入口
+
+use crate::error::Result;
+use crate::key_store::KeyStore;
+
+/// 由使用不同加密常量的构建写入的密钥存储
+///
+/// 由 [`KeyStore::open_for_rewrite`] 或 [`KeyStore::from_path_for_rewrite`] 创建。
+/// 存储的数据用当前构建无法解密，因此只提供重新写入和清除两种操作，完成后返回
+/// 正常的 [`KeyStore`]。
+///
+/// 不能读取存储的数据：
+///
+/// ```compile_fail
+/// # fn main() -> Result<(), self_crypto_key::Error> {
+/// let store = self_crypto_key::KeyStore::open_for_rewrite()?;
+/// store.read_bytes()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StaleKeyStore {
+    /// 跳过常量指纹检查打开的存储
+    inner: KeyStore,
+}
+
+impl StaleKeyStore {
+    pub(crate) fn new(inner: KeyStore) -> Self {
+        Self { inner }
+    }
+
+    /// 用当前构建重新写入密钥
+    ///
+    /// 旧数据无法解密，写入前先清除密钥和元数据（与 [`KeyStore::reset`] 相同），
+    /// 然后按新生成的布局写入，之前的历史版本不会保留
+    ///
+    /// # 参数
+    ///
+    /// * `new_key` - 新的密钥数据（bytes）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回可以正常读写的KeyStore，失败返回Error
+    pub fn update_bytes(mut self, new_key: &[u8]) -> Result<KeyStore> {
+        self.inner.reset()?;
+        self.inner.update_bytes(new_key)?;
+        Ok(self.inner)
+    }
+
+    /// 清除密钥和元数据，使二进制恢复到刚编译出来时的状态
+    ///
+    /// # 返回
+    ///
+    /// 成功返回可以正常读写的KeyStore，失败返回Error
+    pub fn reset(mut self) -> Result<KeyStore> {
+        self.inner.reset()?;
+        Ok(self.inner)
+    }
+}
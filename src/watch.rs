@@ -0,0 +1,137 @@
+//! 监视二进制中的密钥变化（需要 `watch` feature）
+//!
+//! 后台线程通过inotify监视二进制所在的目录，二进制或旁路文件发生变化时重新计算存储内容的
+//! 校验和。监视的是目录而不是文件本身：二进制被rename替换（原子写入）后路径指向新的inode，
+//! 对原文件的监视会失效，而目录监视仍能收到新文件出现的事件，不需要重新注册
+
+use crate::error::{Error, Result};
+use crate::key_store::KeyStore;
+use crate::sidecar;
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// 密钥变化事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// 变化后的存储内容校验和（与 [`KeyStore::stored_checksum`] 一致）
+    pub checksum: [u8; 32],
+}
+
+impl KeyStore {
+    /// 监视密钥的外部变化（需要 `watch` feature）
+    ///
+    /// 启动一个后台线程，通过inotify监视二进制所在的目录。二进制或旁路文件被修改、
+    /// 替换时重新计算 [`stored_checksum`](Self::stored_checksum)，校验和变化时发送
+    /// [`ChangeEvent`]。二进制通过rename被替换后同样能检测到
+    ///
+    /// 没有文件事件时线程阻塞等待，不读取文件。标准库的 `Receiver` 被丢弃时无法通知
+    /// 发送端，因此丢弃返回的 `Receiver` 后，线程在下一次检测到密钥变化、发送失败时退出，
+    /// 在此之前一直保留对目录的监视
+    ///
+    /// # 返回
+    ///
+    /// 成功返回事件接收端；无法监视所在目录时返回Error
+    ///
+    /// # 示例
+    ///
+    /// ```no_run
+    /// # use self_crypto_key::KeyStore;
+    /// let store = KeyStore::new()?;
+    /// let events = store.watch()?;
+    /// for _event in events {
+    ///     let key = store.read_bytes()?;
+    ///     println!("密钥已更新，长度: {}", key.len());
+    /// }
+    /// # Ok::<(), self_crypto_key::Error>(())
+    /// ```
+    pub fn watch(&self) -> Result<Receiver<ChangeEvent>> {
+        let exe_path = self.exe_path().to_path_buf();
+        let dir = match exe_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        // 先开始监视再计算初始校验和，避免遗漏两者之间发生的修改
+        let (fs_sender, fs_events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(fs_sender).map_err(watch_error(&dir))?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error(&dir))?;
+
+        let sections = self.storage_sections();
+        let initial = Self::load_checksum(&exe_path, &sections)?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || observe(watcher, fs_events, exe_path, sections, initial, sender));
+
+        Ok(receiver)
+    }
+}
+
+/// 将inotify的错误转换为带目录路径的Error
+fn watch_error(dir: &Path) -> impl FnOnce(notify::Error) -> Error + '_ {
+    move |e| match e.kind {
+        notify::ErrorKind::Io(source) => Error::io_at(dir)(source),
+        _ => Error::Config(format!("无法监视目录 {}: {}", dir.display(), e)),
+    }
+}
+
+/// 事件循环：处理目录中与二进制或旁路文件有关的事件，发送失败（接收端被丢弃）后退出
+///
+/// `_watcher` 需要在线程中保持存活，被丢弃后inotify监视随之取消
+fn observe(
+    _watcher: RecommendedWatcher,
+    fs_events: Receiver<notify::Result<Event>>,
+    exe_path: PathBuf,
+    sections: Vec<String>,
+    mut last: [u8; 32],
+    sender: Sender<ChangeEvent>,
+) {
+    let names: Vec<OsString> = [exe_path.clone(), sidecar::sidecar_path(&exe_path)]
+        .iter()
+        .filter_map(|path| path.file_name().map(OsString::from))
+        .collect();
+
+    for event in fs_events {
+        // 监视本身出错（例如事件队列溢出）时可能遗漏了事件，重新检查一次
+        if let Ok(event) = &event {
+            if !is_relevant(event, &names) {
+                continue;
+            }
+        }
+
+        // 读取失败（例如替换过程中文件暂时缺失）时等待下一个事件
+        let checksum = match KeyStore::load_checksum(&exe_path, &sections) {
+            Ok(checksum) => checksum,
+            Err(_) => continue,
+        };
+
+        if checksum != last {
+            last = checksum;
+            if sender.send(ChangeEvent { checksum }).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// 事件是否涉及二进制或旁路文件，并且可能改变了文件内容
+///
+/// 打开、读取等访问事件不会改变内容，而且计算校验和本身就会产生这类事件，需要忽略
+fn is_relevant(event: &Event, names: &[OsString]) -> bool {
+    let modifying = match event.kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) => false,
+        _ => true,
+    };
+
+    modifying
+        && event.paths.iter().any(|path| {
+            path.file_name()
+                .is_some_and(|name| names.iter().any(|n| n == name))
+        })
+}
@@ -0,0 +1,97 @@
+//! 调用方指定section名称的测试
+//!
+//! 单独的测试二进制：不使用 `init_key_storage!`，只声明自定义名称的section
+
+use std::fs;
+
+use self_crypto_key::KeyStore;
+
+#[link_section = ".app_meta"]
+#[used]
+#[no_mangle]
+static APP_META: [u8; 4096] = [0u8; 4096];
+
+#[link_section = ".app_vault_a"]
+#[used]
+#[no_mangle]
+static APP_VAULT_A: [u8; 1024] = [0u8; 1024];
+
+#[link_section = ".app_vault_b"]
+#[used]
+#[no_mangle]
+static APP_VAULT_B: [u8; 1024] = [0u8; 1024];
+
+#[link_section = ".app_vault_c"]
+#[used]
+#[no_mangle]
+static APP_VAULT_C: [u8; 1024] = [0u8; 1024];
+
+const SHARDS: [&str; 3] = [".app_vault_a", ".app_vault_b", ".app_vault_c"];
+
+#[test]
+fn test_explicit_sections_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app");
+    fs::copy(std::env::current_exe().unwrap(), &path).unwrap();
+
+    // 没有默认名称的section，常规方式无法打开
+    assert!(KeyStore::from_path(&path).is_err());
+
+    let key: Vec<u8> = (0..2000u32).map(|i| (i * 7 + 3) as u8).collect();
+    let mut store = KeyStore::from_path_with_sections(&path, ".app_meta", &SHARDS).unwrap();
+    assert_eq!(store.capacity(), 3 * 1024);
+    store.update_bytes(&key).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    let names: Vec<String> = store
+        .key_sections()
+        .unwrap()
+        .into_iter()
+        .map(|section| section.name)
+        .collect();
+    assert_eq!(
+        names,
+        [".app_meta", ".app_vault_a", ".app_vault_b", ".app_vault_c"]
+    );
+
+    // 重新打开后从自定义的元数据section读取
+    let reopened = KeyStore::from_path_with_sections(&path, ".app_meta", &SHARDS).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+}
+
+#[test]
+fn test_explicit_sections_validated() {
+    let path = std::env::current_exe().unwrap();
+
+    assert!(KeyStore::from_path_with_sections(&path, ".app_meta", &[".app_missing"]).is_err());
+    assert!(KeyStore::from_path_with_sections(&path, ".app_missing", &SHARDS).is_err());
+    assert!(KeyStore::from_path_with_sections(&path, ".app_meta", &[]).is_err());
+    assert!(KeyStore::from_path_with_sections(
+        &path,
+        ".app_meta",
+        &[".app_vault_a", ".app_vault_a"]
+    )
+    .is_err());
+}
+
+#[test]
+fn test_explicit_sections_grow() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app_grow");
+    fs::copy(std::env::current_exe().unwrap(), &path).unwrap();
+
+    // 先只用两个section写入，再把第三个也交给实例
+    let mut store = KeyStore::from_path_with_sections(&path, ".app_meta", &SHARDS[..2]).unwrap();
+    store.update_bytes(b"explicit-grow").unwrap();
+    let mut store = KeyStore::from_path_with_sections(&path, ".app_meta", &SHARDS).unwrap();
+    assert_eq!(store.capacity(), 2 * 1024);
+
+    store.grow(3).unwrap();
+    assert_eq!(store.capacity(), 3 * 1024);
+    assert_eq!(store.read_bytes().unwrap(), b"explicit-grow");
+
+    // 指定的section都已使用，不会借用其他section
+    assert!(store.grow(4).is_err());
+    assert_eq!(store.capacity(), 3 * 1024);
+    assert_eq!(store.read_bytes().unwrap(), b"explicit-grow");
+}
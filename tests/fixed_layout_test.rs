@@ -0,0 +1,36 @@
+//! 固定布局（无元数据）模式的测试
+
+use self_crypto_key::{init_key_storage, Error, KeyStore};
+use std::fs;
+
+init_key_storage!(fixed);
+
+#[test]
+fn test_fixed_layout_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("fixed");
+    fs::copy("/proc/self/exe", &path).unwrap();
+
+    // 二进制中没有 .key_meta，常规模式无法打开
+    assert!(matches!(
+        KeyStore::from_path(&path),
+        Err(Error::SectionNotFound(_))
+    ));
+
+    let mut store = KeyStore::fixed_from_path(&path).unwrap();
+    store.set_durable(false);
+    assert_eq!(store.capacity(), 8 * 1024);
+    assert_eq!(store.read_bytes().unwrap(), b"");
+
+    let key = KeyStore::generate_random_bytes(1500);
+    store.update_bytes(&key).unwrap();
+    assert_eq!(store.stored_len().unwrap(), 1500);
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    // 分片中存储的是密文
+    let binary = fs::read(&path).unwrap();
+    assert!(!binary.windows(64).any(|w| w == &key[..64]));
+
+    let reopened = KeyStore::fixed_from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+}
@@ -2,7 +2,14 @@
 //!
 //! 测试完整的密钥存储、更新和读取流程
 
-use self_crypto_key::{init_key_storage, KeyStore};
+use self_crypto_key::{
+    binaries_match, init_key_storage, self_test, update_group, AuditOperation, Error,
+    InterleaveStride, KeyStore, Limits, MetaEncoding, MigrationOutcome, Padding, SanityWarning,
+    ShardLayout,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 // 初始化密钥存储（测试用，8KB）
 init_key_storage!();
@@ -20,7 +27,7 @@ fn test_key_generation() {
     // 验证字符范围
     for ch in key1.chars() {
         let code = ch as u32;
-        assert!(code >= 33 && code <= 126, "字符 '{}' 不在可打印范围", ch);
+        assert!((33..=126).contains(&code), "字符 '{}' 不在可打印范围", ch);
     }
 }
 
@@ -56,10 +63,7 @@ fn test_keystore_creation() {
             println!("  总容量: {} 字节", capacity);
             assert!(capacity > 0, "容量应该大于0");
             // 容量取决于随机选择的shard数量（4-8个，每个1KB）
-            assert!(
-                capacity >= 4096 && capacity <= 8192,
-                "容量应该在4KB到8KB之间"
-            );
+            assert!((4096..=8192).contains(&capacity), "容量应该在4KB到8KB之间");
         }
         Err(e) => {
             println!("KeyStore创建失败: {}", e);
@@ -74,7 +78,7 @@ fn test_capacity() {
         let capacity = store.capacity();
         // 容量取决于随机选择的shard数量（4-8个，每个1KB）
         assert!(
-            capacity >= 4 * 1024 && capacity <= 8 * 1024,
+            (4 * 1024..=8 * 1024).contains(&capacity),
             "总容量应该在4KB到8KB之间"
         );
     }
@@ -303,3 +307,3055 @@ fn test_key_validation() {
 
     println!("密钥验证测试通过");
 }
+
+/// 辅助函数：将当前测试二进制复制到临时目录，用于不影响自身的读写测试
+///
+/// 其他测试会通过rename替换测试二进制本身，因此从 `/proc/self/exe` 复制，
+/// 避免 `current_exe()` 指向已被删除的路径
+fn binary_copy(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::copy("/proc/self/exe", &path).unwrap();
+    path
+}
+
+#[test]
+fn test_update_group() {
+    // 测试批量更新多个相同的二进制文件
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<PathBuf> = (0..3)
+        .map(|i| binary_copy(dir.path(), &format!("app_{}", i)))
+        .collect();
+
+    update_group(&paths, b"group-secret").unwrap();
+
+    for path in &paths {
+        let store = KeyStore::from_path(path.clone()).unwrap();
+        assert_eq!(store.read_bytes().unwrap(), b"group-secret");
+    }
+}
+
+#[test]
+fn test_update_group_failure_leaves_originals() {
+    // 测试任一文件失败时所有原始文件保持不变
+    let dir = tempfile::tempdir().unwrap();
+    let mut paths: Vec<PathBuf> = (0..3)
+        .map(|i| binary_copy(dir.path(), &format!("app_{}", i)))
+        .collect();
+
+    // 一个不是ELF的文件，准备阶段必然失败
+    let bogus = dir.path().join("not_a_binary");
+    fs::write(&bogus, b"plain text").unwrap();
+    paths.push(bogus);
+
+    let originals: Vec<Vec<u8>> = paths.iter().map(|p| fs::read(p).unwrap()).collect();
+
+    assert!(update_group(&paths, b"group-secret").is_err());
+
+    for (path, original) in paths.iter().zip(&originals) {
+        assert_eq!(&fs::read(path).unwrap(), original, "{:?} 被修改", path);
+    }
+
+    // 不应残留临时文件
+    let leftovers = fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .contains(".tmp.")
+        })
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn test_update_group_removes_stale_sidecar() {
+    // 旁路文件中的旧密钥不应在批量更新后继续生效
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "group_sidecar");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o555)).unwrap();
+    KeyStore::from_path(path.clone())
+        .unwrap()
+        .update_bytes_auto(b"stale-key")
+        .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    update_group(std::slice::from_ref(&path), b"group-secret").unwrap();
+
+    assert!(!dir.path().join("group_sidecar.key").exists());
+    let store = KeyStore::from_path(path).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"group-secret");
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_update_group_rename_failure_restores_originals() {
+    // rename阶段失败时，已替换的文件（及其旁路文件）从备份恢复
+    use self_crypto_key::update_group_with_hook;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<PathBuf> = (0..3)
+        .map(|i| binary_copy(dir.path(), &format!("app_{}", i)))
+        .collect();
+
+    // 第一个文件的密钥保存在旁路文件中
+    fs::set_permissions(&paths[0], fs::Permissions::from_mode(0o555)).unwrap();
+    KeyStore::from_path(paths[0].clone())
+        .unwrap()
+        .update_bytes_auto(b"sidecar-key")
+        .unwrap();
+    fs::set_permissions(&paths[0], fs::Permissions::from_mode(0o755)).unwrap();
+
+    let originals: Vec<Vec<u8>> = paths.iter().map(|p| fs::read(p).unwrap()).collect();
+    let sidecar = dir.path().join("app_0.key");
+    let original_sidecar = fs::read(&sidecar).unwrap();
+
+    // 第三个文件rename之前删除它的临时文件，使rename失败
+    let target = paths[2].clone();
+    let result = update_group_with_hook(&paths, b"group-secret", |path| {
+        if path != target {
+            return;
+        }
+        for entry in fs::read_dir(path.parent().unwrap()).unwrap() {
+            let entry = entry.unwrap();
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("app_2.tmp.")
+            {
+                fs::remove_file(entry.path()).unwrap();
+            }
+        }
+    });
+    assert!(matches!(result, Err(Error::IoAt { .. })));
+
+    for (path, original) in paths.iter().zip(&originals) {
+        assert_eq!(&fs::read(path).unwrap(), original, "{:?} 未恢复", path);
+    }
+    assert_eq!(fs::read(&sidecar).unwrap(), original_sidecar);
+    let store = KeyStore::from_path(paths[0].clone()).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"sidecar-key");
+
+    // 不应残留临时文件或备份文件
+    let leftovers: Vec<String> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.contains(".tmp.") || name.contains(".bak."))
+        .collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
+
+#[test]
+fn test_update_group_same_stem_targets() {
+    // 只有扩展名不同的目标文件使用各自的临时文件
+    let dir = tempfile::tempdir().unwrap();
+    let first = fresh_binary_copy(dir.path(), "app.v1");
+    let second = fresh_binary_copy(dir.path(), "app.v2");
+    let paths = vec![first.clone(), second];
+
+    update_group(&paths, b"same-stem").unwrap();
+    for path in &paths {
+        let store = KeyStore::from_path(path).unwrap();
+        assert_eq!(store.read_bytes().unwrap(), b"same-stem");
+    }
+
+    // 同一个文件出现两次时拒绝，不修改任何文件
+    let before = fs::read(&first).unwrap();
+    let duplicate = vec![first.clone(), dir.path().join(".").join("app.v1")];
+    assert!(matches!(
+        update_group(&duplicate, b"other"),
+        Err(Error::Config(_))
+    ));
+    assert_eq!(fs::read(&first).unwrap(), before);
+}
+
+/// 辅助函数：定位ELF64二进制中指定section的section header在文件中的偏移
+fn section_header_offset(data: &[u8], name: &str) -> usize {
+    let u16_at = |o: usize| u16::from_le_bytes([data[o], data[o + 1]]) as usize;
+    let u32_at = |o: usize| u32::from_le_bytes(data[o..o + 4].try_into().unwrap()) as usize;
+    let u64_at = |o: usize| u64::from_le_bytes(data[o..o + 8].try_into().unwrap()) as usize;
+
+    let shoff = u64_at(0x28);
+    let shentsize = u16_at(0x3a);
+    let shnum = u16_at(0x3c);
+    let shstrndx = u16_at(0x3e);
+    let strtab_offset = u64_at(shoff + shstrndx * shentsize + 24);
+
+    (0..shnum)
+        .map(|i| shoff + i * shentsize)
+        .find(|&header| {
+            let name_start = strtab_offset + u32_at(header);
+            let name_end = name_start + data[name_start..].iter().position(|&b| b == 0).unwrap();
+            &data[name_start..name_end] == name.as_bytes()
+        })
+        .unwrap_or_else(|| panic!("未找到section {}", name))
+}
+
+/// 辅助函数：将二进制文件中指定section的内容清零
+fn zero_section(path: &Path, name: &str) {
+    let mut data = fs::read(path).unwrap();
+    let range = section_range(&data, name);
+    data[range].fill(0);
+    fs::write(path, data).unwrap();
+}
+
+/// 辅助函数：从ELF64 section头部中读取section的文件范围
+fn section_range(data: &[u8], name: &str) -> std::ops::Range<usize> {
+    let header = section_header_offset(data, name);
+    let offset = u64::from_le_bytes(data[header + 24..header + 32].try_into().unwrap()) as usize;
+    let size = u64::from_le_bytes(data[header + 32..header + 40].try_into().unwrap()) as usize;
+    offset..offset + size
+}
+
+/// 辅助函数：复制一个从未写入过密钥的测试二进制
+///
+/// 测试二进制本身可能已被其他测试写入过密钥，因此清空所有密钥相关section
+fn fresh_binary_copy(dir: &Path, name: &str) -> PathBuf {
+    let path = binary_copy(dir, name);
+    zero_section(&path, ".key_meta");
+    for i in 0..8 {
+        zero_section(&path, &format!(".key_data_{:02}", i));
+    }
+    path
+}
+
+/// 辅助函数：修改二进制文件中指定section的sh_size
+fn set_section_size(path: &Path, name: &str, size: u64) {
+    let mut data = fs::read(path).unwrap();
+    let header = section_header_offset(&data, name);
+    data[header + 32..header + 40].copy_from_slice(&size.to_le_bytes());
+    fs::write(path, data).unwrap();
+}
+
+#[test]
+fn test_small_meta_section_rejected() {
+    // 元数据section小于头部+最小JSON空间时应返回清晰的错误而不是panic
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "small_meta");
+    set_section_size(&path, ".key_meta", 16);
+
+    match KeyStore::from_path(path) {
+        Err(Error::Config(msg)) => assert!(msg.contains("元数据section太小"), "{}", msg),
+        Err(e) => panic!("错误类型不符: {}", e),
+        Ok(_) => panic!("过小的元数据section不应被接受"),
+    }
+}
+
+#[test]
+fn test_zero_size_shard_section() {
+    // 模拟链接器只留下空的占位section
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "zero_shard");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"zero-size").unwrap();
+
+    let active = stored_shard_names(&store);
+    set_section_size(&path, &active[0], 0);
+
+    match store.read_bytes() {
+        Err(Error::Config(msg)) => {
+            assert!(msg.contains("大小为0"), "{}", msg);
+            assert!(msg.contains("#[used]"), "{}", msg);
+            assert!(msg.contains(&active[0]), "{}", msg);
+        }
+        Err(e) => panic!("错误类型不符: {}", e),
+        Ok(_) => panic!("大小为0的shard section不应被接受"),
+    }
+}
+
+#[test]
+fn test_durable_and_non_durable_writes() {
+    // 测试开启和关闭fsync时的写入路径
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "durable");
+    let mut store = KeyStore::from_path(path).unwrap();
+
+    store.update_bytes(b"durable-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"durable-key");
+
+    store.set_durable(false);
+    store.update_bytes(b"fast-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"fast-key");
+}
+
+#[test]
+fn test_peek_raw_meta() {
+    // 测试读取原始元数据section
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "peek_meta");
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.update_bytes(b"peek-key").unwrap();
+
+    let raw = store.peek_raw_meta().unwrap();
+    assert_eq!(raw.len(), 4096);
+    assert_eq!(&raw[..8], &(b"peek-key".len() as u64).to_le_bytes());
+
+    let text = String::from_utf8_lossy(&raw);
+    assert!(text.contains("\"num_shards\""));
+    assert!(text.contains("\"shard_names\""));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compression_threshold() {
+    // 小密钥不压缩，大的重复密钥压缩，两者都能正确读回
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "compression");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_compression_threshold(64);
+
+    let small = b"0123456789";
+    store.update_bytes(small).unwrap();
+    assert_eq!(store.stored_len().unwrap(), small.len());
+    assert!(
+        String::from_utf8_lossy(&store.peek_raw_meta().unwrap()).contains("\"compressed\":false")
+    );
+    assert_eq!(store.read_bytes().unwrap(), small);
+
+    let large = b"repetitive-".repeat(400);
+    store.update_bytes(&large).unwrap();
+    assert!(store.stored_payload_len().unwrap() < large.len());
+    // stored_len 仍然返回密钥本身的长度
+    assert_eq!(store.stored_len().unwrap(), large.len());
+    assert_eq!(
+        KeyStore::from_path_readonly(&path)
+            .unwrap()
+            .stored_len()
+            .unwrap(),
+        large.len()
+    );
+    assert!(
+        String::from_utf8_lossy(&store.peek_raw_meta().unwrap()).contains("\"compressed\":true")
+    );
+    assert_eq!(store.read_bytes().unwrap(), large);
+}
+
+#[test]
+fn test_bench_restores_key() {
+    // 测试bench返回有效耗时，并保留原先存储的密钥
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "bench");
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.set_durable(false);
+    store.update_bytes(b"keep-me").unwrap();
+
+    let result = store.bench(256, 3).unwrap();
+    assert!(result.write_mean > Duration::ZERO);
+    assert!(result.write_median > Duration::ZERO);
+    assert!(result.read_mean > Duration::ZERO);
+    assert!(result.read_median > Duration::ZERO);
+
+    assert_eq!(store.read_bytes().unwrap(), b"keep-me");
+}
+
+#[test]
+fn test_bench_leaves_storage_untouched() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // 测试bench结束后二进制逐字节恢复，而不是重新加密写回
+    let dir = tempfile::tempdir().unwrap();
+
+    // 尚未写入过密钥的二进制保持未写入
+    let fresh_path = fresh_binary_copy(dir.path(), "bench_fresh");
+    let mut fresh = KeyStore::from_path(fresh_path.clone()).unwrap();
+    let before = fs::read(&fresh_path).unwrap();
+    fresh.bench(64, 2).unwrap();
+    assert!(!fresh.is_initialized().unwrap());
+    assert_eq!(fs::read(&fresh_path).unwrap(), before);
+
+    // 已写入的二进制：历史版本、nonce和审计记录都不受影响
+    let events = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&events);
+    let path = binary_copy(dir.path(), "bench_used");
+    let mut store = KeyStore::from_path(path.clone())
+        .unwrap()
+        .with_history(1)
+        .with_remap_on_write(true);
+    store.update_bytes(b"old").unwrap();
+    store.update_bytes(b"current").unwrap();
+    store.set_audit_hook(Box::new(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    }));
+
+    let before = fs::read(&path).unwrap();
+    store.bench(64, 3).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), before);
+    assert_eq!(events.load(Ordering::SeqCst), 0);
+    assert_eq!(store.read_bytes().unwrap(), b"current");
+    assert_eq!(store.read_history(1).unwrap(), b"old");
+}
+
+#[test]
+fn test_store_and_load_json() {
+    // 测试以JSON形式存储结构体
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Credential {
+        username: String,
+        token: String,
+        expiry: u64,
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "json");
+    let mut store = KeyStore::from_path(path).unwrap();
+
+    let credential = Credential {
+        username: "admin".to_string(),
+        token: "tok-123".to_string(),
+        expiry: 1_700_000_000,
+    };
+    store.store_json(&credential).unwrap();
+
+    let loaded: Credential = store.load_json().unwrap();
+    assert_eq!(loaded, credential);
+
+    // 类型不匹配时返回解析错误
+    assert!(matches!(
+        store.load_json::<Vec<u32>>(),
+        Err(Error::Parse(_))
+    ));
+}
+
+#[test]
+fn test_metadata_usage() {
+    // 测试元数据空间使用情况与实际写入的元数据一致
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "meta_usage");
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.update_bytes(b"usage").unwrap();
+
+    let (used, total) = store.metadata_usage().unwrap();
+    assert_eq!(total, 4096);
+    assert!(used < total);
+
+    // 已使用部分之后应全部为0
+    let raw = store.peek_raw_meta().unwrap();
+    assert_ne!(raw[used - 1], 0);
+    assert!(raw[used..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_update_bytes_auto_falls_back_to_sidecar() {
+    // 二进制只读时应写入旁路文件，且能正确读回
+    use self_crypto_key::StorageMode;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "sidecar");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o555)).unwrap();
+    let original = fs::read(&path).unwrap();
+
+    let mut store = KeyStore::from_path(path.clone()).unwrap();
+    assert_eq!(
+        store.update_bytes_auto(b"sidecar-key").unwrap(),
+        StorageMode::Sidecar
+    );
+    assert_eq!(fs::read(&path).unwrap(), original);
+    assert!(dir.path().join("sidecar.key").exists());
+    assert_eq!(store.read_bytes().unwrap(), b"sidecar-key");
+    assert_eq!(store.stored_len().unwrap(), b"sidecar-key".len());
+
+    // 恢复可写后直接写入二进制，并清除旁路文件
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(
+        store.update_bytes_auto(b"in-place-key").unwrap(),
+        StorageMode::InPlace
+    );
+    assert!(!dir.path().join("sidecar.key").exists());
+    assert_eq!(store.read_bytes().unwrap(), b"in-place-key");
+}
+
+#[test]
+fn test_tampered_sidecar_rejected() {
+    // 旁路文件被修改后读取应返回完整性错误，而不是错误的密钥
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "sidecar_tamper");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o555)).unwrap();
+
+    let mut store = KeyStore::from_path(path.clone()).unwrap();
+    store.update_bytes_auto(b"sidecar-key").unwrap();
+    let sidecar_path = dir.path().join("sidecar_tamper.key");
+    let first = fs::read(&sidecar_path).unwrap();
+
+    // 每次写入使用新的nonce，相同的密钥得到不同的内容
+    store.update_bytes_auto(b"sidecar-key").unwrap();
+    let mut content = fs::read(&sidecar_path).unwrap();
+    assert_ne!(content, first);
+
+    let last = content.len() - 1;
+    content[last] ^= 0x01;
+    fs::write(&sidecar_path, &content).unwrap();
+    assert!(matches!(
+        store.read_bytes(),
+        Err(Error::IntegrityCheckFailed(_))
+    ));
+}
+
+#[test]
+fn test_constant_time_read() {
+    // 恒定工作量模式下读取结果不变
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "constant_time");
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.set_constant_time(true);
+
+    store.update_bytes(b"").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"");
+
+    let full = KeyStore::generate_random_bytes(store.capacity());
+    store.update_bytes(&full).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), full);
+
+    // 宽松的计时测试：只涉及一个分片的短密钥与占满所有分片的长密钥耗时相近
+    let median_read = |store: &KeyStore| {
+        let mut samples: Vec<Duration> = (0..7)
+            .map(|_| {
+                let start = std::time::Instant::now();
+                store.read_bytes().unwrap();
+                start.elapsed()
+            })
+            .collect();
+        samples.sort();
+        samples[samples.len() / 2]
+    };
+
+    let long = median_read(&store);
+    store.update_bytes(b"x").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"x");
+    let short = median_read(&store);
+
+    assert!(
+        short * 3 > long,
+        "短密钥读取明显更快: {:?} vs {:?}",
+        short,
+        long
+    );
+}
+
+#[test]
+fn test_reserve() {
+    // reserve只初始化元数据，重复调用不做修改
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "reserve");
+    let mut store = KeyStore::from_path(path.clone()).unwrap();
+
+    store.reserve().unwrap();
+    assert!(String::from_utf8_lossy(&store.peek_raw_meta().unwrap()).contains("\"num_shards\""));
+    assert_eq!(store.stored_len().unwrap(), 0);
+
+    let reserved = fs::read(&path).unwrap();
+    store.reserve().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), reserved);
+
+    let key = vec![0x42; store.capacity()];
+    store.update_bytes(&key).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), key);
+}
+
+#[test]
+fn test_verify_against() {
+    // 只有完全一致的值才能通过校验
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "verify");
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.update_bytes(b"provisioned-secret").unwrap();
+
+    assert!(store.verify_against(b"provisioned-secret").unwrap());
+    assert!(!store.verify_against(b"provisioned-secreT").unwrap());
+    assert!(!store.verify_against(b"provisioned-secret!").unwrap());
+}
+
+#[test]
+fn test_is_initialized_distinguishes_empty_key() {
+    // 从未写入：读取为空，且未初始化
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "never_written");
+    let mut store = KeyStore::from_path(path).unwrap();
+    assert!(store.read_bytes().unwrap().is_empty());
+    assert!(!store.is_initialized().unwrap());
+
+    // 写入失败不会留下已初始化的元数据
+    let too_long = KeyStore::generate_random_bytes(store.capacity() + 1);
+    assert!(matches!(
+        store.update_bytes(&too_long),
+        Err(Error::Config(_))
+    ));
+
+    // 只预留元数据不算写入
+    store.reserve().unwrap();
+    assert!(!store.is_initialized().unwrap());
+    assert_eq!(store.try_read_bytes().unwrap(), None);
+
+    // 显式写入空密钥：读取同样为空，但已初始化
+    store.update_bytes(b"").unwrap();
+    assert!(store.read_bytes().unwrap().is_empty());
+    assert!(store.is_initialized().unwrap());
+}
+
+#[test]
+fn test_padded_shard_sections() {
+    // 模拟链接器对齐填充：section的file_range比逻辑分片大小更大
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "padded");
+    for i in 0..8 {
+        set_section_size(&path, &format!(".key_data_{:02}", i), 4096);
+    }
+
+    let before = fs::read(&path).unwrap();
+    let mut store = KeyStore::from_path(&path).unwrap();
+    let key = vec![0x5a; store.capacity()];
+    store.update_bytes(&key).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    // 每个分片只写入逻辑大小，最后一个分片之后的字节（原本属于其他数据）保持不变
+    let after = fs::read(&path).unwrap();
+    let end = (0..8)
+        .map(|i| {
+            let header = section_header_offset(&after, &format!(".key_data_{:02}", i));
+            u64::from_le_bytes(after[header + 24..header + 32].try_into().unwrap()) as usize
+        })
+        .max()
+        .unwrap()
+        + 1024;
+    assert_eq!(before[end..end + 64], after[end..end + 64]);
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn test_watch_reports_external_update() {
+    // 另一个句柄更新密钥后，监视端应收到通知
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "watch");
+    let watcher = KeyStore::from_path(&path).unwrap();
+    let events = watcher.watch().unwrap();
+
+    let mut writer = KeyStore::from_path(&path).unwrap();
+    writer.update_bytes(b"rotated-key").unwrap();
+
+    let event = events.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(event.checksum, watcher.stored_checksum().unwrap());
+    assert_eq!(watcher.read_bytes().unwrap(), b"rotated-key");
+
+    // 读取文件（包括监视线程自己计算校验和）不会产生新的事件
+    assert!(events.recv_timeout(Duration::from_millis(500)).is_err());
+
+    // 再次替换后仍能收到通知
+    writer.update_bytes(b"rotated-again").unwrap();
+    let event = events.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(event.checksum, watcher.stored_checksum().unwrap());
+}
+
+#[test]
+fn test_derive_sections_round_trip() {
+    // 绑定.rodata后写入的密钥同样可以被新实例读回
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "derive_rodata");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_derive_sections(&[".rodata"]);
+    store.update_bytes(b"rodata-bound").unwrap();
+
+    let reader = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reader.read_bytes().unwrap(), b"rodata-bound");
+}
+
+#[test]
+fn test_suggest_derive_section() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "suggest");
+    let store = KeyStore::from_path(path).unwrap();
+    let suggestions = store.suggest_derive_section().unwrap();
+
+    assert!(!suggestions.is_empty());
+    assert!(suggestions.iter().any(|(name, _)| name == ".text"));
+    assert!(!suggestions
+        .iter()
+        .any(|(name, _)| name.starts_with(".key_")));
+
+    // 按section大小降序排列
+    let data = fs::read(store.exe_path()).unwrap();
+    let sizes: Vec<u64> = suggestions
+        .iter()
+        .map(|(name, _)| {
+            let header = section_header_offset(&data, name);
+            u64::from_le_bytes(data[header + 32..header + 40].try_into().unwrap())
+        })
+        .collect();
+    assert!(sizes.windows(2).all(|w| w[0] >= w[1]));
+}
+
+#[test]
+fn test_blob_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "blob");
+    let store = KeyStore::from_path(path).unwrap();
+
+    let blob = store.encrypt_blob(b"external-config-secret").unwrap();
+    assert!(!blob
+        .windows(b"external-config-secret".len())
+        .any(|w| w == b"external-config-secret"));
+    assert_eq!(
+        store.decrypt_blob(&blob).unwrap(),
+        b"external-config-secret"
+    );
+
+    // 每次加密使用新的nonce
+    assert_ne!(store.encrypt_blob(b"external-config-secret").unwrap(), blob);
+}
+
+#[test]
+fn test_blob_rejects_other_binary() {
+    // .text不同的二进制无法解密
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "blob_origin");
+    let blob = KeyStore::from_path(&path)
+        .unwrap()
+        .encrypt_blob(b"bound")
+        .unwrap();
+
+    let other = binary_copy(dir.path(), "blob_other");
+    let mut data = fs::read(&other).unwrap();
+    let header = section_header_offset(&data, ".text");
+    let offset = u64::from_le_bytes(data[header + 24..header + 32].try_into().unwrap()) as usize;
+    data[offset] ^= 0xff;
+    fs::write(&other, data).unwrap();
+
+    let other_store = KeyStore::from_path(other).unwrap();
+    assert!(matches!(
+        other_store.decrypt_blob(&blob),
+        Err(Error::Crypto(_))
+    ));
+}
+
+#[test]
+fn test_reset() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "reset");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"before-reset").unwrap();
+    assert!(!store.needs_init().unwrap());
+
+    store.reset().unwrap();
+    assert!(store.needs_init().unwrap());
+    assert!(!store.is_initialized().unwrap());
+    assert!(store.read_bytes().unwrap().is_empty());
+
+    // 与新二进制一样重新生成配置
+    let mut fresh = KeyStore::from_path(&path).unwrap();
+    assert!(fresh.needs_init().unwrap());
+    fresh.update_bytes(b"after-reset").unwrap();
+    assert_eq!(fresh.read_bytes().unwrap(), b"after-reset");
+    assert!(!fresh.needs_init().unwrap());
+}
+
+#[test]
+fn test_rewrite_same_key_changes_ciphertext() {
+    // 每次写入使用新的nonce，相同的密钥得到不同的密文
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "nonce");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    let shard_bytes = |path: &Path| {
+        let data = fs::read(path).unwrap();
+        (0..8)
+            .map(|i| {
+                let header = section_header_offset(&data, &format!(".key_data_{:02}", i));
+                let offset =
+                    u64::from_le_bytes(data[header + 24..header + 32].try_into().unwrap()) as usize;
+                data[offset..offset + 1024].to_vec()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    store.update_bytes(b"same-key").unwrap();
+    let first = shard_bytes(&path);
+    assert_eq!(store.read_bytes().unwrap(), b"same-key");
+
+    store.update_bytes(b"same-key").unwrap();
+    let second = shard_bytes(&path);
+    assert_eq!(store.read_bytes().unwrap(), b"same-key");
+
+    assert_ne!(first, second);
+}
+
+/// 从元数据section中解析出分片名称列表
+fn stored_metadata_json(store: &KeyStore) -> serde_json::Value {
+    let raw = store.peek_raw_meta().unwrap();
+    let len = u64::from_le_bytes(raw[8..16].try_into().unwrap()) as usize;
+    serde_json::from_slice(&raw[16..16 + len]).unwrap()
+}
+
+fn stored_shard_names(store: &KeyStore) -> Vec<String> {
+    serde_json::from_value(stored_metadata_json(store)["shard_names"].clone()).unwrap()
+}
+
+#[test]
+fn test_copy_layout_from() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut source = KeyStore::from_path(fresh_binary_copy(dir.path(), "layout_src")).unwrap();
+    source.update_bytes(b"source-key").unwrap();
+
+    // 目标已存储的密钥在采用新布局后保持不变
+    let mut target = KeyStore::from_path(fresh_binary_copy(dir.path(), "layout_dst")).unwrap();
+    target.update_bytes(b"target-key").unwrap();
+    target.copy_layout_from(&source).unwrap();
+    assert_eq!(target.read_bytes().unwrap(), b"target-key");
+
+    target.update_bytes(b"next-key").unwrap();
+    assert_eq!(stored_shard_names(&target), stored_shard_names(&source));
+    assert_eq!(target.capacity(), source.capacity());
+    assert_eq!(target.read_bytes().unwrap(), b"next-key");
+}
+
+#[test]
+fn test_failed_copy_layout_keeps_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut source = KeyStore::from_path(fresh_binary_copy(dir.path(), "layout_src")).unwrap();
+    source.update_bytes(b"source-key").unwrap();
+
+    let mut target = KeyStore::from_path(fresh_binary_copy(dir.path(), "layout_dst")).unwrap();
+    target.update_bytes(b"target-key").unwrap();
+    let names = stored_shard_names(&target);
+
+    // 文件名接近上限时无法创建临时文件，写回失败
+    let path = dir.path().join("c".repeat(250));
+    fs::rename(target.exe_path(), &path).unwrap();
+    let mut target = KeyStore::from_path(&path).unwrap();
+    let capacity = target.capacity();
+
+    assert!(matches!(
+        target.copy_layout_from(&source),
+        Err(Error::IoAt { .. })
+    ));
+    assert_eq!(target.capacity(), capacity);
+    assert_eq!(stored_shard_names(&target), names);
+    assert_eq!(target.read_bytes().unwrap(), b"target-key");
+}
+
+#[test]
+fn test_shards_are_writable_segment() {
+    // 与手工解析程序头得到的结果一致
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "segments");
+    let data = fs::read(&path).unwrap();
+    let u16_at = |o: usize| u16::from_le_bytes(data[o..o + 2].try_into().unwrap()) as usize;
+    let u64_at = |o: usize| u64::from_le_bytes(data[o..o + 8].try_into().unwrap());
+
+    let phoff = u64_at(0x20) as usize;
+    let (phentsize, phnum) = (u16_at(0x36), u16_at(0x38));
+    let writable_load: Vec<(u64, u64)> = (0..phnum)
+        .map(|i| phoff + i * phentsize)
+        .filter(|&ph| {
+            let p_type = u32::from_le_bytes(data[ph..ph + 4].try_into().unwrap());
+            let p_flags = u32::from_le_bytes(data[ph + 4..ph + 8].try_into().unwrap());
+            p_type == 1 && p_flags & 0x2 != 0
+        })
+        .map(|ph| (u64_at(ph + 8), u64_at(ph + 8) + u64_at(ph + 32)))
+        .collect();
+
+    let shard_writable = |name: &str| {
+        let header = section_header_offset(&data, name);
+        let (start, size) = (u64_at(header + 24), u64_at(header + 32));
+        writable_load
+            .iter()
+            .any(|&(s, e)| s <= start && start + size <= e)
+    };
+    let expected = (0..8).all(|i| shard_writable(&format!(".key_data_{:02}", i)));
+
+    let store = KeyStore::from_path(path).unwrap();
+    assert_eq!(store.shards_are_writable_segment().unwrap(), expected);
+
+    // 程序头中的偏移加大小溢出时不panic，这样的段视为不包含分片
+    let mut forged = data.clone();
+    for ph in (0..phnum).map(|i| phoff + i * phentsize) {
+        if u32::from_le_bytes(forged[ph..ph + 4].try_into().unwrap()) == 1 {
+            forged[ph + 8..ph + 16].copy_from_slice(&1u64.to_le_bytes());
+            forged[ph + 32..ph + 40].copy_from_slice(&u64::MAX.to_le_bytes());
+        }
+    }
+    let forged_path = dir.path().join("segments_forged");
+    fs::write(&forged_path, forged).unwrap();
+    let store = KeyStore::from_path(forged_path).unwrap();
+    assert!(!store.shards_are_writable_segment().unwrap());
+}
+
+#[test]
+fn test_io_error_reports_path() {
+    // 文件名接近上限时临时文件名过长，创建失败，错误信息中应包含出错的临时文件路径
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), &"p".repeat(250));
+
+    let mut store = KeyStore::from_path(&path).unwrap();
+    let err = store.update_bytes(b"key").unwrap_err();
+    assert!(matches!(err, Error::IoAt { .. }), "{:?}", err);
+    let temp_prefix = format!("{}.tmp.", path.display());
+    assert!(err.to_string().contains(&temp_prefix), "{}", err);
+
+    // 读取不存在的文件同样带有路径
+    let missing = dir.path().join("missing");
+    let err = KeyStore::from_path(&missing).err().unwrap();
+    assert!(err.to_string().contains(&missing.display().to_string()));
+}
+
+#[test]
+fn test_try_read_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "try_read");
+    let mut store = KeyStore::from_path(path).unwrap();
+
+    assert_eq!(store.try_read_bytes().unwrap(), None);
+
+    store.update_bytes(b"").unwrap();
+    assert_eq!(store.try_read_bytes().unwrap(), Some(Vec::new()));
+
+    store.update_bytes(b"value").unwrap();
+    assert_eq!(store.try_read_bytes().unwrap(), Some(b"value".to_vec()));
+}
+
+#[test]
+fn test_grow_preserves_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "grow");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    // 重新生成布局，直到只使用4个分片
+    while store.capacity() != 4 * 1024 {
+        store.reset().unwrap();
+    }
+    let key = KeyStore::generate_random_bytes(3000);
+    store.update_bytes(&key).unwrap();
+
+    assert!(store.grow(3).is_err());
+    assert!(store.grow(9).is_err());
+
+    store.grow(8).unwrap();
+    assert_eq!(store.capacity(), 8 * 1024);
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    // 新实例从元数据中读到扩大后的布局
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.capacity(), 8 * 1024);
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+}
+
+#[test]
+fn test_failed_grow_keeps_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(binary_copy(dir.path(), "grow_fail")).unwrap();
+    while store.capacity() == 8 * 1024 {
+        store.reset().unwrap();
+    }
+    store.update_bytes(b"grow-key").unwrap();
+
+    // 文件名接近上限时无法创建临时文件，写回失败
+    let path = dir.path().join("g".repeat(250));
+    fs::rename(store.exe_path(), &path).unwrap();
+    let mut store = KeyStore::from_path(&path).unwrap();
+    let capacity = store.capacity();
+
+    assert!(matches!(store.grow(8), Err(Error::IoAt { .. })));
+    assert_eq!(store.capacity(), capacity);
+    assert_eq!(store.read_bytes().unwrap(), b"grow-key");
+}
+
+#[test]
+fn test_utf16_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "utf16");
+    let mut store = KeyStore::from_path(path).unwrap();
+
+    // 包含BMP内的中文和需要代理对的字符
+    let credential: Vec<u16> = "凭据-pässwörd-🔑".encode_utf16().collect();
+    store.update_utf16(&credential).unwrap();
+    assert_eq!(store.read_utf16().unwrap(), credential);
+
+    store.update_bytes(b"odd").unwrap();
+    assert!(matches!(store.read_utf16(), Err(Error::Parse(_))));
+}
+
+#[test]
+fn test_stored_key_fingerprint() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "fingerprint");
+    let mut store = KeyStore::from_path(path).unwrap();
+    assert_eq!(store.stored_key_fingerprint().unwrap(), "");
+
+    store.update_bytes(b"first-key").unwrap();
+    let first = store.stored_key_fingerprint().unwrap();
+    assert_eq!(first.len(), 64);
+    assert!(!first.contains("first-key"));
+    assert_eq!(store.stored_key_fingerprint().unwrap(), first);
+
+    // 重新写入相同的密钥（密文不同）指纹不变，换成其他密钥后指纹变化
+    store.update_bytes(b"first-key").unwrap();
+    assert_eq!(store.stored_key_fingerprint().unwrap(), first);
+    store.update_bytes(b"second-key").unwrap();
+    assert_ne!(store.stored_key_fingerprint().unwrap(), first);
+}
+
+/// 将section头字符串表中的section名称改为另一个等长名称
+fn rename_section(path: &Path, from: &str, to: &str) {
+    let mut data = fs::read(path).unwrap();
+    let u16_at = |d: &[u8], o: usize| u16::from_le_bytes(d[o..o + 2].try_into().unwrap()) as usize;
+    let u64_at = |d: &[u8], o: usize| u64::from_le_bytes(d[o..o + 8].try_into().unwrap()) as usize;
+
+    let shoff = u64_at(&data, 0x28);
+    let strtab_header = shoff + u16_at(&data, 0x3e) * u16_at(&data, 0x3a);
+    let (start, size) = (
+        u64_at(&data, strtab_header + 24),
+        u64_at(&data, strtab_header + 32),
+    );
+
+    let needle = [from.as_bytes(), b"\0"].concat();
+    let pos = data[start..start + size]
+        .windows(needle.len())
+        .position(|w| w == needle.as_slice())
+        .unwrap();
+    data[start + pos..start + pos + to.len()].copy_from_slice(to.as_bytes());
+    fs::write(path, data).unwrap();
+}
+
+/// 将PT_INTERP程序头改为PT_NULL，模拟静态链接的二进制
+fn strip_interp(path: &Path) {
+    let mut data = fs::read(path).unwrap();
+    let phoff = u64::from_le_bytes(data[0x20..0x28].try_into().unwrap()) as usize;
+    let phentsize = u16::from_le_bytes(data[0x36..0x38].try_into().unwrap()) as usize;
+    let phnum = u16::from_le_bytes(data[0x38..0x3a].try_into().unwrap()) as usize;
+    for i in 0..phnum {
+        let ph = phoff + i * phentsize;
+        if u32::from_le_bytes(data[ph..ph + 4].try_into().unwrap()) == 3 {
+            data[ph..ph + 4].copy_from_slice(&0u32.to_le_bytes());
+        }
+    }
+    fs::write(path, data).unwrap();
+}
+
+#[test]
+fn test_missing_shard_in_static_binary_explained() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = KeyStore::generate_random_bytes(7 * 1024 + 1);
+
+    // 写入占满全部8个分片的密钥，之后再让 .key_data_03 消失，读取时必然用到它
+    let open_written = |name: &str| {
+        let path = fresh_binary_copy(dir.path(), name);
+        let mut store = KeyStore::from_path(&path).unwrap();
+        while store.capacity() != 8 * 1024 {
+            store.reset().unwrap();
+        }
+        store.update_bytes(&key).unwrap();
+        rename_section(&path, ".key_data_03", ".key_dat@_03");
+        (path, store)
+    };
+
+    // 动态链接：保持SectionNotFound
+    let (_, store) = open_written("dynamic_gc");
+    assert!(matches!(store.read_bytes(), Err(Error::SectionNotFound(_))));
+
+    // 静态链接：给出重新构建的建议
+    let (static_bin, store) = open_written("static_gc");
+    strip_interp(&static_bin);
+    match store.read_bytes() {
+        Err(Error::Config(msg)) => assert!(msg.contains("--no-gc-sections"), "{}", msg),
+        other => panic!("错误类型不符: {:?}", other.map(|_| ())),
+    }
+}
+
+#[cfg(target_env = "musl")]
+#[test]
+fn test_musl_static_sections_survive() {
+    // musl静态构建中全部密钥section都应保留，且可以正常读写
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "musl");
+    let data = fs::read(&path).unwrap();
+    section_header_offset(&data, ".key_meta");
+    for i in 0..8 {
+        section_header_offset(&data, &format!(".key_data_{:02}", i));
+    }
+
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.update_bytes(b"musl-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"musl-key");
+}
+
+#[test]
+fn test_kdf_iterations() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "kdf");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_kdf_iterations(100_000);
+    store.update_bytes(b"stretched-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"stretched-key");
+
+    // 未显式设置时使用二进制中记录的迭代次数
+    let reader = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reader.read_bytes().unwrap(), b"stretched-key");
+
+    // 设置了不同的迭代次数时读取失败
+    let mismatched = KeyStore::from_path(&path)
+        .unwrap()
+        .with_kdf_iterations(1_000);
+    assert!(matches!(mismatched.read_bytes(), Err(Error::Config(_))));
+}
+
+#[test]
+fn test_key_sections() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "sections");
+    let mut store = KeyStore::from_path(path).unwrap();
+
+    let before = store.key_sections().unwrap();
+    assert_eq!(before.len(), 9);
+    assert!(before.iter().all(|s| s.zeroed));
+
+    store.update_bytes(b"section-status").unwrap();
+    let sections = store.key_sections().unwrap();
+    assert_eq!(sections[0].name, ".key_meta");
+    assert!(sections[0].active && !sections[0].zeroed);
+
+    let data: Vec<_> = sections[1..].iter().collect();
+    assert_eq!(data.len(), 8);
+    assert!(data.iter().all(|s| s.size == 1024));
+    let active: Vec<_> = data.iter().filter(|s| s.active).collect();
+    assert_eq!(active.len() * 1024, store.capacity());
+    assert!(active.iter().all(|s| !s.zeroed));
+    assert!(data.iter().filter(|s| !s.active).all(|s| s.zeroed));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_binary_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "replaced");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    assert!(!store.binary_replaced().unwrap());
+
+    // 自身的写入不算替换
+    store.update_bytes(b"pinned").unwrap();
+    assert!(!store.binary_replaced().unwrap());
+
+    // 模拟部署：新文件原子替换旧路径
+    let staged = binary_copy(dir.path(), "replaced.new");
+    fs::rename(&staged, &path).unwrap();
+    assert!(store.binary_replaced().unwrap());
+}
+
+#[test]
+fn test_binding_consistent() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "binding");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    assert!(store.binding_consistent().unwrap());
+
+    store.update_bytes(b"bound-key").unwrap();
+    assert!(store.binding_consistent().unwrap());
+
+    // 缩小一个活动分片的section后不再一致
+    let active = stored_shard_names(&store);
+    set_section_size(&path, &active[0], 512);
+    assert!(!store.binding_consistent().unwrap());
+}
+
+#[test]
+fn test_read_with_provenance() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(fresh_binary_copy(dir.path(), "provenance")).unwrap();
+    let key = KeyStore::generate_random_bytes(2500);
+    store.update_bytes(&key).unwrap();
+
+    let (read, provenance) = store.read_with_provenance().unwrap();
+    assert_eq!(read, key);
+
+    // 范围连续、覆盖整个密钥，且按顺序对应活动分片
+    let mut expected_start = 0;
+    for (_, range) in &provenance {
+        assert_eq!(range.start, expected_start);
+        expected_start = range.end;
+    }
+    assert_eq!(expected_start, store.stored_len().unwrap());
+
+    // 分片按元数据中记录的逻辑顺序拼接
+    let mut logical = stored_shard_names(&store);
+    let index: Vec<usize> =
+        serde_json::from_value(stored_metadata_json(&store)["logical_index"].clone()).unwrap();
+    let mut order: Vec<usize> = (0..logical.len()).collect();
+    order.sort_by_key(|&i| index[i]);
+    logical = order.into_iter().map(|i| logical[i].clone()).collect();
+
+    let names: Vec<String> = provenance.into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, logical[..3]);
+}
+
+#[test]
+fn test_interleaved_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "interleaved");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_shard_layout(ShardLayout::Interleaved);
+    let key = KeyStore::generate_random_bytes(3000);
+    store.update_bytes(&key).unwrap();
+
+    let reader = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reader.read_bytes().unwrap(), key);
+
+    // 第一个分片只包含每隔n个的字节，而不是连续的前缀
+    let n = stored_shard_names(&reader).len();
+    let (read, provenance) = reader.read_with_provenance().unwrap();
+    assert_eq!(read, key);
+    let first = &provenance[0].0;
+    let from_first: Vec<usize> = provenance
+        .iter()
+        .filter(|(name, _)| name == first)
+        .map(|(_, range)| range.start)
+        .collect();
+    assert_eq!(from_first, (0..3000).step_by(n).collect::<Vec<_>>());
+    assert!(provenance.iter().all(|(_, range)| range.len() == 1));
+}
+
+#[test]
+fn test_length_written_but_shards_zero() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "crashed_write");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"half-written").unwrap();
+
+    // 模拟写入长度后、写入分片前崩溃：元数据完整而分片仍为全零
+    for name in stored_shard_names(&store) {
+        zero_section(&path, &name);
+    }
+
+    assert!(matches!(store.read_bytes(), Err(Error::Parse(_))));
+    store.set_constant_time(true);
+    assert!(matches!(store.read_bytes(), Err(Error::Parse(_))));
+}
+
+#[test]
+fn test_with_allowed_shards() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "allowed");
+    let allowed = [
+        ".key_data_00",
+        ".key_data_01",
+        ".key_data_02",
+        ".key_data_03",
+    ];
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_allowed_shards(&[0, 1, 2, 3])
+        .unwrap();
+    store.update_bytes(b"restricted").unwrap();
+    assert!(stored_shard_names(&store)
+        .iter()
+        .all(|n| allowed.contains(&n.as_str())));
+
+    store.reset().unwrap();
+    store.update_bytes(b"restricted").unwrap();
+    assert!(stored_shard_names(&store)
+        .iter()
+        .all(|n| allowed.contains(&n.as_str())));
+
+    // 允许的分片都已使用时不能再扩大，也不会借用其他分片
+    let used = stored_shard_names(&store);
+    store.grow(used.len()).unwrap();
+    if used.len() == allowed.len() {
+        assert!(matches!(store.grow(used.len() + 1), Err(Error::Config(_))));
+        assert_eq!(stored_shard_names(&store), used);
+        assert_eq!(store.read_bytes().unwrap(), b"restricted");
+    }
+
+    assert!(matches!(
+        KeyStore::from_path(&path)
+            .unwrap()
+            .with_allowed_shards(&[0, 1, 2]),
+        Err(Error::Config(_))
+    ));
+    assert!(matches!(
+        KeyStore::from_path(&path)
+            .unwrap()
+            .with_allowed_shards(&[4, 5, 6, 7]),
+        Err(Error::Config(_))
+    ));
+}
+
+#[test]
+fn test_update_bytes_with() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(fresh_binary_copy(dir.path(), "lazy")).unwrap();
+    let mut computed = 0;
+    let mut expensive = || {
+        computed += 1;
+        b"lazy-key".to_vec()
+    };
+
+    // 未初始化时调用闭包计算并写入
+    let written = store
+        .update_bytes_with(|current| match current {
+            Some(_) => None,
+            None => Some(expensive()),
+        })
+        .unwrap();
+    assert!(written);
+    assert_eq!(store.read_bytes().unwrap(), b"lazy-key");
+
+    // 已存储相同的值时闭包不会计算新密钥，也不会写入
+    let checksum = store.stored_checksum().unwrap();
+    let written = store
+        .update_bytes_with(|current| match current {
+            Some(b"lazy-key") => None,
+            _ => Some(expensive()),
+        })
+        .unwrap();
+    assert!(!written);
+    assert_eq!(computed, 1);
+    assert_eq!(store.stored_checksum().unwrap(), checksum);
+
+    // 返回与当前相同的值同样不写入
+    assert!(!store
+        .update_bytes_with(|_| Some(b"lazy-key".to_vec()))
+        .unwrap());
+    assert_eq!(store.stored_checksum().unwrap(), checksum);
+}
+
+#[cfg(feature = "secrecy")]
+#[test]
+fn test_secret_round_trip() {
+    use self_crypto_key::secrecy::{ExposeSecret, Secret};
+    use self_crypto_key::subtle::ConstantTimeEq;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(binary_copy(dir.path(), "secret")).unwrap();
+
+    let key = Secret::new(b"top-secret".to_vec());
+    store.update_secret(&key).unwrap();
+
+    let read: Secret<Vec<u8>> = store.read_secret().unwrap();
+    assert_eq!(read.expose_secret(), b"top-secret");
+    assert!(bool::from(read.expose_secret().ct_eq(key.expose_secret())));
+    assert!(store.verify_secret(&key).unwrap());
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_usable_shard_size_override() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "small_capacity");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_allowed_shards(&[0, 1, 2, 3])
+        .unwrap();
+
+    assert!(store.set_usable_shard_size(2048).is_err());
+    assert!(store.set_usable_shard_size(0).is_err());
+
+    store.set_usable_shard_size(100).unwrap();
+    assert_eq!(store.capacity(), 400);
+    // 使用随机数据，避免启用compression时被压缩到容量以内
+    let too_large = KeyStore::generate_random_bytes(500);
+    assert!(matches!(
+        store.update_bytes(&too_large),
+        Err(Error::Config(_))
+    ));
+
+    store.update_bytes(&[0x42; 300]).unwrap();
+    let reader = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reader.capacity(), 400);
+    assert_eq!(reader.read_bytes().unwrap(), [0x42; 300]);
+}
+
+#[test]
+fn test_hex_dump() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(fresh_binary_copy(dir.path(), "hexdump")).unwrap();
+    store.update_bytes(b"dump-me").unwrap();
+
+    let dump = store.hex_dump().unwrap();
+    assert!(dump.contains("== .key_meta (4096 bytes) =="));
+
+    // 每个活动分片都带标题，且行数与分片大小一致（每行16字节）
+    for name in stored_shard_names(&store) {
+        let header = format!("== {} (1024 bytes) ==", name);
+        let start = dump.find(&header).unwrap() + header.len() + 1;
+        let lines = dump[start..]
+            .lines()
+            .take_while(|line| !line.starts_with("=="))
+            .count();
+        assert_eq!(lines, 1024 / 16);
+    }
+
+    // 不包含明文
+    assert!(!dump.contains("dump-me"));
+}
+
+#[test]
+fn test_migrate_from_env() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "migrate");
+    let original = fs::read(&path).unwrap();
+    std::env::set_var("SCK_TEST_MIGRATE_SECRET", "from-env-secret");
+
+    // 验证失败时恢复二进制
+    let mut store = KeyStore::from_path(&path).unwrap();
+    let outcome = store
+        .migrate_from_env_with("SCK_TEST_MIGRATE_SECRET", |_, _| false)
+        .unwrap();
+    assert_eq!(outcome, MigrationOutcome::VerificationFailed);
+    assert_eq!(fs::read(&path).unwrap(), original);
+    assert!(!store.is_initialized().unwrap());
+
+    // 迁移成功
+    let outcome = store.migrate_from_env("SCK_TEST_MIGRATE_SECRET").unwrap();
+    assert_eq!(outcome, MigrationOutcome::Migrated);
+    assert_eq!(store.read_bytes().unwrap(), b"from-env-secret");
+
+    // 已存储密钥时跳过，不会覆盖
+    std::env::set_var("SCK_TEST_MIGRATE_SECRET", "changed");
+    let outcome = store.migrate_from_env("SCK_TEST_MIGRATE_SECRET").unwrap();
+    assert_eq!(outcome, MigrationOutcome::Skipped);
+    assert_eq!(store.read_bytes().unwrap(), b"from-env-secret");
+
+    let mut fresh = KeyStore::from_path(fresh_binary_copy(dir.path(), "migrate_missing")).unwrap();
+    assert!(matches!(
+        fresh.migrate_from_env("SCK_TEST_MIGRATE_MISSING"),
+        Err(Error::Config(_))
+    ));
+}
+
+#[cfg(feature = "rustcrypto")]
+#[test]
+fn test_read_key_generic_array() {
+    use self_crypto_key::generic_array::typenum::{U16, U32};
+    use self_crypto_key::generic_array::GenericArray;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(binary_copy(dir.path(), "generic_array")).unwrap();
+    let key = KeyStore::generate_random_bytes(32);
+    store.update_bytes(&key).unwrap();
+
+    let array: GenericArray<u8, U32> = store.read_key().unwrap();
+    assert_eq!(array.as_slice(), key.as_slice());
+
+    assert!(matches!(
+        store.read_key::<U16>(),
+        Err(Error::SizeMismatch {
+            expected: 16,
+            actual: 32
+        })
+    ));
+}
+
+#[test]
+fn test_from_candidates() {
+    let dir = tempfile::tempdir().unwrap();
+    let launcher = dir.path().join("launcher");
+    fs::write(&launcher, b"#!/bin/sh\nexec app-real \"$@\"\n").unwrap();
+    let missing = dir.path().join("missing");
+    let real = binary_copy(dir.path(), "app-real");
+
+    let mut store = KeyStore::from_candidates(&[&launcher, &missing, &real]).unwrap();
+    assert_eq!(store.exe_path(), real.as_path());
+    store.update_bytes(b"found-it").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"found-it");
+
+    match KeyStore::from_candidates(&[&launcher, &missing]) {
+        Err(Error::Config(msg)) => {
+            assert!(
+                msg.contains("launcher") && msg.contains("missing"),
+                "{}",
+                msg
+            )
+        }
+        other => panic!("期望Config错误, 得到 {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_required_shards() {
+    for padding in [Padding::HeaderLength, Padding::SelfDescribing] {
+        let overhead = KeyStore::required_capacity(0, padding, 0);
+        for shard_size in [100, 1024] {
+            for shards in 1..=8 {
+                let capacity = shards * shard_size - overhead;
+                assert_eq!(
+                    KeyStore::required_shards(capacity, shard_size, padding, 0).unwrap(),
+                    shards
+                );
+                assert_eq!(
+                    KeyStore::required_shards(capacity + 1, shard_size, padding, 0).unwrap(),
+                    shards + 1
+                );
+            }
+        }
+    }
+    assert!(matches!(
+        KeyStore::required_shards(1, 0, Padding::HeaderLength, 0),
+        Err(Error::Config(_))
+    ));
+
+    // 恰好等于计算出的容量时可以写入，多一个字节则不行
+    let dir = tempfile::tempdir().unwrap();
+    for padding in [Padding::HeaderLength, Padding::SelfDescribing] {
+        let path = fresh_binary_copy(dir.path(), &format!("required_{:?}", padding));
+        let mut store = KeyStore::from_path(path)
+            .unwrap()
+            .with_padding_scheme(padding);
+        store.reserve().unwrap();
+        let shards = stored_shard_names(&store).len();
+        let capacity = store.capacity();
+        let max_len = (0..=capacity)
+            .rfind(|&len| KeyStore::required_capacity(len, padding, 0) <= capacity)
+            .unwrap();
+        assert_eq!(
+            KeyStore::required_shards(max_len, 1024, padding, 0).unwrap(),
+            shards
+        );
+
+        store
+            .update_bytes(&KeyStore::generate_random_bytes(max_len))
+            .unwrap();
+        let too_large = KeyStore::generate_random_bytes(max_len + 1);
+        assert!(KeyStore::required_shards(too_large.len(), 1024, padding, 0).unwrap() > shards);
+        assert!(matches!(
+            store.update_bytes(&too_large),
+            Err(Error::Config(_))
+        ));
+    }
+
+    // 保留历史版本时，按保存满的历史计算
+    let path = fresh_binary_copy(dir.path(), "required_history");
+    let mut store = KeyStore::from_path(path).unwrap().with_history(2);
+    store.reserve().unwrap();
+    let capacity = store.capacity();
+    let max_len = (0..=capacity)
+        .rfind(|&len| KeyStore::required_capacity(len, Padding::HeaderLength, 2) <= capacity)
+        .unwrap();
+    for _ in 0..3 {
+        store
+            .update_bytes(&KeyStore::generate_random_bytes(max_len))
+            .unwrap();
+    }
+    // 更长的密钥逐个替换掉历史版本，历史保存满之前就会超出容量
+    // （每次使用不同的随机值，避免重复的版本被压缩）
+    assert!((0..3).any(|_| {
+        let too_large = KeyStore::generate_random_bytes(max_len + 1);
+        matches!(store.update_bytes(&too_large), Err(Error::Config(_)))
+    }));
+}
+
+#[test]
+fn test_meta_section_past_end_of_file() {
+    // section头部声明的大小合法，但数据位置超出文件末尾：应返回错误而不是panic
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "truncated_meta");
+    let mut data = fs::read(&path).unwrap();
+    let header = section_header_offset(&data, ".key_meta");
+    let past_end = (data.len() - 8) as u64;
+    data[header + 24..header + 32].copy_from_slice(&past_end.to_le_bytes());
+    fs::write(&path, data).unwrap();
+
+    assert!(matches!(KeyStore::from_path(&path), Err(Error::Parse(_))));
+}
+
+#[test]
+fn test_external_factor() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "two_factor");
+    // 运行时生成的因子，测试二进制的常量中不会包含它
+    let factor = KeyStore::generate_random_bytes(32);
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_external_factor(&factor);
+    store.update_bytes(b"two-factor-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"two-factor-key");
+
+    // 二进制中不包含因子本身
+    let binary = fs::read(&path).unwrap();
+    assert!(!binary.windows(factor.len()).any(|w| w == factor));
+
+    let reader = KeyStore::from_path(&path)
+        .unwrap()
+        .with_external_factor(&factor);
+    assert_eq!(reader.read_bytes().unwrap(), b"two-factor-key");
+
+    let wrong = KeyStore::from_path(&path)
+        .unwrap()
+        .with_external_factor(b"wrong-factor");
+    assert!(matches!(wrong.read_bytes(), Err(Error::Crypto(_))));
+
+    let without = KeyStore::from_path(&path).unwrap();
+    assert!(matches!(without.read_bytes(), Err(Error::Config(_))));
+}
+
+#[test]
+fn test_external_factor_sidecar() {
+    // 二进制只读时写入的旁路文件同样需要外部因子和相同的KDF迭代次数
+    use self_crypto_key::StorageMode;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "two_factor_sidecar");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o555)).unwrap();
+    let factor = KeyStore::generate_random_bytes(32);
+    let open = |factor: Option<&[u8]>| {
+        let store = KeyStore::from_path(&path)
+            .unwrap()
+            .with_kdf_iterations(1000);
+        match factor {
+            Some(factor) => store.with_external_factor(factor),
+            None => store,
+        }
+    };
+
+    let mut store = open(Some(&factor));
+    assert_eq!(
+        store.update_bytes_auto(b"sidecar-two-factor").unwrap(),
+        StorageMode::Sidecar
+    );
+    let content = fs::read(dir.path().join("two_factor_sidecar.key")).unwrap();
+    assert!(!content.windows(factor.len()).any(|w| w == factor));
+
+    assert_eq!(
+        open(Some(&factor)).read_bytes().unwrap(),
+        b"sidecar-two-factor"
+    );
+    assert!(matches!(
+        open(Some(b"wrong-factor")).read_bytes(),
+        Err(Error::Crypto(_))
+    ));
+    assert!(matches!(open(None).read_bytes(), Err(Error::Config(_))));
+}
+
+#[test]
+fn test_from_path_with_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "limits");
+    let size = fs::metadata(&path).unwrap().len();
+
+    let too_small = Limits {
+        max_binary_size: size - 1,
+        ..Limits::default()
+    };
+    assert!(matches!(
+        KeyStore::from_path_with_limits(&path, too_small),
+        Err(Error::Parse(_))
+    ));
+
+    let few_sections = Limits {
+        max_sections: 5,
+        ..Limits::default()
+    };
+    assert!(matches!(
+        KeyStore::from_path_with_limits(&path, few_sections),
+        Err(Error::Parse(_))
+    ));
+
+    let mut store = KeyStore::from_path_with_limits(&path, Limits::default()).unwrap();
+    store.update_bytes(b"within-limits").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"within-limits");
+}
+
+#[test]
+fn test_snapshot_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "snapshot");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    store.update_bytes(b"original-key").unwrap();
+    let snap = store.snapshot().unwrap();
+
+    store.update_bytes(b"experiment-1").unwrap();
+    store
+        .update_bytes(b"a much longer experimental key value")
+        .unwrap();
+    store.reset().unwrap();
+    store.update_bytes(b"experiment-3").unwrap();
+
+    store.restore(&snap).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"original-key");
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"original-key"
+    );
+}
+
+#[test]
+fn test_cbor_metadata_encoding() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "cbor_meta");
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_meta_encoding(MetaEncoding::Cbor);
+    store.update_bytes(b"compact-metadata").unwrap();
+    let (cbor_used, _) = store.metadata_usage().unwrap();
+
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), b"compact-metadata");
+    assert_eq!(reopened.metadata_usage().unwrap().0, cbor_used);
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_meta_encoding(MetaEncoding::Json);
+    store.update_bytes(b"compact-metadata").unwrap();
+    assert!(store.metadata_usage().unwrap().0 > cbor_used);
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"compact-metadata"
+    );
+}
+
+#[test]
+fn test_single_parse_read_matches_across_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "single_parse");
+    let key = KeyStore::generate_random_bytes(3000);
+
+    for layout in [ShardLayout::Contiguous, ShardLayout::Interleaved] {
+        let mut store = KeyStore::from_path(&path)
+            .unwrap()
+            .with_shard_layout(layout);
+        store.update_bytes(&key).unwrap();
+
+        let mut constant = KeyStore::from_path(&path).unwrap();
+        constant.set_constant_time(true);
+        assert_eq!(store.read_bytes().unwrap(), key);
+        assert_eq!(constant.read_bytes().unwrap(), key);
+        assert_eq!(store.read_with_provenance().unwrap().0, key);
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_optimistic_retry_on_concurrent_write() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "optimistic");
+    KeyStore::from_path(&path)
+        .unwrap()
+        .update_bytes(b"initial")
+        .unwrap();
+
+    // 前两次提交之前，另一个写入者抢先写入
+    let calls = Arc::new(AtomicU32::new(0));
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.set_optimistic_retries(3);
+    let counter = Arc::clone(&calls);
+    store.set_before_commit_hook(move |path| {
+        if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+            let mut other = KeyStore::from_path(path).unwrap();
+            other.update_bytes(b"concurrent").unwrap();
+        }
+    });
+
+    store.update_bytes(b"mine").unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"mine"
+    );
+
+    // 每次提交前都有并发写入时，重试耗尽后返回错误
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.set_optimistic_retries(1);
+    store.set_before_commit_hook(|path| {
+        let mut other = KeyStore::from_path(path).unwrap();
+        other.update_bytes(b"concurrent").unwrap();
+    });
+    assert!(matches!(store.update_bytes(b"lost"), Err(Error::Config(_))));
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"concurrent"
+    );
+}
+
+#[test]
+fn test_deferred_writes_flushed_on_drop() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = binary_copy(dir.path(), "deferred");
+    KeyStore::from_path(&path)
+        .unwrap()
+        .update_bytes(b"on-disk")
+        .unwrap();
+
+    let mut store = KeyStore::from_path(&path).unwrap().with_deferred_writes();
+    store.update_bytes(b"pending-1").unwrap();
+    store.update_bytes(b"pending-2").unwrap();
+    assert!(store.has_pending_writes());
+    assert_eq!(store.read_bytes().unwrap(), b"pending-2");
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"on-disk"
+    );
+
+    drop(store);
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"pending-2"
+    );
+
+    let mut store = KeyStore::from_path(&path).unwrap().with_deferred_writes();
+    store.update_bytes(b"flushed").unwrap();
+    store.flush().unwrap();
+    assert!(!store.has_pending_writes());
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"flushed"
+    );
+}
+
+#[test]
+fn test_logical_shard_order_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "logical_order");
+    let key = KeyStore::generate_random_bytes(4096);
+
+    // 直到逻辑顺序与元数据数组顺序不同为止
+    let mut attempts = 0;
+    loop {
+        let mut store = KeyStore::from_path(&path).unwrap();
+        store.update_bytes(&key).unwrap();
+
+        let logical: Vec<usize> =
+            serde_json::from_value(stored_metadata_json(&store)["logical_index"].clone()).unwrap();
+        assert_eq!(logical.len(), stored_shard_names(&store).len());
+
+        assert_eq!(
+            KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+            key
+        );
+        if logical.windows(2).any(|w| w[0] > w[1]) {
+            break;
+        }
+
+        attempts += 1;
+        assert!(attempts < 50, "逻辑顺序始终与数组顺序相同");
+        store.reset().unwrap();
+    }
+}
+
+#[test]
+fn test_wipe_on_tamper() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+
+    for enabled in [true, false] {
+        let path = fresh_binary_copy(dir.path(), &format!("wipe_on_tamper_{}", enabled));
+        let mut store = KeyStore::from_path(&path).unwrap();
+        store
+            .update_bytes(&KeyStore::generate_random_bytes(24))
+            .unwrap();
+
+        // 翻转每个正在使用的分片的第一个字节
+        let mut data = fs::read(&path).unwrap();
+        let ranges: Vec<_> = stored_shard_names(&store)
+            .iter()
+            .map(|shard| section_range(&data, shard))
+            .collect();
+        for range in &ranges {
+            data[range.start] ^= 0xFF;
+        }
+        fs::write(&path, &data).unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+
+        let store = KeyStore::from_path(&path)
+            .unwrap()
+            .with_wipe_on_tamper(enabled);
+        assert!(matches!(
+            store.read_bytes(),
+            Err(Error::IntegrityCheckFailed(_))
+        ));
+
+        // 分片在原文件中覆盖，而不是写入新文件后替换
+        assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+        assert!(!store.binary_replaced().unwrap());
+
+        let after = fs::read(&path).unwrap();
+        if enabled {
+            for range in ranges {
+                assert_ne!(after[range.clone()], data[range]);
+            }
+        } else {
+            assert_eq!(after, data);
+        }
+    }
+}
+
+#[test]
+fn test_diagnostic_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(fresh_binary_copy(dir.path(), "diagnostic")).unwrap();
+    let key = KeyStore::generate_random_key(32);
+    store.update(&key).unwrap();
+
+    let report = store.diagnostic_report().unwrap();
+    assert!(report.contains(&store.exe_path().display().to_string()));
+    assert!(report.contains(".key_meta"));
+    for name in stored_shard_names(&store) {
+        assert!(report.contains(&format!("{} ", name)), "{}", report);
+    }
+
+    let key_hex: String = key.bytes().map(|b| format!("{:02x}", b)).collect();
+    assert!(!report.contains(&key));
+    assert!(!report.contains(&key_hex));
+}
+
+#[cfg(feature = "audit")]
+#[test]
+fn test_derive_keystream_sample() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(fresh_binary_copy(dir.path(), "keystream")).unwrap();
+    store.update_bytes(b"audited-key").unwrap();
+
+    let sample = store.derive_keystream_sample(0, 100).unwrap();
+    assert_eq!(sample.len(), 100);
+    assert_eq!(store.derive_keystream_sample(0, 100).unwrap(), sample);
+    assert!(!sample
+        .windows(b"audited-key".len())
+        .any(|w| w == b"audited-key"));
+
+    // 不同分片使用不同的密钥流
+    assert_ne!(store.derive_keystream_sample(1, 100).unwrap(), sample);
+
+    let num_shards = stored_shard_names(&store).len();
+    assert!(matches!(
+        store.derive_keystream_sample(num_shards, 16),
+        Err(Error::Config(_))
+    ));
+}
+
+#[test]
+fn test_snapshot_readonly_shared_across_threads() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = KeyStore::from_path(fresh_binary_copy(dir.path(), "read_snapshot")).unwrap();
+    let key = KeyStore::generate_random_bytes(100);
+    store.update_bytes(&key).unwrap();
+
+    let snapshot = store.snapshot_readonly().unwrap();
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let snapshot = std::sync::Arc::clone(&snapshot);
+            std::thread::spawn(move || (0..20).map(|_| snapshot.read_bytes().unwrap()).collect())
+        })
+        .collect();
+    for handle in handles {
+        let reads: Vec<Vec<u8>> = handle.join().unwrap();
+        assert!(reads.iter().all(|read| *read == key));
+    }
+
+    // 快照不会看到之后的写入
+    store.update_bytes(b"newer-key").unwrap();
+    assert_eq!(snapshot.read_bytes().unwrap(), key);
+    assert_eq!(
+        store.snapshot_readonly().unwrap().read_bytes().unwrap(),
+        b"newer-key"
+    );
+}
+
+#[test]
+fn test_decoy_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "decoys");
+    let decoys = [
+        ".key_data_04",
+        ".key_data_05",
+        ".key_data_06",
+        ".key_data_07",
+    ];
+
+    // 只使用前4个分片，后4个section不被元数据引用
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_allowed_shards(&[0, 1, 2, 3])
+        .unwrap()
+        .with_decoy_writes(true);
+
+    store.update_bytes(b"first-key").unwrap();
+    let first = fs::read(&path).unwrap();
+    store.update_bytes(b"second-key").unwrap();
+    let second = fs::read(&path).unwrap();
+
+    for name in decoys {
+        let range = section_range(&second, name);
+        assert!(second[range.clone()].iter().any(|&b| b != 0), "{}", name);
+        assert_ne!(first[range.clone()], second[range], "{}", name);
+    }
+    assert_eq!(store.read_bytes().unwrap(), b"second-key");
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"second-key"
+    );
+}
+
+#[test]
+fn test_obfuscated_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "obfuscated_length");
+    let key = KeyStore::generate_random_bytes(37);
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_obfuscated_length(true);
+    store.update_bytes(&key).unwrap();
+
+    let data = fs::read(&path).unwrap();
+    let header = section_range(&data, ".key_meta").start;
+    assert_ne!(data[header..header + 8], 37u64.to_le_bytes());
+
+    assert_eq!(store.stored_len().unwrap(), 37);
+    assert_eq!(store.read_bytes().unwrap(), key);
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.stored_len().unwrap(), 37);
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+
+    // 关闭后重新写入时恢复为明文长度
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(&key).unwrap();
+    let data = fs::read(&path).unwrap();
+    assert_eq!(data[header..header + 8], 37u64.to_le_bytes());
+}
+
+#[test]
+fn test_preflight_missing_sections() {
+    use self_crypto_key::preflight_at;
+
+    let dir = tempfile::tempdir().unwrap();
+    let missing_meta = binary_copy(dir.path(), "preflight_meta");
+    rename_section(&missing_meta, ".key_meta", ".xey_meta");
+    preflight_at(&binary_copy(dir.path(), "preflight_ok")).unwrap();
+
+    let missing_shard = binary_copy(dir.path(), "preflight_shard");
+    rename_section(&missing_shard, ".key_data_05", ".key_dat@_05");
+
+    for (path, name) in [(missing_meta, ".key_meta"), (missing_shard, ".key_data_05")] {
+        match preflight_at(&path) {
+            Err(Error::SectionNotFound(missing)) => assert_eq!(missing, name),
+            Err(Error::Config(message)) => assert!(message.contains(name), "{}", message),
+            other => panic!("{}: {:?}", name, other),
+        }
+    }
+
+    let not_elf = dir.path().join("preflight_not_elf");
+    fs::write(&not_elf, b"#!/bin/sh\n").unwrap();
+    assert!(matches!(preflight_at(&not_elf), Err(Error::Parse(_))));
+}
+
+#[test]
+fn test_constants_fingerprint_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "constants_fingerprint");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"fingerprinted").unwrap();
+
+    let fingerprint = stored_metadata_json(&store)["constants_fingerprint"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(fingerprint.len(), 16);
+
+    // 模拟由使用另一组常量的构建写入：替换元数据中的指纹（长度不变）
+    let other: String = fingerprint
+        .chars()
+        .map(|c| if c == '0' { '1' } else { '0' })
+        .collect();
+    let mut data = fs::read(&path).unwrap();
+    let meta = section_range(&data, ".key_meta");
+    let pos = data[meta.clone()]
+        .windows(fingerprint.len())
+        .position(|w| w == fingerprint.as_bytes())
+        .unwrap();
+    data[meta.start + pos..meta.start + pos + other.len()].copy_from_slice(other.as_bytes());
+    fs::write(&path, &data).unwrap();
+
+    match KeyStore::from_path(&path) {
+        Err(Error::Crypto(message)) => {
+            assert!(message.contains("不同加密常量"), "{}", message);
+            assert!(message.contains(&other), "{}", message);
+        }
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+
+    // 跳过检查打开后用当前构建重新写入，之后可以正常打开
+    let store = KeyStore::from_path_for_rewrite(&path)
+        .unwrap()
+        .update_bytes(b"rewritten")
+        .unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"rewritten");
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), b"rewritten");
+}
+
+#[test]
+fn test_audit_hook() {
+    use std::sync::{Arc, Mutex};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "audit_hook");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&events);
+    store.set_audit_hook(Box::new(move |event| {
+        recorded.lock().unwrap().push(event.clone())
+    }));
+
+    store.update_bytes(b"first-audited").unwrap();
+    let first = store.stored_key_fingerprint().unwrap();
+    store.update_bytes(b"second-audited").unwrap();
+    let second = store.stored_key_fingerprint().unwrap();
+    assert!(store
+        .update_bytes(&KeyStore::generate_random_bytes(store.capacity() + 1))
+        .is_err());
+    store.shred().unwrap();
+
+    let events = events.lock().unwrap();
+    let operations: Vec<_> = events.iter().map(|e| e.operation).collect();
+    assert_eq!(
+        operations,
+        [
+            AuditOperation::Update,
+            AuditOperation::Update,
+            AuditOperation::Shred
+        ]
+    );
+    assert_eq!(events[0].old_fingerprint, None);
+    assert_eq!(events[0].new_fingerprint.as_deref(), Some(first.as_str()));
+    assert_eq!(events[1].old_fingerprint.as_deref(), Some(first.as_str()));
+    assert_eq!(events[1].new_fingerprint.as_deref(), Some(second.as_str()));
+    assert_ne!(first, second);
+    assert_eq!(events[2].old_fingerprint.as_deref(), Some(second.as_str()));
+    assert_eq!(events[2].new_fingerprint, None);
+    assert!(events.iter().all(|e| e.path == path));
+}
+
+#[test]
+fn test_update_bytes_detailed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "detailed");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    store.update_bytes(b"first-key").unwrap();
+    let before = fs::read(&path).unwrap();
+    let modified = store.update_bytes_detailed(b"second-key").unwrap();
+    let after = fs::read(&path).unwrap();
+
+    let mut expected = vec![".key_meta".to_string()];
+    expected.extend(stored_shard_names(&store));
+    assert_eq!(modified, expected);
+    assert_eq!(store.read_bytes().unwrap(), b"second-key");
+
+    // 未列出的shard section保持不变
+    for i in 0..8 {
+        let name = format!(".key_data_{:02}", i);
+        if !modified.contains(&name) {
+            let range = section_range(&after, &name);
+            assert_eq!(before[range.clone()], after[range], "{}", name);
+        }
+    }
+}
+
+#[test]
+fn test_compact_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "compact_meta");
+    let key = KeyStore::generate_random_bytes(300);
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_meta_encoding(MetaEncoding::Json);
+    store.update_bytes(&key).unwrap();
+    let (before, total) = store.metadata_usage().unwrap();
+
+    store.compact_metadata().unwrap();
+    let (after, _) = store.metadata_usage().unwrap();
+    assert!(after < before, "{} >= {}", after, before);
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    // 重新写入的元数据之后没有残留的旧内容
+    let data = fs::read(&path).unwrap();
+    let meta = &data[section_range(&data, ".key_meta")];
+    assert!(meta[after..total].iter().all(|&b| b == 0));
+
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+    assert_eq!(reopened.metadata_usage().unwrap().0, after);
+}
+
+#[test]
+fn test_section_data_digest_detects_transplant() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "digest_target");
+
+    // .text不同的另一个二进制
+    let other = fresh_binary_copy(dir.path(), "digest_other");
+    let mut data = fs::read(&other).unwrap();
+    let text = section_range(&data, ".text");
+    data[text.start] ^= 0xff;
+    fs::write(&other, data).unwrap();
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_section_data_digest(true);
+    store.update_bytes(b"target-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"target-key");
+
+    let mut other_store = KeyStore::from_path(&other)
+        .unwrap()
+        .with_section_data_digest(true);
+    other_store.copy_layout_from(&store).unwrap();
+    other_store.update_bytes(b"other-key").unwrap();
+
+    // 把另一个二进制的一个分片复制过来
+    let shard = &stored_shard_names(&store)[1];
+    let source = fs::read(&other).unwrap();
+    let mut target = fs::read(&path).unwrap();
+    let range = section_range(&target, shard);
+    target[range.clone()].copy_from_slice(&source[section_range(&source, shard)]);
+    fs::write(&path, target).unwrap();
+
+    match KeyStore::from_path(&path).unwrap().read_bytes() {
+        Err(Error::IntegrityCheckFailed(msg)) => {
+            assert!(msg.contains("绑定标签"), "{}", msg);
+            assert!(msg.contains(shard.as_str()), "{}", msg);
+        }
+        other => panic!("应返回分片绑定错误: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_read_bytes_timeout() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "read_timeout");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"timeout-key").unwrap();
+    assert_eq!(
+        store.read_bytes_timeout(Duration::from_secs(10)).unwrap(),
+        b"timeout-key"
+    );
+
+    // 用命名管道代替二进制：没有写入方时打开管道会一直阻塞，模拟挂起的网络文件系统
+    let binary = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+    match store.read_bytes_timeout(Duration::from_millis(100)) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("应返回超时错误: {:?}", other.map(|_| ())),
+    }
+
+    // 阻塞的辅助线程达到上限后不再创建新线程，立即返回超时错误
+    for _ in 1..KeyStore::MAX_TIMEOUT_READERS {
+        assert!(store.read_bytes_timeout(Duration::from_millis(50)).is_err());
+    }
+    let start = std::time::Instant::now();
+    match store.read_bytes_timeout(Duration::from_secs(30)) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("应返回超时错误: {:?}", other.map(|_| ())),
+    }
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    // 打开写入端后关闭，让辅助线程读到EOF并退出
+    drop(fs::OpenOptions::new().write(true).open(&path).unwrap());
+
+    // 辅助线程退出后可以再次读取
+    fs::remove_file(&path).unwrap();
+    fs::write(&path, &binary).unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(30);
+    loop {
+        match store.read_bytes_timeout(Duration::from_secs(10)) {
+            Ok(key) => break assert_eq!(key, b"timeout-key"),
+            Err(_) if std::time::Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(20))
+            }
+            Err(e) => panic!("辅助线程未退出: {:?}", e),
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "compression")]
+fn test_compress_then_encrypt_pipeline() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "pipeline");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    let key = b"pipeline-".repeat(300);
+    store.update_bytes(&key).unwrap();
+    assert!(store.stored_payload_len().unwrap() < key.len());
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    let meta = stored_metadata_json(&store);
+    assert_eq!(
+        meta["pipeline"],
+        serde_json::json!(["Compress", "Pad", "Obfuscate", "Xor"])
+    );
+
+    // 压缩后的数据填充到总容量后才加密：分片全部写满，看不出压缩后的长度
+    let data = fs::read(&path).unwrap();
+    let shards: Vec<u8> = stored_shard_names(&store)
+        .iter()
+        .flat_map(|name| data[section_range(&data, name)].to_vec())
+        .collect();
+    assert_eq!(shards.len(), store.capacity());
+    assert!(shards.iter().filter(|&&b| b == 0).count() < shards.len() / 64);
+
+    // 先加密后压缩的顺序被拒绝
+    let mut data = data;
+    let meta_range = section_range(&data, ".key_meta");
+    let recorded = br#"["Compress","Pad","Obfuscate","Xor"]"#;
+    let reordered = br#"["Pad","Obfuscate","Xor","Compress"]"#;
+    let pos = data[meta_range.clone()]
+        .windows(recorded.len())
+        .position(|w| w == recorded)
+        .unwrap();
+    let start = meta_range.start + pos;
+    data[start..start + reordered.len()].copy_from_slice(reordered);
+    fs::write(&path, &data).unwrap();
+
+    match store.read_bytes() {
+        Err(Error::Config(msg)) => assert!(msg.contains("先加密后压缩"), "{}", msg),
+        other => panic!("应拒绝先加密后压缩: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_update_from_file_and_read_to_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "key_files");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    let key: Vec<u8> = (0..=255u8).rev().chain(0..=255u8).collect();
+    let input = dir.path().join("secret.bin");
+    fs::write(&input, &key).unwrap();
+    store.update_from_file(&input).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), key);
+
+    let output = dir.path().join("dump.bin");
+    store.read_to_file(&output).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), key);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // 已存在的宽松权限文件同样被收紧
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o644)).unwrap();
+        store.read_to_file(&output).unwrap();
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // 超出容量的文件不会被写入
+    let large = dir.path().join("large.bin");
+    fs::write(&large, vec![0u8; store.capacity() + 1]).unwrap();
+    assert!(matches!(
+        store.update_from_file(&large),
+        Err(Error::Config(_))
+    ));
+    assert_eq!(store.read_bytes().unwrap(), key);
+}
+
+#[test]
+fn test_try_from_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "try_new");
+    let before = fs::read(&path).unwrap();
+
+    // 尚未写入元数据：不生成配置，也不写入任何内容
+    assert!(KeyStore::try_from_path(&path).unwrap().is_none());
+    assert_eq!(fs::read(&path).unwrap(), before);
+
+    KeyStore::from_path(&path)
+        .unwrap()
+        .update_bytes(b"initialized")
+        .unwrap();
+    let store = KeyStore::try_from_path(&path).unwrap().unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"initialized");
+}
+
+#[test]
+fn test_shred_progress() {
+    use std::os::unix::fs::MetadataExt;
+    use std::sync::{Arc, Mutex};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "shred_progress");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"shred-me").unwrap();
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&calls);
+    store.set_progress(Box::new(move |done, total| {
+        recorded.lock().unwrap().push((done, total));
+    }));
+    let inode = fs::metadata(&path).unwrap().ino();
+    store.shred_passes(3).unwrap();
+
+    // 每遍覆盖测试二进制中的全部8个shard section
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 3 * 8);
+    assert!(calls.iter().enumerate().all(|(i, &c)| c == (i + 1, 24)));
+    assert!(store.read_bytes().is_err());
+
+    // 原地覆盖，旧密文不会随被替换的inode留在磁盘上
+    assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+
+    assert!(store.shred_passes(0).is_err());
+}
+
+#[test]
+fn test_binaries_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = fresh_binary_copy(dir.path(), "match_a");
+    KeyStore::from_path(&original)
+        .unwrap()
+        .update_bytes(b"provisioned-key")
+        .unwrap();
+
+    // 同一个已写入的二进制的两份副本
+    let copy = dir.path().join("match_b");
+    fs::copy(&original, &copy).unwrap();
+    let report = binaries_match(&original, &copy).unwrap();
+    assert!(report.is_match(), "{:?}", report);
+
+    // 写入了不同密钥、布局各自随机生成的二进制
+    let other = fresh_binary_copy(dir.path(), "match_c");
+    let mut other_store = KeyStore::from_path(&other).unwrap();
+    other_store.update_bytes(b"different-key").unwrap();
+    let report = binaries_match(&original, &other).unwrap();
+    assert!(report.text_match);
+    assert!(!report.key_match);
+    assert!(!report.is_match());
+    assert!(!format!("{:?}", report).contains("different-key"));
+
+    // 采用相同布局后只有密钥不同
+    other_store
+        .copy_layout_from(&KeyStore::from_path(&original).unwrap())
+        .unwrap();
+    let report = binaries_match(&original, &other).unwrap();
+    assert!(report.text_match && report.layout_match && !report.key_match);
+}
+
+#[test]
+fn test_self_describing_padding_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "self_padding");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_padding_scheme(Padding::SelfDescribing);
+    let capacity = store.capacity();
+
+    // 包括以0x80或0结尾的数据，以及恰好占满可用容量（总容量减去标记字节）的数据
+    for len in [1, 127, 128, 1000, capacity - 1] {
+        let key: Vec<u8> = (0..len).map(|i| [0x80, 0x00, 0x5a][i % 3]).collect();
+        store.update_bytes(&key).unwrap();
+        // 头部只记录总容量，不暴露密钥长度
+        assert_eq!(store.stored_len().unwrap(), capacity);
+
+        let reopened = KeyStore::from_path(&path).unwrap();
+        assert_eq!(reopened.read_bytes().unwrap(), key);
+    }
+
+    // 使用不可压缩的数据，确保启用compression时同样超出容量
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let full: Vec<u8> = (0..capacity)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 32) as u8
+        })
+        .collect();
+    assert!(store.update_bytes(&full).is_err());
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap().len(), capacity - 1);
+}
+
+#[test]
+fn test_section_checksums() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "checksums");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"first").unwrap();
+
+    let before = store.section_checksums().unwrap();
+    let mut expected: Vec<String> = store
+        .key_sections()
+        .unwrap()
+        .into_iter()
+        .map(|section| section.name)
+        .collect();
+    expected.sort();
+    assert_eq!(before.keys().cloned().collect::<Vec<_>>(), expected);
+    // 只覆盖密钥存储相关的section
+    assert!(!before.contains_key(".text"));
+
+    store.update_bytes(b"second").unwrap();
+    let after = store.section_checksums().unwrap();
+    assert_ne!(before[".key_meta"], after[".key_meta"]);
+    for name in stored_shard_names(&store) {
+        assert_ne!(before[&name], after[&name], "{name}");
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_cached_reader_invalidated_by_file_change() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "cached_reader");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"cached-v1").unwrap();
+
+    let loads = AtomicUsize::new(0);
+    let backend = KeyStore::from_path(&path).unwrap();
+    let mut reader = store.cached_reader_with(|| {
+        loads.fetch_add(1, Ordering::SeqCst);
+        backend.read_bytes()
+    });
+
+    assert_eq!(reader.read_bytes().unwrap(), b"cached-v1");
+    assert_eq!(reader.read_bytes().unwrap(), b"cached-v1");
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+    // 其他实例写入后缓存失效；模拟两次写入落在时间戳精度之内，修改时间与之前相同，
+    // rename替换后的inode仍然不同
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+    KeyStore::from_path(&path)
+        .unwrap()
+        .update_bytes(b"cached-v2")
+        .unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert_eq!(reader.read_bytes().unwrap(), b"cached-v2");
+    assert_eq!(reader.read_bytes().unwrap(), b"cached-v2");
+    assert_eq!(loads.load(Ordering::SeqCst), 2);
+    drop(reader);
+
+    let mut reader = store.cached_reader();
+    assert_eq!(reader.read().unwrap(), "cached-v2");
+
+    // 查询文件失败时报告路径
+    fs::remove_file(&path).unwrap();
+    match reader.read_bytes() {
+        Err(Error::IoAt { path: reported, .. }) => assert_eq!(reported, path),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_export_and_import_shards() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "shard_export");
+    let shard_dir = dir.path().join("shards");
+    fs::create_dir(&shard_dir).unwrap();
+
+    let key: Vec<u8> = (0..3000u32).map(|i| (i * 31 + 7) as u8).collect();
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(&key).unwrap();
+    store.export_shards_to_dir(&shard_dir).unwrap();
+
+    let shards = stored_shard_names(&store);
+    assert!(shard_dir.join("metadata.bin").exists());
+    for name in &shards {
+        let file = shard_dir.join(format!("shard_{}.bin", name.trim_start_matches('.')));
+        assert_eq!(fs::metadata(file).unwrap().len(), 1024);
+    }
+
+    // 清空二进制中的元数据和分片后读不到原来的密钥
+    zero_section(&path, ".key_meta");
+    for name in &shards {
+        zero_section(&path, name);
+    }
+    assert_ne!(
+        KeyStore::from_path(&path).unwrap().read_bytes().ok(),
+        Some(key.clone())
+    );
+
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.import_shards_from_dir(&shard_dir).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), key);
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        key
+    );
+
+    // 缺少分片文件时不修改二进制
+    fs::remove_file(shard_dir.join(format!("shard_{}.bin", shards[0].trim_start_matches('.'))))
+        .unwrap();
+    let before = fs::read(&path).unwrap();
+    assert!(store.import_shards_from_dir(&shard_dir).is_err());
+    assert_eq!(fs::read(&path).unwrap(), before);
+}
+
+#[test]
+fn test_open_rejects_missing_shard_section() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "missing_shard");
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(b"layout-check").unwrap();
+
+    // 元数据引用的分片section不存在时，打开就失败，而不是等到第一次读写
+    let shard = stored_shard_names(&store).remove(0);
+    let renamed = format!("{}@", &shard[..shard.len() - 1]);
+    rename_section(&path, &shard, &renamed);
+    match KeyStore::from_path(&path) {
+        Err(Error::SectionNotFound(name)) => assert_eq!(name, shard),
+        other => panic!("错误类型不符: {:?}", other.map(|_| ())),
+    }
+    assert!(KeyStore::try_from_path(&path).is_err());
+
+    // 静态链接时分片多半是被链接器回收了，打开时同样给出重新构建的建议
+    strip_interp(&path);
+    match KeyStore::from_path(&path) {
+        Err(Error::Config(msg)) => {
+            assert!(msg.contains(&shard), "{}", msg);
+            assert!(msg.contains("--no-gc-sections"), "{}", msg);
+        }
+        other => panic!("错误类型不符: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_key_history() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "history");
+    let mut store = KeyStore::from_path(&path).unwrap().with_history(2);
+
+    store.update_bytes(b"v0").unwrap();
+    assert!(store.read_history(1).is_err());
+    for key in [b"v1", b"v2", b"v3"] {
+        store.update_bytes(key).unwrap();
+    }
+
+    // 保留最近的两个旧值，最旧的v0已被丢弃
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), b"v3");
+    assert_eq!(reopened.read_history(0).unwrap(), b"v3");
+    assert_eq!(reopened.read_history(1).unwrap(), b"v2");
+    assert_eq!(reopened.read_history(2).unwrap(), b"v1");
+    assert!(matches!(reopened.read_history(3), Err(Error::Config(_))));
+
+    // 历史版本放不下时写入失败，原有内容不变
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let mut noise = |len: usize| -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    };
+    let half = store.capacity() / 2;
+    store.update_bytes(&noise(half)).unwrap();
+    let before = fs::read(&path).unwrap();
+    match store.update_bytes(&noise(half)) {
+        Err(Error::Config(msg)) => assert!(msg.contains("历史版本"), "{}", msg),
+        other => panic!("错误类型不符: {:?}", other.map(|_| ())),
+    }
+    assert_eq!(fs::read(&path).unwrap(), before);
+}
+
+#[test]
+fn test_strict_binding_detects_patched_text() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "strict_binding");
+
+    // 尚未写入密钥时没有需要比对的内容
+    assert!(KeyStore::from_path(&path)
+        .unwrap()
+        .with_strict_binding(true)
+        .is_ok());
+
+    KeyStore::from_path(&path)
+        .unwrap()
+        .update_bytes(b"license")
+        .unwrap();
+    let store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_strict_binding(true)
+        .unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"license");
+
+    // 修改 .text 段中的一个字节
+    let mut data = fs::read(&path).unwrap();
+    let text = section_range(&data, ".text");
+    data[text.start + text.len() / 2] ^= 0xff;
+    fs::write(&path, data).unwrap();
+
+    match KeyStore::from_path(&path)
+        .unwrap()
+        .with_strict_binding(true)
+    {
+        Err(Error::Crypto(msg)) => assert!(msg.contains("binary tampered"), "{}", msg),
+        other => panic!("错误类型不符: {:?}", other.map(|_| ())),
+    }
+    // 不开启时仍可以打开，读取时才发现
+    let store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_strict_binding(false)
+        .unwrap();
+    assert!(store.read_bytes().is_err());
+}
+
+#[test]
+fn test_stored_len_at_offset() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "len_at");
+    let mut store = KeyStore::from_path(&path).unwrap();
+
+    let offsets = store.on_disk_offsets().unwrap();
+    let data = fs::read(&path).unwrap();
+    assert_eq!(offsets[".key_meta"], section_range(&data, ".key_meta"));
+    let meta_offset = offsets[".key_meta"].start;
+
+    assert_eq!(store.stored_len_at(meta_offset).unwrap(), 0);
+    for key in [&b"short"[..], &[7u8; 1500][..]] {
+        store.update_bytes(key).unwrap();
+        assert_eq!(
+            store.stored_len_at(meta_offset).unwrap(),
+            store.stored_payload_len().unwrap()
+        );
+    }
+
+    assert!(store.stored_len_at(data.len()).is_err());
+
+    // 偏移接近usize上限时返回错误而不是溢出
+    for offset in [usize::MAX - 3, usize::MAX] {
+        assert!(matches!(store.stored_len_at(offset), Err(Error::Parse(_))));
+    }
+    let in_memory = KeyStore::from_bytes(data).unwrap();
+    assert!(matches!(
+        in_memory.stored_len_at(usize::MAX - 3),
+        Err(Error::Parse(_))
+    ));
+}
+
+#[test]
+fn test_self_test_passes() {
+    self_test().unwrap();
+}
+
+#[test]
+fn test_partial_writes_keep_unchanged_shards() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "partial_writes");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_partial_writes(true);
+
+    // 随机数据不会被压缩，末尾100字节只落在最后一个使用的分片中
+    let mut key = KeyStore::generate_random_bytes(store.capacity().min(4096));
+    store.update_bytes(&key).unwrap();
+
+    let names = stored_shard_names(&store);
+    let shard_bytes = |path: &Path| -> Vec<Vec<u8>> {
+        let data = fs::read(path).unwrap();
+        names
+            .iter()
+            .map(|name| data[section_range(&data, name)].to_vec())
+            .collect()
+    };
+    let before = shard_bytes(&path);
+
+    let len = key.len();
+    for byte in &mut key[len - 100..] {
+        *byte = !*byte;
+    }
+    store.update_bytes(&key).unwrap();
+    let after = shard_bytes(&path);
+    let changed = before.iter().zip(&after).filter(|(b, a)| b != a).count();
+    assert_eq!(changed, 1);
+
+    // 详细报告只列出内容发生变化的分片
+    for byte in &mut key[len - 100..] {
+        *byte = !*byte;
+    }
+    let modified = store.update_bytes_detailed(&key).unwrap();
+    let rewritten: Vec<&str> = names
+        .iter()
+        .zip(after.iter().zip(&shard_bytes(&path)))
+        .filter(|(_, (a, r))| a != r)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(rewritten.len(), 1);
+    assert_eq!(modified, [".key_meta", rewritten[0]]);
+    for byte in &mut key[len - 100..] {
+        *byte = !*byte;
+    }
+    store.update_bytes(&key).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), key);
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        key
+    );
+
+    // 默认模式下每次写入都会改变全部分片
+    let mut store = KeyStore::from_path(&path).unwrap();
+    store.update_bytes(&key).unwrap();
+    let rewritten = shard_bytes(&path);
+    assert!(after.iter().zip(&rewritten).all(|(a, r)| a != r));
+}
+
+#[test]
+fn test_in_memory_store_into_binary() {
+    let dir = tempfile::tempdir().unwrap();
+    let original = fs::read(fresh_binary_copy(dir.path(), "in_memory")).unwrap();
+
+    let mut store = KeyStore::from_bytes(original.clone()).unwrap();
+    store.update_bytes(b"in-memory-1").unwrap();
+    store.update_bytes(b"in-memory-2").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"in-memory-2");
+    assert!(!store.has_pending_writes());
+    store.flush().unwrap();
+    let patched = store.into_binary().unwrap();
+    assert_ne!(patched, original);
+    assert_eq!(patched.len(), original.len());
+
+    // 取出的二进制可以再次解析，也可以写成文件后正常打开
+    let reparsed = KeyStore::from_bytes(patched.clone()).unwrap();
+    assert_eq!(reparsed.read_bytes().unwrap(), b"in-memory-2");
+    let path = dir.path().join("uploaded");
+    fs::write(&path, &patched).unwrap();
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        b"in-memory-2"
+    );
+
+    // 文件实例返回磁盘上的内容
+    let store = KeyStore::from_path(&path).unwrap();
+    assert_eq!(store.into_binary().unwrap(), patched);
+}
+
+#[test]
+fn test_remap_on_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "remap");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_remap_on_write(true);
+    let key: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+
+    let snapshot = |store: &KeyStore| {
+        let data = fs::read(&path).unwrap();
+        let names = stored_shard_names(store);
+        let sections: Vec<Vec<u8>> = names
+            .iter()
+            .map(|name| data[section_range(&data, name)].to_vec())
+            .collect();
+        (
+            names,
+            stored_metadata_json(store)["logical_index"].clone(),
+            sections,
+        )
+    };
+
+    store.update_bytes(&key).unwrap();
+    let (names_1, order_1, sections_1) = snapshot(&store);
+    store.update_bytes(&key).unwrap();
+    let (names_2, order_2, sections_2) = snapshot(&store);
+
+    // 使用的section不变，逻辑顺序和各section的内容都变了
+    assert_eq!(names_1, names_2);
+    assert_ne!(order_1, order_2);
+    assert!(sections_1.iter().zip(&sections_2).all(|(a, b)| a != b));
+    assert_eq!(
+        KeyStore::from_path(&path).unwrap().read_bytes().unwrap(),
+        key
+    );
+}
+
+#[test]
+fn test_sanity_warning_on_zero_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "sanity");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_sanity_warnings(true);
+
+    store.update_bytes(b"a perfectly normal key").unwrap();
+    let (key, warnings) = store.read_bytes_checked().unwrap();
+    assert_eq!(key, b"a perfectly normal key");
+    assert!(warnings.is_empty());
+
+    // 模拟常数不匹配时常见的解密结果：开头一大段零字节
+    let mut suspicious = vec![0u8; 24];
+    suspicious.extend_from_slice(b"tail");
+    store.update_bytes(&suspicious).unwrap();
+    let (key, warnings) = store.read_bytes_checked().unwrap();
+    assert_eq!(key, suspicious);
+    assert_eq!(warnings, [SanityWarning::ZeroPrefix { len: 16 }]);
+    assert!(warnings[0].to_string().contains("全为零"));
+
+    // 未开启时不检查
+    let (_, warnings) = KeyStore::from_path(&path)
+        .unwrap()
+        .read_bytes_checked()
+        .unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compression_dictionary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "dictionary");
+
+    // 结构相同的JSON，共享一段很长的随机证书链，单独压缩后仍超出最大容量（8个分片）。
+    // 尚未写入时每次打开随机决定分片数量，因此按最大容量而不是当前实例的容量构造
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let chain: String = KeyStore::generate_random_bytes(8 * 1024 * 3 / 2)
+        .iter()
+        .map(|b| ALPHABET[(b % 64) as usize] as char)
+        .collect();
+    let blob = |user: &str| {
+        format!(
+            r#"{{"user":"{}","scope":["read","write"],"chain":"{}"}}"#,
+            user, chain
+        )
+        .into_bytes()
+    };
+    let dictionary = blob("template");
+    let secret = blob("svc-payments");
+
+    let mut plain = KeyStore::from_path(&path).unwrap();
+    assert!(plain.update_bytes(&secret).is_err());
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_compression_dictionary(dictionary.clone());
+    store.update_bytes(&secret).unwrap();
+    assert!(store.stored_payload_len().unwrap() <= store.capacity());
+    assert_eq!(store.read_bytes().unwrap(), secret);
+    assert_eq!(
+        stored_metadata_json(&store)["dictionary_id"]
+            .as_str()
+            .unwrap()
+            .len(),
+        16
+    );
+
+    // 之后读取必须提供同一个字典
+    let reopened = KeyStore::from_path(&path)
+        .unwrap()
+        .with_compression_dictionary(dictionary);
+    assert_eq!(reopened.read_bytes().unwrap(), secret);
+    assert!(matches!(
+        KeyStore::from_path(&path).unwrap().read_bytes(),
+        Err(Error::Config(_))
+    ));
+    assert!(matches!(
+        KeyStore::from_path(&path)
+            .unwrap()
+            .with_compression_dictionary(blob("other"))
+            .read_bytes(),
+        Err(Error::Config(_))
+    ));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_dictionary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "zstd_dictionary");
+
+    // 与 test_compression_dictionary 相同：共享的随机证书链单独压缩后超出最大容量
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let chain: String = KeyStore::generate_random_bytes(8 * 1024 * 3 / 2)
+        .iter()
+        .map(|b| ALPHABET[(b % 64) as usize] as char)
+        .collect();
+    let blob = |user: &str| {
+        format!(
+            r#"{{"user":"{}","scope":["read","write"],"chain":"{}"}}"#,
+            user, chain
+        )
+        .into_bytes()
+    };
+    let dictionary = blob("template");
+    let secret = blob("svc-payments");
+
+    let mut plain = KeyStore::from_path(&path).unwrap();
+    assert!(plain.update_bytes(&secret).is_err());
+
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_zstd_dictionary(dictionary.clone());
+    store.update_bytes(&secret).unwrap();
+    assert!(store.stored_payload_len().unwrap() <= store.capacity());
+    assert_eq!(store.read_bytes().unwrap(), secret);
+    let dictionary_id = stored_metadata_json(&store)["dictionary_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(dictionary_id.starts_with("zstd:"));
+    assert_eq!(dictionary_id.len(), "zstd:".len() + 16);
+
+    // 之后读取必须提供同一个字典
+    let reopened = KeyStore::from_path(&path)
+        .unwrap()
+        .with_zstd_dictionary(dictionary);
+    assert_eq!(reopened.read_bytes().unwrap(), secret);
+    assert!(matches!(
+        KeyStore::from_path(&path).unwrap().read_bytes(),
+        Err(Error::Config(_))
+    ));
+    assert!(matches!(
+        KeyStore::from_path(&path)
+            .unwrap()
+            .with_zstd_dictionary(blob("other"))
+            .read_bytes(),
+        Err(Error::Config(_))
+    ));
+}
+
+#[test]
+fn test_interleave_stride_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = fresh_binary_copy(dir.path(), "stride");
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_shard_layout(ShardLayout::Interleaved)
+        .with_interleave_stride(InterleaveStride(4));
+
+    let key = KeyStore::generate_random_bytes(4096);
+    store.update_bytes(&key).unwrap();
+    assert_eq!(stored_metadata_json(&store)["interleave_stride"], 4);
+
+    // 步长记录在元数据中，重新打开时无需再次设置
+    let reopened = KeyStore::from_path(&path).unwrap();
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+    let mut constant = KeyStore::from_path(&path).unwrap();
+    constant.set_constant_time(true);
+    assert_eq!(constant.read_bytes().unwrap(), key);
+
+    let mut zero = KeyStore::from_path(&path)
+        .unwrap()
+        .with_shard_layout(ShardLayout::Interleaved)
+        .with_interleave_stride(InterleaveStride(0));
+    assert!(matches!(zero.update_bytes(&key), Err(Error::Config(_))));
+    assert_eq!(reopened.read_bytes().unwrap(), key);
+}
@@ -0,0 +1,21 @@
+//! 自定义元数据section大小的测试
+
+use self_crypto_key::{init_key_storage, KeyStore};
+use std::fs;
+
+init_key_storage!(meta_size = 8192);
+
+#[test]
+fn test_custom_meta_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("meta_size");
+    fs::copy("/proc/self/exe", &path).unwrap();
+
+    let mut store = KeyStore::from_path(path).unwrap();
+    store.update_bytes(b"bigger-meta").unwrap();
+
+    let (used, total) = store.metadata_usage().unwrap();
+    assert_eq!(total, 8192);
+    assert!(used < total);
+    assert_eq!(store.read_bytes().unwrap(), b"bigger-meta");
+}
@@ -0,0 +1,90 @@
+//! ELF note格式存储的测试
+
+use self_crypto_key::{init_key_storage, Error, KeyStore, StorageFormat};
+use std::fs;
+
+init_key_storage!(note_vendor = "SCKTEST");
+
+/// 按名称查找section头，返回 (sh_type, sh_offset, sh_size)
+fn section_header(data: &[u8], name: &str) -> (u32, usize, usize) {
+    let u16_at = |o: usize| u16::from_le_bytes(data[o..o + 2].try_into().unwrap()) as usize;
+    let u32_at = |o: usize| u32::from_le_bytes(data[o..o + 4].try_into().unwrap());
+    let u64_at = |o: usize| u64::from_le_bytes(data[o..o + 8].try_into().unwrap()) as usize;
+
+    let shoff = u64_at(0x28);
+    let (shentsize, shnum, shstrndx) = (u16_at(0x3a), u16_at(0x3c), u16_at(0x3e));
+    let strtab = u64_at(shoff + shstrndx * shentsize + 24);
+
+    (0..shnum)
+        .map(|i| shoff + i * shentsize)
+        .find(|&header| {
+            let start = strtab + u32_at(header) as usize;
+            let end = start + data[start..].iter().position(|&b| b == 0).unwrap();
+            &data[start..end] == name.as_bytes()
+        })
+        .map(|header| (u32_at(header + 4), u64_at(header + 24), u64_at(header + 32)))
+        .unwrap()
+}
+
+#[test]
+fn test_note_format_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("note_format");
+    fs::copy("/proc/self/exe", &path).unwrap();
+
+    let format = StorageFormat::ElfNote {
+        vendor: "SCKTEST".to_string(),
+    };
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_storage_format(format.clone());
+    assert_eq!(store.storage_format(), &format);
+
+    store.update_bytes(b"note-stored-key").unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"note-stored-key");
+
+    // 默认格式与二进制不一致时拒绝读写
+    let mismatched = KeyStore::from_path(&path).unwrap();
+    assert!(matches!(mismatched.read_bytes(), Err(Error::Config(_))));
+
+    // 按 `readelf -n` 的方式解析：SHT_NOTE类型，厂商名称和描述数据大小完整
+    let data = fs::read(&path).unwrap();
+    for name in [".note.key_meta", ".note.key_data_00", ".note.key_data_07"] {
+        let (sh_type, offset, size) = section_header(&data, name);
+        assert_eq!(sh_type, 7, "{} 不是SHT_NOTE", name);
+
+        let note = &data[offset..offset + size];
+        let namesz = u32::from_le_bytes(note[0..4].try_into().unwrap()) as usize;
+        let descsz = u32::from_le_bytes(note[4..8].try_into().unwrap()) as usize;
+        assert_eq!(&note[12..12 + namesz], b"SCKTEST\0");
+        assert_eq!(12 + namesz.next_multiple_of(4) + descsz, size);
+    }
+}
+
+#[test]
+fn test_note_sections_not_used_for_derivation() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("note_derive");
+    fs::copy("/proc/self/exe", &path).unwrap();
+    let format = StorageFormat::ElfNote {
+        vendor: "SCKTEST".to_string(),
+    };
+
+    // 每次写入都会修改的note section不会出现在建议中
+    let store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_storage_format(format.clone());
+    let suggested = store.suggest_derive_section().unwrap();
+    assert!(!suggested.is_empty());
+    assert!(suggested.iter().all(|(name, _)| !name.contains("key_")));
+
+    // 显式指定时写入被拒绝
+    let mut store = KeyStore::from_path(&path)
+        .unwrap()
+        .with_storage_format(format)
+        .with_derive_sections(&[".note.key_data_03"]);
+    match store.update_bytes(b"key") {
+        Err(Error::Config(msg)) => assert!(msg.contains(".note.key_data_03"), "{}", msg),
+        other => panic!("错误类型不符: {:?}", other),
+    }
+}
@@ -0,0 +1,12 @@
+//! 启动检查测试
+//!
+//! 单独的测试二进制：其他测试会替换自身的测试二进制，检查当前可执行文件时可能与之冲突
+
+use self_crypto_key::{init_key_storage, preflight};
+
+init_key_storage!();
+
+#[test]
+fn test_preflight_with_macro() {
+    preflight().unwrap();
+}
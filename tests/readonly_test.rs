@@ -0,0 +1,53 @@
+//! 只读密钥存储测试
+//!
+//! 单独的测试二进制，测试过程中不会被改写，可以安全地将其设置为只读
+
+use self_crypto_key::{init_key_storage, KeyStore};
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+init_key_storage!();
+
+#[test]
+fn test_open_readonly_on_readonly_file() {
+    // 将测试二进制本身设置为只读后打开
+    let exe = env::current_exe().unwrap();
+    let original = fs::metadata(&exe).unwrap().permissions();
+    fs::set_permissions(&exe, fs::Permissions::from_mode(0o555)).unwrap();
+
+    let result = KeyStore::open_readonly().and_then(|store| {
+        let capacity = store.capacity();
+        let stored_len = store.stored_len()?;
+        let key = store.read_bytes()?;
+        Ok((capacity, stored_len, key))
+    });
+
+    fs::set_permissions(&exe, original).unwrap();
+
+    let (capacity, stored_len, key) = result.unwrap();
+    assert!(capacity >= 4096);
+    assert_eq!(stored_len, key.len());
+}
+
+#[test]
+fn test_from_path_readonly() {
+    // 在副本中写入密钥后设置为只读，再以只读方式打开
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("provisioned");
+    fs::copy(env::current_exe().unwrap(), &path).unwrap();
+    KeyStore::from_path(&path)
+        .unwrap()
+        .update_bytes(b"provisioned-key")
+        .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+
+    let store = KeyStore::from_path_readonly(&path).unwrap();
+    assert_eq!(store.read_bytes().unwrap(), b"provisioned-key");
+    assert!(store.verify_against(b"provisioned-key").unwrap());
+    assert!(!store.verify_against(b"other-key").unwrap());
+    assert_eq!(
+        fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+        0o444
+    );
+}